@@ -0,0 +1,118 @@
+//! Compresses and decompresses files with any codec from
+//! `the_algorithms_rust::compression`.
+//!
+//! ```text
+//! compress [-c CODEC] INPUT OUTPUT   compress INPUT (default codec: zlib)
+//! compress -d INPUT OUTPUT           decompress, whatever the codec was
+//! compress -b INPUT                  compare all codecs on INPUT
+//! compress -l                        list the codecs
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write as _};
+use std::process::ExitCode;
+use std::time::Instant;
+
+use the_algorithms_rust::compression::{
+    codec_by_name, codecs, compress_stream, decompress_stream, Codec,
+};
+
+const USAGE: &str = "usage: compress [-c CODEC] INPUT OUTPUT | -d INPUT OUTPUT | -b INPUT | -l";
+
+fn ratio(compressed: u64, original: u64) -> f64 {
+    if original == 0 {
+        return 0.0;
+    }
+    100.0 * compressed as f64 / original as f64
+}
+
+fn compress_file(codec: &dyn Codec, input: &str, output: &str) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(input)?);
+    let mut writer = BufWriter::new(File::create(output)?);
+    let original = compress_stream(codec, &mut reader, &mut writer)?;
+    writer.flush()?;
+    let compressed = std::fs::metadata(output)?.len();
+    println!(
+        "{input}: {original} -> {compressed} bytes ({:.1}%) with {}",
+        ratio(compressed, original),
+        codec.name()
+    );
+    Ok(())
+}
+
+fn decompress_file(input: &str, output: &str) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(input)?);
+    let mut writer = BufWriter::new(File::create(output)?);
+    let original = decompress_stream(&mut reader, &mut writer)?;
+    writer.flush()?;
+    let compressed = std::fs::metadata(input)?.len();
+    println!(
+        "{input}: {compressed} -> {original} bytes ({:.1}%)",
+        ratio(compressed, original)
+    );
+    Ok(())
+}
+
+fn benchmark(input: &str) -> io::Result<()> {
+    let data = std::fs::read(input)?;
+    println!("{input}: {} bytes", data.len());
+    for codec in codecs() {
+        let start = Instant::now();
+        let mut compressed = Vec::new();
+        compress_stream(&*codec, &mut &data[..], &mut compressed)?;
+        let compress_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut decompressed = Vec::new();
+        decompress_stream(&mut &compressed[..], &mut decompressed)?;
+        let decompress_time = start.elapsed();
+        if decompressed != data {
+            return Err(io::Error::other(format!(
+                "{} failed to round trip",
+                codec.name()
+            )));
+        }
+        println!(
+            "{:>8} {:>10} bytes {:>6.1}%  compress {:>9.2?}  decompress {:>9.2?}",
+            codec.name(),
+            compressed.len(),
+            ratio(compressed.len() as u64, data.len() as u64),
+            compress_time,
+            decompress_time
+        );
+    }
+    Ok(())
+}
+
+fn run(args: &[String]) -> io::Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args[..] {
+        ["-l"] => {
+            for codec in codecs() {
+                println!("{:>3} {}", codec.id(), codec.name());
+            }
+            Ok(())
+        }
+        ["-b", input] => benchmark(input),
+        ["-d", input, output] => decompress_file(input, output),
+        ["-c", name, input, output] => {
+            let codec = codec_by_name(name).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("unknown codec {name}"))
+            })?;
+            compress_file(&*codec, input, output)
+        }
+        [input, output] => compress_file(&*codec_by_name("zlib").unwrap(), input, output),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("compress: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
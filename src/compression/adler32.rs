@@ -0,0 +1,60 @@
+//! Adler-32 checksum, as used by the zlib format (RFC 1950).
+//!
+//! The checksum is made of two 16-bit sums modulo 65521 (the largest prime
+//! smaller than 2^16): `a` is the sum of all bytes plus one and `b` is the sum
+//! of every intermediate value of `a`.
+
+const MOD_ADLER: u32 = 65521;
+
+/// Largest number of bytes that can be summed before `b` could overflow a `u32`.
+const NMAX: usize = 5552;
+
+/// Continues an Adler-32 computation started with a previous checksum.
+///
+/// Passing `1` as `adler` starts a new checksum, so
+/// `adler32_update(1, data) == adler32(data)`.
+pub fn adler32_update(adler: u32, data: &[u8]) -> u32 {
+    let mut a = adler & 0xffff;
+    let mut b = adler >> 16;
+    for chunk in data.chunks(NMAX) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+/// Computes the Adler-32 checksum of `data`.
+pub fn adler32(data: &[u8]) -> u32 {
+    adler32_update(1, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_values() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"a"), 0x0062_0062);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(b"123456789"), 0x091E_01DE);
+    }
+
+    #[test]
+    fn incremental_matches_one_shot() {
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i * 7 + i / 3) as u8).collect();
+        let (head, tail) = data.split_at(12_345);
+        assert_eq!(adler32_update(adler32(head), tail), adler32(&data));
+    }
+
+    #[test]
+    fn long_run_of_max_bytes() {
+        // exercises the modulo reduction between chunks
+        let data = vec![0xffu8; 100_000];
+        assert_eq!(adler32(&data), 0x149A_302C);
+    }
+}
@@ -0,0 +1,158 @@
+//! Bit-level reading and writing.
+//!
+//! Bits are packed least-significant bit first within each byte, which is the
+//! order used by DEFLATE. Plain values are written with their least
+//! significant bit first, while Huffman codes are written starting from their
+//! most significant bit so that a decoder can walk the code tree one bit at a
+//! time.
+
+/// The input ended before all the requested bits could be read.
+#[derive(Debug, PartialEq, Eq)]
+pub struct EndOfInput;
+
+/// Writes bits into a growing byte buffer.
+#[derive(Debug, Default)]
+pub struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the `count` (at most 32) low bits of `value`, least significant first.
+    pub fn write_bits(&mut self, value: u32, count: u8) {
+        debug_assert!(count <= 32);
+        self.buffer |= ((value as u64) & ((1 << count) - 1)) << self.count;
+        self.count += count as u32;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a `length`-bit Huffman code, most significant bit first.
+    pub fn write_code(&mut self, code: u32, length: u8) {
+        if length > 0 {
+            self.write_bits(code.reverse_bits() >> (32 - length as u32), length);
+        }
+    }
+
+    /// Pads the current byte with zero bits.
+    pub fn align_to_byte(&mut self) {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+            self.buffer = 0;
+            self.count = 0;
+        }
+    }
+
+    /// Writes whole bytes, after padding the current byte.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.align_to_byte();
+        self.out.extend_from_slice(bytes);
+    }
+
+    /// Number of bits written so far.
+    pub fn bit_len(&self) -> usize {
+        self.out.len() * 8 + self.count as usize
+    }
+
+    /// Pads the last byte with zero bits and returns the buffer.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.align_to_byte();
+        self.out
+    }
+}
+
+/// Reads bits from a byte slice.
+#[derive(Debug)]
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    bit_pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader { data, bit_pos: 0 }
+    }
+
+    pub fn read_bit(&mut self) -> Result<u32, EndOfInput> {
+        let byte = self.data.get(self.bit_pos >> 3).ok_or(EndOfInput)?;
+        let bit = (byte >> (self.bit_pos & 7)) & 1;
+        self.bit_pos += 1;
+        Ok(bit as u32)
+    }
+
+    /// Reads a `count`-bit value (at most 32) written by [`BitWriter::write_bits`].
+    pub fn read_bits(&mut self, count: u8) -> Result<u32, EndOfInput> {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.read_bit()? << i;
+        }
+        Ok(value)
+    }
+
+    /// Skips to the start of the next byte, unless already there.
+    pub fn align_to_byte(&mut self) {
+        self.bit_pos = (self.bit_pos + 7) & !7;
+    }
+
+    /// Reads `len` whole bytes, starting at the next byte boundary.
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], EndOfInput> {
+        self.align_to_byte();
+        let start = self.bit_pos >> 3;
+        let bytes = self.data.get(start..start + len).ok_or(EndOfInput)?;
+        self.bit_pos += len << 3;
+        Ok(bytes)
+    }
+
+    /// Number of bytes consumed so far, counting a partially read byte.
+    pub fn bytes_consumed(&self) -> usize {
+        (self.bit_pos + 7) >> 3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut writer = BitWriter::new();
+        writer.write_bits(0b101, 3);
+        writer.write_code(0b110, 3);
+        writer.write_bits(0xdead_beef, 32);
+        writer.write_bytes(b"xy");
+        writer.write_bits(1, 1);
+        assert_eq!(writer.bit_len(), 8 + 32 + 16 + 1);
+        let bytes = writer.finish();
+        assert_eq!(bytes.len(), 8);
+
+        let mut reader = BitReader::new(&bytes);
+        assert_eq!(reader.read_bits(3), Ok(0b101));
+        // a code reads back bit by bit, most significant first
+        assert_eq!(reader.read_bit(), Ok(1));
+        assert_eq!(reader.read_bit(), Ok(1));
+        assert_eq!(reader.read_bit(), Ok(0));
+        assert_eq!(reader.read_bits(32), Ok(0xdead_beef));
+        assert_eq!(reader.read_bytes(2), Ok(&b"xy"[..]));
+        assert_eq!(reader.read_bit(), Ok(1));
+        assert_eq!(reader.bytes_consumed(), 8);
+        assert_eq!(reader.read_bits(8), Err(EndOfInput));
+    }
+
+    #[test]
+    fn lsb_first_packing() {
+        let mut writer = BitWriter::new();
+        writer.write_bits(1, 1);
+        writer.write_bits(0b10, 2);
+        writer.write_bits(0x1f, 5);
+        writer.write_bits(0xffff_ffff, 4);
+        assert_eq!(writer.finish(), [0b1111_1101, 0b0000_1111]);
+    }
+}
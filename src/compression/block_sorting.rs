@@ -0,0 +1,326 @@
+//! bzip2-style block-sorting compression.
+//!
+//! The input is cut into blocks, and every block goes through the same chain
+//! of transforms as in bzip2:
+//!
+//! 1. the [Burrows-Wheeler transform](super::bwt_forward) groups bytes with
+//!    similar contexts together,
+//! 2. [move-to-front](super::move_to_front_encode) turns those groups into
+//!    runs of small numbers, mostly zeros,
+//! 3. zero-run encoding writes every run of zeros as its length in bijective
+//!    base 2, with the two digits `RUNA` and `RUNB`,
+//! 4. canonical Huffman coding of the resulting symbols.
+//!
+//! The container is not compatible with bzip2 itself. It starts with the
+//! magic `BZR` and a level byte `1..=9`, the block size being
+//! `level * 100_000` bytes. Each block then holds, as little-endian 32-bit
+//! values, its length, the origin row of the transform and the CRC-32 of the
+//! block, followed by one 4-bit code length per symbol and the Huffman coded
+//! symbols up to an end-of-block symbol, padded to a whole byte. A block
+//! length of zero ends the stream.
+
+use super::bit_io::{BitReader, BitWriter, EndOfInput};
+use super::burrows_wheeler::{bwt_forward, bwt_inverse};
+use super::canonical_huffman::{canonical_codes, package_merge, CanonicalDecoder, HuffmanError};
+use super::crc32::crc32;
+use super::move_to_front::{move_to_front_decode, move_to_front_encode};
+
+const MAGIC: &[u8; 3] = b"BZR";
+const BLOCK_UNIT: usize = 100_000;
+
+/// Zero-run digit with value 1.
+pub const RUNA: u16 = 0;
+/// Zero-run digit with value 2.
+pub const RUNB: u16 = 1;
+/// Ends the symbols of a block. A non-zero byte `b` is coded as `b + 1`.
+pub const END_OF_BLOCK: u16 = 257;
+const NUM_SYMBOLS: usize = END_OF_BLOCK as usize + 1;
+const MAX_CODE_LENGTH: u8 = 15;
+
+/// Errors that can occur while decompressing block-sorted data.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BlockSortError {
+    /// The stream does not start with a valid magic and level.
+    InvalidHeader,
+    /// The input ended in the middle of a block.
+    UnexpectedEof,
+    /// The code lengths describe an over-subscribed code.
+    InvalidCodeLengths,
+    /// A code or symbol that cannot appear at this point.
+    InvalidCode,
+    /// The block header does not match its contents.
+    InvalidBlock,
+    /// A block decompressed to data with a different CRC-32.
+    ChecksumMismatch,
+}
+
+impl From<EndOfInput> for BlockSortError {
+    fn from(_: EndOfInput) -> Self {
+        BlockSortError::UnexpectedEof
+    }
+}
+
+impl From<HuffmanError> for BlockSortError {
+    fn from(error: HuffmanError) -> Self {
+        match error {
+            HuffmanError::UnexpectedEof => BlockSortError::UnexpectedEof,
+            HuffmanError::InvalidCodeLengths => BlockSortError::InvalidCodeLengths,
+            HuffmanError::InvalidCode => BlockSortError::InvalidCode,
+        }
+    }
+}
+
+/// Replaces every run of zeros with its length in bijective base 2, least
+/// significant digit first, and every other byte `b` with `b + 1`.
+pub fn zero_run_encode(data: &[u8]) -> Vec<u16> {
+    let mut out = Vec::new();
+    let mut run = 0usize;
+    for &byte in data.iter().chain(std::iter::once(&1)) {
+        if byte == 0 {
+            run += 1;
+            continue;
+        }
+        while run > 0 {
+            if run & 1 == 1 {
+                out.push(RUNA);
+                run = (run - 1) / 2;
+            } else {
+                out.push(RUNB);
+                run = (run - 2) / 2;
+            }
+        }
+        out.push(byte as u16 + 1);
+    }
+    // the sentinel byte is not part of the data
+    out.pop();
+    out
+}
+
+/// Inverse of [`zero_run_encode`]. Fails if a symbol is out of range or the
+/// output would grow beyond `limit` bytes.
+pub fn zero_run_decode(symbols: &[u16], limit: usize) -> Result<Vec<u8>, BlockSortError> {
+    let mut out = Vec::new();
+    let mut run = 0usize;
+    let mut weight = 1usize;
+    for &symbol in symbols {
+        match symbol {
+            RUNA | RUNB => {
+                run += weight * (symbol as usize + 1);
+                weight = weight.saturating_mul(2);
+                if out.len() + run > limit {
+                    return Err(BlockSortError::InvalidBlock);
+                }
+            }
+            2..END_OF_BLOCK => {
+                out.resize(out.len() + run, 0);
+                (run, weight) = (0, 1);
+                if out.len() == limit {
+                    return Err(BlockSortError::InvalidBlock);
+                }
+                out.push((symbol - 1) as u8);
+            }
+            _ => return Err(BlockSortError::InvalidCode),
+        }
+    }
+    out.resize(out.len() + run, 0);
+    Ok(out)
+}
+
+fn compress_block(block: &[u8], writer: &mut BitWriter) {
+    let (last, origin) = bwt_forward(block);
+    let mut symbols = zero_run_encode(&move_to_front_encode(&last));
+    symbols.push(END_OF_BLOCK);
+
+    let mut frequencies = [0u64; NUM_SYMBOLS];
+    for &symbol in &symbols {
+        frequencies[symbol as usize] += 1;
+    }
+    let lengths = package_merge(&frequencies, MAX_CODE_LENGTH);
+    let codes = canonical_codes(&lengths);
+
+    writer.write_bits(block.len() as u32, 32);
+    writer.write_bits(origin as u32, 32);
+    writer.write_bits(crc32(block), 32);
+    for &length in &lengths {
+        writer.write_bits(length as u32, 4);
+    }
+    for &symbol in &symbols {
+        let symbol = symbol as usize;
+        writer.write_code(codes[symbol], lengths[symbol]);
+    }
+    writer.align_to_byte();
+}
+
+fn decompress_block(
+    reader: &mut BitReader,
+    len: usize,
+    out: &mut Vec<u8>,
+) -> Result<(), BlockSortError> {
+    let origin = reader.read_bits(32)? as usize;
+    let crc = reader.read_bits(32)?;
+    let lengths = std::iter::repeat_with(|| reader.read_bits(4).map(|length| length as u8))
+        .take(NUM_SYMBOLS)
+        .collect::<Result<Vec<u8>, _>>()?;
+    let decoder = CanonicalDecoder::new(&lengths)?;
+
+    let mut symbols = Vec::new();
+    loop {
+        let symbol = decoder.decode(reader)? as u16;
+        if symbol == END_OF_BLOCK {
+            break;
+        }
+        symbols.push(symbol);
+    }
+    reader.align_to_byte();
+
+    let last = move_to_front_decode(&zero_run_decode(&symbols, len)?);
+    if last.len() != len || origin >= len {
+        return Err(BlockSortError::InvalidBlock);
+    }
+    let block = bwt_inverse(&last, origin);
+    if crc32(&block) != crc {
+        return Err(BlockSortError::ChecksumMismatch);
+    }
+    out.extend_from_slice(&block);
+    Ok(())
+}
+
+/// Compresses `data` in blocks of `level * 100_000` bytes.
+///
+/// # Panics
+///
+/// Panics if `level` is not in `1..=9`.
+pub fn block_sort_compress(data: &[u8], level: u8) -> Vec<u8> {
+    assert!((1..=9).contains(&level), "level must be in 1..=9");
+    let mut writer = BitWriter::new();
+    writer.write_bytes(MAGIC);
+    writer.write_bytes(&[level]);
+    for block in data.chunks(level as usize * BLOCK_UNIT) {
+        compress_block(block, &mut writer);
+    }
+    writer.write_bits(0, 32);
+    writer.finish()
+}
+
+/// Decompresses the output of [`block_sort_compress`].
+pub fn block_sort_decompress(data: &[u8]) -> Result<Vec<u8>, BlockSortError> {
+    if data.len() < 4 || &data[..3] != MAGIC || !(1..=9).contains(&data[3]) {
+        return Err(BlockSortError::InvalidHeader);
+    }
+    let max_len = data[3] as usize * BLOCK_UNIT;
+    let mut reader = BitReader::new(&data[4..]);
+    let mut out = Vec::new();
+    loop {
+        let len = reader.read_bits(32)? as usize;
+        if len == 0 {
+            return Ok(out);
+        }
+        if len > max_len {
+            return Err(BlockSortError::InvalidBlock);
+        }
+        decompress_block(&mut reader, len, &mut out)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::huffman_compress;
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    fn sample_text() -> Vec<u8> {
+        let words = [
+            "to", "be", "or", "not", "that", "is", "the", "question", "\n",
+        ];
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut text = Vec::new();
+        while text.len() < 150_000 {
+            text.extend_from_slice(words.choose(&mut rng).unwrap().as_bytes());
+            text.push(b' ');
+        }
+        text
+    }
+
+    #[test]
+    fn zero_runs() {
+        assert_eq!(zero_run_encode(&[]), []);
+        assert_eq!(zero_run_encode(&[0]), [RUNA]);
+        assert_eq!(zero_run_encode(&[0, 0]), [RUNB]);
+        assert_eq!(zero_run_encode(&[0, 0, 0]), [RUNA, RUNA]);
+        assert_eq!(zero_run_encode(&[0, 0, 0, 0, 5, 0]), [RUNB, RUNA, 6, RUNA]);
+        for len in 0..100 {
+            let data: Vec<u8> = std::iter::repeat_n(0, len).chain([3, 0]).collect();
+            let symbols = zero_run_encode(&data);
+            assert!(symbols.len() <= 2 * (len + 1).ilog2() as usize + 3);
+            assert_eq!(zero_run_decode(&symbols, data.len()).unwrap(), data);
+        }
+        assert_eq!(
+            zero_run_decode(&[RUNB, RUNB], 5),
+            Err(BlockSortError::InvalidBlock)
+        );
+        assert_eq!(zero_run_decode(&[258], 5), Err(BlockSortError::InvalidCode));
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = sample_text();
+        let zeros = vec![0u8; 250_000];
+        let mut state = 1u32;
+        let noise: Vec<u8> = std::iter::repeat_with(|| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .take(120_000)
+        .collect();
+        for data in [&b""[..], b"a", b"banana", &text, &zeros, &noise] {
+            for level in [1, 9] {
+                let compressed = block_sort_compress(data, level);
+                assert_eq!(block_sort_decompress(&compressed).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn compresses_text_better_than_huffman() {
+        let text = sample_text();
+        let compressed = block_sort_compress(&text, 9);
+        assert!(compressed.len() * 2 < huffman_compress(&text).len());
+        assert!(block_sort_compress(&vec![7; 100_000], 1).len() < 200);
+    }
+
+    #[test]
+    fn splits_into_blocks() {
+        let data: Vec<u8> = (0..250_000u32).map(|i| (i % 251) as u8).collect();
+        let compressed = block_sort_compress(&data, 1);
+        let mut reader = BitReader::new(&compressed[4..]);
+        assert_eq!(reader.read_bits(32), Ok(100_000));
+        assert_eq!(block_sort_decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_corrupt_input() {
+        let compressed = block_sort_compress(b"hello hello hello", 1);
+        assert_eq!(
+            block_sort_decompress(b"BZR0"),
+            Err(BlockSortError::InvalidHeader)
+        );
+        assert_eq!(
+            block_sort_decompress(&compressed[..compressed.len() - 4]),
+            Err(BlockSortError::UnexpectedEof)
+        );
+        let mut bad_crc = compressed.clone();
+        bad_crc[12] ^= 1;
+        assert_eq!(
+            block_sort_decompress(&bad_crc),
+            Err(BlockSortError::ChecksumMismatch)
+        );
+        let mut bad_origin = compressed;
+        bad_origin[8] = 200;
+        assert_eq!(
+            block_sort_decompress(&bad_origin),
+            Err(BlockSortError::InvalidBlock)
+        );
+    }
+}
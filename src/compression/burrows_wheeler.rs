@@ -0,0 +1,189 @@
+//! Burrows-Wheeler transform over bytes.
+//!
+//! The transform sorts all rotations of a block and keeps the last byte of
+//! each. Bytes that are followed by similar contexts end up next to each
+//! other, which makes the output easy to compress with simple local models.
+//!
+//! Unlike `string::burrows_wheeler_transform`, which sorts the rotations as
+//! strings, the rotations are ranked by prefix doubling on the cyclic suffix
+//! array: after round `k` every rotation is ranked by its first `2^k` bytes,
+//! using counting sorts only. This takes `O(n log n)` time and `O(n)` memory,
+//! whatever the input.
+
+/// Returns the starting positions of all rotations of `data`, in sorted
+/// order. Equal rotations (of a periodic input) are in no particular order.
+pub fn rotation_order(data: &[u8]) -> Vec<usize> {
+    let n = data.len();
+    if n == 0 {
+        return Vec::new();
+    }
+
+    // sort by first byte
+    let mut counts = vec![0usize; 256.max(n)];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    for i in 1..256 {
+        counts[i] += counts[i - 1];
+    }
+    let mut order = vec![0; n];
+    for i in (0..n).rev() {
+        counts[data[i] as usize] -= 1;
+        order[counts[data[i] as usize]] = i;
+    }
+    let mut classes = vec![0; n];
+    let mut num_classes = 1;
+    for i in 1..n {
+        if data[order[i]] != data[order[i - 1]] {
+            num_classes += 1;
+        }
+        classes[order[i]] = num_classes - 1;
+    }
+
+    let mut shifted = vec![0; n];
+    let mut new_classes = vec![0; n];
+    let mut k = 1;
+    while k < n && num_classes < n {
+        // ordering by the second half is just the current order, shifted back
+        for (slot, &start) in shifted.iter_mut().zip(&order) {
+            *slot = (start + n - k) % n;
+        }
+        // stable sort by the first half
+        counts[..num_classes].fill(0);
+        for &class in &classes {
+            counts[class] += 1;
+        }
+        for i in 1..num_classes {
+            counts[i] += counts[i - 1];
+        }
+        for &start in shifted.iter().rev() {
+            counts[classes[start]] -= 1;
+            order[counts[classes[start]]] = start;
+        }
+
+        new_classes[order[0]] = 0;
+        num_classes = 1;
+        for i in 1..n {
+            let current = (classes[order[i]], classes[(order[i] + k) % n]);
+            let previous = (classes[order[i - 1]], classes[(order[i - 1] + k) % n]);
+            if current != previous {
+                num_classes += 1;
+            }
+            new_classes[order[i]] = num_classes - 1;
+        }
+        std::mem::swap(&mut classes, &mut new_classes);
+        k <<= 1;
+    }
+    order
+}
+
+/// Returns the last column of the sorted rotations of `data` together with
+/// the row holding `data` itself, which the inverse needs.
+pub fn bwt_forward(data: &[u8]) -> (Vec<u8>, usize) {
+    let n = data.len();
+    let mut origin = 0;
+    let last = rotation_order(data)
+        .into_iter()
+        .enumerate()
+        .map(|(row, start)| {
+            if start == 0 {
+                origin = row;
+            }
+            data[(start + n - 1) % n]
+        })
+        .collect();
+    (last, origin)
+}
+
+/// Rebuilds the input of [`bwt_forward`] in `O(n)` time.
+///
+/// # Panics
+///
+/// Panics if `origin` is out of range for a non-empty `last`.
+pub fn bwt_inverse(last: &[u8], origin: usize) -> Vec<u8> {
+    if last.is_empty() {
+        return Vec::new();
+    }
+    assert!(origin < last.len(), "origin out of range");
+
+    // starts[b]: first row of the sorted first column starting with byte b
+    let mut starts = [0usize; 256];
+    for &byte in last {
+        starts[byte as usize] += 1;
+    }
+    let mut sum = 0;
+    for start in starts.iter_mut() {
+        let count = *start;
+        *start = sum;
+        sum += count;
+    }
+
+    // next[row]: the row of the rotation starting one byte later
+    let mut next = vec![0; last.len()];
+    for (row, &byte) in last.iter().enumerate() {
+        next[starts[byte as usize]] = row;
+        starts[byte as usize] += 1;
+    }
+
+    let mut row = next[origin];
+    std::iter::repeat_with(|| {
+        let byte = last[row];
+        row = next[row];
+        byte
+    })
+    .take(last.len())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    fn naive_order(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut rotations: Vec<Vec<u8>> = (0..data.len())
+            .map(|i| [&data[i..], &data[..i]].concat())
+            .collect();
+        rotations.sort();
+        rotations
+    }
+
+    #[test]
+    fn known_transform() {
+        assert_eq!(bwt_forward(b"banana"), (b"nnbaaa".to_vec(), 3));
+        assert_eq!(bwt_inverse(b"nnbaaa", 3), b"banana");
+        assert_eq!(bwt_forward(b""), (Vec::new(), 0));
+        assert_eq!(bwt_inverse(b"", 0), b"");
+    }
+
+    #[test]
+    fn matches_naive_rotation_sort() {
+        let inputs: [&[u8]; 6] = [
+            b"x",
+            b"mississippi",
+            b"abababab",
+            b"aaaaaaa",
+            b"the quick brown fox jumps over the lazy dog",
+            &[255, 0, 255, 0, 1, 255],
+        ];
+        for data in inputs {
+            let rotations = naive_order(data);
+            let order = rotation_order(data);
+            for (row, &start) in order.iter().enumerate() {
+                assert_eq!([&data[start..], &data[..start]].concat(), rotations[row]);
+            }
+            let (last, origin) = bwt_forward(data);
+            assert_eq!(bwt_inverse(&last, origin), data);
+        }
+    }
+
+    #[test]
+    fn round_trips_large_input() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let data: Vec<u8> = std::iter::repeat_with(|| *b"acgt".choose(&mut rng).unwrap())
+            .take(50_000)
+            .collect();
+        let (last, origin) = bwt_forward(&data);
+        assert_eq!(bwt_inverse(&last, origin), data);
+    }
+}
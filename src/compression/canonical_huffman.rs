@@ -0,0 +1,399 @@
+//! Canonical Huffman coding with length-limited codes.
+//!
+//! A canonical Huffman code is fully determined by the code length of every
+//! symbol: codes are assigned in increasing order of length, and within one
+//! length in increasing order of symbol. So, unlike
+//! [`HuffmanDictionary`](crate::general::HuffmanDictionary), only the lengths
+//! have to be stored next to the encoded data.
+//!
+//! The lengths are computed with the package-merge algorithm, which gives an
+//! optimal code among those whose codes are at most a given number of bits
+//! long. Limiting the length keeps the header small and lets decoders use
+//! fixed-size tables.
+//!
+//! [`huffman_compress`] produces a self-contained stream: the input length as
+//! a LEB128 varint, the lowest and highest byte values present, one 4-bit code
+//! length per byte value between them, then the codes.
+//!
+//! See <https://en.wikipedia.org/wiki/Package-merge_algorithm> for package-merge.
+
+use super::bit_io::{BitReader, BitWriter, EndOfInput};
+
+/// Longest code used by [`huffman_compress`], so that lengths fit in 4 bits.
+const MAX_CODE_LENGTH: u8 = 15;
+
+/// Errors that can occur while decoding Huffman coded data.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HuffmanError {
+    /// The input ended before all symbols were decoded.
+    UnexpectedEof,
+    /// The code lengths describe an over-subscribed code.
+    InvalidCodeLengths,
+    /// A bit sequence does not correspond to any symbol.
+    InvalidCode,
+}
+
+impl From<EndOfInput> for HuffmanError {
+    fn from(_: EndOfInput) -> Self {
+        HuffmanError::UnexpectedEof
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Item {
+    Leaf(usize),
+    /// Items `2 * i` and `2 * i + 1` of the previous list.
+    Package(usize),
+}
+
+/// Computes optimal code lengths for `frequencies` such that no code is
+/// longer than `max_length` bits.
+///
+/// Symbols with a frequency of zero get length `0` (no code). A lone symbol
+/// gets a 1-bit code.
+///
+/// # Panics
+///
+/// Panics if more than `2^max_length` symbols have a non-zero frequency.
+pub fn package_merge(frequencies: &[u64], max_length: u8) -> Vec<u8> {
+    let mut lengths = vec![0u8; frequencies.len()];
+    let mut leaves: Vec<(u64, usize)> = frequencies
+        .iter()
+        .enumerate()
+        .filter(|(_, &frequency)| frequency > 0)
+        .map(|(symbol, &frequency)| (frequency, symbol))
+        .collect();
+    match leaves.len() {
+        0 => return lengths,
+        1 => {
+            lengths[leaves[0].1] = 1;
+            return lengths;
+        }
+        n => assert!(
+            max_length < 64 && n as u64 <= 1 << max_length,
+            "{n} symbols do not fit in codes of at most {max_length} bits"
+        ),
+    }
+    leaves.sort_unstable();
+
+    // every list holds (weight, item), sorted by weight
+    let leaf_list: Vec<(u64, Item)> = leaves
+        .iter()
+        .map(|&(frequency, symbol)| (frequency, Item::Leaf(symbol)))
+        .collect();
+    let mut lists = vec![leaf_list.clone()];
+    for _ in 1..max_length {
+        let previous = lists.last().unwrap();
+        let packages = previous
+            .chunks_exact(2)
+            .enumerate()
+            .map(|(i, pair)| (pair[0].0 + pair[1].0, Item::Package(i)));
+        let mut merged = Vec::with_capacity(leaf_list.len() + previous.len() / 2);
+        let mut leaves = leaf_list.iter().copied().peekable();
+        for package in packages {
+            while let Some(leaf) = leaves.next_if(|leaf| leaf.0 <= package.0) {
+                merged.push(leaf);
+            }
+            merged.push(package);
+        }
+        merged.extend(leaves);
+        lists.push(merged);
+    }
+
+    // the code length of a symbol is the number of selected items containing it
+    let selected = 2 * leaves.len() - 2;
+    let mut stack: Vec<(usize, usize)> = (0..selected).map(|i| (lists.len() - 1, i)).collect();
+    while let Some((level, index)) = stack.pop() {
+        match lists[level][index].1 {
+            Item::Leaf(symbol) => lengths[symbol] += 1,
+            Item::Package(i) => {
+                stack.push((level - 1, 2 * i));
+                stack.push((level - 1, 2 * i + 1));
+            }
+        }
+    }
+    lengths
+}
+
+/// Assigns canonical codes to the given code lengths (`0` means unused).
+/// Codes are returned right-aligned, to be written most significant bit first.
+pub fn canonical_codes(lengths: &[u8]) -> Vec<u32> {
+    let max_length = lengths.iter().copied().max().unwrap_or(0) as usize;
+    let mut counts = vec![0u32; max_length + 1];
+    for &length in lengths {
+        counts[length as usize] += 1;
+    }
+    counts[0] = 0;
+    let mut next_code = vec![0u32; max_length + 1];
+    let mut code = 0;
+    for length in 1..=max_length {
+        code = (code + counts[length - 1]) << 1;
+        next_code[length] = code;
+    }
+    lengths
+        .iter()
+        .map(|&length| {
+            if length == 0 {
+                return 0;
+            }
+            let code = next_code[length as usize];
+            next_code[length as usize] += 1;
+            code
+        })
+        .collect()
+}
+
+/// Decodes canonical Huffman codes one bit at a time.
+///
+/// The table only stores how many codes there are of every length and the
+/// symbols in code order.
+#[derive(Debug)]
+pub struct CanonicalDecoder {
+    counts: Vec<u32>,
+    symbols: Vec<usize>,
+}
+
+impl CanonicalDecoder {
+    /// Builds a decoder for the given code lengths (`0` means unused).
+    /// Incomplete codes are accepted, over-subscribed ones are not.
+    pub fn new(lengths: &[u8]) -> Result<Self, HuffmanError> {
+        let max_length = lengths.iter().copied().max().unwrap_or(0) as usize;
+        let mut counts = vec![0u32; max_length + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut left: i64 = 1;
+        for &count in &counts[1..] {
+            left = 2 * left - count as i64;
+            if left < 0 {
+                return Err(HuffmanError::InvalidCodeLengths);
+            }
+        }
+        let mut symbols: Vec<usize> = (0..lengths.len()).filter(|&s| lengths[s] > 0).collect();
+        symbols.sort_by_key(|&symbol| lengths[symbol]);
+        Ok(CanonicalDecoder { counts, symbols })
+    }
+
+    /// Reads one code from `reader` and returns its symbol.
+    pub fn decode(&self, reader: &mut BitReader) -> Result<usize, HuffmanError> {
+        // `code` is the code read so far, `first` the first code of the
+        // current length and `index` the position of `first` in `symbols`
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for &count in &self.counts[1..] {
+            code |= reader.read_bit()?;
+            if code < first + count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(HuffmanError::InvalidCode)
+    }
+}
+
+/// Compresses `data` with a canonical Huffman code built from its byte
+/// frequencies. The output can be decoded without any other information.
+pub fn huffman_compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    let mut len = data.len() as u64;
+    loop {
+        let byte = (len & 0x7f) as u32;
+        len >>= 7;
+        writer.write_bits(byte | (((len > 0) as u32) << 7), 8);
+        if len == 0 {
+            break;
+        }
+    }
+    if data.is_empty() {
+        return writer.finish();
+    }
+
+    let mut frequencies = [0u64; 256];
+    for &byte in data {
+        frequencies[byte as usize] += 1;
+    }
+    let lengths = package_merge(&frequencies, MAX_CODE_LENGTH);
+    let first = lengths.iter().position(|&length| length > 0).unwrap();
+    let last = lengths.iter().rposition(|&length| length > 0).unwrap();
+    writer.write_bits(first as u32, 8);
+    writer.write_bits(last as u32, 8);
+    for &length in &lengths[first..=last] {
+        writer.write_bits(length as u32, 4);
+    }
+    let codes = canonical_codes(&lengths);
+    for &byte in data {
+        writer.write_code(codes[byte as usize], lengths[byte as usize]);
+    }
+    writer.finish()
+}
+
+/// Decompresses the output of [`huffman_compress`].
+pub fn huffman_decompress(data: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+    let mut reader = BitReader::new(data);
+    let mut len = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = reader.read_bits(8)? as u64;
+        len |= (byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+
+    let first = reader.read_bits(8)? as usize;
+    let last = reader.read_bits(8)? as usize;
+    let mut lengths = vec![0u8; last + 1];
+    for length in lengths.iter_mut().skip(first) {
+        *length = reader.read_bits(4)? as u8;
+    }
+    let decoder = CanonicalDecoder::new(&lengths)?;
+    let mut out = Vec::new();
+    for _ in 0..len {
+        out.push(decoder.decode(&mut reader)? as u8);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::general::HuffmanDictionary;
+
+    /// Sum of frequency times code length, i.e. the encoded size in bits.
+    fn cost(frequencies: &[u64], lengths: &[u8]) -> u64 {
+        frequencies
+            .iter()
+            .zip(lengths)
+            .map(|(&f, &l)| f * l as u64)
+            .sum()
+    }
+
+    fn kraft_sum_is_one(lengths: &[u8]) -> bool {
+        let max = *lengths.iter().max().unwrap() as u32;
+        let sum: u64 = lengths
+            .iter()
+            .filter(|&&l| l > 0)
+            .map(|&l| 1u64 << (max - l as u32))
+            .sum();
+        sum == 1 << max
+    }
+
+    #[test]
+    fn package_merge_without_limit_is_optimal() {
+        let frequencies = [45, 13, 12, 16, 9, 5, 0, 1];
+        let lengths = package_merge(&frequencies, 15);
+        assert!(kraft_sum_is_one(&lengths));
+        assert_eq!(lengths[6], 0);
+
+        let alphabet: Vec<(usize, u64)> = frequencies
+            .iter()
+            .enumerate()
+            .filter(|(_, &f)| f > 0)
+            .map(|(s, &f)| (s, f))
+            .collect();
+        let dictionary = HuffmanDictionary::new(&alphabet);
+        let mut huffman_lengths = vec![0u8; frequencies.len()];
+        for (&symbol, value) in &dictionary.alphabet {
+            huffman_lengths[symbol] = value.bits as u8;
+        }
+        assert_eq!(
+            cost(&frequencies, &lengths),
+            cost(&frequencies, &huffman_lengths)
+        );
+    }
+
+    #[test]
+    fn package_merge_limits_lengths() {
+        // Fibonacci frequencies give the deepest possible Huffman tree
+        let mut frequencies = vec![1u64, 1];
+        while frequencies.len() < 20 {
+            let n = frequencies.len();
+            frequencies.push(frequencies[n - 1] + frequencies[n - 2]);
+        }
+        assert_eq!(*package_merge(&frequencies, 32).iter().max().unwrap(), 19);
+        for limit in 5..=19 {
+            let lengths = package_merge(&frequencies, limit);
+            assert_eq!(*lengths.iter().max().unwrap(), limit);
+            assert!(kraft_sum_is_one(&lengths));
+        }
+        assert_eq!(package_merge(&[1, 2, 3, 4], 2), [2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn package_merge_edge_cases() {
+        assert_eq!(package_merge(&[], 8), []);
+        assert_eq!(package_merge(&[0, 0], 8), [0, 0]);
+        assert_eq!(package_merge(&[0, 7, 0], 8), [0, 1, 0]);
+        assert_eq!(package_merge(&[1, 1], 1), [1, 1]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn package_merge_too_many_symbols() {
+        package_merge(&[1, 1, 1], 1);
+    }
+
+    #[test]
+    fn canonical_codes_follow_the_rfc_example() {
+        // RFC 1951, section 3.2.2
+        let lengths = [3, 3, 3, 3, 3, 2, 4, 4];
+        assert_eq!(
+            canonical_codes(&lengths),
+            [0b010, 0b011, 0b100, 0b101, 0b110, 0b00, 0b1110, 0b1111]
+        );
+    }
+
+    #[test]
+    fn decoder_rejects_over_subscribed_codes() {
+        assert_eq!(
+            CanonicalDecoder::new(&[1, 1, 1]).unwrap_err(),
+            HuffmanError::InvalidCodeLengths
+        );
+        // an incomplete code is fine until an unused code shows up
+        let decoder = CanonicalDecoder::new(&[1, 0, 2]).unwrap();
+        assert_eq!(decoder.decode(&mut BitReader::new(&[0b0])), Ok(0));
+        assert_eq!(decoder.decode(&mut BitReader::new(&[0b01])), Ok(2));
+        assert_eq!(
+            decoder.decode(&mut BitReader::new(&[0b11])),
+            Err(HuffmanError::InvalidCode)
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = b"this is an example of a huffman tree".repeat(10);
+        let skewed: Vec<u8> = (0..5000u32)
+            .map(|i| (i.trailing_zeros() * 31) as u8)
+            .collect();
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        for data in [&b""[..], b"a", b"aaaaaaaaaa", &text, &skewed, &all_bytes] {
+            let compressed = huffman_compress(data);
+            assert_eq!(huffman_decompress(&compressed).unwrap(), data);
+        }
+        assert!(huffman_compress(&text).len() < text.len() * 2 / 3);
+        assert!(huffman_compress(&skewed).len() < skewed.len() / 3);
+    }
+
+    #[test]
+    fn header_is_compact() {
+        // length, symbol range, four 4-bit lengths and four 2-bit codes
+        assert_eq!(
+            huffman_compress(b"abcd"),
+            [4, b'a', b'd', 0x22, 0x22, 0b1101_1000]
+        );
+    }
+
+    #[test]
+    fn decompress_rejects_truncated_input() {
+        let compressed = huffman_compress(b"hello, world");
+        assert_eq!(
+            huffman_decompress(&compressed[..compressed.len() - 2]),
+            Err(HuffmanError::UnexpectedEof)
+        );
+        assert_eq!(huffman_decompress(&[]), Err(HuffmanError::UnexpectedEof));
+    }
+}
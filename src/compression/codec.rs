@@ -0,0 +1,556 @@
+//! A common streaming interface over the compressors in this module.
+//!
+//! Every [`Codec`] wraps an [`io::Write`] into an [`Encoder`], which
+//! compresses everything written to it, and an [`io::Read`] into a
+//! [`Decoder`], which yields the decompressed bytes. Codecs whose formats are inherently
+//! streaming (run-length and LZW) process the data as it comes; the others
+//! buffer the input and compress it in one go on [`Encoder::finish`].
+//!
+//! [`compress_stream`] and [`decompress_stream`] add a small container in
+//! front of the payload: the magic `TACZ` followed by the id of the codec, so
+//! that a file can be decompressed without knowing how it was made.
+
+use std::fmt::Debug;
+use std::io::{self, Read, Write};
+
+use super::block_sorting::{block_sort_compress, block_sort_decompress};
+use super::canonical_huffman::{huffman_compress, huffman_decompress};
+use super::gzip::{gzip_compress, gzip_decompress};
+use super::lzw::{LzwDecoder, LzwEncoder, LzwFormat};
+use super::range_coding::{context_mixing_compress, context_mixing_decompress};
+use super::zlib::{zlib_compress, zlib_decompress};
+
+const MAGIC: &[u8; 4] = b"TACZ";
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// The compressing half of a [`Codec`].
+pub trait Encoder: Write {
+    /// Writes out everything still buffered. Dropping an encoder without
+    /// calling this may lose data.
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+/// The decompressing half of a [`Codec`].
+pub trait Decoder: Read {
+    /// Reads the rest of the decompressed data, and fails if it is truncated
+    /// or corrupt. Dropping a decoder before its end skips this check.
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        io::copy(&mut self, &mut io::sink())?;
+        Ok(())
+    }
+}
+
+/// A compression format with streaming encoder and decoder.
+pub trait Codec {
+    /// Short name, used to pick the codec on the command line.
+    fn name(&self) -> &'static str;
+    /// Byte identifying the codec in the container, unique among [`codecs`].
+    fn id(&self) -> u8;
+    /// Compresses everything written to the returned encoder into `inner`.
+    fn encoder<'a>(&self, inner: Box<dyn Write + 'a>) -> Box<dyn Encoder + 'a>;
+    /// Decompresses the data read from `inner`.
+    fn decoder<'a>(&self, inner: Box<dyn Read + 'a>) -> Box<dyn Decoder + 'a>;
+}
+
+/// All available codecs.
+pub fn codecs() -> Vec<Box<dyn Codec>> {
+    vec![
+        Box::new(Store),
+        Box::new(RunLength),
+        Box::new(Lzw),
+        Box::new(OneShot {
+            name: "huffman",
+            id: 3,
+            compress: huffman_compress,
+            decompress: huffman_decompress,
+        }),
+        Box::new(OneShot {
+            name: "zlib",
+            id: 4,
+            compress: zlib_compress,
+            decompress: zlib_decompress,
+        }),
+        Box::new(OneShot {
+            name: "gzip",
+            id: 5,
+            compress: gzip_compress,
+            decompress: gzip_decompress,
+        }),
+        Box::new(OneShot {
+            name: "bwt",
+            id: 6,
+            compress: |data| block_sort_compress(data, 9),
+            decompress: block_sort_decompress,
+        }),
+        Box::new(OneShot {
+            name: "cm",
+            id: 7,
+            compress: |data| context_mixing_compress(data, 4),
+            decompress: context_mixing_decompress,
+        }),
+    ]
+}
+
+pub fn codec_by_name(name: &str) -> Option<Box<dyn Codec>> {
+    codecs().into_iter().find(|codec| codec.name() == name)
+}
+
+pub fn codec_by_id(id: u8) -> Option<Box<dyn Codec>> {
+    codecs().into_iter().find(|codec| codec.id() == id)
+}
+
+fn invalid_data<E: Debug>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{error:?}"))
+}
+
+/// Writes the container header and the data of `input`, compressed with
+/// `codec`, to `output`. Returns the number of uncompressed bytes.
+pub fn compress_stream(
+    codec: &dyn Codec,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> io::Result<u64> {
+    output.write_all(MAGIC)?;
+    output.write_all(&[codec.id()])?;
+    let mut encoder = codec.encoder(Box::new(output));
+    let len = io::copy(input, &mut encoder)?;
+    encoder.finish()?;
+    Ok(len)
+}
+
+/// Decompresses a stream written by [`compress_stream`], whatever codec it
+/// used. Returns the number of decompressed bytes.
+pub fn decompress_stream(input: &mut dyn Read, output: &mut dyn Write) -> io::Result<u64> {
+    let mut header = [0; 5];
+    input.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(invalid_data("not a compressed stream"));
+    }
+    let codec = codec_by_id(header[4]).ok_or_else(|| invalid_data("unknown codec"))?;
+    let mut decoder = codec.decoder(Box::new(input));
+    let len = io::copy(&mut decoder, output)?;
+    decoder.finish()?;
+    Ok(len)
+}
+
+/// Stores the data as is.
+#[derive(Debug)]
+pub struct Store;
+
+struct StoreEncoder<'a>(Box<dyn Write + 'a>);
+
+impl Write for StoreEncoder<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Encoder for StoreEncoder<'_> {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Decoder for Box<dyn Read + '_> {}
+
+impl Codec for Store {
+    fn name(&self) -> &'static str {
+        "store"
+    }
+
+    fn id(&self) -> u8 {
+        0
+    }
+
+    fn encoder<'a>(&self, inner: Box<dyn Write + 'a>) -> Box<dyn Encoder + 'a> {
+        Box::new(StoreEncoder(inner))
+    }
+
+    fn decoder<'a>(&self, inner: Box<dyn Read + 'a>) -> Box<dyn Decoder + 'a> {
+        Box::new(inner)
+    }
+}
+
+/// Byte-level run-length coding: every run of up to 255 equal bytes becomes
+/// a `(length, byte)` pair.
+#[derive(Debug)]
+pub struct RunLength;
+
+struct RunLengthEncoder<'a> {
+    inner: Box<dyn Write + 'a>,
+    byte: u8,
+    run: u8,
+    out: Vec<u8>,
+}
+
+impl RunLengthEncoder<'_> {
+    fn end_run(&mut self) {
+        if self.run > 0 {
+            self.out.extend_from_slice(&[self.run, self.byte]);
+            self.run = 0;
+        }
+    }
+}
+
+impl Write for RunLengthEncoder<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if byte != self.byte || self.run == u8::MAX {
+                self.end_run();
+                self.byte = byte;
+            }
+            self.run += 1;
+        }
+        self.inner.write_all(&self.out)?;
+        self.out.clear();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Encoder for RunLengthEncoder<'_> {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.end_run();
+        self.inner.write_all(&self.out)?;
+        self.inner.flush()
+    }
+}
+
+struct RunLengthDecoder<'a> {
+    inner: Box<dyn Read + 'a>,
+    byte: u8,
+    remaining: usize,
+}
+
+impl Read for RunLengthDecoder<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            let mut pair = [0; 2];
+            if self.inner.read(&mut pair[..1])? == 0 {
+                return Ok(0);
+            }
+            self.inner.read_exact(&mut pair[1..])?;
+            if pair[0] == 0 {
+                return Err(invalid_data("empty run"));
+            }
+            self.remaining = pair[0] as usize;
+            self.byte = pair[1];
+        }
+        let len = self.remaining.min(buf.len());
+        buf[..len].fill(self.byte);
+        self.remaining -= len;
+        Ok(len)
+    }
+}
+
+impl Decoder for RunLengthDecoder<'_> {}
+
+impl Codec for RunLength {
+    fn name(&self) -> &'static str {
+        "rle"
+    }
+
+    fn id(&self) -> u8 {
+        1
+    }
+
+    fn encoder<'a>(&self, inner: Box<dyn Write + 'a>) -> Box<dyn Encoder + 'a> {
+        Box::new(RunLengthEncoder {
+            inner,
+            byte: 0,
+            run: 0,
+            out: Vec::new(),
+        })
+    }
+
+    fn decoder<'a>(&self, inner: Box<dyn Read + 'a>) -> Box<dyn Decoder + 'a> {
+        Box::new(RunLengthDecoder {
+            inner,
+            byte: 0,
+            remaining: 0,
+        })
+    }
+}
+
+/// LZW in the Unix `compress` format, with codes of up to 16 bits.
+#[derive(Debug)]
+pub struct Lzw;
+
+struct LzwWriter<'a> {
+    inner: Box<dyn Write + 'a>,
+    encoder: LzwEncoder,
+    out: Vec<u8>,
+}
+
+impl Write for LzwWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.write(buf, &mut self.out);
+        self.inner.write_all(&self.out)?;
+        self.out.clear();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Encoder for LzwWriter<'_> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        let LzwWriter {
+            mut inner,
+            encoder,
+            mut out,
+        } = *self;
+        encoder.finish(&mut out);
+        inner.write_all(&out)?;
+        inner.flush()
+    }
+}
+
+struct LzwReader<'a> {
+    inner: Box<dyn Read + 'a>,
+    decoder: Option<LzwDecoder>,
+    /// Compressed bytes read from `inner`, kept across calls.
+    chunk: Vec<u8>,
+    out: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for LzwReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.out.len() {
+            let Some(decoder) = self.decoder.as_mut() else {
+                return Ok(0);
+            };
+            self.out.clear();
+            self.pos = 0;
+            let len = self.inner.read(&mut self.chunk)?;
+            if len == 0 {
+                self.decoder
+                    .take()
+                    .unwrap()
+                    .finish()
+                    .map_err(invalid_data)?;
+            } else {
+                decoder
+                    .write(&self.chunk[..len], &mut self.out)
+                    .map_err(invalid_data)?;
+            }
+        }
+        let len = (self.out.len() - self.pos).min(buf.len());
+        buf[..len].copy_from_slice(&self.out[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl Decoder for LzwReader<'_> {}
+
+impl Codec for Lzw {
+    fn name(&self) -> &'static str {
+        "lzw"
+    }
+
+    fn id(&self) -> u8 {
+        2
+    }
+
+    fn encoder<'a>(&self, inner: Box<dyn Write + 'a>) -> Box<dyn Encoder + 'a> {
+        Box::new(LzwWriter {
+            inner,
+            encoder: LzwEncoder::new(LzwFormat::UnixCompress, 16),
+            out: Vec::new(),
+        })
+    }
+
+    fn decoder<'a>(&self, inner: Box<dyn Read + 'a>) -> Box<dyn Decoder + 'a> {
+        Box::new(LzwReader {
+            inner,
+            decoder: Some(LzwDecoder::new(LzwFormat::UnixCompress, 16)),
+            chunk: vec![0; CHUNK_SIZE],
+            out: Vec::new(),
+            pos: 0,
+        })
+    }
+}
+
+/// Adapts a pair of in-memory functions to the streaming interface by
+/// buffering the whole input.
+pub struct OneShot<E> {
+    pub name: &'static str,
+    pub id: u8,
+    pub compress: fn(&[u8]) -> Vec<u8>,
+    pub decompress: fn(&[u8]) -> Result<Vec<u8>, E>,
+}
+
+struct OneShotEncoder<'a> {
+    inner: Box<dyn Write + 'a>,
+    compress: fn(&[u8]) -> Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl Write for OneShotEncoder<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Encoder for OneShotEncoder<'_> {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.inner.write_all(&(self.compress)(&self.data))?;
+        self.inner.flush()
+    }
+}
+
+struct OneShotDecoder<'a, E> {
+    inner: Box<dyn Read + 'a>,
+    decompress: fn(&[u8]) -> Result<Vec<u8>, E>,
+    out: Option<io::Cursor<Vec<u8>>>,
+}
+
+impl<E: Debug> Read for OneShotDecoder<'_, E> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.out.is_none() {
+            let mut data = Vec::new();
+            self.inner.read_to_end(&mut data)?;
+            let out = (self.decompress)(&data).map_err(invalid_data)?;
+            self.out = Some(io::Cursor::new(out));
+        }
+        self.out.as_mut().unwrap().read(buf)
+    }
+}
+
+impl<E: Debug> Decoder for OneShotDecoder<'_, E> {}
+
+impl<E: Debug + 'static> Codec for OneShot<E> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn id(&self) -> u8 {
+        self.id
+    }
+
+    fn encoder<'a>(&self, inner: Box<dyn Write + 'a>) -> Box<dyn Encoder + 'a> {
+        Box::new(OneShotEncoder {
+            inner,
+            compress: self.compress,
+            data: Vec::new(),
+        })
+    }
+
+    fn decoder<'a>(&self, inner: Box<dyn Read + 'a>) -> Box<dyn Decoder + 'a> {
+        Box::new(OneShotDecoder {
+            inner,
+            decompress: self.decompress,
+            out: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_data() -> Vec<u8> {
+        let mut data = b"header\0\0\0\0\0\0\0\0".to_vec();
+        for i in 0..2000u32 {
+            data.extend_from_slice(format!("line {} of the sample\n", i % 50).as_bytes());
+            data.extend(std::iter::repeat_n(b'-', (i % 300) as usize));
+        }
+        data
+    }
+
+    /// Feeds the input in small, uneven pieces to exercise the streaming paths.
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.1 = self.1 % 7 + 1;
+            let len = self.1.min(buf.len()).min(self.0.len());
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn ids_and_names_are_unique() {
+        let codecs = codecs();
+        for (i, a) in codecs.iter().enumerate() {
+            for b in &codecs[i + 1..] {
+                assert_ne!(a.id(), b.id());
+                assert_ne!(a.name(), b.name());
+            }
+        }
+        assert_eq!(codec_by_name("lzw").unwrap().id(), 2);
+        assert!(codec_by_name("zip").is_none());
+        // the zlib codec wraps its DEFLATE data in zlib framing, so it isn't named after raw DEFLATE
+        assert!(codec_by_name("deflate").is_none());
+    }
+
+    #[test]
+    fn every_codec_round_trips() {
+        let data = sample_data();
+        for input in [&b""[..], b"a", &data] {
+            for codec in codecs() {
+                let mut compressed = Vec::new();
+                let len = compress_stream(&*codec, &mut Trickle(input, 0), &mut compressed);
+                assert_eq!(len.unwrap(), input.len() as u64, "{}", codec.name());
+                assert_eq!(compressed[4], codec.id());
+
+                let mut out = Vec::new();
+                decompress_stream(&mut Trickle(&compressed, 3), &mut out).unwrap();
+                assert_eq!(out, input, "{}", codec.name());
+                if input.len() > 1000 && codec.name() != "store" {
+                    assert!(compressed.len() < input.len(), "{}", codec.name());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn payload_matches_one_shot_functions() {
+        let data = sample_data();
+        let mut compressed = Vec::new();
+        let codec = codec_by_name("zlib").unwrap();
+        compress_stream(&*codec, &mut &data[..], &mut compressed).unwrap();
+        assert_eq!(compressed[5..], zlib_compress(&data));
+    }
+
+    #[test]
+    fn rejects_invalid_streams() {
+        let mut out = Vec::new();
+        let error = decompress_stream(&mut &b"ZZZZ\x00"[..], &mut out).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = decompress_stream(&mut &b"TACZ\xff"[..], &mut out).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = decompress_stream(&mut &b"TACZ\x01\x00a"[..], &mut out).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = decompress_stream(&mut &b"TACZ\x04\x78\x9c"[..], &mut out).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = decompress_stream(&mut &b"TAC"[..], &mut out).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        // a decoder only finds out that zlib data is truncated at its end
+        let zlib = codec_by_name("zlib").unwrap();
+        let compressed = zlib_compress(&sample_data());
+        let truncated = &compressed[..compressed.len() - 1];
+        let error = zlib.decoder(Box::new(truncated)).finish().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(zlib.decoder(Box::new(&compressed[..])).finish().is_ok());
+    }
+}
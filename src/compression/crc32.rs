@@ -0,0 +1,68 @@
+//! CRC-32 (IEEE 802.3) checksum, as used by the gzip format (RFC 1952).
+//!
+//! This is the reflected CRC with polynomial `0xEDB88320`, an initial value of
+//! `0xFFFFFFFF` and a final XOR with `0xFFFFFFFF`. A 256-entry lookup table is
+//! computed at compile time so that one byte is processed per step.
+
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                POLYNOMIAL ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+const TABLE: [u32; 256] = make_table();
+
+/// Continues a CRC-32 computation started with a previous checksum.
+///
+/// Passing `0` as `crc` starts a new checksum, so
+/// `crc32_update(0, data) == crc32(data)`.
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut c = !crc;
+    for &byte in data {
+        c = TABLE[((c ^ byte as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+/// Computes the CRC-32 checksum of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414F_A339
+        );
+    }
+
+    #[test]
+    fn incremental_matches_one_shot() {
+        let data = b"The quick brown fox jumps over the lazy dog";
+        let (head, tail) = data.split_at(10);
+        assert_eq!(crc32_update(crc32(head), tail), crc32(data));
+    }
+}
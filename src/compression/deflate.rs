@@ -0,0 +1,717 @@
+//! DEFLATE compressed data format (RFC 1951).
+//!
+//! DEFLATE combines LZ77 (back-references of up to 258 bytes into the
+//! previous 32 KiB of output) with Huffman coding. A stream is a sequence of
+//! blocks, each of which is either:
+//!
+//! * stored: raw bytes, used when the data does not compress,
+//! * fixed: Huffman coded with the code tables defined by the RFC,
+//! * dynamic: Huffman coded with code tables sent at the start of the block.
+//!
+//! The decoder understands all three block types. The encoder finds matches
+//! with hash chains and lazy matching, then for every block picks whichever of
+//! the three encodings is the smallest.
+//!
+//! Specification: <https://www.rfc-editor.org/rfc/rfc1951>.
+
+use super::bit_io::{BitReader, BitWriter, EndOfInput};
+use super::canonical_huffman::{canonical_codes, package_merge, CanonicalDecoder, HuffmanError};
+
+/// Errors that can occur while decoding a DEFLATE, zlib or gzip stream.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeflateError {
+    /// The input ended in the middle of a block or of the container.
+    UnexpectedEof,
+    /// A block header used the reserved block type `11`.
+    InvalidBlockType,
+    /// The length of a stored block does not match its one's complement.
+    StoredLengthMismatch,
+    /// A dynamic block describes a Huffman code that cannot exist.
+    InvalidCodeLengths,
+    /// A bit sequence does not correspond to any symbol, or decodes to a
+    /// symbol that is not allowed at that point.
+    InvalidSymbol,
+    /// A back-reference points before the start of the output.
+    InvalidDistance,
+    /// The zlib or gzip header is malformed or uses an unsupported feature.
+    InvalidHeader,
+    /// The checksum stored in the container does not match the data.
+    ChecksumMismatch,
+    /// The uncompressed size stored in a gzip trailer does not match the data.
+    LengthMismatch,
+}
+
+/// Maximum number of bits in a literal/length or distance code.
+const MAX_BITS: u8 = 15;
+/// Maximum number of bits in a code length code.
+const MAX_CODE_LENGTH_BITS: u8 = 7;
+
+const END_OF_BLOCK: usize = 256;
+const NUM_LITERAL_LENGTH_CODES: usize = 286;
+const NUM_DISTANCE_CODES: usize = 30;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which the code length code lengths are sent in a dynamic block header.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const WINDOW_SIZE: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+/// How many earlier positions with the same hash are tried when looking for a match.
+const MAX_CHAIN: usize = 128;
+/// A match at least this long is taken at once, without looking one byte ahead.
+const LAZY_THRESHOLD: usize = 32;
+/// Maximum number of LZ77 tokens gathered into a single block.
+const BLOCK_TOKENS: usize = 1 << 14;
+const MAX_STORED: usize = 65535;
+
+impl From<EndOfInput> for DeflateError {
+    fn from(_: EndOfInput) -> Self {
+        DeflateError::UnexpectedEof
+    }
+}
+
+impl From<HuffmanError> for DeflateError {
+    fn from(error: HuffmanError) -> Self {
+        match error {
+            HuffmanError::UnexpectedEof => DeflateError::UnexpectedEof,
+            HuffmanError::InvalidCodeLengths => DeflateError::InvalidCodeLengths,
+            HuffmanError::InvalidCode => DeflateError::InvalidSymbol,
+        }
+    }
+}
+
+fn fixed_literal_lengths() -> [u8; 288] {
+    let mut lengths = [8u8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &CanonicalDecoder,
+    distances: &CanonicalDecoder,
+) -> Result<(), DeflateError> {
+    loop {
+        let symbol = literals.decode(reader)?;
+        if symbol < END_OF_BLOCK {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == END_OF_BLOCK {
+            return Ok(());
+        }
+        let index = symbol - 257;
+        if index >= LENGTH_BASE.len() {
+            return Err(DeflateError::InvalidSymbol);
+        }
+        let length = LENGTH_BASE[index] as usize + reader.read_bits(LENGTH_EXTRA[index])? as usize;
+        let index = distances.decode(reader)?;
+        if index >= DISTANCE_BASE.len() {
+            return Err(DeflateError::InvalidSymbol);
+        }
+        let distance =
+            DISTANCE_BASE[index] as usize + reader.read_bits(DISTANCE_EXTRA[index])? as usize;
+        if distance > out.len() {
+            return Err(DeflateError::InvalidDistance);
+        }
+        // copy byte by byte: the source and destination may overlap
+        let start = out.len() - distance;
+        for i in 0..length {
+            out.push(out[start + i]);
+        }
+    }
+}
+
+fn read_dynamic_tables(
+    reader: &mut BitReader,
+) -> Result<(CanonicalDecoder, CanonicalDecoder), DeflateError> {
+    let num_literals = reader.read_bits(5)? as usize + 257;
+    let num_distances = reader.read_bits(5)? as usize + 1;
+    let num_code_lengths = reader.read_bits(4)? as usize + 4;
+    if num_literals > NUM_LITERAL_LENGTH_CODES || num_distances > NUM_DISTANCE_CODES {
+        return Err(DeflateError::InvalidCodeLengths);
+    }
+
+    let mut code_length_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..num_code_lengths] {
+        code_length_lengths[symbol] = reader.read_bits(3)? as u8;
+    }
+    let code_lengths = CanonicalDecoder::new(&code_length_lengths)?;
+
+    let mut lengths = Vec::with_capacity(num_literals + num_distances);
+    while lengths.len() < num_literals + num_distances {
+        let symbol = code_lengths.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or(DeflateError::InvalidCodeLengths)?;
+                (previous, 3 + reader.read_bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.read_bits(3)? as usize),
+            _ => (0, 11 + reader.read_bits(7)? as usize),
+        };
+        if lengths.len() + repeat > num_literals + num_distances {
+            return Err(DeflateError::InvalidCodeLengths);
+        }
+        lengths.extend(std::iter::repeat_n(value, repeat));
+    }
+    if lengths[END_OF_BLOCK] == 0 {
+        return Err(DeflateError::InvalidCodeLengths);
+    }
+    let (literal_lengths, distance_lengths) = lengths.split_at(num_literals);
+    Ok((
+        CanonicalDecoder::new(literal_lengths)?,
+        CanonicalDecoder::new(distance_lengths)?,
+    ))
+}
+
+/// Decodes a raw DEFLATE stream starting at the beginning of `reader`'s data,
+/// appending the output to `out`.
+pub(super) fn inflate_into(reader: &mut BitReader, out: &mut Vec<u8>) -> Result<(), DeflateError> {
+    loop {
+        let is_final = reader.read_bit()? == 1;
+        match reader.read_bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let length = reader.read_bits(16)?;
+                let complement = reader.read_bits(16)?;
+                if length != !complement & 0xffff {
+                    return Err(DeflateError::StoredLengthMismatch);
+                }
+                out.extend_from_slice(reader.read_bytes(length as usize)?);
+            }
+            1 => {
+                let literals = CanonicalDecoder::new(&fixed_literal_lengths())?;
+                let distances = CanonicalDecoder::new(&[5; NUM_DISTANCE_CODES])?;
+                inflate_block(reader, out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_tables(reader)?;
+                inflate_block(reader, out, &literals, &distances)?;
+            }
+            _ => return Err(DeflateError::InvalidBlockType),
+        }
+        if is_final {
+            return Ok(());
+        }
+    }
+}
+
+/// Decompresses a raw DEFLATE stream. Bytes after the final block are ignored.
+pub fn deflate_decompress(data: &[u8]) -> Result<Vec<u8>, DeflateError> {
+    let mut out = Vec::new();
+    inflate_into(&mut BitReader::new(data), &mut out)?;
+    Ok(out)
+}
+
+#[derive(Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+fn length_code(length: usize) -> usize {
+    LENGTH_BASE.partition_point(|&base| base as usize <= length) - 1
+}
+
+fn distance_code(distance: usize) -> usize {
+    DISTANCE_BASE.partition_point(|&base| base as usize <= distance) - 1
+}
+
+/// Finds repeated substrings using hash chains over 3-byte prefixes.
+struct Matcher<'a> {
+    data: &'a [u8],
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Matcher {
+            data,
+            head: vec![usize::MAX; 1 << HASH_BITS],
+            prev: vec![usize::MAX; data.len()],
+        }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let bytes = &self.data[pos..pos + MIN_MATCH];
+        let key = ((bytes[0] as u32) << 16) | ((bytes[1] as u32) << 8) | bytes[2] as u32;
+        (key.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH <= self.data.len() {
+            let hash = self.hash(pos);
+            self.prev[pos] = self.head[hash];
+            self.head[hash] = pos;
+        }
+    }
+
+    /// Returns the longest `(length, distance)` match for `pos` among the
+    /// previously inserted positions.
+    fn longest_match(&self, pos: usize) -> Option<(usize, usize)> {
+        if pos + MIN_MATCH > self.data.len() {
+            return None;
+        }
+        let max_length = MAX_MATCH.min(self.data.len() - pos);
+        let mut best: Option<(usize, usize)> = None;
+        let mut candidate = self.head[self.hash(pos)];
+        let mut chain = 0;
+        while candidate != usize::MAX && pos - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+            let length = self.data[candidate..]
+                .iter()
+                .zip(&self.data[pos..pos + max_length])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length >= MIN_MATCH && best.is_none_or(|(best_length, _)| length > best_length) {
+                best = Some((length, pos - candidate));
+                if length == max_length {
+                    break;
+                }
+            }
+            candidate = self.prev[candidate];
+            chain += 1;
+        }
+        best
+    }
+}
+
+/// Runs LZ77 with one step of lazy matching over the whole input.
+fn tokenize(data: &[u8]) -> Vec<Token> {
+    let mut matcher = Matcher::new(data);
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let current = matcher.longest_match(pos);
+        matcher.insert(pos);
+        let (length, distance) = match current {
+            Some(found) => found,
+            None => {
+                tokens.push(Token::Literal(data[pos]));
+                pos += 1;
+                continue;
+            }
+        };
+        if length < LAZY_THRESHOLD {
+            if let Some((next_length, _)) = matcher.longest_match(pos + 1) {
+                if next_length > length {
+                    // a better match starts at the next byte: emit this one as a literal
+                    tokens.push(Token::Literal(data[pos]));
+                    pos += 1;
+                    continue;
+                }
+            }
+        }
+        tokens.push(Token::Match {
+            length: length as u16,
+            distance: distance as u16,
+        });
+        for skipped in pos + 1..pos + length {
+            matcher.insert(skipped);
+        }
+        pos += length;
+    }
+    tokens
+}
+
+/// Computes Huffman code lengths for the given frequencies, none longer than `max_bits`.
+///
+/// At least two symbols always get a code, so that the code is complete.
+fn huffman_lengths(frequencies: &[u64], max_bits: u8) -> Vec<u8> {
+    let mut frequencies = frequencies.to_vec();
+    let used = frequencies.iter().filter(|&&f| f > 0).count();
+    for symbol in 0..frequencies.len() {
+        if used + symbol >= 2 {
+            break;
+        }
+        if frequencies[symbol] == 0 {
+            frequencies[symbol] = 1;
+        } else {
+            frequencies[symbol + 1] = 1;
+        }
+    }
+    package_merge(&frequencies, max_bits)
+}
+
+/// Run-length encodes the concatenated literal/length and distance code
+/// lengths with the code length alphabet: `(symbol, extra bits value)`.
+fn encode_code_lengths(lengths: &[u8]) -> Vec<(usize, u32)> {
+    let mut encoded = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let value = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&len| len == value).count();
+        let mut remaining = run;
+        if value == 0 {
+            while remaining >= 11 {
+                let count = remaining.min(138);
+                encoded.push((18, (count - 11) as u32));
+                remaining -= count;
+            }
+            if remaining >= 3 {
+                encoded.push((17, (remaining - 3) as u32));
+                remaining = 0;
+            }
+        } else {
+            encoded.push((value as usize, 0));
+            remaining -= 1;
+            while remaining >= 3 {
+                let count = remaining.min(6);
+                encoded.push((16, (count - 3) as u32));
+                remaining -= count;
+            }
+        }
+        encoded.extend(std::iter::repeat_n((value as usize, 0), remaining));
+        i += run;
+    }
+    encoded
+}
+
+fn code_length_extra_bits(symbol: usize) -> u8 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+/// Huffman tables for one block, plus everything needed to write its header.
+struct BlockCodes {
+    literal_lengths: Vec<u8>,
+    distance_lengths: Vec<u8>,
+}
+
+impl BlockCodes {
+    fn fixed() -> Self {
+        BlockCodes {
+            literal_lengths: fixed_literal_lengths().to_vec(),
+            distance_lengths: vec![5; NUM_DISTANCE_CODES],
+        }
+    }
+
+    fn dynamic(tokens: &[Token]) -> Self {
+        let mut literal_frequencies = vec![0u64; NUM_LITERAL_LENGTH_CODES];
+        let mut distance_frequencies = vec![0u64; NUM_DISTANCE_CODES];
+        for token in tokens {
+            match *token {
+                Token::Literal(byte) => literal_frequencies[byte as usize] += 1,
+                Token::Match { length, distance } => {
+                    literal_frequencies[257 + length_code(length as usize)] += 1;
+                    distance_frequencies[distance_code(distance as usize)] += 1;
+                }
+            }
+        }
+        literal_frequencies[END_OF_BLOCK] = 1;
+        BlockCodes {
+            literal_lengths: huffman_lengths(&literal_frequencies, MAX_BITS),
+            distance_lengths: huffman_lengths(&distance_frequencies, MAX_BITS),
+        }
+    }
+
+    /// The dynamic block header: trimmed table sizes, the code length code
+    /// and the run-length encoded code lengths.
+    fn header(&self) -> (usize, usize, Vec<u8>, Vec<(usize, u32)>) {
+        let num_literals = 257.max(
+            self.literal_lengths
+                .iter()
+                .rposition(|&len| len != 0)
+                .map_or(0, |last| last + 1),
+        );
+        let num_distances = 1.max(
+            self.distance_lengths
+                .iter()
+                .rposition(|&len| len != 0)
+                .map_or(0, |last| last + 1),
+        );
+        let mut all_lengths = self.literal_lengths[..num_literals].to_vec();
+        all_lengths.extend_from_slice(&self.distance_lengths[..num_distances]);
+        let encoded = encode_code_lengths(&all_lengths);
+        let mut frequencies = vec![0u64; 19];
+        for &(symbol, _) in &encoded {
+            frequencies[symbol] += 1;
+        }
+        let code_length_lengths = huffman_lengths(&frequencies, MAX_CODE_LENGTH_BITS);
+        (num_literals, num_distances, code_length_lengths, encoded)
+    }
+
+    fn header_bits(&self) -> usize {
+        let (_, _, code_length_lengths, encoded) = self.header();
+        let num_code_lengths = num_code_length_codes(&code_length_lengths);
+        let body: usize = encoded
+            .iter()
+            .map(|&(symbol, _)| {
+                code_length_lengths[symbol] as usize + code_length_extra_bits(symbol) as usize
+            })
+            .sum();
+        5 + 5 + 4 + 3 * num_code_lengths + body
+    }
+
+    fn body_bits(&self, tokens: &[Token]) -> usize {
+        let token_bits: usize = tokens
+            .iter()
+            .map(|token| match *token {
+                Token::Literal(byte) => self.literal_lengths[byte as usize] as usize,
+                Token::Match { length, distance } => {
+                    let length = length_code(length as usize);
+                    let distance = distance_code(distance as usize);
+                    self.literal_lengths[257 + length] as usize
+                        + LENGTH_EXTRA[length] as usize
+                        + self.distance_lengths[distance] as usize
+                        + DISTANCE_EXTRA[distance] as usize
+                }
+            })
+            .sum();
+        token_bits + self.literal_lengths[END_OF_BLOCK] as usize
+    }
+
+    fn write_header(&self, writer: &mut BitWriter) {
+        let (num_literals, num_distances, code_length_lengths, encoded) = self.header();
+        let num_code_lengths = num_code_length_codes(&code_length_lengths);
+        writer.write_bits((num_literals - 257) as u32, 5);
+        writer.write_bits((num_distances - 1) as u32, 5);
+        writer.write_bits((num_code_lengths - 4) as u32, 4);
+        for &symbol in &CODE_LENGTH_ORDER[..num_code_lengths] {
+            writer.write_bits(code_length_lengths[symbol] as u32, 3);
+        }
+        let codes = canonical_codes(&code_length_lengths);
+        for (symbol, extra) in encoded {
+            writer.write_code(codes[symbol], code_length_lengths[symbol]);
+            writer.write_bits(extra, code_length_extra_bits(symbol));
+        }
+    }
+
+    fn write_body(&self, writer: &mut BitWriter, tokens: &[Token]) {
+        let literal_codes = canonical_codes(&self.literal_lengths);
+        let distance_codes = canonical_codes(&self.distance_lengths);
+        for token in tokens {
+            match *token {
+                Token::Literal(byte) => writer.write_code(
+                    literal_codes[byte as usize],
+                    self.literal_lengths[byte as usize],
+                ),
+                Token::Match { length, distance } => {
+                    let code = length_code(length as usize);
+                    writer.write_code(literal_codes[257 + code], self.literal_lengths[257 + code]);
+                    writer.write_bits((length - LENGTH_BASE[code]) as u32, LENGTH_EXTRA[code]);
+                    let code = distance_code(distance as usize);
+                    writer.write_code(distance_codes[code], self.distance_lengths[code]);
+                    writer.write_bits(
+                        (distance - DISTANCE_BASE[code]) as u32,
+                        DISTANCE_EXTRA[code],
+                    );
+                }
+            }
+        }
+        writer.write_code(
+            literal_codes[END_OF_BLOCK],
+            self.literal_lengths[END_OF_BLOCK],
+        );
+    }
+}
+
+fn num_code_length_codes(code_length_lengths: &[u8]) -> usize {
+    4.max(
+        CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&symbol| code_length_lengths[symbol] != 0)
+            .map_or(0, |last| last + 1),
+    )
+}
+
+fn write_stored(writer: &mut BitWriter, bytes: &[u8], is_final: bool) {
+    let mut chunks = bytes.chunks(MAX_STORED).peekable();
+    if chunks.peek().is_none() {
+        // an empty stored block still needs to be written
+        writer.write_bits(is_final as u32, 1);
+        writer.write_bits(0, 2);
+        writer.align_to_byte();
+        writer.write_bits(0, 16);
+        writer.write_bits(0xffff, 16);
+        return;
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = is_final && chunks.peek().is_none();
+        writer.write_bits(last as u32, 1);
+        writer.write_bits(0, 2);
+        writer.align_to_byte();
+        writer.write_bits(chunk.len() as u32, 16);
+        writer.write_bits(!(chunk.len() as u32) & 0xffff, 16);
+        writer.write_bytes(chunk);
+    }
+}
+
+/// Writes one block using the cheapest of the stored, fixed and dynamic encodings.
+fn write_block(writer: &mut BitWriter, tokens: &[Token], bytes: &[u8], is_final: bool) {
+    let fixed = BlockCodes::fixed();
+    let dynamic = BlockCodes::dynamic(tokens);
+    let fixed_bits = 3 + fixed.body_bits(tokens);
+    let dynamic_bits = 3 + dynamic.header_bits() + dynamic.body_bits(tokens);
+    let stored_bits = bytes.len().div_ceil(MAX_STORED).max(1) * (3 + 7 + 32) + 8 * bytes.len();
+
+    if stored_bits <= fixed_bits.min(dynamic_bits) {
+        write_stored(writer, bytes, is_final);
+    } else if fixed_bits <= dynamic_bits {
+        writer.write_bits(is_final as u32, 1);
+        writer.write_bits(1, 2);
+        fixed.write_body(writer, tokens);
+    } else {
+        writer.write_bits(is_final as u32, 1);
+        writer.write_bits(2, 2);
+        dynamic.write_header(writer);
+        dynamic.write_body(writer, tokens);
+    }
+}
+
+/// Compresses `data` into a raw DEFLATE stream.
+pub fn deflate_compress(data: &[u8]) -> Vec<u8> {
+    let tokens = tokenize(data);
+    let mut writer = BitWriter::new();
+    if tokens.is_empty() {
+        write_block(&mut writer, &[], &[], true);
+        return writer.finish();
+    }
+    let mut start = 0;
+    let blocks = tokens.chunks(BLOCK_TOKENS).count();
+    for (i, block) in tokens.chunks(BLOCK_TOKENS).enumerate() {
+        let size: usize = block
+            .iter()
+            .map(|token| match *token {
+                Token::Literal(_) => 1,
+                Token::Match { length, .. } => length as usize,
+            })
+            .sum();
+        write_block(
+            &mut writer,
+            block,
+            &data[start..start + size],
+            i + 1 == blocks,
+        );
+        start += size;
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Output of Python's `zlib.compressobj(9, zlib.DEFLATED, -15)` (i.e. zlib
+    // itself) for "hello hello hello hello\n": a single fixed Huffman block.
+    const ZLIB_FIXED_BLOCK: [u8; 11] = [
+        0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0xb9, 0x00,
+    ];
+
+    fn sample_text() -> Vec<u8> {
+        let mut text = Vec::new();
+        for i in 0..2000 {
+            text.extend_from_slice(
+                format!("line {i}: the quick brown fox jumps over the lazy dog\n").as_bytes(),
+            );
+        }
+        text
+    }
+
+    fn pseudo_random_bytes(len: usize) -> Vec<u8> {
+        let mut state: u32 = 0x1234_5678;
+        std::iter::repeat_with(|| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .take(len)
+        .collect()
+    }
+
+    #[test]
+    fn decodes_zlib_fixed_block() {
+        assert_eq!(
+            deflate_decompress(&ZLIB_FIXED_BLOCK).unwrap(),
+            b"hello hello hello hello\n"
+        );
+    }
+
+    #[test]
+    fn decodes_stored_block() {
+        let stream = [0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c'];
+        assert_eq!(deflate_decompress(&stream).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn round_trips() {
+        for data in [
+            Vec::new(),
+            b"a".to_vec(),
+            b"abababababababababab".to_vec(),
+            vec![0u8; 100_000],
+            sample_text(),
+            pseudo_random_bytes(70_000),
+        ] {
+            let compressed = deflate_compress(&data);
+            assert_eq!(deflate_decompress(&compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn compresses_redundant_data() {
+        let text = sample_text();
+        let compressed = deflate_compress(&text);
+        assert!(compressed.len() * 5 < text.len());
+        // random data falls back to stored blocks and barely grows
+        let random = pseudo_random_bytes(70_000);
+        assert!(deflate_compress(&random).len() < random.len() + 32);
+    }
+
+    #[test]
+    fn uses_each_block_type() {
+        // block type lives in bits 1-2 of the first byte
+        let block_type = |data: &[u8]| (deflate_compress(data)[0] >> 1) & 3;
+        assert_eq!(block_type(&pseudo_random_bytes(1000)), 0);
+        assert_eq!(block_type(b"hello hello hello hello\n"), 1);
+        assert_eq!(block_type(&sample_text()), 2);
+    }
+
+    #[test]
+    fn rejects_corrupt_streams() {
+        assert_eq!(deflate_decompress(&[]), Err(DeflateError::UnexpectedEof));
+        assert_eq!(
+            deflate_decompress(&[0x07]),
+            Err(DeflateError::InvalidBlockType)
+        );
+        assert_eq!(
+            deflate_decompress(&[0x01, 0x03, 0x00, 0xfc, 0xfe, b'a', b'b', b'c']),
+            Err(DeflateError::StoredLengthMismatch)
+        );
+        assert_eq!(
+            deflate_decompress(&ZLIB_FIXED_BLOCK[..6]),
+            Err(DeflateError::UnexpectedEof)
+        );
+        // fixed block whose first symbol is a match of distance 1 with no output yet
+        assert_eq!(
+            deflate_decompress(&[0x03, 0x02, 0x00]),
+            Err(DeflateError::InvalidDistance)
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! gzip file format (RFC 1952).
+//!
+//! A gzip file is one or more members, each made of a header (optionally
+//! carrying the original file name, a comment and extra fields), a raw
+//! DEFLATE stream and a trailer holding the CRC-32 and the size (modulo 2^32)
+//! of the uncompressed data. Decompressing a file with several members
+//! returns the concatenation of their contents, like `gzip -d` does.
+//!
+//! Specification: <https://www.rfc-editor.org/rfc/rfc1952>.
+
+use super::bit_io::BitReader;
+use super::crc32::crc32;
+use super::deflate::{deflate_compress, inflate_into, DeflateError};
+
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+const METHOD_DEFLATE: u8 = 8;
+const OS_UNKNOWN: u8 = 255;
+
+const FLAG_HEADER_CRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+const RESERVED_FLAGS: u8 = 0xe0;
+
+/// Compresses `data` into a single-member gzip file without a file name or
+/// modification time.
+pub fn gzip_compress(data: &[u8]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&[METHOD_DEFLATE, 0, 0, 0, 0, 0, 0, OS_UNKNOWN]);
+    out.extend(deflate_compress(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+fn take<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], DeflateError> {
+    let bytes = data
+        .get(*pos..*pos + len)
+        .ok_or(DeflateError::UnexpectedEof)?;
+    *pos += len;
+    Ok(bytes)
+}
+
+/// Skips a zero-terminated string such as the file name or the comment.
+fn skip_zero_terminated(data: &[u8], pos: &mut usize) -> Result<(), DeflateError> {
+    let len = data[*pos..]
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or(DeflateError::UnexpectedEof)?;
+    *pos += len + 1;
+    Ok(())
+}
+
+/// Parses a member header starting at `pos` and returns the position of the
+/// DEFLATE stream.
+fn parse_header(data: &[u8], mut pos: usize) -> Result<usize, DeflateError> {
+    let start = pos;
+    let header = take(data, &mut pos, 10)?;
+    if header[..2] != MAGIC || header[2] != METHOD_DEFLATE || header[3] & RESERVED_FLAGS != 0 {
+        return Err(DeflateError::InvalidHeader);
+    }
+    let flags = header[3];
+    if flags & FLAG_EXTRA != 0 {
+        let len = take(data, &mut pos, 2)?;
+        let len = u16::from_le_bytes([len[0], len[1]]) as usize;
+        take(data, &mut pos, len)?;
+    }
+    if flags & FLAG_NAME != 0 {
+        skip_zero_terminated(data, &mut pos)?;
+    }
+    if flags & FLAG_COMMENT != 0 {
+        skip_zero_terminated(data, &mut pos)?;
+    }
+    if flags & FLAG_HEADER_CRC != 0 {
+        let expected = take(data, &mut pos, 2)?;
+        let crc = crc32(&data[start..pos - 2]) as u16;
+        if crc.to_le_bytes() != expected {
+            return Err(DeflateError::ChecksumMismatch);
+        }
+    }
+    Ok(pos)
+}
+
+/// Decompresses a gzip file, verifying the CRC-32 and size of every member.
+pub fn gzip_decompress(data: &[u8]) -> Result<Vec<u8>, DeflateError> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    loop {
+        pos = parse_header(data, pos)?;
+        let member_start = out.len();
+        let mut reader = BitReader::new(&data[pos..]);
+        inflate_into(&mut reader, &mut out)?;
+        pos += reader.bytes_consumed();
+        let trailer = take(data, &mut pos, 8)?;
+        let member = &out[member_start..];
+        if crc32(member).to_le_bytes() != trailer[..4] {
+            return Err(DeflateError::ChecksumMismatch);
+        }
+        if (member.len() as u32).to_le_bytes() != trailer[4..] {
+            return Err(DeflateError::LengthMismatch);
+        }
+        if pos == data.len() {
+            return Ok(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HAMLET: &[u8] = include_bytes!("test_data/hamlet.txt");
+    /// `HAMLET` compressed by `gzip -9`, which stores the file name and
+    /// modification time in the header.
+    const HAMLET_GZ: &[u8] = include_bytes!("test_data/hamlet.txt.gz");
+
+    #[test]
+    fn decodes_gzip_output() {
+        assert_eq!(gzip_decompress(HAMLET_GZ).unwrap(), HAMLET);
+    }
+
+    #[test]
+    fn round_trips() {
+        for data in [&b""[..], b"g", HAMLET, &vec![0xa5u8; 70_000]] {
+            let compressed = gzip_compress(data);
+            assert_eq!(&compressed[..3], &[0x1f, 0x8b, 0x08]);
+            assert_eq!(gzip_decompress(&compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn concatenated_members() {
+        let mut file = HAMLET_GZ.to_vec();
+        file.extend(gzip_compress(b"-- and by opposing end them."));
+        let mut expected = HAMLET.to_vec();
+        expected.extend_from_slice(b"-- and by opposing end them.");
+        assert_eq!(gzip_decompress(&file).unwrap(), expected);
+    }
+
+    #[test]
+    fn optional_header_fields() {
+        let mut file = vec![0x1f, 0x8b, 8, FLAG_EXTRA | FLAG_COMMENT | FLAG_HEADER_CRC];
+        file.extend_from_slice(&[0, 0, 0, 0, 0, 3]);
+        file.extend_from_slice(&[3, 0, b'a', b'b', b'c']);
+        file.extend_from_slice(b"a comment\0");
+        let header_crc = crc32(&file) as u16;
+        file.extend_from_slice(&header_crc.to_le_bytes());
+        file.extend_from_slice(&gzip_compress(b"payload")[10..]);
+        assert_eq!(gzip_decompress(&file).unwrap(), b"payload");
+
+        let last = file.len() - 1;
+        file[last] ^= 1;
+        assert_eq!(gzip_decompress(&file), Err(DeflateError::LengthMismatch));
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let mut corrupt = HAMLET_GZ.to_vec();
+        let crc_pos = corrupt.len() - 8;
+        corrupt[crc_pos] ^= 1;
+        assert_eq!(
+            gzip_decompress(&corrupt),
+            Err(DeflateError::ChecksumMismatch)
+        );
+        assert_eq!(
+            gzip_decompress(&HAMLET_GZ[..HAMLET_GZ.len() - 3]),
+            Err(DeflateError::UnexpectedEof)
+        );
+        assert_eq!(
+            gzip_decompress(b"PK\x03\x04 not a gzip file"),
+            Err(DeflateError::InvalidHeader)
+        );
+    }
+}
@@ -0,0 +1,628 @@
+//! Lempel-Ziv-Welch compression with variable-width codes.
+//!
+//! LZW replaces repeated strings with codes from a dictionary that both sides
+//! build as they go: the first 256 codes are the single bytes and every code
+//! the encoder outputs adds "previous string + next byte" as a new entry.
+//! Codes start 9 bits wide and grow by one bit each time the dictionary
+//! outgrows them, up to a maximum width. When the dictionary is full the
+//! encoder emits a clear code and both sides start over.
+//!
+//! Two framings are supported:
+//!
+//! * GIF style: code 256 clears the dictionary, 257 ends the stream and the
+//!   first free code is 258. The stream starts with a clear code. GIF itself
+//!   limits codes to 12 bits, larger widths are accepted here.
+//! * Unix `compress` (`.Z` files): a three byte header with the maximum width,
+//!   code 256 clears the dictionary and the stream ends at the end of the
+//!   input. Codes are written in groups of eight, and whenever the width
+//!   changes or the dictionary is cleared the rest of the current group is
+//!   skipped. Streams whose header lacks the block mode flag, written by
+//!   `compress -C`, have no clear code: the first free code is 256 and the
+//!   dictionary stays as it is once full. Only the decoder supports them.
+//!
+//! In both formats codes are packed least-significant bit first. The encoder
+//! and decoder are incremental: input can be fed in chunks of any size.
+
+use std::collections::HashMap;
+
+/// Errors that can occur while decoding an LZW stream.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LzwError {
+    /// The `.Z` header is missing, has a bad magic number or an unsupported width.
+    InvalidHeader,
+    /// A code refers to a dictionary entry that does not exist yet.
+    InvalidCode,
+    /// A GIF-style stream ended without an end of information code.
+    UnexpectedEof,
+}
+
+/// Which LZW framing to use, see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LzwFormat {
+    Gif,
+    UnixCompress,
+}
+
+const MIN_WIDTH: u8 = 9;
+const MAX_WIDTH: u8 = 16;
+const CLEAR: u16 = 256;
+const END_OF_INFORMATION: u16 = 257;
+const UNIX_COMPRESS_MAGIC: [u8; 2] = [0x1f, 0x9d];
+/// Set in the `.Z` header when the stream may contain clear codes.
+const BLOCK_MODE: u8 = 0x80;
+
+impl LzwFormat {
+    fn first_free_code(self) -> u32 {
+        match self {
+            LzwFormat::Gif => 258,
+            LzwFormat::UnixCompress => 257,
+        }
+    }
+}
+
+/// Tracks the code width exactly like the decoder does, so that the encoder
+/// and the decoder always agree on the width of the next code.
+///
+/// The decoder adds a dictionary entry one code later than the encoder, so
+/// `next_code` is the decoder's next free code.
+#[derive(Debug)]
+struct CodeWidth {
+    format: LzwFormat,
+    max_width: u8,
+    width: u8,
+    next_code: u32,
+    /// Whether the last code read can be extended into a new entry.
+    has_previous: bool,
+    /// Bits written or read since the width last changed, used by `.Z` padding.
+    bits_in_group: usize,
+}
+
+impl CodeWidth {
+    fn new(format: LzwFormat, max_width: u8) -> Self {
+        CodeWidth {
+            format,
+            max_width,
+            width: MIN_WIDTH,
+            next_code: format.first_free_code(),
+            has_previous: false,
+            bits_in_group: 0,
+        }
+    }
+
+    fn table_size(&self) -> u32 {
+        1 << self.max_width
+    }
+
+    /// `.Z` only: if the width must grow before the next code, returns how
+    /// many padding bits to skip first.
+    fn grow_before_code(&mut self) -> Option<usize> {
+        let max_code = if self.width == self.max_width {
+            self.table_size()
+        } else {
+            (1 << self.width) - 1
+        };
+        if self.format == LzwFormat::UnixCompress && self.next_code > max_code {
+            let padding = self.padding();
+            self.width += 1;
+            return Some(padding);
+        }
+        None
+    }
+
+    /// Rest of the current group of eight codes; resets the group.
+    fn padding(&mut self) -> usize {
+        let group = self.width as usize * 8;
+        let padding = (group - self.bits_in_group % group) % group;
+        self.bits_in_group = 0;
+        padding
+    }
+
+    /// Updates the state after a data code (anything but clear and end of
+    /// information) went through.
+    fn after_code(&mut self) {
+        self.bits_in_group += self.width as usize;
+        if self.has_previous && self.next_code < self.table_size() {
+            self.next_code += 1;
+        }
+        self.has_previous = true;
+        if self.format == LzwFormat::Gif
+            && self.next_code == 1 << self.width
+            && self.width < self.max_width
+        {
+            self.width += 1;
+        }
+    }
+
+    /// Resets the state after a clear code, returning the `.Z` padding.
+    fn after_clear(&mut self) -> usize {
+        self.bits_in_group += self.width as usize;
+        let padding = match self.format {
+            LzwFormat::Gif => {
+                self.has_previous = false;
+                self.next_code = self.format.first_free_code();
+                0
+            }
+            LzwFormat::UnixCompress => {
+                // `compress` keeps the previous code, so the first code after
+                // a clear fills the unused entry 256
+                self.next_code = CLEAR as u32;
+                self.padding()
+            }
+        };
+        self.width = MIN_WIDTH;
+        padding
+    }
+}
+
+/// Incremental LZW encoder.
+///
+/// ```
+/// use the_algorithms_rust::compression::{LzwDecoder, LzwEncoder, LzwFormat};
+///
+/// let mut encoder = LzwEncoder::new(LzwFormat::UnixCompress, 16);
+/// let mut compressed = Vec::new();
+/// encoder.write(b"TOBEORNOTTOBE", &mut compressed);
+/// encoder.write(b"ORTOBEORNOT", &mut compressed);
+/// encoder.finish(&mut compressed);
+///
+/// let mut decoder = LzwDecoder::new(LzwFormat::UnixCompress, 16);
+/// let mut decompressed = Vec::new();
+/// decoder.write(&compressed, &mut decompressed).unwrap();
+/// decoder.finish().unwrap();
+/// assert_eq!(decompressed, b"TOBEORNOTTOBEORTOBEORNOT");
+/// ```
+pub struct LzwEncoder {
+    state: CodeWidth,
+    dictionary: HashMap<(u16, u8), u32>,
+    next_entry: u32,
+    current: Option<u32>,
+    buffer: u64,
+    bit_count: u32,
+    header_written: bool,
+}
+
+impl LzwEncoder {
+    /// Creates an encoder whose codes grow up to `max_width` bits (9 to 16).
+    pub fn new(format: LzwFormat, max_width: u8) -> Self {
+        assert!(
+            (MIN_WIDTH..=MAX_WIDTH).contains(&max_width),
+            "code width must be between {MIN_WIDTH} and {MAX_WIDTH} bits"
+        );
+        LzwEncoder {
+            state: CodeWidth::new(format, max_width),
+            dictionary: HashMap::new(),
+            next_entry: format.first_free_code(),
+            current: None,
+            buffer: 0,
+            bit_count: 0,
+            header_written: false,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, count: u8, out: &mut Vec<u8>) {
+        self.buffer |= (value as u64) << self.bit_count;
+        self.bit_count += count as u32;
+        while self.bit_count >= 8 {
+            out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    fn write_padding(&mut self, count: usize, out: &mut Vec<u8>) {
+        // padding can be longer than the bit buffer
+        for _ in 0..count / 8 {
+            self.write_bits(0, 8, out);
+        }
+        self.write_bits(0, (count % 8) as u8, out);
+    }
+
+    fn emit(&mut self, code: u32, out: &mut Vec<u8>) {
+        if let Some(padding) = self.state.grow_before_code() {
+            self.write_padding(padding, out);
+        }
+        self.write_bits(code, self.state.width, out);
+        self.state.after_code();
+    }
+
+    fn emit_clear(&mut self, out: &mut Vec<u8>) {
+        if let Some(padding) = self.state.grow_before_code() {
+            self.write_padding(padding, out);
+        }
+        self.write_bits(CLEAR as u32, self.state.width, out);
+        let padding = self.state.after_clear();
+        self.write_padding(padding, out);
+        self.dictionary.clear();
+        self.next_entry = self.state.format.first_free_code();
+    }
+
+    fn start(&mut self, out: &mut Vec<u8>) {
+        if self.header_written {
+            return;
+        }
+        self.header_written = true;
+        match self.state.format {
+            LzwFormat::Gif => self.emit_clear(out),
+            LzwFormat::UnixCompress => {
+                out.extend_from_slice(&UNIX_COMPRESS_MAGIC);
+                out.push(BLOCK_MODE | self.state.max_width);
+            }
+        }
+    }
+
+    /// Compresses `input`, appending whatever output is ready to `out`.
+    pub fn write(&mut self, input: &[u8], out: &mut Vec<u8>) {
+        self.start(out);
+        for &byte in input {
+            let current = match self.current {
+                Some(current) => current,
+                None => {
+                    self.current = Some(byte as u32);
+                    continue;
+                }
+            };
+            if let Some(&code) = self.dictionary.get(&(current as u16, byte)) {
+                self.current = Some(code);
+                continue;
+            }
+            self.emit(current, out);
+            if self.next_entry < self.state.table_size() {
+                self.dictionary
+                    .insert((current as u16, byte), self.next_entry);
+                self.next_entry += 1;
+            }
+            self.current = Some(byte as u32);
+            if self.next_entry == self.state.table_size() {
+                self.emit_clear(out);
+            }
+        }
+    }
+
+    /// Flushes the last code and any partial byte.
+    pub fn finish(mut self, out: &mut Vec<u8>) {
+        self.start(out);
+        if let Some(current) = self.current {
+            self.emit(current, out);
+        }
+        if self.state.format == LzwFormat::Gif {
+            if let Some(padding) = self.state.grow_before_code() {
+                self.write_padding(padding, out);
+            }
+            self.write_bits(END_OF_INFORMATION as u32, self.state.width, out);
+        }
+        if self.bit_count > 0 {
+            out.push(self.buffer as u8);
+        }
+    }
+}
+
+/// Incremental LZW decoder.
+pub struct LzwDecoder {
+    state: CodeWidth,
+    /// Every entry is its prefix code and last byte; single bytes have no prefix.
+    entries: Vec<(u32, u8)>,
+    previous: u32,
+    buffer: u64,
+    bit_count: u32,
+    skip_bits: usize,
+    header: Vec<u8>,
+    /// Whether code 256 clears the dictionary, false for `.Z` streams without block mode.
+    block_mode: bool,
+    finished: bool,
+    string: Vec<u8>,
+}
+
+impl LzwDecoder {
+    /// Creates a decoder. For GIF-style streams `max_width` must match the
+    /// encoder's. `.Z` streams carry it in their header, so for them it only
+    /// sets an upper bound.
+    pub fn new(format: LzwFormat, max_width: u8) -> Self {
+        assert!(
+            (MIN_WIDTH..=MAX_WIDTH).contains(&max_width),
+            "code width must be between {MIN_WIDTH} and {MAX_WIDTH} bits"
+        );
+        let mut entries: Vec<(u32, u8)> = (0..=255).map(|byte| (u32::MAX, byte)).collect();
+        entries.resize(format.first_free_code() as usize, (u32::MAX, 0));
+        LzwDecoder {
+            state: CodeWidth::new(format, max_width),
+            entries,
+            previous: u32::MAX,
+            buffer: 0,
+            bit_count: 0,
+            skip_bits: 0,
+            header: Vec::new(),
+            block_mode: true,
+            finished: false,
+            string: Vec::new(),
+        }
+    }
+
+    fn read_header<'a>(&mut self, mut input: &'a [u8]) -> Result<&'a [u8], LzwError> {
+        if self.state.format == LzwFormat::Gif {
+            return Ok(input);
+        }
+        while self.header.len() < 3 {
+            match input.split_first() {
+                Some((&byte, rest)) => {
+                    self.header.push(byte);
+                    input = rest;
+                }
+                None => return Ok(input),
+            }
+        }
+        if self.header.len() == 3 {
+            let width = self.header[2] & 0x1f;
+            if self.header[..2] != UNIX_COMPRESS_MAGIC
+                || !(MIN_WIDTH..=self.state.max_width).contains(&width)
+            {
+                return Err(LzwError::InvalidHeader);
+            }
+            self.state.max_width = width;
+            if self.header[2] & BLOCK_MODE == 0 {
+                // code 256 is an ordinary dictionary entry
+                self.block_mode = false;
+                self.state.next_code = CLEAR as u32;
+            }
+            self.header.push(0);
+        }
+        Ok(input)
+    }
+
+    /// Writes the string for `code` to `out` and adds the new dictionary entry.
+    fn decode(&mut self, code: u32, out: &mut Vec<u8>) -> Result<(), LzwError> {
+        let next_code = self.state.next_code;
+        let known = code < next_code;
+        if !known && (code != next_code || !self.state.has_previous) {
+            return Err(LzwError::InvalidCode);
+        }
+        // walk the prefixes back to a single byte
+        self.string.clear();
+        let mut walk = if known { code } else { self.previous };
+        loop {
+            let (prefix, byte) = self.entries[walk as usize];
+            self.string.push(byte);
+            if prefix == u32::MAX {
+                break;
+            }
+            walk = prefix;
+        }
+        self.string.reverse();
+        let first = self.string[0];
+        if !known {
+            // the code being defined right now: previous string + its own first byte
+            self.string.push(first);
+        }
+        out.extend_from_slice(&self.string);
+
+        if self.state.has_previous && next_code < self.state.table_size() {
+            let entry = (self.previous, first);
+            match self.entries.get_mut(next_code as usize) {
+                Some(slot) => *slot = entry,
+                None => self.entries.push(entry),
+            }
+        }
+        self.previous = code;
+        self.state.after_code();
+        Ok(())
+    }
+
+    /// Decompresses `input`, appending the decoded bytes to `out`.
+    pub fn write(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<(), LzwError> {
+        let mut input = self.read_header(input)?;
+        loop {
+            if self.finished {
+                return Ok(());
+            }
+            // consume padding, which may straddle several calls
+            while self.skip_bits > 0 {
+                if self.bit_count == 0 {
+                    match input.split_first() {
+                        Some((&byte, rest)) => {
+                            self.buffer = byte as u64;
+                            self.bit_count = 8;
+                            input = rest;
+                        }
+                        None => return Ok(()),
+                    }
+                }
+                let skipped = self.skip_bits.min(self.bit_count as usize);
+                self.buffer >>= skipped;
+                self.bit_count -= skipped as u32;
+                self.skip_bits -= skipped;
+            }
+            if let Some(padding) = self.state.grow_before_code() {
+                self.skip_bits = padding;
+                continue;
+            }
+            let width = self.state.width as u32;
+            while self.bit_count < width {
+                match input.split_first() {
+                    Some((&byte, rest)) => {
+                        self.buffer |= (byte as u64) << self.bit_count;
+                        self.bit_count += 8;
+                        input = rest;
+                    }
+                    None => return Ok(()),
+                }
+            }
+            let code = (self.buffer & ((1 << width) - 1)) as u32;
+            self.buffer >>= width;
+            self.bit_count -= width;
+
+            if code == CLEAR as u32 && self.block_mode {
+                self.skip_bits = self.state.after_clear();
+                self.entries
+                    .truncate(self.state.format.first_free_code() as usize);
+                if self.state.format == LzwFormat::Gif {
+                    self.previous = u32::MAX;
+                }
+            } else if code == END_OF_INFORMATION as u32 && self.state.format == LzwFormat::Gif {
+                self.finished = true;
+            } else {
+                self.decode(code, out)?;
+            }
+        }
+    }
+
+    /// Checks that the stream ended properly.
+    pub fn finish(self) -> Result<(), LzwError> {
+        match self.state.format {
+            LzwFormat::Gif if !self.finished => Err(LzwError::UnexpectedEof),
+            LzwFormat::UnixCompress if self.header.len() < 3 => Err(LzwError::InvalidHeader),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn compress(format: LzwFormat, max_width: u8, data: &[u8]) -> Vec<u8> {
+        let mut encoder = LzwEncoder::new(format, max_width);
+        let mut out = Vec::new();
+        encoder.write(data, &mut out);
+        encoder.finish(&mut out);
+        out
+    }
+
+    fn decompress(format: LzwFormat, max_width: u8, data: &[u8]) -> Result<Vec<u8>, LzwError> {
+        let mut decoder = LzwDecoder::new(format, max_width);
+        let mut out = Vec::new();
+        decoder.write(data, &mut out)?;
+        decoder.finish()?;
+        Ok(out)
+    }
+
+    fn sample_data() -> Vec<u8> {
+        // long enough to fill a 9 to 12 bit dictionary several times over
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut data = Vec::new();
+        while data.len() < 200_000 {
+            let word = rng.gen_range(0..300);
+            data.extend_from_slice(format!("{word} ").as_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn textbook_example() {
+        // the classic example; `gzip -d` decodes this output
+        let data = b"TOBEORNOTTOBEORTOBEORNOT";
+        let compressed = compress(LzwFormat::UnixCompress, 16, data);
+        assert_eq!(
+            compressed,
+            [
+                0x1f, 0x9d, 0x90, 0x54, 0x9e, 0x08, 0x29, 0xf2, 0x44, 0x8a, 0x93, 0x27, 0x54, 0x02,
+                0x0e, 0x2c, 0xa8, 0x90, 0xa0, 0x41, 0x84
+            ]
+        );
+        assert_eq!(
+            decompress(LzwFormat::UnixCompress, 16, &compressed).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn decodes_streams_without_block_mode() {
+        // `words.txt` as `compress -C -b10` writes it: `compress` is not packaged here, so this was written by
+        // a port of its encoder, and `words.txt` is what `gzip -d` decodes from it
+        let compressed = include_bytes!("test_data/words_without_block_mode.Z");
+        assert_eq!(compressed[2] & BLOCK_MODE, 0);
+        assert_eq!(
+            decompress(LzwFormat::UnixCompress, 16, compressed).unwrap(),
+            include_bytes!("test_data/words.txt")
+        );
+        // code 256 is the first entry rather than a clear code
+        assert_eq!(
+            decompress(
+                LzwFormat::UnixCompress,
+                9,
+                &[0x1f, 0x9d, 0x09, 0x61, 0x00, 0x02]
+            )
+            .unwrap(),
+            b"aaa"
+        );
+    }
+
+    #[test]
+    fn round_trips_every_width() {
+        let data = sample_data();
+        for format in [LzwFormat::Gif, LzwFormat::UnixCompress] {
+            for max_width in MIN_WIDTH..=MAX_WIDTH {
+                let compressed = compress(format, max_width, &data);
+                assert!(compressed.len() < data.len());
+                assert_eq!(
+                    decompress(format, max_width, &compressed).unwrap(),
+                    data,
+                    "{format:?} with {max_width} bit codes"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn edge_cases() {
+        for format in [LzwFormat::Gif, LzwFormat::UnixCompress] {
+            for data in [&b""[..], b"a", b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"] {
+                let compressed = compress(format, 12, data);
+                assert_eq!(decompress(format, 12, &compressed).unwrap(), data);
+            }
+        }
+        assert_eq!(
+            compress(LzwFormat::UnixCompress, 12, b""),
+            [0x1f, 0x9d, 0x8c]
+        );
+        // clear code followed by end of information
+        assert_eq!(compress(LzwFormat::Gif, 12, b""), [0x00, 0x03, 0x02]);
+    }
+
+    #[test]
+    fn streams_in_small_chunks() {
+        let data = sample_data();
+        for format in [LzwFormat::Gif, LzwFormat::UnixCompress] {
+            let mut encoder = LzwEncoder::new(format, 10);
+            let mut compressed = Vec::new();
+            for chunk in data.chunks(777) {
+                encoder.write(chunk, &mut compressed);
+            }
+            encoder.finish(&mut compressed);
+            assert_eq!(compressed, compress(format, 10, &data));
+
+            let mut decoder = LzwDecoder::new(format, 10);
+            let mut decompressed = Vec::new();
+            for chunk in compressed.chunks(1) {
+                decoder.write(chunk, &mut decompressed).unwrap();
+            }
+            decoder.finish().unwrap();
+            assert_eq!(decompressed, data);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!(
+            decompress(LzwFormat::UnixCompress, 16, b"\x1f\x8b\x08"),
+            Err(LzwError::InvalidHeader)
+        );
+        assert_eq!(
+            decompress(LzwFormat::UnixCompress, 12, &[0x1f, 0x9d, 0x90]),
+            Err(LzwError::InvalidHeader)
+        );
+        assert_eq!(
+            decompress(LzwFormat::UnixCompress, 16, &[0x1f]),
+            Err(LzwError::InvalidHeader)
+        );
+        // first code 300 refers to an entry that does not exist
+        assert_eq!(
+            decompress(LzwFormat::UnixCompress, 16, &[0x1f, 0x9d, 0x90, 0x2c, 0x01]),
+            Err(LzwError::InvalidCode)
+        );
+        let gif = compress(LzwFormat::Gif, 12, b"no end marker");
+        assert_eq!(
+            decompress(LzwFormat::Gif, 12, &gif[..gif.len() - 2]),
+            Err(LzwError::UnexpectedEof)
+        );
+    }
+}
@@ -1,3 +1,43 @@
+mod adler32;
+mod bit_io;
+mod block_sorting;
+mod burrows_wheeler;
+mod canonical_huffman;
+mod codec;
+mod crc32;
+mod deflate;
+mod gzip;
+mod lzw;
+mod move_to_front;
+mod range_coding;
 mod run_length_encoding;
+mod zlib;
 
+pub use self::adler32::{adler32, adler32_update};
+pub use self::bit_io::{BitReader, BitWriter, EndOfInput};
+pub use self::block_sorting::{
+    block_sort_compress, block_sort_decompress, zero_run_decode, zero_run_encode, BlockSortError,
+    END_OF_BLOCK, RUNA, RUNB,
+};
+pub use self::burrows_wheeler::{bwt_forward, bwt_inverse, rotation_order};
+pub use self::canonical_huffman::{
+    canonical_codes, huffman_compress, huffman_decompress, package_merge, CanonicalDecoder,
+    HuffmanError,
+};
+pub use self::codec::{
+    codec_by_id, codec_by_name, codecs, compress_stream, decompress_stream, Codec, Decoder,
+    Encoder, Lzw, OneShot, RunLength, Store,
+};
+pub use self::crc32::{crc32, crc32_update};
+pub use self::deflate::{deflate_compress, deflate_decompress, DeflateError};
+pub use self::gzip::{gzip_compress, gzip_decompress};
+pub use self::lzw::{LzwDecoder, LzwEncoder, LzwError, LzwFormat};
+pub use self::move_to_front::{move_to_front_decode, move_to_front_encode};
+pub use self::range_coding::{
+    context_mixing_compress, context_mixing_decompress, range_compress_adaptive,
+    range_compress_static, range_decompress_adaptive, range_decompress_static, AdaptiveModel,
+    BitModel, ContextMixingModel, FrequencyModel, RangeCoderError, RangeDecoder, RangeEncoder,
+    StaticModel,
+};
 pub use self::run_length_encoding::{run_length_decode, run_length_encode};
+pub use self::zlib::{zlib_compress, zlib_decompress};
@@ -0,0 +1,50 @@
+//! Move-to-front transform.
+//!
+//! Every byte is replaced by its position in a list of all byte values, and
+//! then moved to the front of that list. Recently seen bytes get small
+//! numbers, so runs of a byte turn into runs of zeros.
+
+fn identity_list() -> [u8; 256] {
+    let mut list = [0; 256];
+    for (i, entry) in list.iter_mut().enumerate() {
+        *entry = i as u8;
+    }
+    list
+}
+
+pub fn move_to_front_encode(data: &[u8]) -> Vec<u8> {
+    let mut list = identity_list();
+    data.iter()
+        .map(|&byte| {
+            let index = list.iter().position(|&entry| entry == byte).unwrap();
+            list.copy_within(..index, 1);
+            list[0] = byte;
+            index as u8
+        })
+        .collect()
+}
+
+pub fn move_to_front_decode(data: &[u8]) -> Vec<u8> {
+    let mut list = identity_list();
+    data.iter()
+        .map(|&index| {
+            let byte = list[index as usize];
+            list.copy_within(..index as usize, 1);
+            list[0] = byte;
+            byte
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        assert_eq!(move_to_front_encode(b"bbbaac"), [98, 0, 0, 98, 0, 99]);
+        assert_eq!(move_to_front_decode(&[98, 0, 0, 98, 0, 99]), b"bbbaac");
+        let data: Vec<u8> = (0..=255).rev().chain(0..=255).collect();
+        assert_eq!(move_to_front_decode(&move_to_front_encode(&data)), data);
+    }
+}
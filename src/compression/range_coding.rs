@@ -0,0 +1,798 @@
+//! Range coding with static, adaptive and context-mixing models.
+//!
+//! A range coder narrows an interval down for every symbol, in proportion to
+//! the symbol's probability, and outputs the leading bytes of the interval as
+//! soon as they are settled. Unlike Huffman coding it is not restricted to a
+//! whole number of bits per symbol, so it gets within a fraction of a bit of
+//! the entropy given by the model.
+//!
+//! The coder itself only sees `(cumulative frequency, frequency, total)`
+//! triples. Where they come from is up to a model:
+//!
+//! * [`StaticModel`]: fixed order-0 byte frequencies, stored in a header,
+//! * [`AdaptiveModel`]: order-0 frequencies learned while coding,
+//! * [`ContextMixingModel`]: codes bytes bit by bit, mixing the predictions of
+//!   order-0 to order-k contexts. Anything implementing [`BitModel`] can be
+//!   plugged in instead.
+//!
+//! The coder uses a 32-bit range and propagates carries into the bytes
+//! already produced, like the LZMA range coder.
+
+/// Once the range drops below this, a byte is shifted out.
+const TOP: u32 = 1 << 24;
+/// Largest total frequency a model may use, so that `range / total` keeps
+/// enough precision.
+pub const MAX_TOTAL: u32 = 1 << 16;
+/// Precision of the probabilities returned by a [`BitModel`].
+pub const PROBABILITY_BITS: u32 = 12;
+const PROBABILITY_ONE: u32 = 1 << PROBABILITY_BITS;
+/// Highest order [`context_mixing_compress`] accepts. Every order costs a
+/// table of `2^18` probabilities and a pass over the history per byte.
+pub const MAX_ORDER: u8 = 8;
+
+/// Errors that can occur while decoding range coded data.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeCoderError {
+    /// The input ended before the data it describes.
+    UnexpectedEof,
+    /// The header describes an impossible model.
+    InvalidHeader,
+}
+
+/// Narrows the coding interval and writes out the settled bytes.
+#[derive(Debug)]
+pub struct RangeEncoder {
+    low: u64,
+    range: u32,
+    /// Last byte that could still be changed by a carry.
+    cache: u8,
+    /// Number of pending bytes: `cache` followed by `0xff` bytes.
+    pending: u64,
+    out: Vec<u8>,
+}
+
+impl Default for RangeEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RangeEncoder {
+    pub fn new() -> Self {
+        RangeEncoder {
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            pending: 1,
+            out: Vec::new(),
+        }
+    }
+
+    fn shift_low(&mut self) {
+        if self.low < 0xff00_0000 || self.low > u32::MAX as u64 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+            for _ in 0..self.pending {
+                self.out.push(byte.wrapping_add(carry));
+                byte = 0xff;
+            }
+            self.pending = 0;
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.pending += 1;
+        self.low = (self.low & 0x00ff_ffff) << 8;
+    }
+
+    /// Encodes the symbol occupying `[cumulative, cumulative + frequency)`
+    /// out of `total` (at most [`MAX_TOTAL`]).
+    pub fn encode(&mut self, cumulative: u32, frequency: u32, total: u32) {
+        debug_assert!(frequency > 0 && cumulative + frequency <= total && total <= MAX_TOTAL);
+        let r = self.range / total;
+        self.low += cumulative as u64 * r as u64;
+        self.range = r * frequency;
+        while self.range < TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    pub fn encode_symbol<M: FrequencyModel>(&mut self, model: &mut M, symbol: usize) {
+        let (cumulative, frequency) = model.interval(symbol);
+        self.encode(cumulative, frequency, model.total());
+        model.update(symbol);
+    }
+
+    /// Encodes `bit` given the probability `p1` (out of 4096) that it is a 1.
+    pub fn encode_bit(&mut self, p1: u32, bit: u32) {
+        let p0 = PROBABILITY_ONE - p1;
+        if bit == 0 {
+            self.encode(0, p0, PROBABILITY_ONE);
+        } else {
+            self.encode(p0, p1, PROBABILITY_ONE);
+        }
+    }
+
+    /// Flushes the remaining state and returns the coded bytes.
+    pub fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+        self.out
+    }
+}
+
+/// Reads back what [`RangeEncoder`] wrote. Reading past the end of the
+/// input fails with [`RangeCoderError::UnexpectedEof`].
+#[derive(Debug)]
+pub struct RangeDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    code: u32,
+    range: u32,
+    /// Scale of the interval found by the last `decode_target`.
+    r: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, RangeCoderError> {
+        let mut decoder = RangeDecoder {
+            data,
+            pos: 0,
+            code: 0,
+            range: u32::MAX,
+            r: 0,
+        };
+        for _ in 0..5 {
+            decoder.code = (decoder.code << 8) | decoder.next_byte()? as u32;
+        }
+        Ok(decoder)
+    }
+
+    fn next_byte(&mut self) -> Result<u8, RangeCoderError> {
+        let byte = *self
+            .data
+            .get(self.pos)
+            .ok_or(RangeCoderError::UnexpectedEof)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Returns a value in `[0, total)` that falls into the interval of the
+    /// next symbol. It must be followed by `consume` with that interval.
+    pub fn decode_target(&mut self, total: u32) -> u32 {
+        self.r = self.range / total;
+        (self.code / self.r).min(total - 1)
+    }
+
+    /// Removes the symbol occupying `[cumulative, cumulative + frequency)`.
+    pub fn consume(&mut self, cumulative: u32, frequency: u32) -> Result<(), RangeCoderError> {
+        // corrupt input can make the code fall outside the interval
+        self.code = self.code.wrapping_sub(cumulative * self.r);
+        self.range = self.r * frequency;
+        while self.range < TOP {
+            self.range <<= 8;
+            self.code = (self.code << 8) | self.next_byte()? as u32;
+        }
+        Ok(())
+    }
+
+    pub fn decode_symbol<M: FrequencyModel>(
+        &mut self,
+        model: &mut M,
+    ) -> Result<usize, RangeCoderError> {
+        let target = self.decode_target(model.total());
+        let symbol = model.find(target);
+        let (cumulative, frequency) = model.interval(symbol);
+        self.consume(cumulative, frequency)?;
+        model.update(symbol);
+        Ok(symbol)
+    }
+
+    /// Decodes a bit given the probability `p1` (out of 4096) that it is a 1.
+    pub fn decode_bit(&mut self, p1: u32) -> Result<u32, RangeCoderError> {
+        let p0 = PROBABILITY_ONE - p1;
+        if self.decode_target(PROBABILITY_ONE) < p0 {
+            self.consume(0, p0)?;
+            Ok(0)
+        } else {
+            self.consume(p0, p1)?;
+            Ok(1)
+        }
+    }
+}
+
+/// Gives the coder the frequency interval of every symbol.
+pub trait FrequencyModel {
+    /// Sum of all frequencies, at most [`MAX_TOTAL`].
+    fn total(&self) -> u32;
+    /// `(cumulative frequency, frequency)` of `symbol`.
+    fn interval(&self, symbol: usize) -> (u32, u32);
+    /// The symbol whose interval contains `target`.
+    fn find(&self, target: u32) -> usize;
+    /// Called after every coded symbol.
+    fn update(&mut self, symbol: usize);
+}
+
+fn find_in(frequencies: &[u32], target: u32) -> usize {
+    let mut cumulative = 0;
+    for (symbol, &frequency) in frequencies.iter().enumerate() {
+        cumulative += frequency;
+        if target < cumulative {
+            return symbol;
+        }
+    }
+    frequencies.len() - 1
+}
+
+fn interval_in(frequencies: &[u32], symbol: usize) -> (u32, u32) {
+    (frequencies[..symbol].iter().sum(), frequencies[symbol])
+}
+
+/// Order-0 model with fixed frequencies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticModel {
+    frequencies: Vec<u32>,
+    total: u32,
+}
+
+impl StaticModel {
+    /// Scales `counts` down to fit in [`MAX_TOTAL`], keeping every used
+    /// symbol at a frequency of at least 1.
+    pub fn new(counts: &[u64]) -> Self {
+        let sum: u64 = counts.iter().sum();
+        let used = counts.iter().filter(|&&count| count > 0).count() as u64;
+        assert!(used > 0, "at least one symbol must have a non-zero count");
+        assert!(used <= MAX_TOTAL as u64, "too many symbols");
+        // leave room for the symbols that get rounded up to 1
+        let budget = MAX_TOTAL as u64 - used;
+        let frequencies: Vec<u32> = counts
+            .iter()
+            .map(|&count| match count {
+                0 => 0,
+                _ if sum <= MAX_TOTAL as u64 => count as u32,
+                _ => (count * budget / sum) as u32 + 1,
+            })
+            .collect();
+        let total = frequencies.iter().sum();
+        StaticModel { frequencies, total }
+    }
+
+    pub fn frequencies(&self) -> &[u32] {
+        &self.frequencies
+    }
+}
+
+impl FrequencyModel for StaticModel {
+    fn total(&self) -> u32 {
+        self.total
+    }
+
+    fn interval(&self, symbol: usize) -> (u32, u32) {
+        interval_in(&self.frequencies, symbol)
+    }
+
+    fn find(&self, target: u32) -> usize {
+        find_in(&self.frequencies, target)
+    }
+
+    fn update(&mut self, _symbol: usize) {}
+}
+
+/// Order-0 model that starts with all symbols equally likely and adapts to
+/// the data. Older statistics are halved once the total gets too large, so the
+/// model also follows changes in the distribution.
+#[derive(Debug, Clone)]
+pub struct AdaptiveModel {
+    frequencies: Vec<u32>,
+    total: u32,
+    increment: u32,
+}
+
+impl AdaptiveModel {
+    pub fn new(num_symbols: usize) -> Self {
+        assert!(num_symbols > 0 && num_symbols as u32 <= MAX_TOTAL / 2);
+        AdaptiveModel {
+            frequencies: vec![1; num_symbols],
+            total: num_symbols as u32,
+            increment: 32,
+        }
+    }
+}
+
+impl FrequencyModel for AdaptiveModel {
+    fn total(&self) -> u32 {
+        self.total
+    }
+
+    fn interval(&self, symbol: usize) -> (u32, u32) {
+        interval_in(&self.frequencies, symbol)
+    }
+
+    fn find(&self, target: u32) -> usize {
+        find_in(&self.frequencies, target)
+    }
+
+    fn update(&mut self, symbol: usize) {
+        self.frequencies[symbol] += self.increment;
+        self.total += self.increment;
+        if self.total > MAX_TOTAL {
+            self.total = 0;
+            for frequency in &mut self.frequencies {
+                *frequency = frequency.div_ceil(2);
+                self.total += *frequency;
+            }
+        }
+    }
+}
+
+/// Predicts a sequence one bit at a time.
+pub trait BitModel {
+    /// Probability, in 1/4096ths and strictly between 0 and 4096, that the
+    /// next bit is a 1.
+    fn predict(&mut self) -> u32;
+    /// Learns the bit that was actually coded.
+    fn update(&mut self, bit: u32);
+}
+
+/// Logistic function `4096 / (1 + e^-x/256)`, with `x` in `[-2047, 2047]`,
+/// interpolated from a table.
+fn squash(x: i32) -> i32 {
+    const TABLE: [i32; 33] = [
+        1, 2, 3, 6, 10, 16, 27, 45, 73, 120, 194, 310, 488, 747, 1101, 1546, 2047, 2549, 2994,
+        3348, 3607, 3785, 3901, 3975, 4022, 4050, 4068, 4079, 4085, 4089, 4092, 4093, 4094,
+    ];
+    if x > 2047 {
+        return 4095;
+    }
+    if x < -2047 {
+        return 1;
+    }
+    let weight = x & 127;
+    let i = ((x >> 7) + 16) as usize;
+    (TABLE[i] * (128 - weight) + TABLE[i + 1] * weight + 64) >> 7
+}
+
+/// Inverse of [`squash`]: `ln(p / (1 - p))`, scaled the same way.
+fn stretch_table() -> Vec<i16> {
+    let mut table = vec![2047i16; PROBABILITY_ONE as usize];
+    let mut next = 0;
+    for x in -2047..=2047 {
+        let p = squash(x) as usize;
+        for entry in table.iter_mut().take(p + 1).skip(next) {
+            *entry = x as i16;
+        }
+        next = next.max(p + 1);
+    }
+    table
+}
+
+/// Bits of every order's hash table.
+const CONTEXT_TABLE_BITS: u32 = 18;
+const MIXER_LEARNING_RATE: i32 = 6;
+
+/// Bitwise context-mixing model over bytes.
+///
+/// For every order `0..=k`, the last `order` bytes together with the bits of
+/// the current byte seen so far select a slot in a hash table holding an
+/// adaptive probability. The stretched probabilities are combined by a
+/// logistic mixer whose weights are trained online to minimise coding cost.
+#[derive(Debug)]
+pub struct ContextMixingModel {
+    order: usize,
+    /// One table of 16-bit probabilities per order.
+    tables: Vec<Vec<u16>>,
+    /// Slot used by every order for the current bit.
+    slots: Vec<usize>,
+    /// Hash of the last `order` bytes, for every order.
+    hashes: Vec<u32>,
+    history: Vec<u8>,
+    /// Bits of the current byte seen so far, behind a leading 1.
+    partial: u32,
+    weights: Vec<i32>,
+    inputs: Vec<i32>,
+    stretch: Vec<i16>,
+    prediction: i32,
+}
+
+impl ContextMixingModel {
+    pub fn new(order: usize) -> Self {
+        let inputs = order + 1;
+        let mut model = ContextMixingModel {
+            order,
+            tables: vec![vec![1 << 15; 1 << CONTEXT_TABLE_BITS]; inputs],
+            slots: vec![0; inputs],
+            hashes: vec![0; inputs],
+            history: Vec::new(),
+            partial: 1,
+            weights: vec![(1 << 16) / inputs as i32; inputs],
+            inputs: vec![0; inputs],
+            stretch: stretch_table(),
+            prediction: 2048,
+        };
+        model.compute_slots();
+        model
+    }
+
+    fn compute_slots(&mut self) {
+        for (order, slot) in self.slots.iter_mut().enumerate() {
+            let hash = (self.hashes[order] ^ self.partial.wrapping_mul(0x2f0b_3c8d))
+                .wrapping_mul(0x9e37_79b1)
+                .wrapping_add(order as u32);
+            *slot = (hash >> (32 - CONTEXT_TABLE_BITS)) as usize;
+        }
+    }
+
+    /// Called once a whole byte is known.
+    fn end_of_byte(&mut self, byte: u8) {
+        self.history.push(byte);
+        if self.history.len() > self.order {
+            self.history.remove(0);
+        }
+        for order in 0..=self.order {
+            let mut hash = 0u32;
+            for &byte in self.history.iter().rev().take(order) {
+                hash = (hash ^ byte as u32).wrapping_mul(0x0100_0193);
+            }
+            self.hashes[order] = hash.wrapping_add((order as u32).wrapping_mul(0x6b43_a9b5));
+        }
+        self.partial = 1;
+    }
+}
+
+impl BitModel for ContextMixingModel {
+    fn predict(&mut self) -> u32 {
+        let mut dot: i64 = 0;
+        for order in 0..=self.order {
+            let p = (self.tables[order][self.slots[order]] >> 4) as usize;
+            self.inputs[order] = self.stretch[p] as i32;
+            dot += self.inputs[order] as i64 * self.weights[order] as i64;
+        }
+        self.prediction = squash((dot >> 16) as i32);
+        self.prediction as u32
+    }
+
+    fn update(&mut self, bit: u32) {
+        let error = (((bit as i32) << 12) - self.prediction) * MIXER_LEARNING_RATE;
+        for order in 0..=self.order {
+            self.weights[order] += (self.inputs[order] * error + (1 << 9)) >> 10;
+            let p = &mut self.tables[order][self.slots[order]];
+            if bit == 1 {
+                *p += (u16::MAX - *p) >> 4;
+            } else {
+                *p -= *p >> 4;
+            }
+        }
+        self.partial = (self.partial << 1) | bit;
+        if self.partial >= 256 {
+            self.end_of_byte(self.partial as u8);
+        }
+        self.compute_slots();
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Result<u64, RangeCoderError> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*pos).ok_or(RangeCoderError::UnexpectedEof)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(RangeCoderError::InvalidHeader)
+}
+
+/// Compresses `data` with byte frequencies counted up front. The scaled
+/// frequencies are stored in the output.
+pub fn range_compress_static(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, data.len() as u64);
+    if data.is_empty() {
+        return out;
+    }
+    let mut counts = [0u64; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    let mut model = StaticModel::new(&counts);
+    let first = counts.iter().position(|&count| count > 0).unwrap();
+    let last = counts.iter().rposition(|&count| count > 0).unwrap();
+    out.extend_from_slice(&[first as u8, last as u8]);
+    for &frequency in &model.frequencies()[first..=last] {
+        write_varint(&mut out, frequency as u64);
+    }
+    let mut encoder = RangeEncoder::new();
+    for &byte in data {
+        encoder.encode_symbol(&mut model, byte as usize);
+    }
+    out.extend(encoder.finish());
+    out
+}
+
+/// Decompresses the output of [`range_compress_static`].
+pub fn range_decompress_static(data: &[u8]) -> Result<Vec<u8>, RangeCoderError> {
+    let mut pos = 0;
+    let len = read_varint(data, &mut pos)?;
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    let range = data
+        .get(pos..pos + 2)
+        .ok_or(RangeCoderError::UnexpectedEof)?;
+    let (first, last) = (range[0] as usize, range[1] as usize);
+    pos += 2;
+    let mut frequencies = [0u32; 256];
+    for frequency in frequencies.iter_mut().take(last + 1).skip(first) {
+        *frequency = u32::try_from(read_varint(data, &mut pos)?)
+            .map_err(|_| RangeCoderError::InvalidHeader)?;
+    }
+    let total: u64 = frequencies.iter().map(|&f| f as u64).sum();
+    if total == 0 || total > MAX_TOTAL as u64 {
+        return Err(RangeCoderError::InvalidHeader);
+    }
+    let mut model = StaticModel {
+        frequencies: frequencies.to_vec(),
+        total: total as u32,
+    };
+    decode_bytes(&data[pos..], len, &mut model)
+}
+
+/// Decodes `len` bytes coded with `model`. The length comes from the input,
+/// so the output only grows as bytes are actually decoded.
+fn decode_bytes<M: FrequencyModel>(
+    data: &[u8],
+    len: u64,
+    model: &mut M,
+) -> Result<Vec<u8>, RangeCoderError> {
+    let mut decoder = RangeDecoder::new(data)?;
+    let mut out = Vec::new();
+    for _ in 0..len {
+        out.push(decoder.decode_symbol(model)? as u8);
+    }
+    Ok(out)
+}
+
+/// Compresses `data` with an adaptive order-0 model.
+pub fn range_compress_adaptive(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, data.len() as u64);
+    let mut model = AdaptiveModel::new(256);
+    let mut encoder = RangeEncoder::new();
+    for &byte in data {
+        encoder.encode_symbol(&mut model, byte as usize);
+    }
+    out.extend(encoder.finish());
+    out
+}
+
+/// Decompresses the output of [`range_compress_adaptive`].
+pub fn range_decompress_adaptive(data: &[u8]) -> Result<Vec<u8>, RangeCoderError> {
+    let mut pos = 0;
+    let len = read_varint(data, &mut pos)?;
+    decode_bytes(&data[pos..], len, &mut AdaptiveModel::new(256))
+}
+
+/// Compresses `data` bit by bit with a [`ContextMixingModel`] of the given
+/// order, at most [`MAX_ORDER`]. The order is stored in the output.
+pub fn context_mixing_compress(data: &[u8], order: u8) -> Vec<u8> {
+    assert!(order <= MAX_ORDER, "context order above {MAX_ORDER}");
+    let mut out = Vec::new();
+    write_varint(&mut out, data.len() as u64);
+    out.push(order);
+    let mut model = ContextMixingModel::new(order as usize);
+    let mut encoder = RangeEncoder::new();
+    for &byte in data {
+        for i in (0..8).rev() {
+            let bit = (byte >> i) as u32 & 1;
+            encoder.encode_bit(model.predict(), bit);
+            model.update(bit);
+        }
+    }
+    out.extend(encoder.finish());
+    out
+}
+
+/// Decompresses the output of [`context_mixing_compress`].
+pub fn context_mixing_decompress(data: &[u8]) -> Result<Vec<u8>, RangeCoderError> {
+    let mut pos = 0;
+    let len = read_varint(data, &mut pos)?;
+    let order = *data.get(pos).ok_or(RangeCoderError::UnexpectedEof)?;
+    if order > MAX_ORDER {
+        return Err(RangeCoderError::InvalidHeader);
+    }
+    let mut model = ContextMixingModel::new(order as usize);
+    let mut decoder = RangeDecoder::new(&data[pos + 1..])?;
+    let mut out = Vec::new();
+    for _ in 0..len {
+        let mut byte = 0;
+        for _ in 0..8 {
+            let bit = decoder.decode_bit(model.predict())?;
+            model.update(bit);
+            byte = (byte << 1) | bit as u8;
+        }
+        out.push(byte);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::huffman_compress;
+
+    fn sample_text() -> Vec<u8> {
+        let mut text = Vec::new();
+        for i in 0..300 {
+            text.extend_from_slice(
+                format!("{i}: It was the best of times, it was the worst of times.\n").as_bytes(),
+            );
+        }
+        text
+    }
+
+    /// Almost all zeros: Huffman still needs one bit per symbol.
+    fn skewed_bytes() -> Vec<u8> {
+        (0..20_000u32)
+            .map(|i| if i % 97 == 0 { (i % 7) as u8 + 1 } else { 0 })
+            .collect()
+    }
+
+    #[test]
+    fn coder_round_trips_symbols() {
+        let symbols = [(0, 1, 3), (1, 2, 3), (0, 1, 3), (5, 7, 100), (99, 1, 100)];
+        let mut encoder = RangeEncoder::new();
+        for &(cumulative, frequency, total) in &symbols {
+            encoder.encode(cumulative, frequency, total);
+        }
+        let bytes = encoder.finish();
+        let mut decoder = RangeDecoder::new(&bytes).unwrap();
+        for &(cumulative, frequency, total) in &symbols {
+            let target = decoder.decode_target(total);
+            assert!((cumulative..cumulative + frequency).contains(&target));
+            decoder.consume(cumulative, frequency).unwrap();
+        }
+    }
+
+    #[test]
+    fn carries_propagate() {
+        // always coding the top of the interval pushes `low` to overflow
+        let mut encoder = RangeEncoder::new();
+        for _ in 0..10_000 {
+            encoder.encode(MAX_TOTAL - 1, 1, MAX_TOTAL);
+            encoder.encode(0, 1, MAX_TOTAL);
+        }
+        let bytes = encoder.finish();
+        let mut decoder = RangeDecoder::new(&bytes).unwrap();
+        for _ in 0..10_000 {
+            assert_eq!(decoder.decode_target(MAX_TOTAL), MAX_TOTAL - 1);
+            decoder.consume(MAX_TOTAL - 1, 1).unwrap();
+            assert_eq!(decoder.decode_target(MAX_TOTAL), 0);
+            decoder.consume(0, 1).unwrap();
+        }
+    }
+
+    #[test]
+    fn static_model_scales_counts() {
+        let model = StaticModel::new(&[1_000_000, 0, 1, 3]);
+        assert!(model.total() <= MAX_TOTAL);
+        assert_eq!(model.frequencies()[1], 0);
+        assert!(model.frequencies()[2] >= 1);
+        assert_eq!(model.find(model.interval(3).0), 3);
+        assert_eq!(StaticModel::new(&[2, 5]).frequencies(), [2, 5]);
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = sample_text();
+        let skewed = skewed_bytes();
+        let all_bytes: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        for data in [&b""[..], b"x", &text, &skewed, &all_bytes] {
+            assert_eq!(
+                range_decompress_static(&range_compress_static(data)).unwrap(),
+                data
+            );
+            assert_eq!(
+                range_decompress_adaptive(&range_compress_adaptive(data)).unwrap(),
+                data
+            );
+            for order in [0, 2, 4] {
+                assert_eq!(
+                    context_mixing_decompress(&context_mixing_compress(data, order)).unwrap(),
+                    data
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn beats_huffman_on_skewed_data() {
+        let skewed = skewed_bytes();
+        let huffman = huffman_compress(&skewed).len();
+        assert!(range_compress_static(&skewed).len() * 5 < huffman);
+        assert!(range_compress_adaptive(&skewed).len() * 5 < huffman);
+    }
+
+    #[test]
+    fn higher_orders_compress_text_better() {
+        let text = sample_text();
+        let huffman = huffman_compress(&text).len();
+        let order0 = context_mixing_compress(&text, 0).len();
+        let order3 = context_mixing_compress(&text, 3).len();
+        assert!(order3 * 4 < order0);
+        assert!(order3 * 4 < huffman);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        assert_eq!(
+            range_decompress_static(&[]),
+            Err(RangeCoderError::UnexpectedEof)
+        );
+        assert_eq!(
+            range_decompress_static(&[5, 10]),
+            Err(RangeCoderError::UnexpectedEof)
+        );
+        assert_eq!(
+            range_decompress_static(&[5, 10, 9]),
+            Err(RangeCoderError::InvalidHeader)
+        );
+        assert_eq!(
+            context_mixing_decompress(&[5]),
+            Err(RangeCoderError::UnexpectedEof)
+        );
+        assert_eq!(
+            context_mixing_decompress(&[5, MAX_ORDER + 1, 0, 0, 0, 0]),
+            Err(RangeCoderError::InvalidHeader)
+        );
+        // a frequency that does not fit in 32 bits
+        let mut header = vec![5, 10, 10];
+        write_varint(&mut header, u32::MAX as u64 + 1);
+        assert_eq!(
+            range_decompress_static(&header),
+            Err(RangeCoderError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let text = sample_text();
+        let static_coded = range_compress_static(&text);
+        let adaptive = range_compress_adaptive(&text);
+        let mixed = context_mixing_compress(&text, 2);
+        assert_eq!(
+            range_decompress_static(&static_coded[..static_coded.len() - 1]),
+            Err(RangeCoderError::UnexpectedEof)
+        );
+        assert_eq!(
+            range_decompress_adaptive(&adaptive[..adaptive.len() - 1]),
+            Err(RangeCoderError::UnexpectedEof)
+        );
+        assert_eq!(
+            context_mixing_decompress(&mixed[..mixed.len() - 1]),
+            Err(RangeCoderError::UnexpectedEof)
+        );
+        // a forged length fails once the input runs out, without allocating it up front
+        let mut pos = 0;
+        read_varint(&adaptive, &mut pos).unwrap();
+        let mut forged = Vec::new();
+        write_varint(&mut forged, u64::MAX);
+        forged.extend_from_slice(&adaptive[pos..]);
+        assert_eq!(
+            range_decompress_adaptive(&forged),
+            Err(RangeCoderError::UnexpectedEof)
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! zlib compressed data format (RFC 1950).
+//!
+//! A zlib stream is a two byte header (compression method and flags), a raw
+//! DEFLATE stream and the big-endian Adler-32 checksum of the uncompressed
+//! data. Preset dictionaries are not supported.
+//!
+//! Specification: <https://www.rfc-editor.org/rfc/rfc1950>.
+
+use super::adler32::adler32;
+use super::bit_io::BitReader;
+use super::deflate::{deflate_compress, inflate_into, DeflateError};
+
+/// Compression method 8 (DEFLATE) with a 32 KiB window.
+const CMF: u8 = 0x78;
+/// Default compression level, with the check bits making `CMF * 256 + FLG`
+/// a multiple of 31.
+const FLG: u8 = 0x9c;
+const PRESET_DICTIONARY: u8 = 0x20;
+
+/// Compresses `data` into a zlib stream.
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![CMF, FLG];
+    out.extend(deflate_compress(data));
+    let checksum = adler32(data);
+    out.extend([24, 16, 8, 0].map(|shift| (checksum >> shift) as u8));
+    out
+}
+
+/// Decompresses a zlib stream and verifies its Adler-32 checksum.
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, DeflateError> {
+    let (&cmf, &flg) = match data {
+        [cmf, flg, ..] => (cmf, flg),
+        _ => return Err(DeflateError::UnexpectedEof),
+    };
+    if cmf & 0x0f != 8
+        || cmf >> 4 > 7
+        || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31)
+        || flg & PRESET_DICTIONARY != 0
+    {
+        return Err(DeflateError::InvalidHeader);
+    }
+    let mut reader = BitReader::new(&data[2..]);
+    let mut out = Vec::new();
+    inflate_into(&mut reader, &mut out)?;
+    let trailer = 2 + reader.bytes_consumed();
+    let checksum = data
+        .get(trailer..trailer + 4)
+        .ok_or(DeflateError::UnexpectedEof)?
+        .iter()
+        .fold(0, |checksum, &byte| (checksum << 8) | byte as u32);
+    if checksum != adler32(&out) {
+        return Err(DeflateError::ChecksumMismatch);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HAMLET: &[u8] = include_bytes!("test_data/hamlet.txt");
+    /// `HAMLET` compressed by Python's `zlib.compress(data, 9)`.
+    const HAMLET_ZLIB: &[u8] = include_bytes!("test_data/hamlet.zlib");
+
+    #[test]
+    fn decodes_zlib_output() {
+        assert_eq!(zlib_decompress(HAMLET_ZLIB).unwrap(), HAMLET);
+    }
+
+    #[test]
+    fn round_trips() {
+        for data in [&b""[..], b"z", HAMLET, &[7u8; 5000]] {
+            let compressed = zlib_compress(data);
+            assert_eq!(&compressed[..2], &[0x78, 0x9c]);
+            assert_eq!(zlib_decompress(&compressed).unwrap(), data);
+        }
+        assert!(zlib_compress(HAMLET).len() < HAMLET.len());
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let mut corrupt = HAMLET_ZLIB.to_vec();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        assert_eq!(
+            zlib_decompress(&corrupt),
+            Err(DeflateError::ChecksumMismatch)
+        );
+        assert_eq!(
+            zlib_decompress(&HAMLET_ZLIB[..HAMLET_ZLIB.len() - 2]),
+            Err(DeflateError::UnexpectedEof)
+        );
+        assert_eq!(
+            zlib_decompress(&[0x78, 0x9d, 0x03, 0x00]),
+            Err(DeflateError::InvalidHeader)
+        );
+        assert_eq!(
+            zlib_decompress(&[0x78, 0xbb, 0, 0, 0, 1]),
+            Err(DeflateError::InvalidHeader)
+        );
+    }
+}
@@ -2,25 +2,45 @@ use std::{
     cmp::{max, Ordering},
     iter::FromIterator,
     mem,
-    ops::Not,
+    ops::{Bound, Not, RangeBounds},
 };
 
-/// An internal node of an `AVLTree`.
-struct AVLNode<T: Ord> {
-    value: T,
+/// An internal node of an `AVLTree`, or of another tree balanced the same way.
+pub(super) struct AVLNode<K: Ord, V, S = ()> {
+    key: K,
+    value: V,
     height: usize,
-    left: Option<Box<AVLNode<T>>>,
-    right: Option<Box<AVLNode<T>>>,
+    /// The number of nodes in the subtree rooted here, for order statistics.
+    size: usize,
+    /// What the tree keeps about the subtree rooted here, besides its size.
+    summary: S,
+    left: Option<Box<AVLNode<K, V, S>>>,
+    right: Option<Box<AVLNode<K, V, S>>>,
 }
 
-/// A set based on an AVL Tree.
+/// Data about a subtree that a node computes from its own entry and the summaries of its children.
+///
+/// Insertions, including those replacing a value, removals and rotations keep it up to date, so
+/// trees built on the insertion and removal functions of this module can prune their searches with
+/// it, like the largest end of the intervals of an interval tree.
+pub(super) trait Summary<K, V> {
+    fn summarize(key: &K, value: &V, left: Option<&Self>, right: Option<&Self>) -> Self;
+}
+
+impl<K, V> Summary<K, V> for () {
+    fn summarize(_: &K, _: &V, _: Option<&Self>, _: Option<&Self>) -> Self {}
+}
+
+/// An ordered map based on an AVL Tree.
 ///
 /// An AVL Tree is a self-balancing binary search tree. It tracks the height of each node
 /// and performs internal rotations to maintain a height difference of at most 1 between
 /// each sibling pair.
-pub struct AVLTree<T: Ord> {
-    root: Option<Box<AVLNode<T>>>,
-    length: usize,
+///
+/// Every node also stores the size of its subtree, which answers order statistics queries
+/// (`rank`, `select`) and sizes `range` iterators in O(log n).
+pub struct AVLTree<K: Ord, V> {
+    root: Option<Box<AVLNode<K, V>>>,
 }
 
 /// Refers to the left or right subtree of an `AVLNode`.
@@ -30,163 +50,392 @@ enum Side {
     Right,
 }
 
-impl<T: Ord> AVLTree<T> {
+impl<K: Ord, V> AVLTree<K, V> {
     /// Creates an empty `AVLTree`.
-    pub fn new() -> AVLTree<T> {
-        AVLTree {
-            root: None,
-            length: 0,
-        }
+    pub fn new() -> AVLTree<K, V> {
+        AVLTree { root: None }
     }
 
-    /// Returns `true` if the tree contains a value.
-    pub fn contains(&self, value: &T) -> bool {
+    /// Returns `true` if the tree contains a key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns a reference to the value stored under a key.
+    pub fn get(&self, key: &K) -> Option<&V> {
         let mut current = &self.root;
         while let Some(node) = current {
-            current = match value.cmp(&node.value) {
-                Ordering::Equal => return true,
+            current = match key.cmp(&node.key) {
+                Ordering::Equal => return Some(&node.value),
                 Ordering::Less => &node.left,
                 Ordering::Greater => &node.right,
             }
         }
-        false
+        None
     }
 
-    /// Adds a value to the tree.
-    ///
-    /// Returns `true` if the tree did not yet contain the value.
-    pub fn insert(&mut self, value: T) -> bool {
-        let inserted = insert(&mut self.root, value);
-        if inserted {
-            self.length += 1;
+    /// Returns a mutable reference to the value stored under a key.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let mut current = &mut self.root;
+        while let Some(node) = current {
+            current = match key.cmp(&node.key) {
+                Ordering::Equal => return Some(&mut node.value),
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => &mut node.right,
+            }
         }
-        inserted
+        None
+    }
+
+    /// Adds a key-value pair to the tree.
+    ///
+    /// Returns the previous value if the tree already contained the key; the key itself is
+    /// not updated.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        insert(&mut self.root, key, value)
     }
 
-    /// Removes a value from the tree.
+    /// Removes a key from the tree.
     ///
-    /// Returns `true` if the tree contained the value.
-    pub fn remove(&mut self, value: &T) -> bool {
-        let removed = remove(&mut self.root, value);
-        if removed {
-            self.length -= 1;
+    /// Returns the value that was stored under the key.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        remove(&mut self.root, key)
+    }
+
+    /// Gets the entry for a key, to inspect or update it in place.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        let index = self.rank(&key);
+        if self.select(index).is_some_and(|(k, _)| *k == key) {
+            Entry::Occupied(OccupiedEntry {
+                tree: self,
+                key,
+                index,
+            })
+        } else {
+            Entry::Vacant(VacantEntry {
+                tree: self,
+                key,
+                index,
+            })
         }
-        removed
     }
 
-    /// Returns the number of values in the tree.
+    /// Returns the number of entries in the tree.
     pub fn len(&self) -> usize {
-        self.length
+        size(self.root.as_deref())
     }
 
-    /// Returns `true` if the tree contains no values.
+    /// Returns `true` if the tree contains no entries.
     pub fn is_empty(&self) -> bool {
-        self.length == 0
+        self.root.is_none()
     }
 
-    /// Returns an iterator that visits the nodes in the tree in order.
-    fn node_iter(&self) -> NodeIter<T> {
+    /// Returns the entry with the smallest key.
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.select(0)
+    }
+
+    /// Returns the entry with the largest key.
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.select(self.len().checked_sub(1)?)
+    }
+
+    /// Removes and returns the entry with the smallest key.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        take_extreme(&mut self.root, Side::Left).map(|node| (node.key, node.value))
+    }
+
+    /// Removes and returns the entry with the largest key.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        take_extreme(&mut self.root, Side::Right).map(|node| (node.key, node.value))
+    }
+
+    /// Returns the entry with the largest key less than or equal to `key`.
+    pub fn floor(&self, key: &K) -> Option<(&K, &V)> {
+        let mut current = &self.root;
+        let mut best = None;
+        while let Some(node) = current {
+            current = match key.cmp(&node.key) {
+                Ordering::Equal => return Some((&node.key, &node.value)),
+                Ordering::Less => &node.left,
+                Ordering::Greater => {
+                    best = Some((&node.key, &node.value));
+                    &node.right
+                }
+            }
+        }
+        best
+    }
+
+    /// Returns the entry with the smallest key greater than or equal to `key`.
+    pub fn ceiling(&self, key: &K) -> Option<(&K, &V)> {
+        let mut current = &self.root;
+        let mut best = None;
+        while let Some(node) = current {
+            current = match key.cmp(&node.key) {
+                Ordering::Equal => return Some((&node.key, &node.value)),
+                Ordering::Greater => &node.right,
+                Ordering::Less => {
+                    best = Some((&node.key, &node.value));
+                    &node.left
+                }
+            }
+        }
+        best
+    }
+
+    /// Returns the number of keys strictly less than `key`.
+    pub fn rank(&self, key: &K) -> usize {
+        self.count_below(key, false)
+    }
+
+    /// Returns the number of keys less than `key`, or less than or equal to it if
+    /// `inclusive` is set.
+    fn count_below(&self, key: &K, inclusive: bool) -> usize {
+        let mut current = &self.root;
+        let mut count = 0;
+        while let Some(node) = current {
+            let go_right = match key.cmp(&node.key) {
+                Ordering::Less => false,
+                Ordering::Equal => inclusive,
+                Ordering::Greater => true,
+            };
+            current = if go_right {
+                count += size(node.left.as_deref()) + 1;
+                &node.right
+            } else {
+                &node.left
+            };
+        }
+        count
+    }
+
+    /// Returns the entry with the `index`-th smallest key, counting from 0.
+    pub fn select(&self, index: usize) -> Option<(&K, &V)> {
+        let mut current = &self.root;
+        let mut index = index;
+        while let Some(node) = current {
+            let left = size(node.left.as_deref());
+            current = match index.cmp(&left) {
+                Ordering::Equal => return Some((&node.key, &node.value)),
+                Ordering::Less => &node.left,
+                Ordering::Greater => {
+                    index -= left + 1;
+                    &node.right
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns a mutable reference to the value of the `index`-th smallest key.
+    fn select_mut(&mut self, index: usize) -> Option<&mut V> {
+        let mut current = &mut self.root;
+        let mut index = index;
+        while let Some(node) = current {
+            let left = size(node.left.as_deref());
+            current = match index.cmp(&left) {
+                Ordering::Equal => return Some(&mut node.value),
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => {
+                    index -= left + 1;
+                    &mut node.right
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns an iterator over the nodes from the `index`-th smallest one, in order.
+    fn node_iter_from(&self, index: usize) -> NodeIter<'_, K, V> {
         let cap = self.root.as_ref().map_or(0, |n| n.height);
         let mut node_iter = NodeIter {
             stack: Vec::with_capacity(cap),
         };
-        // Initialize stack with path to leftmost child
+        // Initialize stack with the nodes on the search path that come at or after `index`
         let mut child = &self.root;
+        let mut index = index;
         while let Some(node) = child {
-            node_iter.stack.push(node.as_ref());
-            child = &node.left;
+            let left = size(node.left.as_deref());
+            if index <= left {
+                node_iter.stack.push(node.as_ref());
+                child = &node.left;
+            } else {
+                index -= left + 1;
+                child = &node.right;
+            }
         }
         node_iter
     }
 
-    /// Returns an iterator that visits the values in the tree in ascending order.
-    pub fn iter(&self) -> Iter<T> {
+    /// Returns an iterator that visits the nodes in the tree in order.
+    fn node_iter(&self) -> NodeIter<'_, K, V> {
+        self.node_iter_from(0)
+    }
+
+    /// Returns an iterator that visits the entries in the tree in ascending order of keys.
+    pub fn iter(&self) -> Iter<'_, K, V> {
         Iter {
             node_iter: self.node_iter(),
+            remaining: self.len(),
+        }
+    }
+
+    /// Returns an iterator over the keys in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Returns an iterator over the values in ascending order of keys.
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// Returns an iterator over the entries whose keys lie in `range`, in ascending order.
+    ///
+    /// Both ends are turned into ranks first, so the iterator knows its length and stops
+    /// without comparing keys. A range whose start lies after its end is empty.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, K, V> {
+        let start = match range.start_bound() {
+            Bound::Included(key) => self.count_below(key, false),
+            Bound::Excluded(key) => self.count_below(key, true),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => self.count_below(key, true),
+            Bound::Excluded(key) => self.count_below(key, false),
+            Bound::Unbounded => self.len(),
+        };
+        Iter {
+            node_iter: self.node_iter_from(start),
+            remaining: end.saturating_sub(start),
         }
     }
 }
 
+/// Returns the number of nodes in a subtree.
+pub(super) fn size<K: Ord, V, S>(tree: Option<&AVLNode<K, V, S>>) -> usize {
+    tree.map_or(0, |n| n.size)
+}
+
 /// Recursive helper function for `AVLTree` insertion.
-fn insert<T: Ord>(tree: &mut Option<Box<AVLNode<T>>>, value: T) -> bool {
+pub(super) fn insert<K: Ord, V, S: Summary<K, V>>(
+    tree: &mut Option<Box<AVLNode<K, V, S>>>,
+    key: K,
+    value: V,
+) -> Option<V> {
     if let Some(node) = tree {
-        let inserted = match value.cmp(&node.value) {
-            Ordering::Equal => false,
-            Ordering::Less => insert(&mut node.left, value),
-            Ordering::Greater => insert(&mut node.right, value),
+        let old = match key.cmp(&node.key) {
+            Ordering::Equal => Some(mem::replace(&mut node.value, value)),
+            Ordering::Less => insert(&mut node.left, key, value),
+            Ordering::Greater => insert(&mut node.right, key, value),
         };
-        if inserted {
+        if old.is_none() {
             node.rebalance();
+        } else {
+            // the shape did not change, but the summaries on the path may depend on the value
+            node.update();
         }
-        inserted
+        old
     } else {
         *tree = Some(Box::new(AVLNode {
+            summary: S::summarize(&key, &value, None, None),
+            key,
             value,
             height: 1,
+            size: 1,
             left: None,
             right: None,
         }));
-        true
+        None
     }
 }
 
 /// Recursive helper function for `AVLTree` deletion.
-fn remove<T: Ord>(tree: &mut Option<Box<AVLNode<T>>>, value: &T) -> bool {
-    if let Some(node) = tree {
-        let removed = match value.cmp(&node.value) {
-            Ordering::Less => remove(&mut node.left, value),
-            Ordering::Greater => remove(&mut node.right, value),
-            Ordering::Equal => {
-                *tree = match (node.left.take(), node.right.take()) {
-                    (None, None) => None,
-                    (Some(b), None) | (None, Some(b)) => Some(b),
-                    (Some(left), Some(right)) => Some(merge(left, right)),
-                };
-                return true;
-            }
-        };
-        if removed {
-            node.rebalance();
+pub(super) fn remove<K: Ord, V, S: Summary<K, V>>(
+    tree: &mut Option<Box<AVLNode<K, V, S>>>,
+    key: &K,
+) -> Option<V> {
+    let node = tree.as_mut()?;
+    let removed = match key.cmp(&node.key) {
+        Ordering::Less => remove(&mut node.left, key),
+        Ordering::Greater => remove(&mut node.right, key),
+        Ordering::Equal => {
+            let mut node = tree.take().unwrap();
+            *tree = match (node.left.take(), node.right.take()) {
+                (None, None) => None,
+                (Some(b), None) | (None, Some(b)) => Some(b),
+                (Some(left), Some(right)) => Some(merge(left, right)),
+            };
+            return Some(node.value);
         }
-        removed
-    } else {
-        false
+    };
+    if removed.is_some() {
+        node.rebalance();
     }
+    removed
 }
 
 /// Merges two trees and returns the root of the merged tree.
-fn merge<T: Ord>(left: Box<AVLNode<T>>, right: Box<AVLNode<T>>) -> Box<AVLNode<T>> {
+fn merge<K: Ord, V, S: Summary<K, V>>(
+    left: Box<AVLNode<K, V, S>>,
+    right: Box<AVLNode<K, V, S>>,
+) -> Box<AVLNode<K, V, S>> {
     let mut op_right = Some(right);
     // Guaranteed not to panic since right has at least one node
-    let mut root = take_min(&mut op_right).unwrap();
+    let mut root = take_extreme(&mut op_right, Side::Left).unwrap();
     root.left = Some(left);
     root.right = op_right;
     root.rebalance();
     root
 }
 
-/// Removes the smallest node from the tree, if one exists.
-fn take_min<T: Ord>(tree: &mut Option<Box<AVLNode<T>>>) -> Option<Box<AVLNode<T>>> {
-    if let Some(mut node) = tree.take() {
-        // Recurse along the left side
-        if let Some(small) = take_min(&mut node.left) {
-            // Took the smallest from below; update this node and put it back in the tree
-            node.rebalance();
-            *tree = Some(node);
-            Some(small)
-        } else {
-            // Take this node and replace it with its right child
-            *tree = node.right.take();
-            Some(node)
-        }
+/// Removes the smallest (`Side::Left`) or largest (`Side::Right`) node from the tree, if
+/// one exists.
+fn take_extreme<K: Ord, V, S: Summary<K, V>>(
+    tree: &mut Option<Box<AVLNode<K, V, S>>>,
+    side: Side,
+) -> Option<Box<AVLNode<K, V, S>>> {
+    let mut node = tree.take()?;
+    // Recurse along the given side
+    if let Some(extreme) = take_extreme(node.child_mut(side), side) {
+        // Took the extreme from below; update this node and put it back in the tree
+        node.rebalance();
+        *tree = Some(node);
+        Some(extreme)
     } else {
-        None
+        // Take this node and replace it with its other child
+        *tree = node.child_mut(!side).take();
+        node.update();
+        Some(node)
+    }
+}
+
+impl<K: Ord, V, S> AVLNode<K, V, S> {
+    pub(super) fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub(super) fn value(&self) -> &V {
+        &self.value
+    }
+
+    pub(super) fn summary(&self) -> &S {
+        &self.summary
+    }
+
+    pub(super) fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+
+    pub(super) fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
     }
 }
 
-impl<T: Ord> AVLNode<T> {
+impl<K: Ord, V, S: Summary<K, V>> AVLNode<K, V, S> {
     /// Returns a reference to the left or right child.
-    fn child(&self, side: Side) -> &Option<Box<AVLNode<T>>> {
+    fn child(&self, side: Side) -> &Option<Box<AVLNode<K, V, S>>> {
         match side {
             Side::Left => &self.left,
             Side::Right => &self.right,
@@ -194,7 +443,7 @@ impl<T: Ord> AVLNode<T> {
     }
 
     /// Returns a mutable reference to the left or right child.
-    fn child_mut(&mut self, side: Side) -> &mut Option<Box<AVLNode<T>>> {
+    fn child_mut(&mut self, side: Side) -> &mut Option<Box<AVLNode<K, V, S>>> {
         match side {
             Side::Left => &mut self.left,
             Side::Right => &mut self.right,
@@ -216,26 +465,33 @@ impl<T: Ord> AVLNode<T> {
         }
     }
 
-    /// Recomputes the `height` field.
-    fn update_height(&mut self) {
+    /// Recomputes the `height`, `size` and `summary` fields.
+    fn update(&mut self) {
         self.height = 1 + max(self.height(Side::Left), self.height(Side::Right));
+        self.size = 1 + size(self.left.as_deref()) + size(self.right.as_deref());
+        self.summary = S::summarize(
+            &self.key,
+            &self.value,
+            self.left.as_ref().map(|n| &n.summary),
+            self.right.as_ref().map(|n| &n.summary),
+        );
     }
 
     /// Performs a left or right rotation.
     fn rotate(&mut self, side: Side) {
         let mut subtree = self.child_mut(!side).take().unwrap();
         *self.child_mut(!side) = subtree.child_mut(side).take();
-        self.update_height();
+        self.update();
         // Swap root and child nodes in memory
         mem::swap(self, subtree.as_mut());
         // Set old root (subtree) as child of new root (self)
         *self.child_mut(side) = Some(subtree);
-        self.update_height();
+        self.update();
     }
 
     /// Performs left or right tree rotations to balance this node.
     fn rebalance(&mut self) {
-        self.update_height();
+        self.update();
         let side = match self.balance_factor() {
             -2 => Side::Left,
             2 => Side::Right,
@@ -251,7 +507,7 @@ impl<T: Ord> AVLNode<T> {
     }
 }
 
-impl<T: Ord> Default for AVLTree<T> {
+impl<K: Ord, V> Default for AVLTree<K, V> {
     fn default() -> Self {
         Self::new()
     }
@@ -268,25 +524,147 @@ impl Not for Side {
     }
 }
 
-impl<T: Ord> FromIterator<T> for AVLTree<T> {
-    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+impl<K: Ord, V> FromIterator<(K, V)> for AVLTree<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
         let mut tree = AVLTree::new();
-        for value in iter {
-            tree.insert(value);
-        }
+        tree.extend(iter);
         tree
     }
 }
 
+impl<K: Ord, V> Extend<(K, V)> for AVLTree<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<'a, K: Ord, V> IntoIterator for &'a AVLTree<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A view into a single entry of an `AVLTree`, which may be vacant or occupied.
+///
+/// This enum is created by the `entry` method of `AVLTree`.
+pub enum Entry<'a, K: Ord, V> {
+    Vacant(VacantEntry<'a, K, V>),
+    Occupied(OccupiedEntry<'a, K, V>),
+}
+
+/// An entry of an `AVLTree` whose key is not in the tree.
+pub struct VacantEntry<'a, K: Ord, V> {
+    tree: &'a mut AVLTree<K, V>,
+    key: K,
+    /// The rank the key will have once inserted.
+    index: usize,
+}
+
+/// An entry of an `AVLTree` whose key is in the tree.
+pub struct OccupiedEntry<'a, K: Ord, V> {
+    tree: &'a mut AVLTree<K, V>,
+    key: K,
+    /// The rank of the key, which locates the entry without comparing keys.
+    index: usize,
+}
+
+impl<'a, K: Ord, V> Entry<'a, K, V> {
+    /// Returns the key of this entry.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Vacant(entry) => entry.key(),
+            Entry::Occupied(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant, and returns the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of `default` if the entry is vacant, and returns the value.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default()),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    /// Calls `f` on the value if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Ord, V: Default> Entry<'a, K, V> {
+    /// Inserts the default value if the entry is vacant, and returns the value.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: Ord, V> VacantEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts the value under this entry's key, and returns a reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.tree.insert(self.key, value);
+        self.tree.select_mut(self.index).unwrap()
+    }
+}
+
+impl<'a, K: Ord, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> &V {
+        self.tree.select(self.index).unwrap().1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.tree.select_mut(self.index).unwrap()
+    }
+
+    /// Converts the entry into a reference to its value, with the lifetime of the tree.
+    pub fn into_mut(self) -> &'a mut V {
+        self.tree.select_mut(self.index).unwrap()
+    }
+
+    /// Replaces the value of the entry, and returns the old value.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the tree, and returns its value.
+    pub fn remove(self) -> V {
+        self.tree.remove(&self.key).unwrap()
+    }
+}
+
 /// An iterator over the nodes of an `AVLTree`.
 ///
 /// This struct is created by the `node_iter` method of `AVLTree`.
-struct NodeIter<'a, T: Ord> {
-    stack: Vec<&'a AVLNode<T>>,
+struct NodeIter<'a, K: Ord, V> {
+    stack: Vec<&'a AVLNode<K, V>>,
 }
 
-impl<'a, T: Ord> Iterator for NodeIter<'a, T> {
-    type Item = &'a AVLNode<T>;
+impl<'a, K: Ord, V> Iterator for NodeIter<'a, K, V> {
+    type Item = &'a AVLNode<K, V>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(node) = self.stack.pop() {
@@ -303,78 +681,208 @@ impl<'a, T: Ord> Iterator for NodeIter<'a, T> {
     }
 }
 
-/// An iterator over the items of an `AVLTree`.
+/// An iterator over the entries of an `AVLTree`.
 ///
-/// This struct is created by the `iter` method of `AVLTree`.
-pub struct Iter<'a, T: Ord> {
-    node_iter: NodeIter<'a, T>,
+/// This struct is created by the `iter` and `range` methods of `AVLTree`.
+pub struct Iter<'a, K: Ord, V> {
+    node_iter: NodeIter<'a, K, V>,
+    remaining: usize,
 }
 
-impl<'a, T: Ord> Iterator for Iter<'a, T> {
-    type Item = &'a T;
+impl<'a, K: Ord, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
 
-    fn next(&mut self) -> Option<&'a T> {
-        match self.node_iter.next() {
-            Some(node) => Some(&node.value),
-            None => None,
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
         }
+        self.remaining -= 1;
+        self.node_iter.next().map(|node| (&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
     }
 }
 
+impl<K: Ord, V> ExactSizeIterator for Iter<'_, K, V> {}
+
 #[cfg(test)]
 mod tests {
-    use super::AVLTree;
+    use super::{AVLNode, AVLTree, Entry, Summary};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    /// Returns `true` if all nodes in the tree are balanced and have the right size.
+    fn is_balanced<K: Ord, V>(tree: &AVLTree<K, V>) -> bool {
+        tree.node_iter().all(|n| {
+            (-1..=1).contains(&n.balance_factor())
+                && n.size == 1 + super::size(n.left.as_deref()) + super::size(n.right.as_deref())
+        })
+    }
 
-    /// Returns `true` if all nodes in the tree are balanced.
-    fn is_balanced<T: Ord>(tree: &AVLTree<T>) -> bool {
-        tree.node_iter()
-            .all(|n| (-1..=1).contains(&n.balance_factor()))
+    fn squares(keys: impl Iterator<Item = i32>) -> AVLTree<i32, i32> {
+        keys.map(|x| (x, x * x)).collect()
     }
 
     #[test]
     fn len() {
-        let tree: AVLTree<_> = (1..4).collect();
+        let tree = squares(1..4);
         assert_eq!(tree.len(), 3);
     }
 
     #[test]
     fn contains() {
-        let tree: AVLTree<_> = (1..4).collect();
-        assert!(tree.contains(&1));
-        assert!(!tree.contains(&4));
+        let tree = squares(1..4);
+        assert!(tree.contains_key(&1));
+        assert!(!tree.contains_key(&4));
     }
 
     #[test]
     fn insert() {
         let mut tree = AVLTree::new();
         // First insert succeeds
-        assert!(tree.insert(1));
-        // Second insert fails
-        assert!(!tree.insert(1));
+        assert_eq!(tree.insert(1, 'a'), None);
+        // Second insert replaces the value
+        assert_eq!(tree.insert(1, 'b'), Some('a'));
+        assert_eq!(tree.get(&1), Some(&'b'));
+        assert_eq!(tree.len(), 1);
     }
 
     #[test]
     fn remove() {
-        let mut tree: AVLTree<_> = (1..8).collect();
+        let mut tree = squares(1..8);
         // First remove succeeds
-        assert!(tree.remove(&4));
+        assert_eq!(tree.remove(&4), Some(16));
         // Second remove fails
-        assert!(!tree.remove(&4));
+        assert_eq!(tree.remove(&4), None);
     }
 
     #[test]
     fn sorted() {
-        let tree: AVLTree<_> = (1..8).rev().collect();
-        assert!((1..8).eq(tree.iter().copied()));
+        let tree = squares((1..8).rev());
+        assert!((1..8).eq(tree.keys().copied()));
+        assert!((1..8).map(|x| x * x).eq(tree.values().copied()));
     }
 
     #[test]
     fn balanced() {
-        let mut tree: AVLTree<_> = (1..8).collect();
+        let mut tree = squares(1..8);
         assert!(is_balanced(&tree));
         for x in 1..8 {
             tree.remove(&x);
             assert!(is_balanced(&tree));
         }
     }
+
+    #[test]
+    fn get_mut_and_entry() {
+        let mut counts = AVLTree::new();
+        for word in "the cat and the dog and the bird".split(' ') {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(counts.get(&"the"), Some(&3));
+        assert_eq!(counts.get(&"and"), Some(&2));
+        if let Some(count) = counts.get_mut(&"cat") {
+            *count = 10;
+        }
+        counts.entry("cat").and_modify(|c| *c += 1).or_default();
+        counts.entry("emu").and_modify(|c| *c += 1).or_default();
+        assert_eq!(counts.get(&"cat"), Some(&11));
+        assert_eq!(counts.get(&"emu"), Some(&0));
+        match counts.entry("dog") {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), 1),
+            Entry::Vacant(_) => unreachable!(),
+        }
+        assert!(!counts.contains_key(&"dog"));
+        assert!(is_balanced(&counts));
+    }
+
+    #[test]
+    fn ends_and_neighbours() {
+        let mut tree = squares((0..20).map(|x| x * 5));
+        assert_eq!(tree.first(), Some((&0, &0)));
+        assert_eq!(tree.last(), Some((&95, &9025)));
+        assert_eq!(tree.floor(&42), Some((&40, &1600)));
+        assert_eq!(tree.floor(&45), Some((&45, &2025)));
+        assert_eq!(tree.floor(&-1), None);
+        assert_eq!(tree.ceiling(&42), Some((&45, &2025)));
+        assert_eq!(tree.ceiling(&96), None);
+        assert_eq!(tree.pop_first(), Some((0, 0)));
+        assert_eq!(tree.pop_last(), Some((95, 9025)));
+        assert_eq!(tree.len(), 18);
+        assert!(is_balanced(&tree));
+        let mut empty = AVLTree::<i32, ()>::new();
+        assert_eq!(empty.first(), None);
+        assert_eq!(empty.last(), None);
+        assert_eq!(empty.pop_first(), None);
+    }
+
+    #[test]
+    fn order_statistics() {
+        let tree = squares((0..50).map(|x| x * 2));
+        for i in 0..50 {
+            assert_eq!(tree.select(i as usize), Some((&(2 * i), &(4 * i * i))));
+            assert_eq!(tree.rank(&(2 * i)), i as usize);
+            assert_eq!(tree.rank(&(2 * i + 1)), i as usize + 1);
+        }
+        assert_eq!(tree.select(50), None);
+    }
+
+    #[test]
+    fn ranges() {
+        let tree = squares(0..10);
+        let keys = |it: super::Iter<'_, i32, i32>| it.map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys(tree.range(3..6)), vec![3, 4, 5]);
+        assert_eq!(keys(tree.range(3..=6)), vec![3, 4, 5, 6]);
+        assert_eq!(keys(tree.range(..2)), vec![0, 1]);
+        assert_eq!(keys(tree.range(8..)), vec![8, 9]);
+        assert_eq!(keys(tree.range(-5..100)), (0..10).collect::<Vec<_>>());
+        assert_eq!(keys(tree.range(6..3)), vec![]);
+        assert_eq!(tree.range(2..7).len(), 5);
+    }
+
+    /// The largest value of a subtree.
+    struct MaxValue(u32);
+
+    impl Summary<u32, u32> for MaxValue {
+        fn summarize(_: &u32, value: &u32, left: Option<&Self>, right: Option<&Self>) -> Self {
+            let children = left.into_iter().chain(right).map(|s| s.0);
+            MaxValue(children.fold(*value, u32::max))
+        }
+    }
+
+    #[test]
+    fn replacing_a_value_updates_the_summaries() {
+        let mut root: Option<Box<AVLNode<u32, u32, MaxValue>>> = None;
+        for key in 0..15 {
+            super::insert(&mut root, key, key);
+        }
+        assert_eq!(super::insert(&mut root, 3, 100), Some(3));
+        assert_eq!(root.as_ref().unwrap().summary().0, 100);
+        assert_eq!(super::insert(&mut root, 3, 0), Some(100));
+        assert_eq!(root.as_ref().unwrap().summary().0, 14);
+        super::remove(&mut root, &14);
+        assert_eq!(root.as_ref().unwrap().summary().0, 13);
+    }
+
+    #[test]
+    fn matches_btree_map() {
+        let mut tree = AVLTree::new();
+        let mut map = BTreeMap::new();
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..2000 {
+            let key = rng.gen_range(0..200);
+            if rng.gen_ratio(1, 3) {
+                assert_eq!(tree.remove(&key), map.remove(&key));
+            } else {
+                let value: u32 = rng.gen();
+                assert_eq!(tree.insert(key, value), map.insert(key, value));
+            }
+            assert_eq!(tree.len(), map.len());
+        }
+        assert!(tree.iter().eq(map.iter()));
+        assert!(tree.range(50..150).eq(map.range(50..150)));
+        assert!(is_balanced(&tree));
+    }
 }
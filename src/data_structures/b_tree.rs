@@ -1,169 +1,688 @@
-use std::convert::TryFrom;
-use std::fmt::Debug;
+use std::fmt;
+use std::iter::FromIterator;
 use std::mem;
+use std::ops::{Bound, RangeBounds};
 
-struct Node<T> {
-    keys: Vec<T>,
-    children: Vec<Node<T>>,
-}
+/// The minimum degree used by `BTree::default`, the same as the standard library's B-tree.
+const DEFAULT_MIN_DEGREE: usize = 6;
 
-pub struct BTree<T> {
-    root: Node<T>,
-    props: BTreeProps,
+struct Node<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+    children: Vec<Node<K, V>>,
 }
 
-// Why to need a different Struct for props...
-// Check - http://smallcultfollowing.com/babysteps/blog/2018/11/01/after-nll-interprocedural-conflicts/#fnref:improvement
-struct BTreeProps {
-    degree: usize,
-    max_keys: usize,
-    mid_key_index: usize,
+/// An ordered map stored in a B-tree.
+///
+/// With minimum degree `t`, every node but the root holds between `t - 1` and `2t - 1` keys, and every inner
+/// node with `k` keys has `k + 1` children, so all leaves are at the same depth and the height is
+/// O(log_t n). Nodes keep their keys in sorted vectors, which makes a search a binary search per level.
+///
+/// Insertion splits full nodes on the way down, and deletion makes sure every node it descends into has at
+/// least `t` keys, by borrowing a key from a sibling or merging with it, so neither has to walk back up.
+pub struct BTree<K, V> {
+    root: Node<K, V>,
+    len: usize,
+    min_degree: usize,
 }
 
-impl<T> Node<T>
-where
-    T: Ord,
-{
-    fn new(degree: usize, _keys: Option<Vec<T>>, _children: Option<Vec<Node<T>>>) -> Self {
+impl<K: Ord, V> Node<K, V> {
+    fn new() -> Self {
         Node {
-            keys: match _keys {
-                Some(_keys) => _keys,
-                None => Vec::with_capacity(degree - 1),
-            },
-            children: match _children {
-                Some(_children) => _children,
-                None => Vec::with_capacity(degree),
-            },
+            keys: Vec::new(),
+            values: Vec::new(),
+            children: Vec::new(),
         }
     }
 
     fn is_leaf(&self) -> bool {
         self.children.is_empty()
     }
-}
 
-impl BTreeProps {
-    fn new(degree: usize) -> Self {
-        BTreeProps {
-            degree,
-            max_keys: degree - 1,
-            mid_key_index: (degree - 1) / 2,
-        }
+    /// Splits the full child at `index` in two halves of `t - 1` keys, and moves its middle key up here.
+    fn split_child(&mut self, index: usize, t: usize) {
+        let child = &mut self.children[index];
+        let right = Node {
+            keys: child.keys.split_off(t),
+            values: child.values.split_off(t),
+            children: if child.is_leaf() {
+                Vec::new()
+            } else {
+                child.children.split_off(t)
+            },
+        };
+        let key = child.keys.pop().unwrap();
+        let value = child.values.pop().unwrap();
+        self.keys.insert(index, key);
+        self.values.insert(index, value);
+        self.children.insert(index + 1, right);
     }
 
-    fn is_maxed_out<T: Ord + Copy>(&self, node: &Node<T>) -> bool {
-        node.keys.len() == self.max_keys
+    /// Inserts a key that is not in the tree, into a node that is not full, and returns its value.
+    fn insert_non_full(&mut self, key: K, value: V, t: usize) -> &mut V {
+        let mut index = self.keys.partition_point(|k| *k < key);
+        if self.is_leaf() {
+            self.keys.insert(index, key);
+            self.values.insert(index, value);
+            return &mut self.values[index];
+        }
+        if self.children[index].keys.len() == 2 * t - 1 {
+            self.split_child(index, t);
+            if self.keys[index] < key {
+                index += 1;
+            }
+        }
+        self.children[index].insert_non_full(key, value, t)
     }
 
-    // Split Child expects the Child Node to be full
-    /// Move the middle_key to parent node and split the child_node's
-    /// keys/chilren_nodes into half
-    fn split_child<T: Ord + Copy + Default>(&self, parent: &mut Node<T>, child_index: usize) {
-        let child = &mut parent.children[child_index];
-        let middle_key = child.keys[self.mid_key_index];
-        let right_keys = match child.keys.split_off(self.mid_key_index).split_first() {
-            Some((_first, _others)) => {
-                // We don't need _first, as it will move to parent node.
-                _others.to_vec()
+    fn remove(&mut self, key: &K, t: usize) -> Option<(K, V)> {
+        match self.keys.binary_search(key) {
+            Ok(index) if self.is_leaf() => {
+                Some((self.keys.remove(index), self.values.remove(index)))
             }
-            None => Vec::with_capacity(self.max_keys),
-        };
-        let right_children = if !child.is_leaf() {
-            Some(child.children.split_off(self.mid_key_index + 1))
-        } else {
-            None
-        };
-        let new_child_node: Node<T> = Node::new(self.degree, Some(right_keys), right_children);
+            Ok(index) => {
+                // replace the key with its predecessor or successor from a child that can spare a key
+                if self.children[index].keys.len() >= t {
+                    let (k, v) = self.children[index].pop_last(t);
+                    Some(self.replace(index, k, v))
+                } else if self.children[index + 1].keys.len() >= t {
+                    let (k, v) = self.children[index + 1].pop_first(t);
+                    Some(self.replace(index, k, v))
+                } else {
+                    self.merge(index);
+                    self.children[index].remove(key, t)
+                }
+            }
+            Err(_) if self.is_leaf() => None,
+            Err(index) => {
+                let index = self.fill(index, t);
+                self.children[index].remove(key, t)
+            }
+        }
+    }
 
-        parent.keys.insert(child_index, middle_key);
-        parent.children.insert(child_index + 1, new_child_node);
+    fn replace(&mut self, index: usize, key: K, value: V) -> (K, V) {
+        (
+            mem::replace(&mut self.keys[index], key),
+            mem::replace(&mut self.values[index], value),
+        )
     }
 
-    fn insert_non_full<T: Ord + Copy + Default>(&mut self, node: &mut Node<T>, key: T) {
-        let mut index: isize = isize::try_from(node.keys.len()).ok().unwrap() - 1;
-        while index >= 0 && node.keys[index as usize] >= key {
-            index -= 1;
+    /// Removes the smallest entry of a non-empty subtree.
+    fn pop_first(&mut self, t: usize) -> (K, V) {
+        if self.is_leaf() {
+            return (self.keys.remove(0), self.values.remove(0));
         }
+        let index = self.fill(0, t);
+        self.children[index].pop_first(t)
+    }
 
-        let mut u_index: usize = usize::try_from(index + 1).ok().unwrap();
-        if node.is_leaf() {
-            // Just insert it, as we know this method will be called only when node is not full
-            node.keys.insert(u_index, key);
-        } else {
-            if self.is_maxed_out(&node.children[u_index]) {
-                self.split_child(node, u_index);
-                if node.keys[u_index] < key {
-                    u_index += 1;
-                }
-            }
-
-            self.insert_non_full(&mut node.children[u_index], key);
+    /// Removes the largest entry of a non-empty subtree.
+    fn pop_last(&mut self, t: usize) -> (K, V) {
+        if self.is_leaf() {
+            return (self.keys.pop().unwrap(), self.values.pop().unwrap());
         }
+        let index = self.fill(self.keys.len(), t);
+        self.children[index].pop_last(t)
     }
-    fn traverse_node<T: Ord + Debug>(node: &Node<T>, depth: usize) {
-        if node.is_leaf() {
-            print!(" {0:{<1$}{2:?}{0:}<1$} ", "", depth, node.keys);
+
+    /// Makes sure the child at `index` has at least `t` keys before descending into it, and returns where the
+    /// child is afterwards, which moves one to the left if it was merged into its left sibling.
+    fn fill(&mut self, index: usize, t: usize) -> usize {
+        if self.children[index].keys.len() >= t {
+            return index;
+        }
+        if index > 0 && self.children[index - 1].keys.len() >= t {
+            // rotate a key from the left sibling through this node
+            let (left, right) = self.children.split_at_mut(index);
+            let (left, child) = (&mut left[index - 1], &mut right[0]);
+            let key = mem::replace(&mut self.keys[index - 1], left.keys.pop().unwrap());
+            let value = mem::replace(&mut self.values[index - 1], left.values.pop().unwrap());
+            child.keys.insert(0, key);
+            child.values.insert(0, value);
+            if let Some(grandchild) = left.children.pop() {
+                child.children.insert(0, grandchild);
+            }
+            index
+        } else if index + 1 < self.children.len() && self.children[index + 1].keys.len() >= t {
+            // rotate a key from the right sibling through this node
+            let (left, right) = self.children.split_at_mut(index + 1);
+            let (child, right) = (&mut left[index], &mut right[0]);
+            let key = mem::replace(&mut self.keys[index], right.keys.remove(0));
+            let value = mem::replace(&mut self.values[index], right.values.remove(0));
+            child.keys.push(key);
+            child.values.push(value);
+            if !right.is_leaf() {
+                child.children.push(right.children.remove(0));
+            }
+            index
+        } else if index + 1 < self.children.len() {
+            self.merge(index);
+            index
         } else {
-            let _depth = depth + 1;
-            for (index, key) in node.keys.iter().enumerate() {
-                Self::traverse_node(&node.children[index], _depth);
-                // Check https://doc.rust-lang.org/std/fmt/index.html
-                // And https://stackoverflow.com/a/35280799/2849127
-                print!("{0:{<1$}{2:?}{0:}<1$}", "", depth, key);
+            self.merge(index - 1);
+            index - 1
+        }
+    }
+
+    /// Merges the children at `index` and `index + 1`, together with the key between them.
+    fn merge(&mut self, index: usize) {
+        let right = self.children.remove(index + 1);
+        let child = &mut self.children[index];
+        child.keys.push(self.keys.remove(index));
+        child.values.push(self.values.remove(index));
+        child.keys.extend(right.keys);
+        child.values.extend(right.values);
+        child.children.extend(right.children);
+    }
+
+    /// Checks the subtree, and returns its number of entries and the depth of its leaves.
+    fn validate(
+        &self,
+        t: usize,
+        is_root: bool,
+        lower: Option<&K>,
+        upper: Option<&K>,
+    ) -> Result<(usize, usize), BTreeViolation> {
+        if self.keys.len() > 2 * t - 1 {
+            return Err(BTreeViolation::TooManyKeys);
+        }
+        if !is_root && self.keys.len() < t - 1 {
+            return Err(BTreeViolation::TooFewKeys);
+        }
+        if self.values.len() != self.keys.len()
+            || (!self.is_leaf() && self.children.len() != self.keys.len() + 1)
+        {
+            return Err(BTreeViolation::WrongChildCount);
+        }
+        let in_order = self.keys.windows(2).all(|pair| pair[0] < pair[1])
+            && self
+                .keys
+                .first()
+                .is_none_or(|first| lower.is_none_or(|l| l < first))
+            && self
+                .keys
+                .last()
+                .is_none_or(|last| upper.is_none_or(|u| last < u));
+        if !in_order {
+            return Err(BTreeViolation::KeysOutOfOrder);
+        }
+        let mut count = self.keys.len();
+        let mut depth = None;
+        for (i, child) in self.children.iter().enumerate() {
+            let lower = if i == 0 {
+                lower
+            } else {
+                Some(&self.keys[i - 1])
+            };
+            let upper = self.keys.get(i).or(upper);
+            let (child_count, child_depth) = child.validate(t, false, lower, upper)?;
+            if depth.is_some_and(|depth| depth != child_depth) {
+                return Err(BTreeViolation::UnevenLeaves);
             }
-            Self::traverse_node(node.children.last().unwrap(), _depth);
+            depth = Some(child_depth);
+            count += child_count;
         }
+        Ok((count, depth.map_or(0, |depth| depth + 1)))
     }
 }
 
-impl<T> BTree<T>
-where
-    T: Ord + Copy + Debug + Default,
-{
+impl<K: Ord, V> BTree<K, V> {
+    /// Creates an empty tree whose nodes have at most `2 * branch_factor` children, and so hold up to
+    /// `2 * branch_factor - 1` keys: this is `with_min_degree(branch_factor)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `branch_factor` is less than 2.
     pub fn new(branch_factor: usize) -> Self {
-        let degree = 2 * branch_factor;
+        Self::with_min_degree(branch_factor)
+    }
+
+    /// Creates an empty tree whose nodes hold between `min_degree - 1` and `2 * min_degree - 1` keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min_degree` is less than 2.
+    pub fn with_min_degree(min_degree: usize) -> Self {
+        assert!(min_degree >= 2, "the minimum degree must be at least 2");
         BTree {
-            root: Node::new(degree, None, None),
-            props: BTreeProps::new(degree),
+            root: Node::new(),
+            len: 0,
+            min_degree,
         }
     }
 
-    pub fn insert(&mut self, key: T) {
-        if self.props.is_maxed_out(&self.root) {
-            // Create an empty root and split the old root...
-            let mut new_root = Node::new(self.props.degree, None, None);
-            mem::swap(&mut new_root, &mut self.root);
-            self.root.children.insert(0, new_root);
-            self.props.split_child(&mut self.root, 0);
+    /// Builds a tree from entries sorted by strictly increasing keys in O(n), filling the nodes evenly level by
+    /// level instead of inserting the entries one by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min_degree` is less than 2, or if the keys are not strictly increasing.
+    pub fn from_sorted<I: IntoIterator<Item = (K, V)>>(min_degree: usize, entries: I) -> Self {
+        let mut tree = BTree::with_min_degree(min_degree);
+        let entries: Vec<(K, V)> = entries.into_iter().collect();
+        assert!(
+            entries.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "keys must be strictly increasing"
+        );
+        tree.len = entries.len();
+        let (mut nodes, mut separators) = build_level(entries, None, min_degree);
+        while nodes.len() > 1 {
+            (nodes, separators) = build_level(separators, Some(nodes), min_degree);
         }
-        self.props.insert_non_full(&mut self.root, key);
+        debug_assert!(separators.is_empty());
+        tree.root = nodes.pop().unwrap();
+        tree
+    }
+
+    pub fn min_degree(&self) -> usize {
+        self.min_degree
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
     }
 
-    pub fn traverse(&self) {
-        BTreeProps::traverse_node(&self.root, 0);
-        println!();
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut node = &self.root;
+        loop {
+            match node.keys.binary_search(key) {
+                Ok(index) => return Some(&node.values[index]),
+                Err(_) if node.is_leaf() => return None,
+                Err(index) => node = &node.children[index],
+            }
+        }
     }
 
-    pub fn search(&self, key: T) -> bool {
-        let mut current_node = &self.root;
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let mut node = &mut self.root;
         loop {
-            match current_node.keys.binary_search(&key) {
-                Ok(_) => return true,
-                Err(index) => {
-                    if current_node.is_leaf() {
-                        return false;
-                    } else {
-                        current_node = &current_node.children[index];
-                    }
+            match node.keys.binary_search(key) {
+                Ok(index) => return Some(&mut node.values[index]),
+                Err(_) if node.is_leaf() => return None,
+                Err(index) => node = &mut node.children[index],
+            }
+        }
+    }
+
+    /// Inserts a key-value pair, and returns the previous value of the key.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(old) = self.get_mut(&key) {
+            return Some(mem::replace(old, value));
+        }
+        self.insert_new(key, value);
+        None
+    }
+
+    /// Inserts a key that is not in the tree, and returns its value.
+    fn insert_new(&mut self, key: K, value: V) -> &mut V {
+        let t = self.min_degree;
+        if self.root.keys.len() == 2 * t - 1 {
+            // the tree grows at the root: split the old root under a new, empty one
+            let old_root = mem::replace(&mut self.root, Node::new());
+            self.root.children.push(old_root);
+            self.root.split_child(0, t);
+        }
+        self.len += 1;
+        self.root.insert_non_full(key, value, t)
+    }
+
+    /// Removes a key, and returns its value.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (_, value) = self.root.remove(key, self.min_degree)?;
+        self.len -= 1;
+        self.shrink_root();
+        Some(value)
+    }
+
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let entry = self.root.pop_first(self.min_degree);
+        self.len -= 1;
+        self.shrink_root();
+        Some(entry)
+    }
+
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let entry = self.root.pop_last(self.min_degree);
+        self.len -= 1;
+        self.shrink_root();
+        Some(entry)
+    }
+
+    /// The tree shrinks at the root: a root left without keys by a merge is replaced by its only child.
+    fn shrink_root(&mut self) {
+        if self.root.keys.is_empty() {
+            if let Some(child) = self.root.children.pop() {
+                self.root = child;
+            }
+        }
+    }
+
+    /// Gets the entry for a key, to inspect or update it in place.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        if self.contains_key(&key) {
+            Entry::Occupied(OccupiedEntry { tree: self, key })
+        } else {
+            Entry::Vacant(VacantEntry { tree: self, key })
+        }
+    }
+
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.iter().next()
+    }
+
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = &self.root;
+        while let Some(child) = node.children.last() {
+            node = child;
+        }
+        Some((node.keys.last()?, node.values.last()?))
+    }
+
+    /// Returns an iterator over the entries in ascending order of keys.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            stack: seek(&self.root, |_| true),
+            end: None,
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Returns an iterator over the entries whose keys lie in `range`, in ascending order of keys.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, K, V> {
+        let stack = seek(&self.root, |key| match range.start_bound() {
+            Bound::Included(start) => key >= start,
+            Bound::Excluded(start) => key > start,
+            Bound::Unbounded => true,
+        });
+        let past_end = |key: &K| match range.end_bound() {
+            Bound::Included(end) => key > end,
+            Bound::Excluded(end) => key >= end,
+            Bound::Unbounded => false,
+        };
+        let mut iter = Iter { stack, end: None };
+        match iter.peek() {
+            // the range is empty
+            Some((node, index)) if past_end(&node.keys[index]) => iter.stack.clear(),
+            Some(_) => {
+                // stop at the position of the first key past the end
+                iter.end = Iter {
+                    stack: seek(&self.root, past_end),
+                    end: None,
                 }
+                .peek();
             }
+            None => {}
+        }
+        iter
+    }
+
+    /// Checks the B-tree invariants: the number of keys in every node, the number of children of inner nodes,
+    /// the order of the keys, that all leaves are at the same depth, and the length.
+    pub fn validate(&self) -> Result<(), BTreeViolation> {
+        let (count, _) = self.root.validate(self.min_degree, true, None, None)?;
+        if count != self.len {
+            return Err(BTreeViolation::WrongLength);
         }
+        Ok(())
+    }
+}
+
+/// The nodes of a level of a tree under construction, and the separators between them.
+type Level<K, V> = (Vec<Node<K, V>>, Vec<(K, V)>);
+
+/// Turns a level of `items`, with `children.len() == items.len() + 1` when there are children, into evenly
+/// filled nodes and the separators between them, which are the items of the level above.
+fn build_level<K, V>(
+    items: Vec<(K, V)>,
+    children: Option<Vec<Node<K, V>>>,
+    t: usize,
+) -> Level<K, V> {
+    // every node but the last takes up to 2t - 1 keys and a separator
+    let count = (items.len() + 1).div_ceil(2 * t);
+    let keys = items.len() + 1 - count;
+    let mut items = items.into_iter();
+    let mut children = children.map(Vec::into_iter);
+    let mut nodes = Vec::with_capacity(count);
+    let mut separators = Vec::with_capacity(count - 1);
+    for i in 0..count {
+        // spread the remainder over the first nodes
+        let size = keys / count + usize::from(i < keys % count);
+        let (node_keys, node_values) = items.by_ref().take(size).unzip();
+        nodes.push(Node {
+            keys: node_keys,
+            values: node_values,
+            children: match &mut children {
+                Some(children) => children.by_ref().take(size + 1).collect(),
+                None => Vec::new(),
+            },
+        });
+        if i + 1 < count {
+            separators.push(items.next().unwrap());
+        }
+    }
+    (nodes, separators)
+}
+
+/// Returns the path from `root` to the first key satisfying `pred`, which must hold for a suffix of the keys.
+fn seek<K, V, F: Fn(&K) -> bool>(root: &Node<K, V>, pred: F) -> Vec<(&Node<K, V>, usize)> {
+    let mut stack = Vec::new();
+    let mut node = root;
+    loop {
+        let index = node.keys.partition_point(|key| !pred(key));
+        stack.push((node, index));
+        match node.children.get(index) {
+            Some(child) => node = child,
+            None => return stack,
+        }
+    }
+}
+
+/// A broken invariant found by `BTree::validate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BTreeViolation {
+    TooManyKeys,
+    TooFewKeys,
+    WrongChildCount,
+    KeysOutOfOrder,
+    UnevenLeaves,
+    WrongLength,
+}
+
+impl<K: Ord, V> Default for BTree<K, V> {
+    fn default() -> Self {
+        BTree::with_min_degree(DEFAULT_MIN_DEGREE)
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for BTree<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut tree = BTree::default();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for BTree<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<'a, K: Ord, V> IntoIterator for &'a BTree<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: Ord + fmt::Debug, V: fmt::Debug> fmt::Debug for BTree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// An iterator over the entries of a `BTree` in ascending order of keys.
+pub struct Iter<'a, K, V> {
+    /// The path to the next entry: a node and the index of its next key, for every level.
+    stack: Vec<(&'a Node<K, V>, usize)>,
+    /// The position where the iteration stops, if before the end of the tree.
+    end: Option<(&'a Node<K, V>, usize)>,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    /// Drops the finished nodes from the path, and returns the position of the next entry.
+    fn peek(&mut self) -> Option<(&'a Node<K, V>, usize)> {
+        while let Some(&(node, index)) = self.stack.last() {
+            if index < node.keys.len() {
+                return Some((node, index));
+            }
+            self.stack.pop();
+        }
+        None
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, index) = self.peek()?;
+        if self
+            .end
+            .is_some_and(|(end, end_index)| std::ptr::eq(node, end) && index == end_index)
+        {
+            self.stack.clear();
+            return None;
+        }
+        self.stack.last_mut().unwrap().1 += 1;
+        // continue with the leftmost path of the subtree after the key
+        let mut child = node.children.get(index + 1);
+        while let Some(next) = child {
+            self.stack.push((next, 0));
+            child = next.children.first();
+        }
+        Some((&node.keys[index], &node.values[index]))
+    }
+}
+
+/// A view into a single entry of a `BTree`, which may be vacant or occupied.
+pub enum Entry<'a, K: Ord, V> {
+    Vacant(VacantEntry<'a, K, V>),
+    Occupied(OccupiedEntry<'a, K, V>),
+}
+
+pub struct VacantEntry<'a, K: Ord, V> {
+    tree: &'a mut BTree<K, V>,
+    key: K,
+}
+
+/// An entry known to be in the tree, which looks its key up again for every access.
+pub struct OccupiedEntry<'a, K: Ord, V> {
+    tree: &'a mut BTree<K, V>,
+    key: K,
+}
+
+impl<'a, K: Ord, V> Entry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Vacant(entry) => &entry.key,
+            Entry::Occupied(entry) => &entry.key,
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant, and returns the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of `default` if the entry is vacant, and returns the value.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default()),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    /// Calls `f` on the value if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Ord, V: Default> Entry<'a, K, V> {
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: Ord, V> VacantEntry<'a, K, V> {
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.tree.insert_new(self.key, value)
+    }
+}
+
+impl<'a, K: Ord, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> &V {
+        self.tree.get(&self.key).unwrap()
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.tree.get_mut(&self.key).unwrap()
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        self.tree.get_mut(&self.key).unwrap()
+    }
+
+    /// Replaces the value, and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        self.tree.remove(&self.key).unwrap()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::BTree;
+    use super::{BTree, BTreeViolation, Entry};
+    use quickcheck::TestResult;
+    use quickcheck_macros::quickcheck;
+    use std::collections::BTreeMap;
 
     macro_rules! test_search {
         ($($name:ident: $number_of_children:expr,)*) => {
@@ -171,28 +690,28 @@ mod test {
             #[test]
             fn $name() {
                 let mut tree = BTree::new($number_of_children);
-                tree.insert(10);
-                tree.insert(20);
-                tree.insert(30);
-                tree.insert(5);
-                tree.insert(6);
-                tree.insert(7);
-                tree.insert(11);
-                tree.insert(12);
-                tree.insert(15);
-                assert!(!tree.search(4));
-                assert!(tree.search(5));
-                assert!(tree.search(6));
-                assert!(tree.search(7));
-                assert!(!tree.search(8));
-                assert!(!tree.search(9));
-                assert!(tree.search(10));
-                assert!(tree.search(11));
-                assert!(tree.search(12));
-                assert!(!tree.search(13));
-                assert!(!tree.search(14));
-                assert!(tree.search(15));
-                assert!(!tree.search(16));
+                tree.insert(10, ());
+                tree.insert(20, ());
+                tree.insert(30, ());
+                tree.insert(5, ());
+                tree.insert(6, ());
+                tree.insert(7, ());
+                tree.insert(11, ());
+                tree.insert(12, ());
+                tree.insert(15, ());
+                assert!(!tree.contains_key(&4));
+                assert!(tree.contains_key(&5));
+                assert!(tree.contains_key(&6));
+                assert!(tree.contains_key(&7));
+                assert!(!tree.contains_key(&8));
+                assert!(!tree.contains_key(&9));
+                assert!(tree.contains_key(&10));
+                assert!(tree.contains_key(&11));
+                assert!(tree.contains_key(&12));
+                assert!(!tree.contains_key(&13));
+                assert!(!tree.contains_key(&14));
+                assert!(tree.contains_key(&15));
+                assert!(!tree.contains_key(&16));
             }
         )*
         }
@@ -207,4 +726,108 @@ mod test {
         children_60: 60,
         children_101: 101,
     }
+
+    #[test]
+    fn map_api() {
+        let mut tree = BTree::new(2);
+        for k in 0..50 {
+            assert_eq!(tree.insert(k, k * 10), None);
+        }
+        assert_eq!(tree.insert(7, 0), Some(70));
+        *tree.get_mut(&8).unwrap() += 1;
+        assert_eq!(tree.get(&8), Some(&81));
+        assert_eq!(tree.remove(&9), Some(90));
+        assert_eq!(tree.remove(&9), None);
+        assert_eq!(tree.len(), 49);
+        assert_eq!(tree.first_key_value(), Some((&0, &0)));
+        assert_eq!(tree.last_key_value(), Some((&49, &490)));
+        assert_eq!(tree.pop_first(), Some((0, 0)));
+        assert_eq!(tree.pop_last(), Some((49, 490)));
+        assert_eq!(tree.validate(), Ok(()));
+        let small: BTree<_, _> = [(2, 'b'), (1, 'a')].into_iter().collect();
+        assert_eq!(format!("{small:?}"), "{1: 'a', 2: 'b'}");
+        assert!(BTree::<u8, ()>::default().is_empty());
+        // up to 2 * 3 children per node is a minimum degree of 3
+        assert_eq!(BTree::<u8, ()>::new(3).min_degree(), 3);
+    }
+
+    #[test]
+    fn entries() {
+        let mut counts = BTree::new(2);
+        for word in "a b c a d e f a g b h i".split(' ') {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(counts.get(&"a"), Some(&3));
+        assert_eq!(counts.get(&"b"), Some(&2));
+        *counts.entry("z").or_default() += 5;
+        assert_eq!(counts.get(&"z"), Some(&5));
+        if let Entry::Occupied(entry) = counts.entry("c") {
+            assert_eq!(entry.remove(), 1);
+        }
+        assert!(!counts.contains_key(&"c"));
+        assert_eq!(counts.validate(), Ok(()));
+    }
+
+    #[test]
+    fn ranges() {
+        let tree: BTree<i32, ()> = BTree::from_sorted(2, (0..40).map(|k| (2 * k, ())));
+        let keys = |range: super::Iter<i32, ()>| range.map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys(tree.range(5..11)), [6, 8, 10]);
+        assert_eq!(keys(tree.range(6..=10)), [6, 8, 10]);
+        assert_eq!(keys(tree.range(..3)), [0, 2]);
+        assert_eq!(keys(tree.range(75..)), [76, 78]);
+        assert!(keys(tree.range(7..8)).is_empty());
+        assert!(keys(tree.range(100..)).is_empty());
+        assert_eq!(tree.range(..).count(), 40);
+    }
+
+    #[test]
+    fn bulk_load() {
+        for t in 2..6 {
+            for n in 0..200 {
+                let tree = BTree::from_sorted(t, (0..n).map(|k| (k, k)));
+                assert_eq!(tree.validate(), Ok(()), "t = {t}, n = {n}");
+                assert!(tree.iter().map(|(k, _)| *k).eq(0..n));
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "keys must be strictly increasing")]
+    fn bulk_load_unsorted() {
+        BTree::from_sorted(3, [(2, ()), (1, ())]);
+    }
+
+    #[test]
+    fn validate_detects_violations() {
+        let mut tree = BTree::from_sorted(2, (0..10).map(|k| (k, ())));
+        tree.len = 9;
+        assert_eq!(tree.validate(), Err(BTreeViolation::WrongLength));
+        tree.len = 10;
+        tree.root.children[0].keys.clear();
+        assert_eq!(tree.validate(), Err(BTreeViolation::TooFewKeys));
+    }
+
+    #[quickcheck]
+    fn matches_btree_map(ops: Vec<(u8, u8, u16)>, min_degree: u8) -> TestResult {
+        let mut tree = BTree::with_min_degree(min_degree as usize % 4 + 2);
+        let mut expected = BTreeMap::new();
+        for (op, key, value) in ops {
+            let same = match op % 4 {
+                0 => tree.remove(&key) == expected.remove(&key),
+                1 => tree.pop_first() == expected.pop_first(),
+                _ => tree.insert(key, value) == expected.insert(key, value),
+            };
+            if !same || tree.validate().is_err() {
+                return TestResult::failed();
+            }
+        }
+        let (low, high) = (64, 192);
+        TestResult::from_bool(
+            tree.len() == expected.len()
+                && tree.iter().eq(expected.iter())
+                && tree.range(low..high).eq(expected.range(low..high))
+                && tree.last_key_value() == expected.last_key_value(),
+        )
+    }
 }
@@ -0,0 +1,382 @@
+use super::store::{Entry, List, Store};
+use super::{CacheStats, Clock, EvictionCause, SystemClock};
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::time::Duration;
+
+/// The list of an entry of an `AdaptiveReplacementCache`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    /// T1: the entries used once since they entered the cache.
+    Recent,
+    /// T2: the entries used at least twice.
+    Frequent,
+    /// B1: the keys evicted from `Recent`, without their value.
+    RecentGhost,
+    /// B2: the keys evicted from `Frequent`, without their value.
+    FrequentGhost,
+}
+
+impl Segment {
+    fn index(self) -> usize {
+        match self {
+            Segment::Recent => 0,
+            Segment::Frequent => 1,
+            Segment::RecentGhost => 2,
+            Segment::FrequentGhost => 3,
+        }
+    }
+
+    fn is_ghost(self) -> bool {
+        matches!(self, Segment::RecentGhost | Segment::FrequentGhost)
+    }
+}
+
+/// The adaptive replacement cache of Megiddo and Modha <https://en.wikipedia.org/wiki/Adaptive_replacement_cache>.
+///
+/// The entries used once and the entries used again are in two LRU lists, whose sizes add up to the capacity. The
+/// cache also remembers as many keys evicted from each list: a miss on a key evicted from a list means that list was
+/// too short, so the cache moves its target size for the list of recent entries towards it. A scan of keys used once
+/// only goes through the list of recent entries, and leaves the entries used again in the cache.
+pub struct AdaptiveReplacementCache<K, V, C = SystemClock> {
+    /// The list of an entry is its metadata.
+    store: Store<K, V, Segment, C>,
+    /// The lists by `Segment::index`, from the most to the least recently used.
+    lists: [List; 4],
+    /// The size of the list of recent entries the cache aims for.
+    target: usize,
+    capacity: usize,
+}
+
+impl<K: Hash + Eq + Clone, V> AdaptiveReplacementCache<K, V> {
+    /// Creates a cache of at most `capacity` entries, which must be positive.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_clock(capacity, SystemClock::default())
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, C: Clock> AdaptiveReplacementCache<K, V, C> {
+    /// Creates a cache of at most `capacity` entries, whose entries expire according to `clock`.
+    pub fn with_clock(capacity: usize, clock: C) -> Self {
+        assert!(capacity > 0, "the capacity must be positive");
+        AdaptiveReplacementCache {
+            store: Store::new(clock),
+            lists: Default::default(),
+            target: 0,
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of entries, including the expired entries not dropped yet, but not the evicted keys the
+    /// cache remembers.
+    pub fn len(&self) -> usize {
+        self.list(Segment::Recent).len() + self.list(Segment::Frequent).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of entries used once the cache currently aims to keep.
+    pub fn target(&self) -> usize {
+        self.target
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.store.stats
+    }
+
+    /// Calls `listener` with every entry evicted for room or dropped on expiration, but not with removed entries.
+    pub fn set_eviction_listener(
+        &mut self,
+        listener: impl FnMut(K, V, EvictionCause) + Send + 'static,
+    ) {
+        self.store.set_listener(Box::new(listener));
+    }
+
+    /// Returns the value of a key, without counting it as a use.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.live_value(self.store.find(key)?)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek(key).is_some()
+    }
+
+    /// Returns the value of a key, and moves it to the entries used again.
+    ///
+    /// A key the cache evicted is a miss, as only `put` brings its value back.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = match self.find_live(key) {
+            Some(node) if !self.store.entry(node).meta.is_ghost() => node,
+            _ => {
+                self.store.stats.misses += 1;
+                return None;
+            }
+        };
+        self.store.stats.hits += 1;
+        self.move_to(node, Segment::Frequent);
+        self.store.entry(node).value.as_ref()
+    }
+
+    /// Inserts a key-value pair that never expires, and returns the previous value of the key.
+    ///
+    /// Replacing the value of a key counts as a use of it.
+    pub fn put(&mut self, key: K, value: V) -> Option<V> {
+        self.insert(key, value, None)
+    }
+
+    /// Inserts a key-value pair that expires after `time_to_live`, and returns the previous value of the key.
+    pub fn put_with_ttl(&mut self, key: K, value: V, time_to_live: Duration) -> Option<V> {
+        self.insert(key, value, Some(time_to_live))
+    }
+
+    fn insert(&mut self, key: K, value: V, time_to_live: Option<Duration>) -> Option<V> {
+        let expires_at = time_to_live.map(|time_to_live| self.store.deadline(time_to_live));
+        let Some(node) = self.find_live(&key) else {
+            self.make_room_for_new_key();
+            let node = self.store.insert(Entry {
+                key,
+                value: Some(value),
+                expires_at,
+                meta: Segment::Recent,
+            });
+            self.store
+                .push_front(&mut self.lists[Segment::Recent.index()], node);
+            return None;
+        };
+        let segment = self.store.entry(node).meta;
+        let recent_ghosts = self.list(Segment::RecentGhost).len();
+        let frequent_ghosts = self.list(Segment::FrequentGhost).len();
+        match segment {
+            Segment::Recent | Segment::Frequent => {}
+            Segment::RecentGhost => {
+                let step = (frequent_ghosts / recent_ghosts).max(1);
+                self.target = (self.target + step).min(self.capacity);
+            }
+            Segment::FrequentGhost => {
+                let step = (recent_ghosts / frequent_ghosts).max(1);
+                self.target = self.target.saturating_sub(step);
+            }
+        }
+        if segment.is_ghost() && self.len() >= self.capacity {
+            self.replace(segment == Segment::FrequentGhost);
+        }
+        self.move_to(node, Segment::Frequent);
+        let entry = self.store.entry_mut(node);
+        entry.expires_at = expires_at;
+        entry.value.replace(value)
+    }
+
+    /// Removes a key, and returns its value. The cache also forgets the key if it was evicted.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.find_live(key)?;
+        self.unlink(node);
+        self.store.remove(node).value
+    }
+
+    /// Makes room for a key the cache neither holds nor remembers, keeping at most `capacity` keys used once and
+    /// `2 * capacity` keys in all.
+    fn make_room_for_new_key(&mut self) {
+        let recent = self.list(Segment::Recent).len();
+        let remembered = self.lists.iter().map(List::len).sum::<usize>();
+        if recent + self.list(Segment::RecentGhost).len() >= self.capacity {
+            if recent == self.capacity {
+                let node = self.list(Segment::Recent).back().unwrap();
+                self.drop_entry(node, EvictionCause::Capacity);
+                return;
+            }
+            self.forget_oldest(Segment::RecentGhost);
+        } else if remembered >= 2 * self.capacity {
+            self.forget_oldest(Segment::FrequentGhost);
+        }
+        if self.len() >= self.capacity {
+            self.replace(false);
+        }
+    }
+
+    /// Evicts the value of the least recently used entry of the list that is over its target size, and remembers its
+    /// key.
+    fn replace(&mut self, hit_frequent_ghost: bool) {
+        let recent = self.list(Segment::Recent).len();
+        let from_recent = recent > 0
+            && (recent > self.target
+                || (hit_frequent_ghost && recent == self.target)
+                || self.list(Segment::Frequent).is_empty());
+        let (from, to) = if from_recent {
+            (Segment::Recent, Segment::RecentGhost)
+        } else {
+            (Segment::Frequent, Segment::FrequentGhost)
+        };
+        let node = self.list(from).back().unwrap();
+        let cause = if self.store.is_expired(node) {
+            EvictionCause::Expired
+        } else {
+            EvictionCause::Capacity
+        };
+        self.move_to(node, to);
+        self.store.evict_value(node, cause);
+        self.store.entry_mut(node).expires_at = None;
+    }
+
+    fn forget_oldest(&mut self, ghosts: Segment) {
+        if let Some(node) = self.list(ghosts).back() {
+            self.unlink(node);
+            self.store.remove(node);
+        }
+    }
+
+    fn list(&self, segment: Segment) -> &List {
+        &self.lists[segment.index()]
+    }
+
+    /// Moves an entry to the front of a list.
+    fn move_to(&mut self, node: usize, segment: Segment) {
+        self.unlink(node);
+        self.store.entry_mut(node).meta = segment;
+        self.store
+            .push_front(&mut self.lists[segment.index()], node);
+    }
+
+    fn unlink(&mut self, node: usize) {
+        let segment = self.store.entry(node).meta;
+        self.store.unlink(&mut self.lists[segment.index()], node);
+    }
+
+    /// Returns the entry or the remembered key of a key if it did not expire, and drops the entry otherwise.
+    fn find_live<Q>(&mut self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.store.find(key)?;
+        if self.store.is_expired(node) {
+            self.drop_entry(node, EvictionCause::Expired);
+            return None;
+        }
+        Some(node)
+    }
+
+    /// Drops an entry without remembering its key.
+    fn drop_entry(&mut self, node: usize, cause: EvictionCause) {
+        // an expired entry is dropped because it expired, whatever made the cache look at it
+        let cause = if self.store.is_expired(node) {
+            EvictionCause::Expired
+        } else {
+            cause
+        };
+        self.unlink(node);
+        self.store.evict(node, cause);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AdaptiveReplacementCache;
+    use crate::data_structures::cache::{EvictionCause, ManualClock};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn resists_scans() {
+        let mut cache = AdaptiveReplacementCache::new(4);
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+        cache.set_eviction_listener(move |k, _, cause| log.lock().unwrap().push((k, cause)));
+
+        cache.put(-1, "a");
+        cache.put(-2, "b");
+        assert_eq!(cache.get(&-1), Some(&"a"));
+        assert_eq!(cache.get(&-2), Some(&"b"));
+        for key in 0..10 {
+            cache.put(key, "scan");
+        }
+        // an LRU cache would only hold the last keys of the scan
+        assert!(cache.contains_key(&-1) && cache.contains_key(&-2));
+        assert!(cache.contains_key(&8) && cache.contains_key(&9));
+        assert_eq!(cache.len(), 4);
+        let expected: Vec<_> = (0..8).map(|key| (key, EvictionCause::Capacity)).collect();
+        assert_eq!(*evicted.lock().unwrap(), expected);
+        assert_eq!(cache.stats().evictions, 8);
+    }
+
+    #[test]
+    fn adapts_to_misses_on_evicted_keys() {
+        let mut cache = AdaptiveReplacementCache::new(4);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.get("a");
+        cache.get("b");
+        for key in ["c", "d", "e", "f"] {
+            cache.put(key, 0);
+        }
+        assert_eq!(cache.target(), 0);
+        // c was evicted, but the cache remembers it: getting it is still a miss
+        assert_eq!(cache.get("c"), None);
+        assert_eq!(cache.stats().misses, 1);
+        // putting it back means the recent entries need more room, which e pays for
+        assert_eq!(cache.put("c", 3), None);
+        assert_eq!(cache.target(), 1);
+        assert_eq!(cache.peek("e"), None);
+        assert_eq!(cache.get("c"), Some(&3));
+        assert!(cache.contains_key("a") && cache.contains_key("b") && cache.contains_key("f"));
+
+        // the misses on evicted frequent entries move the target back
+        cache.get("f");
+        cache.put("g", 0);
+        cache.put("h", 0); // a and b are the least recently used frequent entries
+        assert!(!cache.contains_key("a") && !cache.contains_key("b"));
+        assert_eq!(cache.put("a", 0), None);
+        assert_eq!(cache.target(), 0);
+        assert_eq!(cache.peek("g"), None);
+        assert_eq!(cache.remove("a"), Some(0));
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn expires_entries() {
+        let clock = ManualClock::new();
+        let mut cache = AdaptiveReplacementCache::with_clock(2, clock.clone());
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+        cache.set_eviction_listener(move |k, v, cause| log.lock().unwrap().push((k, v, cause)));
+
+        cache.put_with_ttl(1, "one", Duration::from_secs(5));
+        cache.put(2, "two");
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.len(), 1);
+        cache.put(3, "three");
+        cache.put(4, "four");
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            [
+                (1, "one", EvictionCause::Expired),
+                (2, "two", EvictionCause::Capacity)
+            ]
+        );
+        assert_eq!(cache.remove(&3), Some("three"));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.stats().expirations, 1);
+    }
+}
@@ -0,0 +1,294 @@
+use super::store::{Entry, List, Store};
+use super::{CacheStats, Clock, EvictionCause, SystemClock};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Duration;
+
+/// A cache that evicts the least frequently used entry, and the least recently used among those
+/// <https://en.wikipedia.org/wiki/Least_frequently_used>.
+///
+/// The entries used the same number of times are in a list, from the most to the least recently used, and a use
+/// moves an entry to the front of the next list. Tracking the smallest use count that has entries finds the entry
+/// to evict in O(1), as described in <http://dhruvbird.com/lfu.pdf>.
+pub struct LfuCache<K, V, C = SystemClock> {
+    /// The use count of an entry is its metadata.
+    store: Store<K, V, u64, C>,
+    /// The entries by use count.
+    lists: HashMap<u64, List>,
+    /// The smallest use count of an entry. Removals can leave it without entries, but they also make room, and the
+    /// insertion that fills the cache again resets it: it is exact whenever an entry must be evicted.
+    min_uses: u64,
+    len: usize,
+    capacity: usize,
+}
+
+impl<K: Hash + Eq + Clone, V> LfuCache<K, V> {
+    /// Creates a cache of at most `capacity` entries, which must be positive.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_clock(capacity, SystemClock::default())
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, C: Clock> LfuCache<K, V, C> {
+    /// Creates a cache of at most `capacity` entries, whose entries expire according to `clock`.
+    pub fn with_clock(capacity: usize, clock: C) -> Self {
+        assert!(capacity > 0, "the capacity must be positive");
+        LfuCache {
+            store: Store::new(clock),
+            lists: HashMap::new(),
+            min_uses: 0,
+            len: 0,
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of entries, including the expired entries not dropped yet.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.store.stats
+    }
+
+    /// Calls `listener` with every entry evicted for room or dropped on expiration, but not with removed entries.
+    pub fn set_eviction_listener(
+        &mut self,
+        listener: impl FnMut(K, V, EvictionCause) + Send + 'static,
+    ) {
+        self.store.set_listener(Box::new(listener));
+    }
+
+    /// Returns the value of a key, without counting it as a use.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.live_value(self.store.find(key)?)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek(key).is_some()
+    }
+
+    /// Returns the number of times a key was inserted or looked up since it entered the cache.
+    pub fn uses<Q>(&self, key: &Q) -> Option<u64>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek(key)?;
+        Some(self.store.entry(self.store.find(key)?).meta)
+    }
+
+    /// Returns the value of a key, and counts a use of it.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(node) = self.find_live(key) else {
+            self.store.stats.misses += 1;
+            return None;
+        };
+        self.store.stats.hits += 1;
+        self.count_use(node);
+        self.store.entry(node).value.as_ref()
+    }
+
+    /// Inserts a key-value pair that never expires, and returns the previous value of the key.
+    ///
+    /// Replacing the value of a key counts as a use of it.
+    pub fn put(&mut self, key: K, value: V) -> Option<V> {
+        self.insert(key, value, None)
+    }
+
+    /// Inserts a key-value pair that expires after `time_to_live`, and returns the previous value of the key.
+    pub fn put_with_ttl(&mut self, key: K, value: V, time_to_live: Duration) -> Option<V> {
+        self.insert(key, value, Some(time_to_live))
+    }
+
+    fn insert(&mut self, key: K, value: V, time_to_live: Option<Duration>) -> Option<V> {
+        let expires_at = time_to_live.map(|time_to_live| self.store.deadline(time_to_live));
+        if let Some(node) = self.find_live(&key) {
+            let entry = self.store.entry_mut(node);
+            entry.expires_at = expires_at;
+            let old = entry.value.replace(value);
+            self.count_use(node);
+            return old;
+        }
+        if self.len == self.capacity {
+            let node = self.lists[&self.min_uses].back().unwrap();
+            self.drop_entry(node, EvictionCause::Capacity);
+        }
+        let node = self.store.insert(Entry {
+            key,
+            value: Some(value),
+            expires_at,
+            meta: 1,
+        });
+        self.store
+            .push_front(self.lists.entry(1).or_default(), node);
+        self.min_uses = 1;
+        self.len += 1;
+        None
+    }
+
+    /// Removes a key, and returns its value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.find_live(key)?;
+        self.unlink(node);
+        self.len -= 1;
+        self.store.remove(node).value
+    }
+
+    /// Moves an entry to the list of the next use count.
+    fn count_use(&mut self, node: usize) {
+        let uses = self.store.entry(node).meta;
+        self.unlink(node);
+        if self.min_uses == uses && !self.lists.contains_key(&uses) {
+            self.min_uses = uses + 1;
+        }
+        self.store.entry_mut(node).meta = uses + 1;
+        self.store
+            .push_front(self.lists.entry(uses + 1).or_default(), node);
+    }
+
+    /// Takes an entry out of its list, and drops the list if it becomes empty.
+    fn unlink(&mut self, node: usize) {
+        let uses = self.store.entry(node).meta;
+        let list = self.lists.get_mut(&uses).unwrap();
+        self.store.unlink(list, node);
+        if list.is_empty() {
+            self.lists.remove(&uses);
+        }
+    }
+
+    /// Returns the entry of a key if it did not expire, and drops it otherwise.
+    fn find_live<Q>(&mut self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.store.find(key)?;
+        if self.store.is_expired(node) {
+            self.drop_entry(node, EvictionCause::Expired);
+            return None;
+        }
+        Some(node)
+    }
+
+    fn drop_entry(&mut self, node: usize, cause: EvictionCause) {
+        // an expired entry is dropped because it expired, whatever made the cache look at it
+        let cause = if self.store.is_expired(node) {
+            EvictionCause::Expired
+        } else {
+            cause
+        };
+        self.unlink(node);
+        self.len -= 1;
+        self.store.evict(node, cause);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LfuCache;
+    use crate::data_structures::cache::{EvictionCause, ManualClock};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn evicts_the_least_frequently_used() {
+        let mut cache = LfuCache::new(3);
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+        cache.set_eviction_listener(move |k, v, cause| log.lock().unwrap().push((k, v, cause)));
+
+        cache.put('a', 1);
+        cache.put('b', 2);
+        cache.put('c', 3);
+        assert_eq!(cache.get(&'a'), Some(&1));
+        assert_eq!(cache.get(&'a'), Some(&1));
+        assert_eq!(cache.get(&'b'), Some(&2));
+        assert_eq!(cache.peek(&'c'), Some(&3)); // peeking is not a use
+        assert_eq!(cache.uses(&'a'), Some(3));
+        cache.put('d', 4); // c has the fewest uses
+        assert_eq!(cache.get(&'c'), None);
+        cache.put('e', 5); // d and e have one use each: d was used less recently
+        assert_eq!(cache.put('b', 20), Some(2));
+        assert_eq!(cache.uses(&'b'), Some(3));
+        assert_eq!(cache.remove(&'a'), Some(1));
+        assert_eq!(cache.len(), 2);
+        cache.put('f', 6);
+        cache.put('g', 7); // e and f have one use each: e was used less recently
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            [
+                ('c', 3, EvictionCause::Capacity),
+                ('d', 4, EvictionCause::Capacity),
+                ('e', 5, EvictionCause::Capacity)
+            ]
+        );
+        assert_eq!(cache.stats().hits, 3);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn fills_up_after_removals() {
+        let mut cache = LfuCache::new(2);
+        cache.put(1, ());
+        cache.put(2, ());
+        cache.get(&1);
+        cache.get(&2);
+        // the entries with one use are gone, but the cache is full again only after an insertion
+        cache.remove(&1);
+        cache.put(3, ());
+        cache.get(&3);
+        cache.get(&3);
+        cache.put(4, ());
+        assert!(cache.contains_key(&3) && cache.contains_key(&4));
+        assert!(!cache.contains_key(&2));
+    }
+
+    #[test]
+    fn expires_entries() {
+        let clock = ManualClock::new();
+        let mut cache = LfuCache::with_clock(2, clock.clone());
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+        cache.set_eviction_listener(move |k, _, cause| log.lock().unwrap().push((k, cause)));
+
+        cache.put_with_ttl("hot", 1, Duration::from_secs(5));
+        for _ in 0..10 {
+            cache.get("hot");
+        }
+        cache.put("cold", 2);
+        clock.advance(Duration::from_secs(5));
+        // the expired entry goes, although it is the most frequently used
+        assert_eq!(cache.get("hot"), None);
+        assert_eq!(cache.len(), 1);
+        cache.put("new", 3);
+        assert_eq!(cache.get("cold"), Some(&2));
+        assert_eq!(*evicted.lock().unwrap(), [("hot", EvictionCause::Expired)]);
+        assert_eq!(cache.stats().expirations, 1);
+    }
+}
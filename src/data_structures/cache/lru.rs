@@ -0,0 +1,245 @@
+use super::store::{Entry, List, Store};
+use super::{CacheStats, Clock, EvictionCause, SystemClock};
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::time::Duration;
+
+/// A cache that evicts the least recently used entry <https://en.wikipedia.org/wiki/Cache_replacement_policies#LRU>.
+///
+/// The entries are in a list from the most to the least recently used: a lookup moves its entry to the front, and
+/// the entry at the back is evicted.
+pub struct LruCache<K, V, C = SystemClock> {
+    store: Store<K, V, (), C>,
+    list: List,
+    capacity: usize,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    /// Creates a cache of at most `capacity` entries, which must be positive.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_clock(capacity, SystemClock::default())
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, C: Clock> LruCache<K, V, C> {
+    /// Creates a cache of at most `capacity` entries, whose entries expire according to `clock`.
+    pub fn with_clock(capacity: usize, clock: C) -> Self {
+        assert!(capacity > 0, "the capacity must be positive");
+        LruCache {
+            store: Store::new(clock),
+            list: List::default(),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of entries, including the expired entries not dropped yet.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.store.stats
+    }
+
+    /// Calls `listener` with every entry evicted for room or dropped on expiration, but not with removed entries.
+    pub fn set_eviction_listener(
+        &mut self,
+        listener: impl FnMut(K, V, EvictionCause) + Send + 'static,
+    ) {
+        self.store.set_listener(Box::new(listener));
+    }
+
+    /// Returns the value of a key, without counting it as a use.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.live_value(self.store.find(key)?)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek(key).is_some()
+    }
+
+    /// Returns the value of a key, and marks it as the most recently used.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(node) = self.find_live(key) else {
+            self.store.stats.misses += 1;
+            return None;
+        };
+        self.store.stats.hits += 1;
+        self.store.unlink(&mut self.list, node);
+        self.store.push_front(&mut self.list, node);
+        self.store.entry(node).value.as_ref()
+    }
+
+    /// Inserts a key-value pair that never expires, and returns the previous value of the key.
+    pub fn put(&mut self, key: K, value: V) -> Option<V> {
+        self.insert(key, value, None)
+    }
+
+    /// Inserts a key-value pair that expires after `time_to_live`, and returns the previous value of the key.
+    pub fn put_with_ttl(&mut self, key: K, value: V, time_to_live: Duration) -> Option<V> {
+        self.insert(key, value, Some(time_to_live))
+    }
+
+    fn insert(&mut self, key: K, value: V, time_to_live: Option<Duration>) -> Option<V> {
+        let expires_at = time_to_live.map(|time_to_live| self.store.deadline(time_to_live));
+        if let Some(node) = self.find_live(&key) {
+            let entry = self.store.entry_mut(node);
+            entry.expires_at = expires_at;
+            let old = entry.value.replace(value);
+            self.store.unlink(&mut self.list, node);
+            self.store.push_front(&mut self.list, node);
+            return old;
+        }
+        if self.list.len() == self.capacity {
+            let node = self.list.back().unwrap();
+            self.drop_entry(node, EvictionCause::Capacity);
+        }
+        let node = self.store.insert(Entry {
+            key,
+            value: Some(value),
+            expires_at,
+            meta: (),
+        });
+        self.store.push_front(&mut self.list, node);
+        None
+    }
+
+    /// Removes a key, and returns its value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.find_live(key)?;
+        self.store.unlink(&mut self.list, node);
+        self.store.remove(node).value
+    }
+
+    /// Returns the entry of a key if it did not expire, and drops it otherwise.
+    fn find_live<Q>(&mut self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.store.find(key)?;
+        if self.store.is_expired(node) {
+            self.drop_entry(node, EvictionCause::Expired);
+            return None;
+        }
+        Some(node)
+    }
+
+    fn drop_entry(&mut self, node: usize, cause: EvictionCause) {
+        // an expired entry is dropped because it expired, whatever made the cache look at it
+        let cause = if self.store.is_expired(node) {
+            EvictionCause::Expired
+        } else {
+            cause
+        };
+        self.store.unlink(&mut self.list, node);
+        self.store.evict(node, cause);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LruCache;
+    use crate::data_structures::cache::{CacheStats, EvictionCause, ManualClock};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+        cache.set_eviction_listener(move |k, v, cause| log.lock().unwrap().push((k, v, cause)));
+
+        assert_eq!(cache.put("a", 1), None);
+        assert_eq!(cache.put("b", 2), None);
+        assert_eq!(cache.get("a"), Some(&1));
+        assert_eq!(cache.put("c", 3), None); // evicts b
+        assert_eq!(cache.peek("b"), None);
+        assert_eq!(cache.peek("a"), Some(&1)); // peeking does not make a recent
+        assert_eq!(cache.put("d", 4), None); // evicts a
+        assert_eq!(cache.put("c", 30), Some(3));
+        assert_eq!(cache.remove("d"), Some(4));
+        assert_eq!(cache.remove("d"), None);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            [
+                ("b", 2, EvictionCause::Capacity),
+                ("a", 1, EvictionCause::Capacity)
+            ]
+        );
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                evictions: 2,
+                expirations: 0
+            }
+        );
+        assert_eq!(cache.stats().hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn expires_entries() {
+        let clock = ManualClock::new();
+        let mut cache = LruCache::with_clock(3, clock.clone());
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+        cache.set_eviction_listener(move |k, _, cause| log.lock().unwrap().push((k, cause)));
+
+        cache.put_with_ttl(1, "one", Duration::from_secs(10));
+        cache.put_with_ttl(2, "two", Duration::from_secs(20));
+        cache.put(3, "three");
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), Some(&"two"));
+        // a new value restarts the time to live
+        assert_eq!(
+            cache.put_with_ttl(2, "TWO", Duration::from_secs(20)),
+            Some("two")
+        );
+        clock.advance(Duration::from_secs(15));
+        assert_eq!(cache.get(&2), Some(&"TWO"));
+        assert_eq!(cache.get(&3), Some(&"three"));
+        clock.advance(Duration::from_secs(5));
+        // the least recently used entry expired: evicting it counts as an expiration
+        cache.put(4, "four");
+        cache.put(5, "five");
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(&3), Some(&"three"));
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            [(1, EvictionCause::Expired), (2, EvictionCause::Expired)]
+        );
+        assert_eq!(cache.stats().expirations, 2);
+        assert_eq!(cache.stats().evictions, 0);
+    }
+}
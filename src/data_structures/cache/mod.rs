@@ -0,0 +1,104 @@
+//! In-memory caches of a bounded number of entries, which evict an entry when a new one does not fit.
+//!
+//! The caches differ in the entry they evict:
+//!     * `LruCache` evicts the least recently used entry,
+//!     * `LfuCache` evicts the least frequently used entry, and the least recently used among those,
+//!     * `AdaptiveReplacementCache` keeps entries used once apart from entries used again, and remembers the keys it
+//!       evicted lately to balance both parts
+//!       <https://www.usenix.org/conference/fast-03/arc-self-tuning-low-overhead-replacement-cache>.
+//!
+//! All operations take O(1) time: the entries are in linked lists, in a `HashTable` from the keys to the entries.
+//! An entry can expire after a time to live, measured by a `Clock` that tests can replace with a `ManualClock`.
+
+mod arc;
+mod lfu;
+mod lru;
+mod store;
+
+pub use self::arc::AdaptiveReplacementCache;
+pub use self::lfu::LfuCache;
+pub use self::lru::LruCache;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Counters of a cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// The entries evicted to make room for others.
+    pub evictions: u64,
+    /// The entries dropped because their time to live ran out.
+    pub expirations: u64,
+}
+
+impl CacheStats {
+    /// Returns the fraction of lookups that were hits, or 0 before any lookup.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / lookups as f64
+    }
+}
+
+/// Why a cache dropped an entry, as told to its eviction listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionCause {
+    /// The cache was full.
+    Capacity,
+    /// The time to live of the entry ran out.
+    Expired,
+}
+
+/// The time source of the expiration of entries.
+pub trait Clock {
+    /// Returns the time elapsed since some fixed instant, which must never decrease.
+    fn now(&self) -> Duration;
+}
+
+/// The monotonic clock of the system.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A clock that only moves when told to. Its clones share the same time, so a test can keep one and give another
+/// to a cache.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+}
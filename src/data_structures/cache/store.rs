@@ -0,0 +1,203 @@
+//! The entries of a cache, in doubly linked lists over one arena and indexed by key, which the eviction policies
+//! move between lists in O(1).
+
+use super::{CacheStats, Clock, EvictionCause};
+use crate::data_structures::HashTable;
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::time::Duration;
+
+/// The index of the missing node at both ends of a list.
+const NIL: usize = usize::MAX;
+
+pub(super) type Listener<K, V> = Box<dyn FnMut(K, V, EvictionCause) + Send>;
+
+pub(super) struct Entry<K, V, M> {
+    pub key: K,
+    /// `None` for the keys the adaptive cache remembers without their value.
+    pub value: Option<V>,
+    pub expires_at: Option<Duration>,
+    /// The data of the eviction policy about the entry.
+    pub meta: M,
+}
+
+struct Node<T> {
+    item: Option<T>,
+    prev: usize,
+    next: usize,
+}
+
+/// A list of entries of a `Store`, from the front to the back.
+pub(super) struct List {
+    front: usize,
+    back: usize,
+    len: usize,
+}
+
+impl Default for List {
+    fn default() -> Self {
+        List {
+            front: NIL,
+            back: NIL,
+            len: 0,
+        }
+    }
+}
+
+impl List {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn back(&self) -> Option<usize> {
+        (self.back != NIL).then_some(self.back)
+    }
+}
+
+pub(super) struct Store<K, V, M, C> {
+    nodes: Vec<Node<Entry<K, V, M>>>,
+    free: Vec<usize>,
+    index: HashTable<K, usize>,
+    pub clock: C,
+    pub stats: CacheStats,
+    listener: Option<Listener<K, V>>,
+}
+
+impl<K: Hash + Eq + Clone, V, M, C: Clock> Store<K, V, M, C> {
+    pub fn new(clock: C) -> Self {
+        Store {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            index: HashTable::new(),
+            clock,
+            stats: CacheStats::default(),
+            listener: None,
+        }
+    }
+
+    pub fn set_listener(&mut self, listener: Listener<K, V>) {
+        self.listener = Some(listener);
+    }
+
+    pub fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.index.get(key).copied()
+    }
+
+    pub fn entry(&self, node: usize) -> &Entry<K, V, M> {
+        self.nodes[node].item.as_ref().unwrap()
+    }
+
+    pub fn entry_mut(&mut self, node: usize) -> &mut Entry<K, V, M> {
+        self.nodes[node].item.as_mut().unwrap()
+    }
+
+    /// Returns the value of an entry, if it has one that did not expire.
+    pub fn live_value(&self, node: usize) -> Option<&V> {
+        if self.is_expired(node) {
+            return None;
+        }
+        self.entry(node).value.as_ref()
+    }
+
+    pub fn is_expired(&self, node: usize) -> bool {
+        self.entry(node)
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= self.clock.now())
+    }
+
+    /// Returns the expiration time of an entry inserted now.
+    pub fn deadline(&self, time_to_live: Duration) -> Duration {
+        self.clock.now() + time_to_live
+    }
+
+    /// Stores an entry, outside of any list.
+    pub fn insert(&mut self, entry: Entry<K, V, M>) -> usize {
+        let key = entry.key.clone();
+        let node = Node {
+            item: Some(entry),
+            prev: NIL,
+            next: NIL,
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        self.index.insert(key, index);
+        index
+    }
+
+    /// Removes an entry that is in no list.
+    pub fn remove(&mut self, node: usize) -> Entry<K, V, M> {
+        let entry = self.nodes[node].item.take().unwrap();
+        self.index.remove(&entry.key);
+        self.free.push(node);
+        entry
+    }
+
+    /// Removes an entry that is in no list, and tells the listener.
+    pub fn evict(&mut self, node: usize, cause: EvictionCause) {
+        let entry = self.remove(node);
+        self.report(entry.key, entry.value, cause);
+    }
+
+    /// Drops the value of an entry but keeps its key, and tells the listener.
+    pub fn evict_value(&mut self, node: usize, cause: EvictionCause) {
+        let entry = self.entry_mut(node);
+        let (key, value) = (entry.key.clone(), entry.value.take());
+        self.report(key, value, cause);
+    }
+
+    fn report(&mut self, key: K, value: Option<V>, cause: EvictionCause) {
+        let Some(value) = value else {
+            return;
+        };
+        match cause {
+            EvictionCause::Capacity => self.stats.evictions += 1,
+            EvictionCause::Expired => self.stats.expirations += 1,
+        }
+        if let Some(listener) = &mut self.listener {
+            listener(key, value, cause);
+        }
+    }
+
+    pub fn push_front(&mut self, list: &mut List, node: usize) {
+        self.nodes[node].prev = NIL;
+        self.nodes[node].next = list.front;
+        if list.front == NIL {
+            list.back = node;
+        } else {
+            self.nodes[list.front].prev = node;
+        }
+        list.front = node;
+        list.len += 1;
+    }
+
+    pub fn unlink(&mut self, list: &mut List, node: usize) {
+        let Node { prev, next, .. } = self.nodes[node];
+        if prev == NIL {
+            list.front = next;
+        } else {
+            self.nodes[prev].next = next;
+        }
+        if next == NIL {
+            list.back = prev;
+        } else {
+            self.nodes[next].prev = prev;
+        }
+        list.len -= 1;
+    }
+}
@@ -0,0 +1,552 @@
+use super::skip_list::{random_level, MAX_LEVEL};
+use rand::{rngs::StdRng, SeedableRng as _};
+use std::{
+    iter,
+    ops::{Bound, RangeBounds},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, RwLock,
+    },
+    thread,
+};
+
+type Link<K, V> = Option<Arc<Node<K, V>>>;
+
+struct Node<K, V> {
+    /// `None` for the head, which comes before every key.
+    key: Option<K>,
+    /// Taken when the node is removed.
+    value: RwLock<Option<V>>,
+    /// The next node of every level the node is on.
+    next: Vec<Mutex<Link<K, V>>>,
+    /// Held while the links leaving the node change.
+    lock: Mutex<()>,
+    /// Set when the node is logically removed, before it is unlinked.
+    marked: AtomicBool,
+    /// Set once the node is linked on all its levels.
+    fully_linked: AtomicBool,
+}
+
+impl<K, V> Node<K, V> {
+    fn new(key: Option<K>, value: Option<V>, levels: usize) -> Self {
+        Node {
+            key,
+            value: RwLock::new(value),
+            next: iter::repeat_with(|| Mutex::new(None))
+                .take(levels)
+                .collect(),
+            lock: Mutex::new(()),
+            marked: AtomicBool::new(false),
+            fully_linked: AtomicBool::new(false),
+        }
+    }
+
+    fn key(&self) -> &K {
+        self.key.as_ref().unwrap()
+    }
+
+    fn next(&self, level: usize) -> Link<K, V> {
+        self.next[level].lock().unwrap().clone()
+    }
+
+    fn set_next(&self, level: usize, next: Link<K, V>) {
+        *self.next[level].lock().unwrap() = next;
+    }
+
+    /// Returns `true` if the node is in the map: fully linked, and not removed.
+    fn is_live(&self) -> bool {
+        self.fully_linked.load(Ordering::Acquire) && !self.marked.load(Ordering::Acquire)
+    }
+}
+
+fn same<K, V>(a: &Link<K, V>, b: &Link<K, V>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Locks the predecessors of a key from the lowest level up, and checks that they are still in the list and that
+/// `valid` holds on every level: returns the guards if so.
+fn lock_predecessors<K, V>(
+    predecessors: &[Arc<Node<K, V>>],
+    valid: impl Fn(usize, &Node<K, V>) -> bool,
+) -> Option<Vec<MutexGuard<'_, ()>>> {
+    let mut guards = Vec::with_capacity(predecessors.len());
+    let mut locked: Option<&Arc<Node<K, V>>> = None;
+    for (level, predecessor) in predecessors.iter().enumerate() {
+        // a node can be the predecessor on several consecutive levels
+        if locked.is_none_or(|locked| !Arc::ptr_eq(locked, predecessor)) {
+            guards.push(predecessor.lock.lock().unwrap());
+            locked = Some(predecessor);
+        }
+        if predecessor.marked.load(Ordering::Acquire) || !valid(level, predecessor) {
+            return None;
+        }
+    }
+    Some(guards)
+}
+
+/// The nodes around a key on every level searched.
+struct Search<K, V> {
+    /// The last node whose key is less than the key.
+    predecessors: Vec<Arc<Node<K, V>>>,
+    /// The node after each predecessor.
+    successors: Vec<Link<K, V>>,
+    /// The highest level where the successor has the key.
+    found: Option<usize>,
+}
+
+/// An ordered map based on a skip list, which several threads can use at once.
+///
+/// This is the "lazy" skip list of Herlihy, Lev, Luchangco and Shavit: insertions and removals lock only the
+/// predecessors of the key, validate that nothing changed around it, then relink. A removal first marks its node,
+/// which removes the key logically; threads that still reach the node skip it. Nodes are reference counted, so a node
+/// stays valid for the threads traversing it after it is unlinked.
+///
+/// Unlike the original, which reads links atomically, every link here sits behind its own mutex, held only while the
+/// link is cloned or replaced. Searches never hold more than one of them at a time and never take the node locks of
+/// the algorithm, so they do not wait for an insertion or removal to finish, but they do contend with each other on
+/// the links near the head. Insertions also draw their level from a generator shared behind a mutex.
+///
+/// Node locks are taken in descending order of keys, so that operations never deadlock.
+pub struct ConcurrentSkipList<K, V> {
+    head: Arc<Node<K, V>>,
+    len: AtomicUsize,
+    /// The number of levels any node was linked on.
+    height: AtomicUsize,
+    probability: f64,
+    rng: Mutex<StdRng>,
+}
+
+impl<K: Ord, V> ConcurrentSkipList<K, V> {
+    /// Creates a list where every node is on the next level with probability 1/2.
+    pub fn new() -> Self {
+        Self::with_parameters(0.5, 0)
+    }
+
+    /// Creates a list where every node is on the next level with probability `probability`, between 0 and 1
+    /// exclusive, and whose levels are drawn from a generator seeded with `seed`.
+    ///
+    /// The shape of the list still depends on the order in which the threads draw levels.
+    pub fn with_parameters(probability: f64, seed: u64) -> Self {
+        assert!(
+            probability > 0.0 && probability < 1.0,
+            "the probability must be between 0 and 1"
+        );
+        ConcurrentSkipList {
+            head: Arc::new(Node::new(None, None, MAX_LEVEL)),
+            len: AtomicUsize::new(0),
+            height: AtomicUsize::new(1),
+            probability,
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// Returns the number of entries, which may be outdated as soon as it returns if other threads change the map.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Finds the nodes around a key on the lowest `levels` levels.
+    fn search(&self, key: &K, levels: usize) -> Search<K, V> {
+        let mut predecessors = vec![self.head.clone(); levels];
+        let mut successors = vec![None; levels];
+        let mut found = None;
+        let mut predecessor = self.head.clone();
+        for level in (0..levels).rev() {
+            let mut current = predecessor.next(level);
+            while let Some(node) = current.as_ref().filter(|node| node.key() < key) {
+                predecessor = node.clone();
+                current = predecessor.next(level);
+            }
+            if found.is_none() && current.as_ref().is_some_and(|node| node.key() == key) {
+                found = Some(level);
+            }
+            predecessors[level] = predecessor.clone();
+            successors[level] = current;
+        }
+        Search {
+            predecessors,
+            successors,
+            found,
+        }
+    }
+
+    /// Returns the node of a key, if it is in the map.
+    fn find(&self, key: &K) -> Option<Arc<Node<K, V>>> {
+        let mut search = self.search(key, self.height.load(Ordering::Acquire));
+        let node = search.successors[search.found?].take()?;
+        node.is_live().then_some(node)
+    }
+
+    /// Returns `true` if the map contains a key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Returns a copy of the value stored under a key.
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.find(key)?.value.read().unwrap().clone()
+    }
+
+    /// Inserts a key-value pair, and returns the previous value stored under the key.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let levels = random_level(&mut *self.rng.lock().unwrap(), self.probability);
+        loop {
+            let search = self.search(&key, levels.max(self.height.load(Ordering::Acquire)));
+            if let Some(level) = search.found {
+                let node = search.successors[level].as_ref().unwrap();
+                if !node.marked.load(Ordering::Acquire) {
+                    // the key is being inserted by another thread
+                    while !node.fully_linked.load(Ordering::Acquire) {
+                        thread::yield_now();
+                    }
+                    let mut old = node.value.write().unwrap();
+                    // the value is gone if the node was removed meanwhile
+                    if old.is_some() {
+                        return old.replace(value);
+                    }
+                }
+                // wait for the removal to unlink the node
+                thread::yield_now();
+                continue;
+            }
+
+            let Some(_guards) =
+                lock_predecessors(&search.predecessors[..levels], |level, predecessor| {
+                    let successor = &search.successors[level];
+                    successor
+                        .as_ref()
+                        .is_none_or(|successor| !successor.marked.load(Ordering::Acquire))
+                        && same(&predecessor.next(level), successor)
+                })
+            else {
+                // another thread changed the list around the key
+                thread::yield_now();
+                continue;
+            };
+
+            let node = Arc::new(Node::new(Some(key), Some(value), levels));
+            for (level, successor) in search.successors.into_iter().take(levels).enumerate() {
+                node.set_next(level, successor);
+            }
+            // searches must reach the top level of the node before it counts as inserted
+            self.height.fetch_max(levels, Ordering::AcqRel);
+            for (level, predecessor) in search.predecessors[..levels].iter().enumerate() {
+                predecessor.set_next(level, Some(node.clone()));
+            }
+            node.fully_linked.store(true, Ordering::Release);
+            self.len.fetch_add(1, Ordering::AcqRel);
+            return None;
+        }
+    }
+
+    /// Removes a key from the map, and returns its value.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut victim: Option<Arc<Node<K, V>>> = None;
+        let mut levels = self.height.load(Ordering::Acquire);
+        loop {
+            let search = self.search(key, levels);
+            if victim.is_none() {
+                let node = search.successors[search.found?].as_ref().unwrap();
+                if node.next.len() > levels {
+                    // the node was linked after `levels` was read
+                    levels = node.next.len();
+                    continue;
+                }
+                if !node.fully_linked.load(Ordering::Acquire)
+                    || node.marked.swap(true, Ordering::AcqRel)
+                {
+                    // the node is not inserted yet, or another thread removes it
+                    return None;
+                }
+                victim = Some(node.clone());
+            }
+            let node = victim.as_ref().unwrap();
+
+            let victim_guard = node.lock.lock().unwrap();
+            let levels = node.next.len();
+            let Some(_guards) =
+                lock_predecessors(&search.predecessors[..levels], |level, predecessor| {
+                    predecessor
+                        .next(level)
+                        .is_some_and(|next| Arc::ptr_eq(&next, node))
+                })
+            else {
+                drop(victim_guard);
+                thread::yield_now();
+                continue;
+            };
+
+            for level in (0..node.next.len()).rev() {
+                search.predecessors[level].set_next(level, node.next(level));
+            }
+            self.len.fetch_sub(1, Ordering::AcqRel);
+            return node.value.write().unwrap().take();
+        }
+    }
+
+    /// Returns a copy of the entry with the smallest key.
+    pub fn first(&self) -> Option<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.range(..).into_iter().next()
+    }
+
+    /// Removes the entry with the smallest key, and returns it.
+    pub fn pop_first(&self) -> Option<(K, V)>
+    where
+        K: Clone,
+    {
+        loop {
+            let mut current = self.head.next(0);
+            while let Some(node) = current.as_ref().filter(|node| !node.is_live()) {
+                current = node.next(0);
+            }
+            let key = current?.key().clone();
+            // another thread may remove the key first
+            if let Some(value) = self.remove(&key) {
+                return Some((key, value));
+            }
+        }
+    }
+
+    /// Returns copies of the entries whose keys lie in `range`, in ascending order.
+    ///
+    /// The entries are collected while other threads may change the map: an entry that is not inserted or removed
+    /// meanwhile is returned if and only if its key lies in the range.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let before_start = |key: &K| match range.start_bound() {
+            Bound::Included(start) => key < start,
+            Bound::Excluded(start) => key <= start,
+            Bound::Unbounded => false,
+        };
+        let past_end = |key: &K| match range.end_bound() {
+            Bound::Included(end) => key > end,
+            Bound::Excluded(end) => key >= end,
+            Bound::Unbounded => false,
+        };
+
+        let mut predecessor = self.head.clone();
+        for level in (0..self.height.load(Ordering::Acquire)).rev() {
+            while let Some(node) = predecessor
+                .next(level)
+                .filter(|node| before_start(node.key()))
+            {
+                predecessor = node;
+            }
+        }
+        let mut entries = Vec::new();
+        let mut current = predecessor.next(0);
+        while let Some(node) = current.filter(|node| !past_end(node.key())) {
+            if node.is_live() {
+                let value = node.value.read().unwrap().clone();
+                if let Some(value) = value {
+                    entries.push((node.key().clone(), value));
+                }
+            }
+            current = node.next(0);
+        }
+        entries
+    }
+}
+
+impl<K: Ord, V> Default for ConcurrentSkipList<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Drop for ConcurrentSkipList<K, V> {
+    fn drop(&mut self) {
+        // unlink the nodes one by one, as dropping the first node would otherwise drop the whole list recursively
+        for level in 1..MAX_LEVEL {
+            self.head.set_next(level, None);
+        }
+        let mut current = self.head.next[0].lock().unwrap().take();
+        while let Some(node) = current {
+            for level in 1..node.next.len() {
+                node.set_next(level, None);
+            }
+            current = node.next[0].lock().unwrap().take();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConcurrentSkipList;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeMap;
+    use std::thread;
+
+    const THREADS: usize = 8;
+
+    #[test]
+    fn behaves_like_a_map() {
+        let list = ConcurrentSkipList::with_parameters(0.25, 3);
+        assert_eq!(list.insert(2, "b"), None);
+        assert_eq!(list.insert(1, "a"), None);
+        assert_eq!(list.insert(3, "c"), None);
+        assert_eq!(list.insert(2, "B"), Some("b"));
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.get(&2), Some("B"));
+        assert_eq!(list.range(2..), [(2, "B"), (3, "c")]);
+        assert_eq!(list.first(), Some((1, "a")));
+        assert_eq!(list.pop_first(), Some((1, "a")));
+        assert_eq!(list.remove(&3), Some("c"));
+        assert_eq!(list.remove(&3), None);
+        assert!(!list.contains_key(&3));
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn drops_long_lists() {
+        let list = ConcurrentSkipList::new();
+        for i in 0..200_000 {
+            list.insert(i, i);
+        }
+        drop(list);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn concurrent_disjoint_inserts() {
+        let list = ConcurrentSkipList::new();
+        thread::scope(|scope| {
+            for t in 0..THREADS {
+                let list = &list;
+                scope.spawn(move || {
+                    for i in 0..2000 {
+                        assert_eq!(list.insert(i * THREADS + t, t), None);
+                    }
+                });
+            }
+        });
+        assert_eq!(list.len(), 2000 * THREADS);
+        let entries = list.range(..);
+        assert!(entries
+            .iter()
+            .enumerate()
+            .all(|(i, &(key, t))| key == i && t == i % THREADS));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn concurrent_contended_keys() {
+        let list = ConcurrentSkipList::new();
+        let inserted: usize = thread::scope(|scope| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|t| {
+                    let list = &list;
+                    scope.spawn(move || (0..1000).filter(|&i| list.insert(i, t).is_none()).count())
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert_eq!(inserted, 1000);
+        assert_eq!(list.len(), 1000);
+
+        let removed: usize = thread::scope(|scope| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    let list = &list;
+                    scope.spawn(move || (0..1000).filter(|i| list.remove(i).is_some()).count())
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert_eq!(removed, 1000);
+        assert!(list.is_empty());
+        assert_eq!(list.range(..), []);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn concurrent_mixed_operations() {
+        let list = ConcurrentSkipList::with_parameters(0.5, 1);
+        let models: Vec<BTreeMap<usize, u32>> = thread::scope(|scope| {
+            // every thread owns the keys equal to its index modulo THREADS, and tracks them in a model
+            let writers: Vec<_> = (0..THREADS)
+                .map(|t| {
+                    let list = &list;
+                    scope.spawn(move || {
+                        let mut model = BTreeMap::new();
+                        let mut rng = StdRng::seed_from_u64(t as u64);
+                        for _ in 0..5000 {
+                            let key = rng.gen_range(0..300) * THREADS + t;
+                            if rng.gen_ratio(1, 3) {
+                                assert_eq!(list.remove(&key), model.remove(&key));
+                            } else {
+                                let value: u32 = rng.gen();
+                                assert_eq!(list.insert(key, value), model.insert(key, value));
+                            }
+                            assert_eq!(list.get(&key), model.get(&key).copied());
+                        }
+                        model
+                    })
+                })
+                .collect();
+            // meanwhile, scans always see sorted keys
+            let list = &list;
+            scope.spawn(move || {
+                for _ in 0..200 {
+                    let keys: Vec<usize> =
+                        list.range(100..2000).into_iter().map(|(k, _)| k).collect();
+                    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+                    assert!(keys.iter().all(|k| (100..2000).contains(k)));
+                }
+            });
+            writers.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let expected: BTreeMap<usize, u32> = models.into_iter().flatten().collect();
+        assert_eq!(list.len(), expected.len());
+        assert_eq!(list.range(..), expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn concurrent_pop_first() {
+        let list = ConcurrentSkipList::new();
+        for i in 0..10_000 {
+            list.insert(i, ());
+        }
+        let mut popped: Vec<usize> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    let list = &list;
+                    scope.spawn(move || {
+                        let mut keys = Vec::new();
+                        while let Some((key, ())) = list.pop_first() {
+                            // a thread pops its keys in ascending order
+                            assert!(keys.last().is_none_or(|&last| last < key));
+                            keys.push(key);
+                        }
+                        keys
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+        popped.sort_unstable();
+        assert_eq!(popped, (0..10_000).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,422 @@
+//! A hash map with cuckoo hashing: every key can only live in one slot of each of two tables, so a lookup
+//! reads at most two slots, plus a small stash.
+//!
+//! Inserting into an occupied slot evicts its entry, which moves to its slot in the other table, possibly
+//! evicting another entry, and so on. When this chain of evictions runs too long, the entry left over goes to
+//! the stash, which keeps a few entries that fit nowhere and makes rebuilding the tables much rarer. When the
+//! stash is full too, the tables are rebuilt with a new hash seed, and grown if that does not help.
+//!
+//! The tables are kept at most half full, the load cuckoo hashing with two tables needs to insert in
+//! expected constant time.
+//!
+//! See also <https://en.wikipedia.org/wiki/Cuckoo_hashing>.
+
+use super::bloom_filter::BloomFilter;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+/// The number of slots of each table allocated by the first insertion.
+const INITIAL_SLOTS: usize = 8;
+
+/// How many entries may be evicted in a row before the last one goes to the stash.
+const MAX_EVICTIONS: usize = 64;
+
+/// How many entries the stash holds before the tables are rebuilt.
+const STASH_SIZE: usize = 4;
+
+/// How many times in a row the tables may be rebuilt for one entry, growing every other time. With a hasher that
+/// mixes in the seed, a single rebuild almost always succeeds; running out of attempts means that more than
+/// `STASH_SIZE + 2` keys share their slots whatever the seed.
+const MAX_REBUILD_ATTEMPTS: usize = 8;
+
+type Table<K, V> = Vec<Option<(K, V)>>;
+
+pub struct CuckooHashMap<K, V, S = RandomState> {
+    tables: [Table<K, V>; 2],
+    stash: Vec<(K, V)>,
+    len: usize,
+    /// Mixed into every hash, and changed to rebuild the tables with different slots.
+    seed: u64,
+    rebuilds: usize,
+    hasher: S,
+}
+
+/// Where an entry is stored.
+enum Location {
+    Table(usize, usize),
+    Stash(usize),
+}
+
+impl<K, V> CuckooHashMap<K, V> {
+    pub fn new() -> Self {
+        CuckooHashMap::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, V, S: Default> Default for CuckooHashMap<K, V, S> {
+    fn default() -> Self {
+        CuckooHashMap::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> CuckooHashMap<K, V, S> {
+    pub fn with_hasher(hasher: S) -> Self {
+        CuckooHashMap {
+            tables: [Vec::new(), Vec::new()],
+            stash: Vec::new(),
+            len: 0,
+            seed: 0,
+            rebuilds: 0,
+            hasher,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of entries in the stash.
+    pub fn stash_len(&self) -> usize {
+        self.stash.len()
+    }
+
+    /// Returns how many times the tables were rebuilt because an entry did not fit.
+    pub fn rebuilds(&self) -> usize {
+        self.rebuilds
+    }
+
+    /// Returns the fraction of table slots in use.
+    pub fn load_factor(&self) -> f64 {
+        let slots = 2 * self.tables[0].len();
+        if slots == 0 {
+            0.0
+        } else {
+            (self.len - self.stash.len()) as f64 / slots as f64
+        }
+    }
+
+    /// Returns an iterator over the entries, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.tables
+            .iter()
+            .flatten()
+            .flatten()
+            .chain(&self.stash)
+            .map(|(key, value)| (key, value))
+    }
+
+    /// Takes all entries out of the tables and the stash.
+    fn drain(&mut self) -> Vec<(K, V)> {
+        let mut entries: Vec<(K, V)> = self
+            .tables
+            .iter_mut()
+            .flat_map(|table| table.iter_mut().filter_map(Option::take))
+            .collect();
+        entries.append(&mut self.stash);
+        entries
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> CuckooHashMap<K, V, S> {
+    /// The slots of `key` in both tables.
+    fn slots<Q: Hash + ?Sized>(&self, key: &Q) -> [usize; 2] {
+        let hash = self.hasher.hash_one((self.seed, key));
+        let mask = self.tables[0].len() - 1;
+        [hash as usize & mask, (hash >> 32) as usize & mask]
+    }
+
+    fn find<Q>(&self, key: &Q) -> Option<Location>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.is_empty() {
+            return None;
+        }
+        for (table, slot) in self.slots(key).into_iter().enumerate() {
+            if matches!(&self.tables[table][slot], Some((k, _)) if k.borrow() == key) {
+                return Some(Location::Table(table, slot));
+            }
+        }
+        self.stash
+            .iter()
+            .position(|(k, _)| k.borrow() == key)
+            .map(Location::Stash)
+    }
+
+    fn entry_at(&mut self, location: Location) -> &mut (K, V) {
+        match location {
+            Location::Table(table, slot) => self.tables[table][slot].as_mut().unwrap(),
+            Location::Stash(index) => &mut self.stash[index],
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.find(key)? {
+            Location::Table(table, slot) => self.tables[table][slot].as_ref().map(|(_, v)| v),
+            Location::Stash(index) => Some(&self.stash[index].1),
+        }
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let location = self.find(key)?;
+        Some(&mut self.entry_at(location).1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Inserts a key-value pair, and returns the previous value of the key.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(location) = self.find(&key) {
+            return Some(mem::replace(&mut self.entry_at(location).1, value));
+        }
+        if self.len + 1 > self.tables[0].len() {
+            // keep the tables at most half full
+            self.rebuild(None, true);
+        }
+        if let Err(entry) = self.place((key, value)) {
+            self.rebuild(Some(entry), false);
+        }
+        self.len += 1;
+        None
+    }
+
+    /// Removes a key, and returns its value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (_, value) = match self.find(key)? {
+            Location::Table(table, slot) => self.tables[table][slot].take().unwrap(),
+            Location::Stash(index) => self.stash.swap_remove(index),
+        };
+        self.len -= 1;
+        // a stashed entry may fit in the slot that was freed
+        let free = (0..self.stash.len()).find_map(|index| {
+            let slots = self.slots(&self.stash[index].0);
+            (0..2)
+                .find(|&table| self.tables[table][slots[table]].is_none())
+                .map(|table| (index, table, slots[table]))
+        });
+        if let Some((index, table, slot)) = free {
+            self.tables[table][slot] = Some(self.stash.swap_remove(index));
+        }
+        Some(value)
+    }
+
+    /// Puts an entry in its slot of the first table, moving evicted entries to their slot in the other table,
+    /// and falls back to the stash. Returns the entry left over if the stash is full.
+    fn place(&mut self, mut entry: (K, V)) -> Result<(), (K, V)> {
+        for eviction in 0..MAX_EVICTIONS {
+            let table = eviction % 2;
+            let slot = self.slots(&entry.0)[table];
+            match self.tables[table][slot].replace(entry) {
+                None => return Ok(()),
+                Some(evicted) => entry = evicted,
+            }
+        }
+        if self.stash.len() < STASH_SIZE {
+            self.stash.push(entry);
+            Ok(())
+        } else {
+            Err(entry)
+        }
+    }
+
+    /// Puts all entries, and `extra`, into new tables with a new seed, doubling the tables every other time an
+    /// entry still does not fit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the entries still do not fit after `MAX_REBUILD_ATTEMPTS` attempts, which only happens with a
+    /// hasher that ignores the seed.
+    fn rebuild(&mut self, extra: Option<(K, V)>, grow: bool) {
+        let mut entries = self.drain();
+        entries.extend(extra);
+        let mut size = self.tables[0].len();
+        if grow {
+            size = (2 * size).max(INITIAL_SLOTS);
+        }
+        for attempt in 1.. {
+            assert!(
+                attempt <= MAX_REBUILD_ATTEMPTS,
+                "the tables were rebuilt {MAX_REBUILD_ATTEMPTS} times in a row: too many keys collide whatever the seed, is the hasher ignoring it?"
+            );
+            self.rebuilds += 1;
+            self.seed = splitmix64(self.seed);
+            self.tables = [empty_table(size), empty_table(size)];
+            let mut entries_left = entries.into_iter();
+            let Some(failed) = entries_left
+                .by_ref()
+                .find_map(|entry| self.place(entry).err())
+            else {
+                return;
+            };
+            entries = self.drain();
+            entries.push(failed);
+            entries.extend(entries_left);
+            if attempt % 2 == 0 {
+                size *= 2;
+            }
+        }
+    }
+}
+
+fn empty_table<K, V>(size: usize) -> Table<K, V> {
+    std::iter::repeat_with(|| None).take(size).collect()
+}
+
+/// The splitmix64 finalizer, which turns a counter into well-mixed seeds.
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Default> FromIterator<(K, V)> for CuckooHashMap<K, V, S> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = CuckooHashMap::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> Extend<(K, V)> for CuckooHashMap<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for CuckooHashMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// A cuckoo map without values is an exact set, which answers like a filter without false positives.
+impl<K: Hash + Eq, S: BuildHasher> BloomFilter<K> for CuckooHashMap<K, (), S> {
+    fn insert(&mut self, item: K) {
+        CuckooHashMap::insert(self, item, ());
+    }
+
+    fn contains(&self, item: &K) -> bool {
+        self.contains_key(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BloomFilter, CuckooHashMap};
+    use crate::data_structures::identity_hasher::Identity;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::HashMap;
+
+    #[test]
+    fn map_operations() {
+        let mut map: CuckooHashMap<String, usize> = CuckooHashMap::new();
+        for (i, word) in "the quick brown fox jumps over the lazy dog"
+            .split(' ')
+            .enumerate()
+        {
+            map.insert(word.to_string(), i);
+        }
+        assert_eq!(map.len(), 8);
+        assert_eq!(map.get("the"), Some(&6));
+        *map.get_mut("fox").unwrap() += 100;
+        assert_eq!(map.get("fox"), Some(&103));
+        assert_eq!(map.remove("quick"), Some(1));
+        assert_eq!(map.remove("quick"), None);
+        assert!(!map.contains_key("cat"));
+        assert!(map.load_factor() <= 0.5);
+    }
+
+    #[test]
+    fn stash_holds_colliding_keys() {
+        let mut map: CuckooHashMap<u64, u64, Identity> = CuckooHashMap::default();
+        // the identity hasher keeps only the key written after the seed, so these keys share their slot in
+        // both tables whatever the seed
+        for i in 0..6 {
+            assert_eq!(map.insert(i << 40, i), None);
+        }
+        assert_eq!(map.stash_len(), 4);
+        assert!((0..6).all(|i| map.get(&(i << 40)) == Some(&i)));
+        assert_eq!(map.remove(&0), Some(0));
+        // a stashed key moved into the freed slot
+        assert_eq!(map.stash_len(), 3);
+        assert!((1..6).all(|i| map.get(&(i << 40)) == Some(&i)));
+    }
+
+    #[test]
+    #[should_panic(expected = "too many keys collide whatever the seed")]
+    fn gives_up_on_a_hasher_ignoring_the_seed() {
+        let mut map: CuckooHashMap<u64, u64, Identity> = CuckooHashMap::default();
+        // two slots and the stash hold 6 of them, and no seed or size separates the 7th
+        for i in 0..7 {
+            map.insert(i << 40, i);
+        }
+    }
+
+    #[test]
+    fn hashes_other_keys_with_the_test_hasher() {
+        let mut map: CuckooHashMap<&str, usize, Identity> = CuckooHashMap::default();
+        for (i, word) in ["alpha", "beta", "gamma", "delta"].into_iter().enumerate() {
+            map.insert(word, i);
+        }
+        assert_eq!(map.get("gamma"), Some(&2));
+        assert_eq!(map.len(), 4);
+    }
+
+    #[test]
+    fn matches_std_hash_map() {
+        let mut map = CuckooHashMap::new();
+        let mut expected = HashMap::new();
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..20_000 {
+            let key = rng.gen_range(0..3000);
+            if rng.gen_ratio(1, 3) {
+                assert_eq!(map.remove(&key), expected.remove(&key));
+            } else {
+                let value: u32 = rng.gen();
+                assert_eq!(map.insert(key, value), expected.insert(key, value));
+            }
+        }
+        assert_eq!(map.len(), expected.len());
+        assert!(expected
+            .iter()
+            .all(|(key, value)| map.get(key) == Some(value)));
+        assert_eq!(map.iter().count(), expected.len());
+    }
+
+    #[test]
+    fn works_as_an_exact_filter() {
+        let mut set: CuckooHashMap<&str, ()> = CuckooHashMap::new();
+        BloomFilter::insert(&mut set, "apple");
+        assert!(BloomFilter::contains(&set, &"apple"));
+        assert!(!BloomFilter::contains(&set, &"pear"));
+    }
+}
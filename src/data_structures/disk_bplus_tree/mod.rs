@@ -0,0 +1,779 @@
+//! A B+ tree of byte-string keys and values, stored in a file of fixed-size pages.
+//!
+//! Inner pages only route searches, and every entry lives in a leaf. The leaves are chained from left to
+//! right, so a range scan finds its first leaf and then follows the chain instead of climbing the tree.
+//! Nodes split when their encoded size outgrows a page, and merge with a sibling when they fall under a
+//! quarter of a page and the two fit in one page. Pages freed by merges go on a free list for reuse.
+//!
+//! Pages are read through an LRU cache of a configurable number of pages, and every `insert` or `remove` is
+//! committed through a write-ahead log before it returns, so after a crash the file holds either the state
+//! before the operation or after it.
+
+mod page;
+mod pager;
+
+use self::page::{
+    Page, PageId, INNER_ENTRY_OVERHEAD, LEAF_ENTRY_OVERHEAD, MAX_PAGE_SIZE, MIN_PAGE_SIZE,
+    NODE_HEADER, NO_PAGE,
+};
+pub use self::pager::CacheStats;
+use self::pager::Pager;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+
+const DEFAULT_PAGE_SIZE: usize = 4096;
+const DEFAULT_CACHE_PAGES: usize = 64;
+
+#[derive(Debug)]
+pub enum BPlusTreeError {
+    Io(io::Error),
+    /// The file or the log holds something this tree did not write.
+    Corrupt(&'static str),
+    /// A key and value too large to keep at least four entries in a page.
+    EntryTooLarge {
+        len: usize,
+        max: usize,
+    },
+    /// A node that does not fit in its page, which is not written.
+    PageOverflow {
+        len: usize,
+        page_size: usize,
+    },
+    /// A change was committed to the log but not to the file, which must be opened again to finish it.
+    Poisoned,
+}
+
+impl fmt::Display for BPlusTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BPlusTreeError::Io(err) => write!(f, "I/O error: {err}"),
+            BPlusTreeError::Corrupt(reason) => write!(f, "corrupt B+ tree file: {reason}"),
+            BPlusTreeError::EntryTooLarge { len, max } => {
+                write!(
+                    f,
+                    "entry of {len} bytes is larger than the maximum of {max}"
+                )
+            }
+            BPlusTreeError::PageOverflow { len, page_size } => {
+                write!(f, "node of {len} bytes overflows a page of {page_size}")
+            }
+            BPlusTreeError::Poisoned => {
+                write!(f, "a commit is unfinished, reopen the tree to finish it")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BPlusTreeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BPlusTreeError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BPlusTreeError {
+    fn from(err: io::Error) -> Self {
+        BPlusTreeError::Io(err)
+    }
+}
+
+pub struct BPlusTree {
+    pager: Pager,
+}
+
+/// A page split in two: the smallest key of the new right half, and the page holding it.
+type Split = Option<(Vec<u8>, PageId)>;
+
+impl BPlusTree {
+    /// Opens or creates a tree with pages of 4 KiB and a cache of 64 pages.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, BPlusTreeError> {
+        BPlusTree::open_with(path, DEFAULT_PAGE_SIZE, DEFAULT_CACHE_PAGES)
+    }
+
+    /// Opens or creates a tree that caches up to `cache_pages` pages. A new file gets pages of `page_size`
+    /// bytes, which must be between 256 and 65536, while an existing file keeps the page size it was created
+    /// with.
+    pub fn open_with<P: AsRef<Path>>(
+        path: P,
+        page_size: usize,
+        cache_pages: usize,
+    ) -> Result<Self, BPlusTreeError> {
+        if !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "page size must be between 256 and 65536",
+            )
+            .into());
+        }
+        Ok(BPlusTree {
+            pager: Pager::open(path.as_ref(), page_size, cache_pages)?,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.pager.header.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.pager.header.page_size
+    }
+
+    /// Returns the number of pages in the file, including the header and free pages.
+    pub fn page_count(&self) -> u64 {
+        self.pager.header.page_count
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.pager.stats
+    }
+
+    /// Returns the largest `key.len() + value.len()` an entry may have.
+    pub fn max_entry_len(&self) -> usize {
+        // four entries of this size fit in a page, in a leaf or as keys of an inner page, so a page that
+        // overflows by one entry splits into two halves that each fit in a page
+        (self.page_size() - NODE_HEADER) / 4 - LEAF_ENTRY_OVERHEAD - INNER_ENTRY_OVERHEAD
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, BPlusTreeError> {
+        match self.find_leaf(Some(key))? {
+            Page::Leaf {
+                keys, mut values, ..
+            } => Ok(keys
+                .binary_search_by(|k| k.as_slice().cmp(key))
+                .ok()
+                .map(|index| values.swap_remove(index))),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Inserts a key-value pair, and returns the previous value of the key.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>, BPlusTreeError> {
+        let len = key.len() + value.len();
+        if len > self.max_entry_len() {
+            return Err(BPlusTreeError::EntryTooLarge {
+                len,
+                max: self.max_entry_len(),
+            });
+        }
+        self.atomically(|tree| {
+            let root = tree.pager.header.root;
+            let (old, split) = tree.insert_into(root, key, value)?;
+            if let Some((separator, right)) = split {
+                // the tree grows at the root
+                tree.pager.header.root = tree.pager.allocate(Page::Inner {
+                    keys: vec![separator],
+                    children: vec![root, right],
+                })?;
+            }
+            if old.is_none() {
+                tree.pager.header.len += 1;
+            }
+            Ok(old)
+        })
+    }
+
+    /// Removes a key, and returns its value.
+    pub fn remove(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, BPlusTreeError> {
+        self.atomically(|tree| {
+            let root = tree.pager.header.root;
+            let old = tree.remove_from(root, key)?;
+            if old.is_some() {
+                tree.pager.header.len -= 1;
+                // the tree shrinks at the root when its last two children merged
+                if let Page::Inner { keys, children } = tree.pager.read(root)? {
+                    if keys.is_empty() {
+                        tree.pager.header.root = children[0];
+                        tree.pager.free(root);
+                    }
+                }
+            }
+            Ok(old)
+        })
+    }
+
+    /// Returns the entries whose keys lie in `range`, in ascending order of keys, reading the leaves one at a
+    /// time along their chain.
+    pub fn range<'k, R: RangeBounds<&'k [u8]>>(
+        &mut self,
+        range: R,
+    ) -> Result<Scan<'_>, BPlusTreeError> {
+        let start = match range.start_bound() {
+            Bound::Included(start) | Bound::Excluded(start) => Some(*start),
+            Bound::Unbounded => None,
+        };
+        let leaf = self.find_leaf(start)?;
+        let mut scan = Scan {
+            buffer: VecDeque::new(),
+            next: NO_PAGE,
+            end: range.end_bound().map(|end| end.to_vec()),
+            tree: self,
+        };
+        scan.fill(leaf);
+        let before_start = |key: &[u8]| match range.start_bound() {
+            Bound::Included(start) => key < *start,
+            Bound::Excluded(start) => key <= *start,
+            Bound::Unbounded => false,
+        };
+        while scan
+            .buffer
+            .front()
+            .is_some_and(|(key, _)| before_start(key))
+        {
+            scan.buffer.pop_front();
+        }
+        Ok(scan)
+    }
+
+    /// Runs `change`, then commits it, or forgets it if it fails before it reaches the log.
+    fn atomically<T, F>(&mut self, change: F) -> Result<T, BPlusTreeError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, BPlusTreeError>,
+    {
+        match change(self).and_then(|result| self.pager.commit().map(|()| result)) {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                self.pager.rollback()?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Returns the leaf that would hold `key`, or the first leaf.
+    fn find_leaf(&mut self, key: Option<&[u8]>) -> Result<Page, BPlusTreeError> {
+        let mut page = self.pager.read(self.pager.header.root)?;
+        while let Page::Inner { keys, children } = page {
+            let index = key.map_or(0, |key| child_index(&keys, key));
+            page = self.pager.read(children[index])?;
+        }
+        Ok(page)
+    }
+
+    fn insert_into(
+        &mut self,
+        id: PageId,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(Option<Vec<u8>>, Split), BPlusTreeError> {
+        match self.pager.read(id)? {
+            Page::Leaf {
+                mut keys,
+                mut values,
+                next,
+            } => {
+                let old = match keys.binary_search_by(|k| k.as_slice().cmp(key)) {
+                    Ok(index) => Some(std::mem::replace(&mut values[index], value.to_vec())),
+                    Err(index) => {
+                        keys.insert(index, key.to_vec());
+                        values.insert(index, value.to_vec());
+                        None
+                    }
+                };
+                let split = self.store(id, Page::Leaf { keys, values, next })?;
+                Ok((old, split))
+            }
+            Page::Inner {
+                mut keys,
+                mut children,
+            } => {
+                let index = child_index(&keys, key);
+                let (old, split) = self.insert_into(children[index], key, value)?;
+                let Some((separator, right)) = split else {
+                    return Ok((old, None));
+                };
+                keys.insert(index, separator);
+                children.insert(index + 1, right);
+                let split = self.store(id, Page::Inner { keys, children })?;
+                Ok((old, split))
+            }
+            Page::Free { .. } => Err(BPlusTreeError::Corrupt("tree links to a free page")),
+        }
+    }
+
+    /// Writes `page` to `id`, splitting it by size if it does not fit.
+    fn store(&mut self, id: PageId, page: Page) -> Result<Split, BPlusTreeError> {
+        let page_size = self.page_size();
+        if page.encoded_len() <= page_size {
+            self.pager.write(id, page);
+            return Ok(None);
+        }
+        let (left, separator, right) = match page {
+            Page::Leaf {
+                mut keys,
+                mut values,
+                next,
+            } => {
+                let mid = split_index(
+                    keys.iter()
+                        .zip(&values)
+                        .map(|(k, v)| k.len() + v.len() + LEAF_ENTRY_OVERHEAD),
+                );
+                let right = Page::Leaf {
+                    keys: keys.split_off(mid),
+                    values: values.split_off(mid),
+                    next,
+                };
+                let separator = keys_of(&right)[0].clone();
+                (Page::Leaf { keys, values, next }, separator, right)
+            }
+            Page::Inner {
+                mut keys,
+                mut children,
+            } => {
+                let mid = split_index(keys.iter().map(|k| k.len() + INNER_ENTRY_OVERHEAD));
+                let right = Page::Inner {
+                    keys: keys.split_off(mid + 1),
+                    children: children.split_off(mid + 1),
+                };
+                let separator = keys.pop().unwrap();
+                (Page::Inner { keys, children }, separator, right)
+            }
+            Page::Free { .. } => unreachable!(),
+        };
+        for half in [&left, &right] {
+            if half.encoded_len() > page_size {
+                return Err(BPlusTreeError::PageOverflow {
+                    len: half.encoded_len(),
+                    page_size,
+                });
+            }
+        }
+        let right = self.pager.allocate(right)?;
+        let left = match left {
+            // link the new leaf into the chain
+            Page::Leaf { keys, values, .. } => Page::Leaf {
+                keys,
+                values,
+                next: right,
+            },
+            inner => inner,
+        };
+        self.pager.write(id, left);
+        Ok(Some((separator, right)))
+    }
+
+    fn remove_from(&mut self, id: PageId, key: &[u8]) -> Result<Option<Vec<u8>>, BPlusTreeError> {
+        match self.pager.read(id)? {
+            Page::Leaf {
+                mut keys,
+                mut values,
+                next,
+            } => match keys.binary_search_by(|k| k.as_slice().cmp(key)) {
+                Ok(index) => {
+                    keys.remove(index);
+                    let old = values.remove(index);
+                    self.pager.write(id, Page::Leaf { keys, values, next });
+                    Ok(Some(old))
+                }
+                Err(_) => Ok(None),
+            },
+            Page::Inner {
+                mut keys,
+                mut children,
+            } => {
+                let index = child_index(&keys, key);
+                let old = self.remove_from(children[index], key)?;
+                if old.is_some() && self.merge_underfull(&mut keys, &mut children, index)? {
+                    self.pager.write(id, Page::Inner { keys, children });
+                }
+                Ok(old)
+            }
+            Page::Free { .. } => Err(BPlusTreeError::Corrupt("tree links to a free page")),
+        }
+    }
+
+    /// Merges the child at `index` with a sibling if it is less than a quarter full and the two fit in one
+    /// page, and returns whether the parent's `keys` and `children` changed.
+    fn merge_underfull(
+        &mut self,
+        keys: &mut Vec<Vec<u8>>,
+        children: &mut Vec<PageId>,
+        index: usize,
+    ) -> Result<bool, BPlusTreeError> {
+        let page_size = self.page_size();
+        if self.pager.read(children[index])?.encoded_len() >= page_size / 4 || children.len() < 2 {
+            return Ok(false);
+        }
+        let left = index.min(children.len() - 2);
+        let merged = match (
+            self.pager.read(children[left])?,
+            self.pager.read(children[left + 1])?,
+        ) {
+            (
+                Page::Leaf {
+                    keys: mut left_keys,
+                    values: mut left_values,
+                    ..
+                },
+                Page::Leaf {
+                    keys: right_keys,
+                    values: right_values,
+                    next,
+                },
+            ) => {
+                left_keys.extend(right_keys);
+                left_values.extend(right_values);
+                Page::Leaf {
+                    keys: left_keys,
+                    values: left_values,
+                    next,
+                }
+            }
+            (
+                Page::Inner {
+                    keys: mut left_keys,
+                    children: mut left_children,
+                },
+                Page::Inner {
+                    keys: right_keys,
+                    children: right_children,
+                },
+            ) => {
+                // the separator comes back down between the two halves
+                left_keys.push(keys[left].clone());
+                left_keys.extend(right_keys);
+                left_children.extend(right_children);
+                Page::Inner {
+                    keys: left_keys,
+                    children: left_children,
+                }
+            }
+            _ => return Err(BPlusTreeError::Corrupt("siblings of different kinds")),
+        };
+        if merged.encoded_len() > page_size {
+            return Ok(false);
+        }
+        self.pager.write(children[left], merged);
+        self.pager.free(children[left + 1]);
+        keys.remove(left);
+        children.remove(left + 1);
+        Ok(true)
+    }
+}
+
+/// Returns the child of an inner page whose subtree would hold `key`.
+fn child_index(keys: &[Vec<u8>], key: &[u8]) -> usize {
+    keys.partition_point(|k| k.as_slice() <= key)
+}
+
+/// Returns where to split entries of the given sizes so that both halves have about as many bytes, and
+/// neither is empty.
+fn split_index<I: Iterator<Item = usize> + Clone>(sizes: I) -> usize {
+    let total: usize = sizes.clone().sum();
+    let count = sizes.clone().count();
+    let mut prefix = 0;
+    let index = sizes
+        .take_while(|size| {
+            prefix += size;
+            prefix < total / 2
+        })
+        .count()
+        + 1;
+    index.clamp(1, count - 1)
+}
+
+fn keys_of(page: &Page) -> &[Vec<u8>] {
+    match page {
+        Page::Leaf { keys, .. } | Page::Inner { keys, .. } => keys,
+        Page::Free { .. } => &[],
+    }
+}
+
+/// An iterator over the entries of a range of a `BPlusTree`, which reads the next leaf when it runs out of
+/// entries.
+pub struct Scan<'a> {
+    tree: &'a mut BPlusTree,
+    /// The remaining entries of the current leaf.
+    buffer: VecDeque<(Vec<u8>, Vec<u8>)>,
+    next: PageId,
+    end: Bound<Vec<u8>>,
+}
+
+impl Scan<'_> {
+    fn fill(&mut self, leaf: Page) {
+        if let Page::Leaf { keys, values, next } = leaf {
+            self.buffer.extend(keys.into_iter().zip(values));
+            self.next = next;
+        }
+    }
+}
+
+impl Iterator for Scan<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>), BPlusTreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.is_empty() && self.next != NO_PAGE {
+            match self.tree.pager.read(self.next) {
+                Ok(leaf) => self.fill(leaf),
+                Err(err) => {
+                    self.next = NO_PAGE;
+                    return Some(Err(err));
+                }
+            }
+        }
+        let (key, value) = self.buffer.pop_front()?;
+        let past_end = match &self.end {
+            Bound::Included(end) => key > *end,
+            Bound::Excluded(end) => key >= *end,
+            Bound::Unbounded => false,
+        };
+        if past_end {
+            self.buffer.clear();
+            self.next = NO_PAGE;
+            return None;
+        }
+        Some(Ok((key, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::page::{Page, NO_PAGE};
+    use super::{BPlusTree, BPlusTreeError};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    /// A file in the temporary directory, deleted with its log when dropped.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("bplus-{}-{name}.db", std::process::id()));
+            let temp = TempPath(path);
+            temp.remove();
+            temp
+        }
+
+        fn wal(&self) -> PathBuf {
+            let mut wal = self.0.clone().into_os_string();
+            wal.push(".wal");
+            wal.into()
+        }
+
+        fn remove(&self) {
+            let _ = std::fs::remove_file(&self.0);
+            let _ = std::fs::remove_file(self.wal());
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            self.remove();
+        }
+    }
+
+    fn key(i: u32) -> Vec<u8> {
+        format!("key-{i:05}").into_bytes()
+    }
+
+    fn scan(tree: &mut BPlusTree) -> Vec<(Vec<u8>, Vec<u8>)> {
+        tree.range(..).unwrap().collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn persists_across_reopen() {
+        let path = TempPath::new("reopen");
+        let mut expected = BTreeMap::new();
+        {
+            let mut tree = BPlusTree::open_with(&path.0, 256, 8).unwrap();
+            // a stride through the keys inserts them out of order
+            for i in (0..600).map(|i| i * 7 % 600) {
+                let value = format!("value {i}").into_bytes();
+                assert_eq!(tree.insert(&key(i), &value).unwrap(), None);
+                expected.insert(key(i), value);
+            }
+            assert_eq!(
+                tree.insert(&key(5), b"replaced").unwrap(),
+                Some(b"value 5".to_vec())
+            );
+            expected.insert(key(5), b"replaced".to_vec());
+            assert!(tree.cache_stats().evictions > 0);
+            assert!(tree.pager.cached_pages() <= 8);
+        }
+        let mut tree = BPlusTree::open_with(&path.0, 4096, 8).unwrap();
+        // the file keeps its page size
+        assert_eq!(tree.page_size(), 256);
+        assert_eq!(tree.len(), 600);
+        assert_eq!(tree.get(&key(5)).unwrap(), Some(b"replaced".to_vec()));
+        assert_eq!(tree.get(b"missing").unwrap(), None);
+        let entries: Vec<_> = expected.into_iter().collect();
+        assert_eq!(scan(&mut tree), entries);
+    }
+
+    #[test]
+    fn range_scans_follow_leaf_links() {
+        let path = TempPath::new("range");
+        let mut tree = BPlusTree::open_with(&path.0, 256, 4).unwrap();
+        for i in 0..300 {
+            tree.insert(&key(2 * i), b"").unwrap();
+        }
+        let keys = |tree: &mut BPlusTree, start: &[u8], end: &[u8]| -> Vec<Vec<u8>> {
+            tree.range(start..end)
+                .unwrap()
+                .map(|entry| entry.unwrap().0)
+                .collect()
+        };
+        assert_eq!(
+            keys(&mut tree, &key(101), &key(107)),
+            [key(102), key(104), key(106)]
+        );
+        assert_eq!(keys(&mut tree, &key(300), &key(500)).len(), 100);
+        assert!(keys(&mut tree, &key(1001), &key(1002)).is_empty());
+        let tail = tree.range(&key(590)[..]..).unwrap().count();
+        assert_eq!(tail, 5);
+    }
+
+    #[test]
+    fn matches_btree_map_with_removals() {
+        let path = TempPath::new("removals");
+        let mut tree = BPlusTree::open_with(&path.0, 256, 16).unwrap();
+        let mut expected = BTreeMap::new();
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..3000 {
+            let k = key(rng.gen_range(0..400));
+            if rng.gen_ratio(1, 3) {
+                assert_eq!(tree.remove(&k).unwrap(), expected.remove(&k));
+            } else {
+                let value = vec![b'v'; rng.gen_range(0..20)];
+                assert_eq!(tree.insert(&k, &value).unwrap(), expected.insert(k, value));
+            }
+        }
+        assert_eq!(tree.len(), expected.len() as u64);
+        let entries: Vec<_> = expected.into_iter().collect();
+        assert_eq!(scan(&mut tree), entries);
+    }
+
+    #[test]
+    fn reuses_freed_pages() {
+        let path = TempPath::new("free-list");
+        let mut tree = BPlusTree::open_with(&path.0, 256, 16).unwrap();
+        for i in 0..500 {
+            tree.insert(&key(i), b"some value").unwrap();
+        }
+        let pages = tree.page_count();
+        for i in 0..500 {
+            assert!(tree.remove(&key(i)).unwrap().is_some());
+        }
+        assert!(tree.is_empty());
+        for i in 0..500 {
+            tree.insert(&key(i), b"some value").unwrap();
+        }
+        assert!(tree.page_count() <= pages + 2);
+    }
+
+    #[test]
+    fn recovers_a_commit_from_the_log() {
+        let path = TempPath::new("recover");
+        {
+            let mut tree = BPlusTree::open_with(&path.0, 256, 8).unwrap();
+            tree.insert(b"before", b"1").unwrap();
+            // the first write to the file fails, and redoing the commit from the log succeeds
+            tree.pager.failing_file_writes = 1;
+            assert_eq!(tree.insert(b"retried", b"2").unwrap(), None);
+            assert_eq!(tree.get(b"retried").unwrap(), Some(b"2".to_vec()));
+            // the file refuses all writes: the commit is in the log, so it still succeeds
+            tree.pager.failing_file_writes = usize::MAX;
+            assert_eq!(tree.insert(b"during", b"3").unwrap(), None);
+            assert!(matches!(tree.get(b"before"), Err(BPlusTreeError::Poisoned)));
+            assert!(matches!(
+                tree.insert(b"after", b"4"),
+                Err(BPlusTreeError::Poisoned)
+            ));
+        }
+        let mut tree = BPlusTree::open_with(&path.0, 256, 8).unwrap();
+        assert_eq!(tree.get(b"during").unwrap(), Some(b"3".to_vec()));
+        assert_eq!(tree.get(b"after").unwrap(), None);
+        assert_eq!(tree.len(), 3);
+    }
+
+    #[test]
+    fn ignores_a_torn_log() {
+        let path = TempPath::new("torn");
+        {
+            let mut tree = BPlusTree::open_with(&path.0, 256, 8).unwrap();
+            for i in 0..50 {
+                tree.insert(&key(i), b"kept").unwrap();
+            }
+            // the process dies while writing the log
+            tree.pager.tear_wal = true;
+            assert!(tree.insert(b"lost", b"x").is_err());
+            // the failed change is forgotten in memory too
+            assert_eq!(tree.get(b"lost").unwrap(), None);
+        }
+        assert!(std::fs::metadata(path.wal()).unwrap().len() > 0);
+        let mut tree = BPlusTree::open_with(&path.0, 256, 8).unwrap();
+        assert_eq!(tree.get(b"lost").unwrap(), None);
+        assert_eq!(tree.len(), 50);
+        assert_eq!(scan(&mut tree).len(), 50);
+    }
+
+    #[test]
+    fn forgets_a_log_that_could_not_be_synced() {
+        let path = TempPath::new("unsynced");
+        {
+            let mut tree = BPlusTree::open_with(&path.0, 256, 8).unwrap();
+            tree.insert(b"kept", b"1").unwrap();
+            // the whole log is written, but syncing it fails
+            tree.pager.fail_wal_sync = true;
+            assert!(tree.insert(b"failed", b"2").is_err());
+            assert_eq!(tree.get(b"failed").unwrap(), None);
+        }
+        assert_eq!(std::fs::metadata(path.wal()).unwrap().len(), 0);
+        let mut tree = BPlusTree::open_with(&path.0, 256, 8).unwrap();
+        assert_eq!(tree.get(b"failed").unwrap(), None);
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn refuses_to_truncate_a_node() {
+        let leaf = Page::Leaf {
+            keys: vec![vec![1; 200], vec![2; 200]],
+            values: vec![Vec::new(), Vec::new()],
+            next: NO_PAGE,
+        };
+        assert_eq!(leaf.encode(512).unwrap().len(), 512);
+        assert!(matches!(
+            leaf.encode(256),
+            Err(BPlusTreeError::PageOverflow {
+                len: 419,
+                page_size: 256
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let path = TempPath::new("errors");
+        let mut tree = BPlusTree::open_with(&path.0, 256, 8).unwrap();
+        let big = vec![0; tree.max_entry_len() + 1];
+        assert!(matches!(
+            tree.insert(&big, b""),
+            Err(BPlusTreeError::EntryTooLarge { .. })
+        ));
+        std::fs::write(&path.0, b"definitely not a tree").unwrap();
+        assert!(matches!(
+            BPlusTree::open(&path.0),
+            Err(BPlusTreeError::Corrupt(_))
+        ));
+        // a header whose page size is too small to hold a node, or too large to read
+        for page_size in [16u32, 1 << 30] {
+            let mut header = b"BPLUSTRE".to_vec();
+            header.extend_from_slice(&page_size.to_le_bytes());
+            header.resize(256, 0);
+            std::fs::write(&path.0, &header).unwrap();
+            assert!(matches!(
+                BPlusTree::open(&path.0),
+                Err(BPlusTreeError::Corrupt("page size out of range"))
+            ));
+        }
+    }
+}
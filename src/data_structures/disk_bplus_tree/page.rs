@@ -0,0 +1,233 @@
+//! The layout of the pages in the file. All integers are little-endian.
+//!
+//! Page 0 is the header:
+//!
+//! ```text
+//! magic "BPLUSTRE" | page size: u32 | root: u64 | page count: u64 | free list head: u64 | len: u64
+//! ```
+//!
+//! Every other page holds one node, or is on the free list:
+//!
+//! ```text
+//! leaf:  1 | count: u16 | next leaf: u64  | count × (key len: u16 | value len: u16 | key | value)
+//! inner: 2 | count: u16 | first child: u64 | count × (key len: u16 | key | child: u64)
+//! free:  3 | next free page: u64
+//! ```
+//!
+//! Page 0 never holds a node, so it doubles as the null page id.
+
+use super::BPlusTreeError;
+
+pub type PageId = u64;
+
+/// The null page id, for the end of the leaf chain and of the free list.
+pub const NO_PAGE: PageId = 0;
+
+const MAGIC: &[u8; 8] = b"BPLUSTRE";
+
+/// The smallest and largest page sizes a file may use.
+pub const MIN_PAGE_SIZE: usize = 256;
+pub const MAX_PAGE_SIZE: usize = 65536;
+
+/// The type and count bytes and the page id at the start of node pages.
+pub const NODE_HEADER: usize = 11;
+
+/// The lengths stored with every leaf entry.
+pub const LEAF_ENTRY_OVERHEAD: usize = 4;
+
+/// The length and child id stored with every inner key.
+pub const INNER_ENTRY_OVERHEAD: usize = 10;
+
+const LEAF: u8 = 1;
+const INNER: u8 = 2;
+const FREE: u8 = 3;
+
+/// The contents of page 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub page_size: usize,
+    pub root: PageId,
+    pub page_count: u64,
+    pub free_head: PageId,
+    pub len: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Page {
+    /// Entries sorted by key, and the leaf with the next keys.
+    Leaf {
+        keys: Vec<Vec<u8>>,
+        values: Vec<Vec<u8>>,
+        next: PageId,
+    },
+    /// Children and the keys between them: `keys[i]` is the smallest key under `children[i + 1]`.
+    Inner {
+        keys: Vec<Vec<u8>>,
+        children: Vec<PageId>,
+    },
+    Free {
+        next: PageId,
+    },
+}
+
+impl Page {
+    /// The number of bytes the page takes once encoded.
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Page::Leaf { keys, values, .. } => {
+                let entries: usize = keys
+                    .iter()
+                    .zip(values)
+                    .map(|(k, v)| k.len() + v.len())
+                    .sum();
+                NODE_HEADER + entries + keys.len() * LEAF_ENTRY_OVERHEAD
+            }
+            Page::Inner { keys, .. } => {
+                let entries: usize = keys.iter().map(Vec::len).sum();
+                NODE_HEADER + entries + keys.len() * INNER_ENTRY_OVERHEAD
+            }
+            Page::Free { .. } => 9,
+        }
+    }
+
+    /// Encodes the page into a buffer of `page_size` bytes, padded with zeros, or fails if it does not fit.
+    pub fn encode(&self, page_size: usize) -> Result<Vec<u8>, BPlusTreeError> {
+        let mut buf = Vec::with_capacity(page_size);
+        match self {
+            Page::Leaf { keys, values, next } => {
+                buf.push(LEAF);
+                buf.extend_from_slice(&(keys.len() as u16).to_le_bytes());
+                buf.extend_from_slice(&next.to_le_bytes());
+                for (key, value) in keys.iter().zip(values) {
+                    buf.extend_from_slice(&(key.len() as u16).to_le_bytes());
+                    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+                    buf.extend_from_slice(key);
+                    buf.extend_from_slice(value);
+                }
+            }
+            Page::Inner { keys, children } => {
+                buf.push(INNER);
+                buf.extend_from_slice(&(keys.len() as u16).to_le_bytes());
+                buf.extend_from_slice(&children[0].to_le_bytes());
+                for (key, child) in keys.iter().zip(&children[1..]) {
+                    buf.extend_from_slice(&(key.len() as u16).to_le_bytes());
+                    buf.extend_from_slice(key);
+                    buf.extend_from_slice(&child.to_le_bytes());
+                }
+            }
+            Page::Free { next } => {
+                buf.push(FREE);
+                buf.extend_from_slice(&next.to_le_bytes());
+            }
+        }
+        if buf.len() > page_size {
+            return Err(BPlusTreeError::PageOverflow {
+                len: buf.len(),
+                page_size,
+            });
+        }
+        buf.resize(page_size, 0);
+        Ok(buf)
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Page, BPlusTreeError> {
+        let mut reader = Reader { buf, pos: 1 };
+        match buf.first() {
+            Some(&LEAF) => {
+                let count = reader.u16()?;
+                let next = reader.u64()?;
+                let mut keys = Vec::with_capacity(count);
+                let mut values = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let key_len = reader.u16()?;
+                    let value_len = reader.u16()?;
+                    keys.push(reader.bytes(key_len)?);
+                    values.push(reader.bytes(value_len)?);
+                }
+                Ok(Page::Leaf { keys, values, next })
+            }
+            Some(&INNER) => {
+                let count = reader.u16()?;
+                let mut children = vec![reader.u64()?];
+                let mut keys = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let key_len = reader.u16()?;
+                    keys.push(reader.bytes(key_len)?);
+                    children.push(reader.u64()?);
+                }
+                Ok(Page::Inner { keys, children })
+            }
+            Some(&FREE) => Ok(Page::Free {
+                next: reader.u64()?,
+            }),
+            _ => Err(BPlusTreeError::Corrupt("unknown page type")),
+        }
+    }
+}
+
+impl Header {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.page_size);
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&(self.page_size as u32).to_le_bytes());
+        for field in [self.root, self.page_count, self.free_head, self.len] {
+            buf.extend_from_slice(&field.to_le_bytes());
+        }
+        buf.resize(self.page_size, 0);
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Header, BPlusTreeError> {
+        if buf.get(..MAGIC.len()) != Some(MAGIC) {
+            return Err(BPlusTreeError::Corrupt("not a B+ tree file"));
+        }
+        let mut reader = Reader {
+            buf,
+            pos: MAGIC.len(),
+        };
+        let page_size = reader.u32()? as usize;
+        if !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+            return Err(BPlusTreeError::Corrupt("page size out of range"));
+        }
+        Ok(Header {
+            page_size,
+            root: reader.u64()?,
+            page_count: reader.u64()?,
+            free_head: reader.u64()?,
+            len: reader.u64()?,
+        })
+    }
+}
+
+/// Reads little-endian fields from a page, failing on truncated data instead of panicking.
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, len: usize) -> Result<Vec<u8>, BPlusTreeError> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or(BPlusTreeError::Corrupt("truncated page"))?;
+        self.pos += len;
+        Ok(bytes.to_vec())
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], BPlusTreeError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u16(&mut self) -> Result<usize, BPlusTreeError> {
+        Ok(u16::from_le_bytes(self.array()?) as usize)
+    }
+
+    fn u32(&mut self) -> Result<u32, BPlusTreeError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, BPlusTreeError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+}
@@ -0,0 +1,399 @@
+//! Reads and writes pages through an LRU cache, and makes every commit atomic with a write-ahead log.
+//!
+//! Changed pages stay in the cache until the next commit, which first appends their new images to the log
+//! and syncs it, then writes them to the file and empties the log. A crash before the log is complete leaves
+//! the file untouched, and a crash after it is redone from the log when the file is opened again, so the file
+//! always holds the state of some commit. Only clean pages are evicted, so the file never sees a change before
+//! it is in the log.
+//!
+//! The log holds a single commit:
+//!
+//! ```text
+//! magic "BPLUSWAL" | page size: u32 | count: u32 | count × (page id: u64 | page) | checksum: u64
+//! ```
+
+use super::page::{Header, Page, PageId, NO_PAGE};
+use super::BPlusTreeError;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek as _, SeekFrom, Write as _};
+use std::path::Path;
+
+const WAL_MAGIC: &[u8; 8] = b"BPLUSWAL";
+
+/// Counters of the page cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+struct Cached {
+    page: Page,
+    dirty: bool,
+    /// When the page was last used, as a key into `Pager::lru`.
+    tick: u64,
+}
+
+pub struct Pager {
+    file: File,
+    wal: File,
+    pub header: Header,
+    cache: HashMap<PageId, Cached>,
+    /// The cached pages by the time of their last use, oldest first.
+    lru: BTreeMap<u64, PageId>,
+    tick: u64,
+    capacity: usize,
+    pub stats: CacheStats,
+    /// Set when a commit is in the log but could not be written to the file. The cache then holds the committed
+    /// state while the file holds part of it, so the pager refuses to work until the file is opened again, which
+    /// redoes the commit from the log.
+    poisoned: bool,
+    /// The number of page writes to the file that fail from now on.
+    #[cfg(test)]
+    pub failing_file_writes: usize,
+    /// Makes the next write to the log stop short and fail, as if the process died during it.
+    #[cfg(test)]
+    pub tear_wal: bool,
+    /// Makes the next sync of the log fail after the whole log was written.
+    #[cfg(test)]
+    pub fail_wal_sync: bool,
+}
+
+impl Pager {
+    /// Opens the file at `path` and its log next to it, finishing a commit that was interrupted by a crash.
+    /// A new file starts with an empty root leaf.
+    pub fn open(path: &Path, page_size: usize, capacity: usize) -> Result<Pager, BPlusTreeError> {
+        let mut wal_path = OsString::from(path);
+        wal_path.push(".wal");
+        let open = |path: &Path| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+        };
+        let mut pager = Pager {
+            file: open(path)?,
+            wal: open(Path::new(&wal_path))?,
+            header: Header {
+                page_size,
+                root: 1,
+                page_count: 2,
+                free_head: NO_PAGE,
+                len: 0,
+            },
+            cache: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            capacity: capacity.max(1),
+            stats: CacheStats::default(),
+            poisoned: false,
+            #[cfg(test)]
+            failing_file_writes: 0,
+            #[cfg(test)]
+            tear_wal: false,
+            #[cfg(test)]
+            fail_wal_sync: false,
+        };
+        pager.recover()?;
+        if pager.file.metadata()?.len() == 0 {
+            pager.write(
+                1,
+                Page::Leaf {
+                    keys: Vec::new(),
+                    values: Vec::new(),
+                    next: NO_PAGE,
+                },
+            );
+            pager.commit()?;
+        } else {
+            pager.header = pager.read_header()?;
+        }
+        Ok(pager)
+    }
+
+    fn read_header(&mut self) -> Result<Header, BPlusTreeError> {
+        let mut buf = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        Read::by_ref(&mut self.file)
+            .take(44)
+            .read_to_end(&mut buf)?;
+        Header::decode(&buf)
+    }
+
+    /// Redoes the commit in the log if it is complete, and empties the log.
+    fn recover(&mut self) -> Result<(), BPlusTreeError> {
+        let mut log = Vec::new();
+        self.wal.seek(SeekFrom::Start(0))?;
+        self.wal.read_to_end(&mut log)?;
+        if let Some((page_size, pages)) = parse_wal(&log) {
+            for (id, page) in pages {
+                self.write_page(id, page_size, page)?;
+            }
+            self.file.sync_data()?;
+        }
+        // an incomplete log belongs to a commit that never happened
+        self.wal.set_len(0)?;
+        self.wal.sync_data()?;
+        Ok(())
+    }
+
+    fn write_page(
+        &mut self,
+        id: PageId,
+        page_size: usize,
+        page: &[u8],
+    ) -> Result<(), BPlusTreeError> {
+        #[cfg(test)]
+        if self.failing_file_writes > 0 {
+            self.failing_file_writes -= 1;
+            return Err(std::io::Error::other("simulated write failure").into());
+        }
+        self.file.seek(SeekFrom::Start(id * page_size as u64))?;
+        self.file.write_all(page)?;
+        Ok(())
+    }
+
+    /// Returns a copy of a page.
+    pub fn read(&mut self, id: PageId) -> Result<Page, BPlusTreeError> {
+        if self.poisoned {
+            return Err(BPlusTreeError::Poisoned);
+        }
+        if id == NO_PAGE || id >= self.header.page_count {
+            return Err(BPlusTreeError::Corrupt("page id out of bounds"));
+        }
+        if self.cache.contains_key(&id) {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            let mut buf = vec![0; self.header.page_size];
+            self.file
+                .seek(SeekFrom::Start(id * self.header.page_size as u64))?;
+            self.file.read_exact(&mut buf)?;
+            let page = Page::decode(&buf)?;
+            self.insert(id, page, false);
+        }
+        let tick = self.touch(id);
+        let cached = self.cache.get_mut(&id).unwrap();
+        cached.tick = tick;
+        Ok(cached.page.clone())
+    }
+
+    /// Replaces a page in the cache; the file sees it at the next commit.
+    pub fn write(&mut self, id: PageId, page: Page) {
+        self.insert(id, page, true);
+        let tick = self.touch(id);
+        self.cache.get_mut(&id).unwrap().tick = tick;
+    }
+
+    /// Stores `page` in a page from the free list, or in a new page at the end of the file.
+    pub fn allocate(&mut self, page: Page) -> Result<PageId, BPlusTreeError> {
+        let id = if self.header.free_head == NO_PAGE {
+            self.header.page_count += 1;
+            self.header.page_count - 1
+        } else {
+            let id = self.header.free_head;
+            match self.read(id)? {
+                Page::Free { next } => self.header.free_head = next,
+                _ => return Err(BPlusTreeError::Corrupt("free list holds a node")),
+            }
+            id
+        };
+        self.write(id, page);
+        Ok(id)
+    }
+
+    /// Puts a page on the free list.
+    pub fn free(&mut self, id: PageId) {
+        let next = self.header.free_head;
+        self.write(id, Page::Free { next });
+        self.header.free_head = id;
+    }
+
+    fn insert(&mut self, id: PageId, page: Page, dirty: bool) {
+        match self.cache.get_mut(&id) {
+            Some(cached) => {
+                cached.page = page;
+                cached.dirty |= dirty;
+            }
+            None => {
+                self.evict(self.capacity - 1);
+                self.cache.insert(
+                    id,
+                    Cached {
+                        page,
+                        dirty,
+                        tick: 0,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Marks a cached page as the most recently used, and returns its new tick.
+    fn touch(&mut self, id: PageId) -> u64 {
+        if let Some(cached) = self.cache.get(&id) {
+            self.lru.remove(&cached.tick);
+        }
+        self.tick += 1;
+        self.lru.insert(self.tick, id);
+        self.tick
+    }
+
+    /// Drops the least recently used clean pages until at most `limit` pages are cached. Dirty pages stay until
+    /// they are committed, so the cache may hold more pages than its capacity during a large change.
+    fn evict(&mut self, limit: usize) {
+        let mut excess = self.cache.len().saturating_sub(limit);
+        let victims: Vec<(u64, PageId)> = self
+            .lru
+            .iter()
+            .filter(|(_, id)| !self.cache[id].dirty)
+            .take_while(|_| {
+                let more = excess > 0;
+                excess = excess.saturating_sub(1);
+                more
+            })
+            .map(|(&tick, &id)| (tick, id))
+            .collect();
+        for (tick, id) in victims {
+            self.lru.remove(&tick);
+            self.cache.remove(&id);
+            self.stats.evictions += 1;
+        }
+    }
+
+    /// Makes the changes since the last commit durable, as a whole or not at all.
+    ///
+    /// An error means the changes are not committed, and must be rolled back. Once the log is synced the changes
+    /// are committed whatever happens next: if writing them to the file fails, they are redone from the log, and
+    /// if that fails too, the pager is poisoned until the file is opened again.
+    pub fn commit(&mut self) -> Result<(), BPlusTreeError> {
+        if self.poisoned {
+            return Err(BPlusTreeError::Poisoned);
+        }
+        let page_size = self.header.page_size;
+        let mut dirty: Vec<PageId> = self
+            .cache
+            .iter()
+            .filter(|(_, cached)| cached.dirty)
+            .map(|(&id, _)| id)
+            .collect();
+        dirty.sort_unstable();
+        let mut pages = vec![(0, self.header.encode())];
+        for &id in &dirty {
+            pages.push((id, self.cache[&id].page.encode(page_size)?));
+        }
+
+        let mut log = Vec::with_capacity(16 + pages.len() * (8 + page_size) + 8);
+        log.extend_from_slice(WAL_MAGIC);
+        log.extend_from_slice(&(page_size as u32).to_le_bytes());
+        log.extend_from_slice(&(pages.len() as u32).to_le_bytes());
+        for (id, page) in &pages {
+            log.extend_from_slice(&id.to_le_bytes());
+            log.extend_from_slice(page);
+        }
+        log.extend_from_slice(&checksum(&log).to_le_bytes());
+        self.wal.set_len(0)?;
+        self.wal.seek(SeekFrom::Start(0))?;
+        #[cfg(test)]
+        if self.tear_wal {
+            self.tear_wal = false;
+            self.wal.write_all(&log[..log.len() - 3])?;
+            return Err(std::io::Error::other("simulated torn write").into());
+        }
+        if let Err(err) = self.write_wal(&log) {
+            // the log may be complete on disk: empty it, or opening the file again would redo a commit that is
+            // rolled back. If even that fails, only opening the file again can tell whether it happened.
+            if self
+                .wal
+                .set_len(0)
+                .and_then(|()| self.wal.sync_data())
+                .is_err()
+            {
+                self.poisoned = true;
+            }
+            return Err(err);
+        }
+
+        // the changes are committed: the file must get them, now or when it is opened again
+        if self.apply(&pages).is_err() && self.recover().is_err() {
+            self.poisoned = true;
+        }
+        for id in dirty {
+            self.cache.get_mut(&id).unwrap().dirty = false;
+        }
+        self.evict(self.capacity);
+        Ok(())
+    }
+
+    fn write_wal(&mut self, log: &[u8]) -> Result<(), BPlusTreeError> {
+        self.wal.write_all(log)?;
+        #[cfg(test)]
+        if self.fail_wal_sync {
+            self.fail_wal_sync = false;
+            return Err(std::io::Error::other("simulated sync failure").into());
+        }
+        self.wal.sync_data()?;
+        Ok(())
+    }
+
+    /// Writes the pages of a commit in the log to the file, and empties the log.
+    fn apply(&mut self, pages: &[(PageId, Vec<u8>)]) -> Result<(), BPlusTreeError> {
+        for (id, page) in pages {
+            self.write_page(*id, self.header.page_size, page)?;
+        }
+        self.file.sync_data()?;
+        self.wal.set_len(0)?;
+        Ok(())
+    }
+
+    /// Forgets the changes since the last commit, which must not have reached the log.
+    pub fn rollback(&mut self) -> Result<(), BPlusTreeError> {
+        self.cache.retain(|_, cached| !cached.dirty);
+        let cache = &self.cache;
+        self.lru.retain(|_, id| cache.contains_key(id));
+        self.header = self.read_header()?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn cached_pages(&self) -> usize {
+        self.cache.len()
+    }
+}
+
+/// The pages of a commit in the log, by page id.
+type LoggedPages<'a> = Vec<(PageId, &'a [u8])>;
+
+/// Returns the page size and the pages of a complete log.
+fn parse_wal(log: &[u8]) -> Option<(usize, LoggedPages<'_>)> {
+    let (body, sum) = log.split_at_checked(log.len().checked_sub(8)?)?;
+    if body.get(..8)? != WAL_MAGIC || checksum(body).to_le_bytes() != sum {
+        return None;
+    }
+    let page_size = u32::from_le_bytes(body.get(8..12)?.try_into().ok()?) as usize;
+    let count = u32::from_le_bytes(body.get(12..16)?.try_into().ok()?) as usize;
+    let records = &body[16..];
+    if records.len() != count * (8 + page_size) {
+        return None;
+    }
+    let pages = records
+        .chunks(8 + page_size)
+        .map(|record| {
+            let (id, page) = record.split_at(8);
+            (u64::from_le_bytes(id.try_into().unwrap()), page)
+        })
+        .collect();
+    Some((page_size, pages))
+}
+
+/// The 64-bit FNV-1a hash, which catches torn writes of the log.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -1,25 +1,53 @@
-use std::ops::{Add, AddAssign};
+use std::ops::{Add, AddAssign, Mul, Range, Sub};
 
-/// Fenwick Tree / Binary Indexed Tree
+/// Fenwick Tree / Binary Indexed Tree.
 ///
 /// Consider we have an array `arr[0...n-1]`. We would like to:
 /// 1. Compute the sum of the first i elements.
 /// 2. Modify the value of a specified element of the array `arr[i] = x`, where `0 <= i <= n-1`.
+///
+/// Node `i` (1-based) stores the sum of the `lowbit(i)` elements ending at `i`, so both operations take O(log n).
+#[derive(Debug, Clone, PartialEq)]
 pub struct FenwickTree<T: Add + AddAssign + Copy + Default> {
     data: Vec<T>,
 }
 
+impl<T: Add<Output = T> + AddAssign + Copy + Default> From<&[T]> for FenwickTree<T> {
+    /// construct a FenwickTree holding the values of `arr`, in O(n).
+    fn from(arr: &[T]) -> Self {
+        let mut data = vec![T::default(); arr.len() + 1];
+        data[1..].copy_from_slice(arr);
+        // every node passes its sum on to its parent, which covers it
+        for i in 1..data.len() {
+            let parent = i + lowbit(i);
+            if parent < data.len() {
+                let value = data[i];
+                data[parent] += value;
+            }
+        }
+        FenwickTree { data }
+    }
+}
+
 impl<T: Add<Output = T> + AddAssign + Copy + Default> FenwickTree<T> {
-    /// construct a new FenwickTree with given length
+    /// construct a new FenwickTree with given length.
     pub fn with_len(len: usize) -> Self {
         FenwickTree {
             data: vec![T::default(); len + 1],
         }
     }
 
-    /// add `val` to `idx`
+    pub fn len(&self) -> usize {
+        self.data.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// add `val` to `idx`.
     pub fn add(&mut self, i: usize, val: T) {
-        assert!(i < self.data.len());
+        assert!(i < self.len(), "index out of bounds");
         let mut i = i + 1;
         while i < self.data.len() {
             self.data[i] += val;
@@ -27,10 +55,15 @@ impl<T: Add<Output = T> + AddAssign + Copy + Default> FenwickTree<T> {
         }
     }
 
-    /// get the sum of [0, i]
+    /// get the sum of [0, i].
     pub fn prefix_sum(&self, i: usize) -> T {
-        assert!(i < self.data.len());
-        let mut i = i + 1;
+        assert!(i < self.len(), "index out of bounds");
+        self.sum_of_first(i + 1)
+    }
+
+    /// get the sum of [0, i).
+    fn sum_of_first(&self, i: usize) -> T {
+        let mut i = i;
         let mut res = T::default();
         while i > 0 {
             res += self.data[i];
@@ -38,9 +71,250 @@ impl<T: Add<Output = T> + AddAssign + Copy + Default> FenwickTree<T> {
         }
         res
     }
+
+    /// Returns the first index `i` such that `pred(prefix_sum(i))` is false, or `len()` if there is none.
+    ///
+    /// `pred` must be monotone over the prefix sums, which holds for `sum < target` when all values are
+    /// non-negative. Instead of a binary search over `prefix_sum` (O(log² n)), this walks down the implicit tree,
+    /// trying the largest jumps first, in O(log n).
+    pub fn partition_point<P: Fn(T) -> bool>(&self, pred: P) -> usize {
+        let mut pos = 0;
+        let mut sum = T::default();
+        let mut step = self.len().checked_ilog2().map_or(0, |bits| 1 << bits);
+        while step > 0 {
+            if pos + step < self.data.len() {
+                let next = sum + self.data[pos + step];
+                if pred(next) {
+                    pos += step;
+                    sum = next;
+                }
+            }
+            step /= 2;
+        }
+        pos
+    }
+}
+
+impl<T: Add<Output = T> + AddAssign + Copy + Default + PartialOrd> FenwickTree<T> {
+    /// Returns the first index whose prefix sum is at least `target`, or `len()` if there is none.
+    /// All values must be non-negative.
+    pub fn lower_bound(&self, target: T) -> usize {
+        self.partition_point(|sum| sum < target)
+    }
+
+    /// When the tree counts occurrences (`add(x, 1)` for every `x` in a multiset of small integers), returns the
+    /// `k`-th smallest element of the multiset, counting from 0, or `len()` if it has `k` elements or fewer.
+    pub fn find_kth(&self, k: T) -> usize {
+        self.partition_point(|sum| sum <= k)
+    }
+}
+
+impl<T: Add<Output = T> + AddAssign + Sub<Output = T> + Copy + Default> FenwickTree<T> {
+    /// get the sum of the elements in `range`.
+    pub fn range_sum(&self, range: Range<usize>) -> T {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "range out of bounds"
+        );
+        self.sum_of_first(range.end) - self.sum_of_first(range.start)
+    }
+
+    /// set the element at `i` to `val`.
+    ///
+    /// A smaller value is subtracted from the nodes covering `i` instead of adding a negative difference, so
+    /// unsigned `T` works as well.
+    pub fn set(&mut self, i: usize, val: T)
+    where
+        T: PartialOrd,
+    {
+        let old = self.range_sum(i..i + 1);
+        if val >= old {
+            self.add(i, val - old);
+        } else {
+            let diff = old - val;
+            let mut i = i + 1;
+            while i < self.data.len() {
+                self.data[i] = self.data[i] - diff;
+                i += lowbit(i);
+            }
+        }
+    }
+}
+
+/// Fenwick tree supporting both range updates and range queries, with the dual-BIT trick.
+///
+/// Adding `x` to `arr[l..r]` changes the sum of the first `i` elements by `x * (min(i, r) - l)` for `i > l`. This is a
+/// linear function of `i` on each piece, so it can be tracked by ordinary Fenwick trees over the differences: the
+/// slope, with `+x` at `l` and `-x` at `r`, and the offset, with `+x*l` at `l` and `-x*r` at `r`. Then the sum of
+/// the first `i` elements is `slope(i) * i - offset(i)`.
+///
+/// The added and subtracted parts of the slope and of the offset are kept in separate trees, and subtracted only
+/// when a sum is known not to be negative, so `T` can be unsigned. Indices are converted to `T` through
+/// `From<u32>`, so `T` can be `i64`, `u64`, `f64`, ...
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeFenwickTree<T: Add + AddAssign + Copy + Default> {
+    /// `+x` at the start of every range.
+    slope_starts: FenwickTree<T>,
+    /// `x` at the end of every range, which the slope subtracts.
+    slope_ends: FenwickTree<T>,
+    /// `x*l` at the start of every range, which the offset adds.
+    offset_starts: FenwickTree<T>,
+    /// `x*r` at the end of every range and the initial values, which the offset subtracts.
+    offset_ends: FenwickTree<T>,
+}
+
+impl<T> RangeFenwickTree<T>
+where
+    T: Add<Output = T> + AddAssign + Sub<Output = T> + Mul<Output = T> + From<u32> + Copy + Default,
+{
+    pub fn with_len(len: usize) -> Self {
+        assert!(len < u32::MAX as usize, "too many elements");
+        RangeFenwickTree {
+            slope_starts: FenwickTree::with_len(len + 1),
+            slope_ends: FenwickTree::with_len(len + 1),
+            offset_starts: FenwickTree::with_len(len + 1),
+            offset_ends: FenwickTree::with_len(len + 1),
+        }
+    }
+
+    /// Builds the tree from initial values in O(n). With a zero slope the sum of the first `i` elements is
+    /// `-offset(i)`, so the subtracted part of the offset just holds the values.
+    pub fn from_slice(arr: &[T]) -> Self {
+        assert!(arr.len() < u32::MAX as usize, "too many elements");
+        let mut values = arr.to_vec();
+        values.push(T::default());
+        RangeFenwickTree {
+            offset_ends: FenwickTree::from(values.as_slice()),
+            ..RangeFenwickTree::with_len(arr.len())
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slope_starts.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds `val` to every element in `range`.
+    pub fn add(&mut self, range: Range<usize>, val: T) {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "range out of bounds"
+        );
+        let (l, r) = (T::from(range.start as u32), T::from(range.end as u32));
+        self.slope_starts.add(range.start, val);
+        self.slope_ends.add(range.end, val);
+        self.offset_starts.add(range.start, val * l);
+        self.offset_ends.add(range.end, val * r);
+    }
+
+    /// Sum of the first `i` elements.
+    fn sum_of_first(&self, i: usize) -> T {
+        // every range ending before `i` starts before it too, so the slope is not negative, and neither is the
+        // sum, so the subtracted offset comes last
+        let slope = self.slope_starts.sum_of_first(i) - self.slope_ends.sum_of_first(i);
+        slope * T::from(i as u32) + self.offset_ends.sum_of_first(i)
+            - self.offset_starts.sum_of_first(i)
+    }
+
+    /// Sum of the elements in `range`.
+    pub fn range_sum(&self, range: Range<usize>) -> T {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "range out of bounds"
+        );
+        self.sum_of_first(range.end) - self.sum_of_first(range.start)
+    }
+
+    pub fn get(&self, i: usize) -> T {
+        self.range_sum(i..i + 1)
+    }
+}
+
+/// Two-dimensional Fenwick tree: point updates and sums over rectangles of a grid in O(log rows * log cols).
+///
+/// It is a Fenwick tree over rows, whose nodes are Fenwick trees over columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FenwickTree2D<T: Add + AddAssign + Copy + Default> {
+    rows: usize,
+    cols: usize,
+    data: Vec<Vec<T>>,
 }
 
-/// get the lowest bit of `i`
+impl<T: Add<Output = T> + AddAssign + Copy + Default> FenwickTree2D<T> {
+    pub fn with_size(rows: usize, cols: usize) -> Self {
+        FenwickTree2D {
+            rows,
+            cols,
+            data: vec![vec![T::default(); cols + 1]; rows + 1],
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// add `val` to the cell at row `r` and column `c`.
+    pub fn add(&mut self, r: usize, c: usize, val: T) {
+        assert!(r < self.rows && c < self.cols, "index out of bounds");
+        let mut i = r + 1;
+        while i <= self.rows {
+            let mut j = c + 1;
+            while j <= self.cols {
+                self.data[i][j] += val;
+                j += lowbit(j);
+            }
+            i += lowbit(i);
+        }
+    }
+
+    /// sum of the first `r` rows and `c` columns.
+    fn sum_of_first(&self, r: usize, c: usize) -> T {
+        let mut res = T::default();
+        let mut i = r;
+        while i > 0 {
+            let mut j = c;
+            while j > 0 {
+                res += self.data[i][j];
+                j -= lowbit(j);
+            }
+            i -= lowbit(i);
+        }
+        res
+    }
+
+    /// get the sum of the cells in rows [0, r] and columns [0, c].
+    pub fn prefix_sum(&self, r: usize, c: usize) -> T {
+        assert!(r < self.rows && c < self.cols, "index out of bounds");
+        self.sum_of_first(r + 1, c + 1)
+    }
+}
+
+impl<T: Add<Output = T> + AddAssign + Sub<Output = T> + Copy + Default> FenwickTree2D<T> {
+    /// get the sum of the cells in the rectangle `rows` x `cols`.
+    pub fn rect_sum(&self, rows: Range<usize>, cols: Range<usize>) -> T {
+        assert!(
+            rows.start <= rows.end && rows.end <= self.rows,
+            "range out of bounds"
+        );
+        assert!(
+            cols.start <= cols.end && cols.end <= self.cols,
+            "range out of bounds"
+        );
+        // inclusion-exclusion over the four prefix rectangles
+        // both differences are sums of cells, so unsigned `T` never goes below zero
+        (self.sum_of_first(rows.end, cols.end) - self.sum_of_first(rows.start, cols.end))
+            - (self.sum_of_first(rows.end, cols.start) - self.sum_of_first(rows.start, cols.start))
+    }
+}
+
+/// get the lowest bit of `i`.
 const fn lowbit(x: usize) -> usize {
     let x = x as isize;
     (x & (-x)) as usize
@@ -49,6 +323,9 @@ const fn lowbit(x: usize) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use quickcheck::TestResult;
+    use quickcheck_macros::quickcheck;
+
     #[test]
     fn it_works() {
         let mut ft = FenwickTree::with_len(10);
@@ -73,4 +350,162 @@ mod tests {
         assert_eq!(ft.prefix_sum(8), 45);
         assert_eq!(ft.prefix_sum(9), 55);
     }
+
+    #[test]
+    fn from_slice_matches_incremental_build() {
+        let values: Vec<i64> = (1..=37).map(|x| (x * 7919) % 101 - 50).collect();
+        let mut incremental = FenwickTree::with_len(values.len());
+        for (i, &value) in values.iter().enumerate() {
+            incremental.add(i, value);
+        }
+        assert_eq!(FenwickTree::from(values.as_slice()), incremental);
+    }
+
+    #[test]
+    fn range_sum_and_set() {
+        let mut ft = FenwickTree::from([5, 1, 4, 2, 3].as_slice());
+        assert_eq!(ft.range_sum(1..4), 7);
+        assert_eq!(ft.range_sum(2..2), 0);
+        assert_eq!(ft.range_sum(0..5), 15);
+        ft.set(2, 10);
+        assert_eq!(ft.range_sum(1..4), 13);
+        assert_eq!(ft.range_sum(2..3), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn add_out_of_bounds() {
+        FenwickTree::with_len(3).add(3, 1);
+    }
+
+    #[test]
+    fn leaderboard_ranks() {
+        // count of players per score, scores 0..100
+        let scores = [42, 17, 99, 42, 3, 68, 17, 42];
+        let mut counts = FenwickTree::with_len(100);
+        for &score in &scores {
+            counts.add(score, 1u32);
+        }
+        // number of players strictly ahead of a score
+        let ahead = |score: usize| counts.range_sum(score + 1..100);
+        assert_eq!(ahead(99), 0);
+        assert_eq!(ahead(68), 1);
+        assert_eq!(ahead(42), 2);
+        assert_eq!(ahead(3), 7);
+        // k-th lowest score
+        let mut sorted = scores;
+        sorted.sort_unstable();
+        for (k, &score) in sorted.iter().enumerate() {
+            assert_eq!(counts.find_kth(k as u32), score);
+        }
+        assert_eq!(counts.find_kth(8), 100);
+        // the median player is the first whose prefix count reaches half the field
+        assert_eq!(counts.lower_bound(4), 42);
+        assert_eq!(counts.lower_bound(0), 0);
+        assert_eq!(counts.lower_bound(9), 100);
+    }
+
+    #[quickcheck]
+    fn lower_bound_matches_linear_scan(values: Vec<u16>, target: u32) -> TestResult {
+        if values.is_empty() {
+            return TestResult::discard();
+        }
+        let values: Vec<u32> = values.into_iter().map(u32::from).collect();
+        let ft = FenwickTree::from(values.as_slice());
+        let target = target % (values.iter().sum::<u32>() + 2);
+        let expected = (0..values.len())
+            .find(|&i| ft.prefix_sum(i) >= target)
+            .unwrap_or(values.len());
+        TestResult::from_bool(ft.lower_bound(target) == expected)
+    }
+
+    #[test]
+    fn range_update_range_query() {
+        let mut naive = vec![3i64, -1, 4, 1, -5, 9, 2, -6];
+        let mut ft = RangeFenwickTree::from_slice(&naive);
+        assert_eq!(ft.len(), naive.len());
+        let updates = [(0, 8, 2), (2, 5, -3), (7, 8, 10), (4, 4, 100), (1, 6, 1)];
+        for &(l, r, val) in &updates {
+            ft.add(l..r, val);
+            naive[l..r].iter_mut().for_each(|x| *x += val);
+            for start in 0..=naive.len() {
+                for end in start..=naive.len() {
+                    assert_eq!(
+                        ft.range_sum(start..end),
+                        naive[start..end].iter().sum::<i64>()
+                    );
+                }
+            }
+        }
+        assert_eq!(ft.get(7), naive[7]);
+    }
+
+    #[test]
+    fn unsigned_values() {
+        let mut naive = vec![5u64, 0, 7, 2];
+        let mut ft = RangeFenwickTree::from_slice(&naive);
+        for (range, val) in [(1..3, 4), (0..4, 1), (3..4, 9)] {
+            ft.add(range.clone(), val);
+            naive[range].iter_mut().for_each(|x| *x += val);
+        }
+        for start in 0..=naive.len() {
+            for end in start..=naive.len() {
+                assert_eq!(
+                    ft.range_sum(start..end),
+                    naive[start..end].iter().sum::<u64>()
+                );
+            }
+        }
+
+        let mut tree = FenwickTree::from(naive.as_slice());
+        tree.set(2, 1);
+        tree.set(0, 0);
+        assert_eq!(tree.range_sum(0..4), 5 + 1 + 12);
+
+        let mut grid = FenwickTree2D::with_size(3, 3);
+        grid.add(0, 0, 1u64);
+        grid.add(2, 2, 1);
+        assert_eq!(grid.rect_sum(1..3, 1..3), 1);
+    }
+
+    #[test]
+    fn range_update_floats() {
+        let mut ft = RangeFenwickTree::<f64>::with_len(4);
+        ft.add(1..3, 0.5);
+        assert_eq!(ft.range_sum(0..4), 1.0);
+        assert_eq!(ft.get(0), 0.0);
+    }
+
+    #[test]
+    fn grid_prefix_sums() {
+        let (rows, cols) = (5, 7);
+        let mut grid = vec![vec![0i32; cols]; rows];
+        let mut ft = FenwickTree2D::with_size(rows, cols);
+        for r in 0..rows {
+            for c in 0..cols {
+                let value = ((r * 31 + c * 17) % 11) as i32 - 5;
+                grid[r][c] += value;
+                ft.add(r, c, value);
+            }
+        }
+        ft.add(2, 3, 40);
+        grid[2][3] += 40;
+        for r0 in 0..=rows {
+            for r1 in r0..=rows {
+                for c0 in 0..=cols {
+                    for c1 in c0..=cols {
+                        let expected: i32 = grid[r0..r1]
+                            .iter()
+                            .map(|row| row[c0..c1].iter().sum::<i32>())
+                            .sum();
+                        assert_eq!(ft.rect_sum(r0..r1, c0..c1), expected);
+                    }
+                }
+            }
+        }
+        assert_eq!(
+            ft.prefix_sum(rows - 1, cols - 1),
+            ft.rect_sum(0..rows, 0..cols)
+        );
+    }
 }
@@ -0,0 +1,410 @@
+// Fibonacci heap
+// A collection of heap-ordered trees, whose roots are kept in a circular list with a pointer to the smallest one.
+// Pushing and melding only add roots, in O(1). Popping the minimum moves its children to the roots, then links roots
+// of equal degree until all degrees differ, in O(log n) amortized. Decreasing a key cuts the node to the roots, and
+// a parent that loses a second child is cut as well ("cascading cut"), which keeps a tree of degree d at least
+// Fibonacci(d + 2) large: decreasing a key takes O(1) amortized.
+// https://en.wikipedia.org/wiki/Fibonacci_heap
+
+pub use super::node_pool::Handle;
+use super::node_pool::{NodePool, Slot};
+use super::priority_queue::PriorityQueue;
+use std::ptr;
+
+struct Node<P, T> {
+    priority: P,
+    value: T,
+    parent: *mut Slot<Node<P, T>>,
+    /// Any child: the children are in a circular list.
+    child: *mut Slot<Node<P, T>>,
+    left: *mut Slot<Node<P, T>>,
+    right: *mut Slot<Node<P, T>>,
+    degree: usize,
+    /// Whether the node lost a child since it became a child itself.
+    marked: bool,
+}
+
+type Link<P, T> = *mut Slot<Node<P, T>>;
+
+pub struct FibonacciHeap<P, T> {
+    pool: NodePool<Node<P, T>>,
+    min: Link<P, T>,
+    len: usize,
+}
+
+// The unsafe blocks below only follow links between the live nodes of the heap's pool.
+unsafe fn node<'a, P, T>(slot: Link<P, T>) -> &'a Node<P, T> {
+    NodePool::node(slot)
+}
+
+unsafe fn node_mut<'a, P, T>(slot: Link<P, T>) -> &'a mut Node<P, T> {
+    NodePool::node_mut(slot)
+}
+
+unsafe fn less<P: Ord, T>(a: Link<P, T>, b: Link<P, T>) -> bool {
+    node(a).priority < node(b).priority
+}
+
+/// Joins the circular lists of `a` and `b` into one.
+unsafe fn splice<P, T>(a: Link<P, T>, b: Link<P, T>) {
+    let a_right = node(a).right;
+    let b_left = node(b).left;
+    node_mut(a).right = b;
+    node_mut(b).left = a;
+    node_mut(b_left).right = a_right;
+    node_mut(a_right).left = b_left;
+}
+
+/// Takes a node out of its circular list, and returns another node of the list, or null if it was alone.
+unsafe fn unlink<P, T>(slot: Link<P, T>) -> Link<P, T> {
+    let Node { left, right, .. } = *node(slot);
+    let node = node_mut(slot);
+    node.left = slot;
+    node.right = slot;
+    if right == slot {
+        return ptr::null_mut();
+    }
+    node_mut(left).right = right;
+    node_mut(right).left = left;
+    right
+}
+
+impl<P: Ord, T> Default for FibonacciHeap<P, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Ord, T> FibonacciHeap<P, T> {
+    pub fn new() -> Self {
+        FibonacciHeap {
+            pool: NodePool::default(),
+            min: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, priority: P, value: T) -> Handle {
+        let slot = self.pool.insert(Node {
+            priority,
+            value,
+            parent: ptr::null_mut(),
+            child: ptr::null_mut(),
+            left: ptr::null_mut(),
+            right: ptr::null_mut(),
+            degree: 0,
+            marked: false,
+        });
+        unsafe {
+            let node = node_mut(slot);
+            node.left = slot;
+            node.right = slot;
+            self.add_root(slot);
+        }
+        self.len += 1;
+        self.pool.handle(slot)
+    }
+
+    pub fn peek(&self) -> Option<(&P, &T)> {
+        if self.min.is_null() {
+            return None;
+        }
+        let min = unsafe { node(self.min) };
+        Some((&min.priority, &min.value))
+    }
+
+    pub fn pop(&mut self) -> Option<(P, T)> {
+        if self.min.is_null() {
+            return None;
+        }
+        let min = self.min;
+        unsafe {
+            let child = node(min).child;
+            if !child.is_null() {
+                let mut current = child;
+                loop {
+                    let node = node_mut(current);
+                    node.parent = ptr::null_mut();
+                    node.marked = false;
+                    current = node.right;
+                    if current == child {
+                        break;
+                    }
+                }
+                splice(min, child);
+            }
+            self.min = unlink(min);
+            if !self.min.is_null() {
+                self.consolidate();
+            }
+            self.len -= 1;
+            let node = self.pool.remove(min);
+            Some((node.priority, node.value))
+        }
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<(&P, &T)> {
+        let slot = self.pool.find(handle)?;
+        let node = unsafe { node(slot) };
+        Some((&node.priority, &node.value))
+    }
+
+    /// Lowers the priority of an element.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the element is no longer in the heap, or if `priority` is greater than its current one.
+    pub fn decrease_key(&mut self, handle: Handle, priority: P) {
+        let slot = self.pool.find(handle).expect("stale handle");
+        unsafe {
+            assert!(
+                priority <= node(slot).priority,
+                "the new priority is greater than the current one"
+            );
+            node_mut(slot).priority = priority;
+            let parent = node(slot).parent;
+            if !parent.is_null() && less(slot, parent) {
+                self.cut(slot);
+            }
+            if less(slot, self.min) {
+                self.min = slot;
+            }
+        }
+    }
+
+    /// Removes an element, and returns it if it was still in the heap.
+    pub fn remove(&mut self, handle: Handle) -> Option<(P, T)> {
+        let slot = self.pool.find(handle)?;
+        unsafe {
+            if !node(slot).parent.is_null() {
+                self.cut(slot);
+            }
+        }
+        // the node is a root now, which `pop` removes as if its priority were the smallest
+        self.min = slot;
+        self.pop()
+    }
+
+    /// Moves all elements of `other` into this heap in O(1). The handles of `other` stay valid in this heap.
+    pub fn meld(&mut self, other: Self) {
+        if !other.min.is_null() {
+            unsafe {
+                self.add_root(other.min);
+            }
+        }
+        self.len += other.len;
+        self.pool.merge(other.pool);
+    }
+
+    /// Adds a circular list of roots.
+    unsafe fn add_root(&mut self, slot: Link<P, T>) {
+        if self.min.is_null() {
+            self.min = slot;
+            return;
+        }
+        splice(self.min, slot);
+        if less(slot, self.min) {
+            self.min = slot;
+        }
+    }
+
+    /// Moves a child to the roots, then the ancestors that lost their second child.
+    unsafe fn cut(&mut self, mut slot: Link<P, T>) {
+        loop {
+            let parent = node(slot).parent;
+            let sibling = unlink(slot);
+            let parent_node = node_mut(parent);
+            if parent_node.child == slot {
+                parent_node.child = sibling;
+            }
+            parent_node.degree -= 1;
+            let node = node_mut(slot);
+            node.parent = ptr::null_mut();
+            node.marked = false;
+            splice(self.min, slot);
+
+            let parent_node = node_mut(parent);
+            if parent_node.parent.is_null() {
+                return;
+            }
+            if !parent_node.marked {
+                parent_node.marked = true;
+                return;
+            }
+            slot = parent;
+        }
+    }
+
+    /// Links roots of equal degree until all roots have different degrees, and finds the new minimum.
+    unsafe fn consolidate(&mut self) {
+        let mut roots = vec![self.min];
+        let mut current = node(self.min).right;
+        while current != self.min {
+            roots.push(current);
+            current = node(current).right;
+        }
+        let mut by_degree: Vec<Link<P, T>> = Vec::new();
+        for mut root in roots {
+            let mut degree = node(root).degree;
+            while let Some(other) = by_degree.get_mut(degree).filter(|other| !other.is_null()) {
+                let mut child = std::mem::replace(other, ptr::null_mut());
+                if less(child, root) {
+                    std::mem::swap(&mut root, &mut child);
+                }
+                unlink(child);
+                node_mut(child).parent = root;
+                let root_node = node_mut(root);
+                if root_node.child.is_null() {
+                    root_node.child = child;
+                } else {
+                    splice(root_node.child, child);
+                }
+                root_node.degree += 1;
+                degree += 1;
+            }
+            if by_degree.len() <= degree {
+                by_degree.resize(degree + 1, ptr::null_mut());
+            }
+            by_degree[degree] = root;
+        }
+        self.min = ptr::null_mut();
+        for root in by_degree.into_iter().filter(|root| !root.is_null()) {
+            if self.min.is_null() || less(root, self.min) {
+                self.min = root;
+            }
+        }
+    }
+}
+
+impl<P: Ord, T> PriorityQueue<P, T> for FibonacciHeap<P, T> {
+    type Handle = Handle;
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn push(&mut self, priority: P, value: T) -> Handle {
+        self.push(priority, value)
+    }
+
+    fn peek(&self) -> Option<(&P, &T)> {
+        self.peek()
+    }
+
+    fn pop(&mut self) -> Option<(P, T)> {
+        self.pop()
+    }
+
+    fn decrease_key(&mut self, handle: Handle, priority: P) {
+        self.decrease_key(handle, priority);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FibonacciHeap;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn pops_in_order() {
+        let mut heap = FibonacciHeap::new();
+        for (i, priority) in [5, 3, 8, 1, 9, 2, 7].into_iter().enumerate() {
+            heap.push(priority, i);
+        }
+        assert_eq!(heap.peek(), Some((&1, &3)));
+        let order: Vec<i32> = std::iter::from_fn(|| heap.pop().map(|(p, _)| p)).collect();
+        assert_eq!(order, [1, 2, 3, 5, 7, 8, 9]);
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn cuts_decreased_keys() {
+        let mut heap = FibonacciHeap::new();
+        let handles: Vec<_> = (0..32).map(|i| heap.push(i, i)).collect();
+        // the first pop links the remaining roots into trees
+        assert_eq!(heap.pop(), Some((0, 0)));
+        for &handle in handles[16..].iter().rev() {
+            let (&priority, _) = heap.get(handle).unwrap();
+            heap.decrease_key(handle, priority - 100);
+        }
+        let order: Vec<i32> = std::iter::from_fn(|| heap.pop().map(|(_, v)| v)).collect();
+        let expected: Vec<i32> = (16..32).chain(1..16).collect();
+        assert_eq!(order, expected);
+    }
+
+    #[test]
+    fn melds_and_keeps_handles() {
+        let mut a = FibonacciHeap::new();
+        let mut b = FibonacciHeap::new();
+        let x = a.push(10, "x");
+        let y = b.push(20, "y");
+        b.push(5, "z");
+        a.meld(b);
+        assert_eq!(a.len(), 3);
+        assert_eq!(a.peek(), Some((&5, &"z")));
+        a.decrease_key(y, 1);
+        assert_eq!(a.pop(), Some((1, "y")));
+        assert_eq!(a.get(y), None);
+        assert_eq!(a.remove(x), Some((10, "x")));
+        assert_eq!(a.pop(), Some((5, "z")));
+        assert_eq!(a.pop(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "stale handle")]
+    fn rejects_stale_handles() {
+        let mut heap = FibonacciHeap::new();
+        let handle = heap.push(1, ());
+        heap.pop();
+        heap.push(1, ());
+        heap.decrease_key(handle, 0);
+    }
+
+    #[test]
+    fn matches_a_sorted_set() {
+        let mut heap = FibonacciHeap::new();
+        let mut expected = BTreeSet::new();
+        let mut handles = Vec::new();
+        let mut rng = StdRng::seed_from_u64(3);
+        for id in 0..3000_u32 {
+            let priority = rng.gen_range(0..4096);
+            match rng.gen_range(0..5) {
+                0 | 1 => {
+                    handles.push((heap.push(priority, id), priority, id));
+                    expected.insert((priority, id));
+                }
+                2 if !handles.is_empty() => {
+                    let index = rng.gen_range(0..handles.len());
+                    let (handle, old, id) = handles[index];
+                    let priority = old.min(priority);
+                    expected.remove(&(old, id));
+                    expected.insert((priority, id));
+                    heap.decrease_key(handle, priority);
+                    handles[index].1 = priority;
+                }
+                3 if !handles.is_empty() => {
+                    let (handle, old, id) = handles.swap_remove(rng.gen_range(0..handles.len()));
+                    expected.remove(&(old, id));
+                    assert_eq!(heap.remove(handle), Some((old, id)));
+                }
+                _ => {
+                    if let Some((priority, id)) = heap.pop() {
+                        assert_eq!(expected.first().map(|e| e.0), Some(priority));
+                        expected.remove(&(priority, id));
+                        handles.retain(|h| h.2 != id);
+                    }
+                }
+            }
+            assert_eq!(heap.len(), expected.len());
+        }
+    }
+}
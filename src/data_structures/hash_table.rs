@@ -1,81 +1,407 @@
-use std::collections::LinkedList;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
 
-pub struct HashTable<K, V> {
-    elements: Vec<LinkedList<(K, V)>>,
-    count: usize,
+/// The number of buckets allocated by the first insertion.
+const INITIAL_BUCKETS: usize = 8;
+
+/// A hash map with separate chaining: every bucket holds the entries whose hashes select it.
+///
+/// The number of buckets is a power of two, and doubles when the load factor (entries per bucket) would
+/// exceed 3/4, so chains stay short on average. Keys are hashed with any `BuildHasher`, `RandomState` by
+/// default, as in `std::collections::HashMap`.
+pub struct HashTable<K, V, S = RandomState> {
+    buckets: Vec<Vec<(K, V)>>,
+    len: usize,
+    hasher: S,
 }
 
-impl<K: Hashable + std::cmp::PartialEq, V> Default for HashTable<K, V> {
-    fn default() -> Self {
-        Self::new()
+/// How full a hash table is, and how far lookups have to search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TableStats {
+    pub len: usize,
+    /// The number of buckets, or of slots for open addressing.
+    pub capacity: usize,
+    pub load_factor: f64,
+    /// The most entries a successful lookup compares keys with.
+    pub max_probe_length: usize,
+    /// The average number of entries a successful lookup compares keys with.
+    pub mean_probe_length: f64,
+}
+
+impl TableStats {
+    /// Builds the statistics of a table of `capacity` buckets or slots from the probe length of every entry.
+    pub(super) fn from_probe_lengths<I: IntoIterator<Item = usize>>(
+        capacity: usize,
+        probe_lengths: I,
+    ) -> Self {
+        let (mut len, mut total, mut max) = (0, 0, 0);
+        for probe_length in probe_lengths {
+            len += 1;
+            total += probe_length;
+            max = max.max(probe_length);
+        }
+        TableStats {
+            len,
+            capacity,
+            load_factor: if capacity == 0 {
+                0.0
+            } else {
+                len as f64 / capacity as f64
+            },
+            max_probe_length: max,
+            mean_probe_length: if len == 0 {
+                0.0
+            } else {
+                total as f64 / len as f64
+            },
+        }
     }
 }
 
-pub trait Hashable {
-    fn hash(&self) -> usize;
+impl fmt::Display for TableStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} entries in {} slots (load factor {:.2}), probe length {:.2} on average and {} at most",
+            self.len, self.capacity, self.load_factor, self.mean_probe_length, self.max_probe_length
+        )
+    }
 }
 
-impl<K: Hashable + std::cmp::PartialEq, V> HashTable<K, V> {
-    pub fn new() -> HashTable<K, V> {
-        let initial_capacity = 3000;
-        let mut elements = Vec::with_capacity(initial_capacity);
+impl<K, V> HashTable<K, V> {
+    pub fn new() -> Self {
+        HashTable::with_hasher(RandomState::new())
+    }
 
-        for _ in 0..initial_capacity {
-            elements.push(LinkedList::new());
-        }
+    /// Creates a table that holds `capacity` entries without growing.
+    pub fn with_capacity(capacity: usize) -> Self {
+        HashTable::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S: Default> Default for HashTable<K, V, S> {
+    fn default() -> Self {
+        HashTable::with_hasher(S::default())
+    }
+}
 
-        HashTable { elements, count: 0 }
+impl<K, V, S> HashTable<K, V, S> {
+    pub fn with_hasher(hasher: S) -> Self {
+        HashTable {
+            buckets: Vec::new(),
+            len: 0,
+            hasher,
+        }
     }
 
-    pub fn insert(&mut self, key: K, value: V) {
-        if self.count >= self.elements.len() * 3 / 4 {
-            self.resize();
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        let mut table = HashTable::with_hasher(hasher);
+        if capacity > 0 {
+            table.buckets = empty_buckets(bucket_count_for(capacity));
         }
-        let index = key.hash() % self.elements.len();
-        self.elements[index].push_back((key, value));
-        self.count += 1;
+        table
     }
 
-    pub fn search(&self, key: K) -> Option<&V> {
-        let index = key.hash() % self.elements.len();
-        self.elements[index]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hasher
+    }
+
+    /// Removes all entries, keeping the buckets.
+    pub fn clear(&mut self) {
+        self.buckets.iter_mut().for_each(Vec::clear);
+        self.len = 0;
+    }
+
+    /// Returns an iterator over the entries, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.buckets
             .iter()
-            .find(|(k, _)| *k == key)
-            .map(|(_, v)| v)
+            .flatten()
+            .map(|(key, value)| (key, value))
+    }
+
+    /// Returns an iterator over the entries with mutable values, in no particular order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.buckets
+            .iter_mut()
+            .flatten()
+            .map(|(key, value)| (&*key, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
     }
 
-    fn resize(&mut self) {
-        let new_size = self.elements.len() * 2;
-        let mut new_elements = Vec::with_capacity(new_size);
+    /// Returns the load factor and the length of the chains.
+    pub fn stats(&self) -> TableStats {
+        // a successful lookup compares keys with the entries before its own in the chain
+        TableStats::from_probe_lengths(
+            self.buckets.len(),
+            self.buckets.iter().flat_map(|bucket| 1..=bucket.len()),
+        )
+    }
+}
 
-        for _ in 0..new_size {
-            new_elements.push(LinkedList::new());
+impl<K: Hash + Eq, V, S: BuildHasher> HashTable<K, V, S> {
+    fn bucket_of<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        // the number of buckets is a power of two
+        self.hasher.hash_one(key) as usize & (self.buckets.len() - 1)
+    }
+
+    /// Returns the bucket of `key`, and its position in the bucket if it is there.
+    fn find<Q>(&self, key: &Q) -> Option<(usize, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
         }
+        let bucket = self.bucket_of(key);
+        let index = self.buckets[bucket]
+            .iter()
+            .position(|(k, _)| k.borrow() == key)?;
+        Some((bucket, index))
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (bucket, index) = self.find(key)?;
+        Some(&self.buckets[bucket][index].1)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (bucket, index) = self.find(key)?;
+        Some(&mut self.buckets[bucket][index].1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
 
-        for old_list in self.elements.drain(..) {
-            for (key, value) in old_list {
-                let new_index = key.hash() % new_size;
-                new_elements[new_index].push_back((key, value));
+    /// Inserts a key-value pair, and returns the previous value of the key.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
             }
         }
+    }
+
+    /// Removes a key, and returns its value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (bucket, index) = self.find(key)?;
+        self.len -= 1;
+        // the order within a bucket does not matter
+        Some(self.buckets[bucket].swap_remove(index).1)
+    }
+
+    /// Gets the entry for a key, to inspect or update it in place.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        match self.find(&key) {
+            Some((bucket, index)) => Entry::Occupied(OccupiedEntry {
+                table: self,
+                bucket,
+                index,
+            }),
+            None => Entry::Vacant(VacantEntry { table: self, key }),
+        }
+    }
+
+    /// Doubles the number of buckets if one more entry would exceed the maximum load factor.
+    fn reserve_one(&mut self) {
+        if 4 * (self.len + 1) <= 3 * self.buckets.len() {
+            return;
+        }
+        let count = (2 * self.buckets.len()).max(INITIAL_BUCKETS);
+        let old = mem::replace(&mut self.buckets, empty_buckets(count));
+        for (key, value) in old.into_iter().flatten() {
+            let bucket = self.bucket_of(&key);
+            self.buckets[bucket].push((key, value));
+        }
+    }
+}
+
+/// Returns the number of buckets that holds `capacity` entries under the maximum load factor.
+fn bucket_count_for(capacity: usize) -> usize {
+    (capacity * 4)
+        .div_ceil(3)
+        .next_power_of_two()
+        .max(INITIAL_BUCKETS)
+}
+
+fn empty_buckets<K, V>(count: usize) -> Vec<Vec<(K, V)>> {
+    std::iter::repeat_with(Vec::new).take(count).collect()
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Default> FromIterator<(K, V)> for HashTable<K, V, S> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut table = HashTable::default();
+        table.extend(iter);
+        table
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> Extend<(K, V)> for HashTable<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for HashTable<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// A view into a single entry of a `HashTable`, which may be vacant or occupied.
+pub enum Entry<'a, K, V, S> {
+    Vacant(VacantEntry<'a, K, V, S>),
+    Occupied(OccupiedEntry<'a, K, V, S>),
+}
+
+pub struct VacantEntry<'a, K, V, S> {
+    table: &'a mut HashTable<K, V, S>,
+    key: K,
+}
+
+pub struct OccupiedEntry<'a, K, V, S> {
+    table: &'a mut HashTable<K, V, S>,
+    bucket: usize,
+    index: usize,
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> Entry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Vacant(entry) => &entry.key,
+            Entry::Occupied(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant, and returns the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of `default` if the entry is vacant, and returns the value.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default()),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
 
-        self.elements = new_elements;
+    /// Calls `f` on the value if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Hash + Eq, V: Default, S: BuildHasher> Entry<'a, K, V, S> {
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> VacantEntry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        let table = self.table;
+        table.reserve_one();
+        let bucket = table.bucket_of(&self.key);
+        table.len += 1;
+        let chain = &mut table.buckets[bucket];
+        chain.push((self.key, value));
+        &mut chain.last_mut().unwrap().1
+    }
+}
+
+impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.table.buckets[self.bucket][self.index].0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.table.buckets[self.bucket][self.index].1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.table.buckets[self.bucket][self.index].1
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.table.buckets[self.bucket][self.index].1
+    }
+
+    /// Replaces the value, and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry, and returns its key and value.
+    pub fn remove_entry(self) -> (K, V) {
+        self.table.len -= 1;
+        self.table.buckets[self.bucket].swap_remove(self.index)
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data_structures::bloom_filter::SeededState;
+    use crate::data_structures::identity_hasher::Identity;
+    use crate::data_structures::RobinHoodHashTable;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::HashMap;
 
-    #[derive(Debug, PartialEq, Eq)]
+    #[derive(Debug, PartialEq, Eq, Hash)]
     struct TestKey(usize);
 
-    impl Hashable for TestKey {
-        fn hash(&self) -> usize {
-            self.0
-        }
-    }
-
     #[test]
     fn test_insert_and_search() {
         let mut hash_table = HashTable::new();
@@ -83,21 +409,21 @@ mod tests {
         let value = TestKey(10);
 
         hash_table.insert(key, value);
-        let result = hash_table.search(TestKey(1));
+        let result = hash_table.get(&TestKey(1));
 
         assert_eq!(result, Some(&TestKey(10)));
     }
 
     #[test]
     fn test_resize() {
-        let mut hash_table = HashTable::new();
-        let initial_capacity = hash_table.elements.capacity();
+        let mut hash_table = HashTable::with_capacity(100);
+        let initial_capacity = hash_table.stats().capacity;
 
         for i in 0..initial_capacity * 3 / 4 + 1 {
             hash_table.insert(TestKey(i), TestKey(i + 10));
         }
 
-        assert!(hash_table.elements.capacity() > initial_capacity);
+        assert!(hash_table.stats().capacity > initial_capacity);
     }
 
     #[test]
@@ -107,7 +433,7 @@ mod tests {
         let value = TestKey(10);
 
         hash_table.insert(key, value);
-        let result = hash_table.search(TestKey(2));
+        let result = hash_table.get(&TestKey(2));
 
         assert_eq!(result, None);
     }
@@ -120,26 +446,124 @@ mod tests {
         }
 
         for i in 0..10 {
-            let result = hash_table.search(TestKey(i));
+            let result = hash_table.get(&TestKey(i));
             assert_eq!(result, Some(&TestKey(i + 100)));
         }
     }
 
     #[test]
-    fn test_not_overwrite_existing_key() {
+    fn test_overwrite_existing_key() {
         let mut hash_table = HashTable::new();
         hash_table.insert(TestKey(1), TestKey(100));
-        hash_table.insert(TestKey(1), TestKey(200));
+        let old = hash_table.insert(TestKey(1), TestKey(200));
 
-        let result = hash_table.search(TestKey(1));
-        assert_eq!(result, Some(&TestKey(100)));
+        assert_eq!(old, Some(TestKey(100)));
+        assert_eq!(hash_table.get(&TestKey(1)), Some(&TestKey(200)));
+        assert_eq!(hash_table.len(), 1);
     }
 
     #[test]
     fn test_empty_search() {
         let hash_table: HashTable<TestKey, TestKey> = HashTable::new();
-        let result = hash_table.search(TestKey(1));
+        let result = hash_table.get(&TestKey(1));
 
         assert_eq!(result, None);
     }
+
+    #[test]
+    fn test_remove_and_entry() {
+        let mut counts: HashTable<String, usize> = HashTable::new();
+        for word in "one two three two three three".split(' ') {
+            *counts.entry(word.to_string()).or_insert(0) += 1;
+        }
+        // keys are looked up by any borrowed form
+        assert_eq!(counts.get("three"), Some(&3));
+        assert_eq!(counts.remove("two"), Some(2));
+        assert_eq!(counts.remove("two"), None);
+        counts.entry("one".to_string()).and_modify(|n| *n += 10);
+        assert_eq!(counts.get("one"), Some(&11));
+        if let Entry::Occupied(entry) = counts.entry("three".to_string()) {
+            assert_eq!(entry.remove_entry(), ("three".to_string(), 3));
+        }
+        assert_eq!(counts.len(), 1);
+        for (_, value) in counts.iter_mut() {
+            *value = 0;
+        }
+        assert_eq!(counts.values().sum::<usize>(), 0);
+        counts.clear();
+        assert!(counts.is_empty());
+    }
+
+    #[test]
+    fn test_colliding_keys() {
+        // every key lands in bucket 0 of the 64 buckets
+        let mut table: HashTable<usize, usize, Identity> =
+            HashTable::with_capacity_and_hasher(40, Identity::default());
+        for i in 0..10 {
+            table.insert(i * 64, i);
+        }
+        assert_eq!(table.get(&(5 * 64)), Some(&5));
+        assert_eq!(table.remove(&(3 * 64)), Some(3));
+        let stats = table.stats();
+        assert_eq!(stats.len, 9);
+        assert_eq!(stats.capacity, 64);
+        assert_eq!(stats.max_probe_length, 9);
+        assert_eq!(stats.mean_probe_length, 5.0);
+    }
+
+    /// Runs the same operations on both tables and `std::collections::HashMap`, checks that they agree, and
+    /// returns the statistics of the chaining and Robin Hood tables. Both hash with a fixed seed, so the
+    /// statistics are the same on every run.
+    fn compare_tables(ops: &[(u8, u16, u32)]) -> (TableStats, TableStats) {
+        let mut chaining = HashTable::with_hasher(SeededState::new(0));
+        let mut robin_hood = RobinHoodHashTable::with_hasher(SeededState::new(0));
+        let mut expected = HashMap::new();
+        for &(op, key, value) in ops {
+            match op % 3 {
+                0 => {
+                    let removed = expected.remove(&key);
+                    assert_eq!(chaining.remove(&key), removed);
+                    assert_eq!(robin_hood.remove(&key), removed);
+                }
+                _ => {
+                    let old = expected.insert(key, value);
+                    assert_eq!(chaining.insert(key, value), old);
+                    assert_eq!(robin_hood.insert(key, value), old);
+                }
+            }
+        }
+        for (key, value) in &expected {
+            assert_eq!(chaining.get(key), Some(value));
+            assert_eq!(robin_hood.get(key), Some(value));
+        }
+        let mut entries: Vec<_> = chaining.iter().map(|(&k, &v)| (k, v)).collect();
+        let mut other: Vec<_> = robin_hood.iter().map(|(&k, &v)| (k, v)).collect();
+        entries.sort_unstable();
+        other.sort_unstable();
+        assert_eq!(entries, other);
+        assert_eq!(entries.len(), expected.len());
+        (chaining.stats(), robin_hood.stats())
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn tables_match_std(ops: Vec<(u8, u16, u32)>) {
+        compare_tables(&ops);
+    }
+
+    #[test]
+    fn compare_probe_lengths() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let ops: Vec<_> = (0..20_000).map(|_| rng.gen()).collect();
+        let (chaining, robin_hood) = compare_tables(&ops);
+        assert_eq!(chaining.len, robin_hood.len);
+        assert!(chaining.load_factor <= 0.75);
+        assert!(robin_hood.load_factor <= 0.875);
+        // both keep successful lookups short on average at their maximum load factors
+        assert!(chaining.mean_probe_length < 2.0);
+        assert!(robin_hood.mean_probe_length < 4.0);
+        // open addressing probes further than chaining, but Robin Hood keeps the longest probe short too
+        assert!(chaining.mean_probe_length < robin_hood.mean_probe_length);
+        assert!(chaining.max_probe_length <= 8);
+        assert!(robin_hood.max_probe_length <= 16);
+    }
 }
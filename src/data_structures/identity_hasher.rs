@@ -0,0 +1,28 @@
+use std::hash::{BuildHasherDefault, Hasher};
+
+/// Hashes an integer key to itself, so tests control which keys collide. Other keys are hashed by shifting in
+/// their bytes.
+#[derive(Default)]
+pub struct IdentityHasher(u64);
+
+impl Hasher for IdentityHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 << 8) | u64::from(byte);
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0 = i;
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.0 = i as u64;
+    }
+}
+
+pub type Identity = BuildHasherDefault<IdentityHasher>;
@@ -0,0 +1,548 @@
+use super::treap::{self, Augment, Link, TreapNode};
+use super::{Action, Monoid};
+use std::cmp::min;
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::Range;
+
+/// A node of an `ImplicitTreap`: a treap node without key, holding one value of the sequence.
+type Node<T, F> = TreapNode<(), T, Fold<T, F>>;
+
+/// What an `ImplicitTreap` keeps about a subtree, with the updates it has not passed on to the children yet.
+///
+/// The value and the aggregates of a node are always up to date; pending reversals and actions only concern its
+/// children.
+#[derive(Clone)]
+struct Fold<T, F> {
+    /// The aggregate of the subtree, from left to right.
+    sum: T,
+    /// The aggregate of the subtree, from right to left, which becomes `sum` when the subtree is reversed.
+    rev_sum: T,
+    /// An action applied to this node but not to its children yet.
+    lazy: Option<F>,
+    /// Whether the children still have to be reversed.
+    reversed: bool,
+}
+
+impl<T: Monoid, F: Action<T>> Augment<(), T> for Fold<T, F> {
+    fn summarize(_key: &(), value: &T, left: Option<&Self>, right: Option<&Self>) -> Self {
+        let (mut sum, mut rev_sum) = (T::identity(), T::identity());
+        if let Some(left) = left {
+            sum = left.sum.clone();
+            rev_sum = left.rev_sum.clone();
+        }
+        sum = sum.combine(value);
+        rev_sum = value.combine(&rev_sum);
+        if let Some(right) = right {
+            sum = sum.combine(&right.sum);
+            rev_sum = right.rev_sum.combine(&rev_sum);
+        }
+        Fold {
+            sum,
+            rev_sum,
+            lazy: None,
+            reversed: false,
+        }
+    }
+
+    /// Passes the pending reversal and action on to the children.
+    fn push(node: &mut Node<T, F>) {
+        let reversed = mem::take(&mut node.augment_mut().reversed);
+        let lazy = node.augment_mut().lazy.take();
+        for child in node.children_mut().into_iter().flatten() {
+            if reversed {
+                reverse(child);
+            }
+            if let Some(action) = &lazy {
+                apply(child, action);
+            }
+        }
+    }
+}
+
+/// Reverses a subtree: the children are swapped now, and their own children later.
+fn reverse<T, F>(node: &mut Node<T, F>) {
+    let [left, right] = node.children_mut();
+    mem::swap(left, right);
+    let fold = node.augment_mut();
+    mem::swap(&mut fold.sum, &mut fold.rev_sum);
+    fold.reversed = !fold.reversed;
+}
+
+/// Applies `action` to every value of a subtree: to its root now, and to the other nodes later.
+fn apply<T, F: Action<T>>(node: &mut Node<T, F>, action: &F) {
+    let value = node.value_mut();
+    *value = action.apply(value);
+    let fold = node.augment_mut();
+    fold.sum = action.apply(&fold.sum);
+    fold.rev_sum = action.apply(&fold.rev_sum);
+    fold.lazy = Some(match &fold.lazy {
+        Some(earlier) => action.compose(earlier),
+        None => action.clone(),
+    });
+}
+
+/// The left and right child of a node.
+type Children<'a, T, F> = (Option<&'a Node<T, F>>, Option<&'a Node<T, F>>);
+
+/// A sequence based on a treap keyed by position (an "implicit" treap).
+///
+/// It is built from the nodes of a [`Treap`](super::Treap), which form a binary search tree by key and a heap by
+/// random priority, but the key of a node is never stored: it is the number of nodes before it, which is known from
+/// the subtree sizes on the way down. Splitting and merging by position then give insertion, deletion, slicing and
+/// concatenation anywhere in the sequence in O(log n) expected time.
+///
+/// The values form a [`Monoid`], and every node keeps the aggregate of its subtree, so any range can be folded in
+/// O(log n). Ranges can also be reversed, or updated with an [`Action`], lazily: the change is recorded on the root
+/// of the range and only pushed to the children when an operation goes below it. Use `()` as the action when range
+/// updates are not needed.
+#[derive(Clone)]
+pub struct ImplicitTreap<T: Monoid, F: Action<T> = ()> {
+    root: Link<(), T, Fold<T, F>>,
+}
+
+impl<T: Monoid, F: Action<T>> Default for ImplicitTreap<T, F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Monoid, F: Action<T>> ImplicitTreap<T, F> {
+    pub fn new() -> Self {
+        ImplicitTreap { root: None }
+    }
+
+    pub fn len(&self) -> usize {
+        treap::size(self.root.as_deref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns the value at `index`, with all pending updates applied.
+    pub fn get(&self, index: usize) -> Option<T> {
+        let mut current = self.root.as_deref();
+        let mut view = View::default();
+        let mut index = index;
+        while let Some(node) = current {
+            let (left, right) = view.children(node);
+            let left_size = treap::size(left);
+            if index == left_size {
+                return Some(view.value(node));
+            }
+            view = view.descend(node);
+            current = if index < left_size {
+                left
+            } else {
+                index -= left_size + 1;
+                right
+            };
+        }
+        None
+    }
+
+    /// Inserts `value` at `index`, shifting all values after it to the right.
+    pub fn insert(&mut self, index: usize, value: T) {
+        let node = Some(Box::new(Node::new((), value)));
+        self.insert_all(index, ImplicitTreap { root: node });
+    }
+
+    /// Inserts the whole of `other` at `index`.
+    pub fn insert_all(&mut self, index: usize, other: ImplicitTreap<T, F>) {
+        assert!(index <= self.len(), "index out of bounds");
+        let (left, right) = treap::split_at(self.root.take(), index);
+        self.root = treap::merge(treap::merge(left, other.root), right);
+    }
+
+    /// Appends `value` at the end of the sequence.
+    pub fn push(&mut self, value: T) {
+        self.insert(self.len(), value);
+    }
+
+    /// Removes and returns the value at `index`, shifting all values after it to the left.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len(), "index out of bounds");
+        let removed = self.remove_range(index..index + 1);
+        removed.root.unwrap().into_value()
+    }
+
+    /// Removes the values in `range` and returns them as a new sequence.
+    pub fn remove_range(&mut self, range: Range<usize>) -> ImplicitTreap<T, F> {
+        self.check_range(&range);
+        let (rest, right) = treap::split_at(self.root.take(), range.end);
+        let (left, middle) = treap::split_at(rest, range.start);
+        self.root = treap::merge(left, right);
+        ImplicitTreap { root: middle }
+    }
+
+    /// Returns a copy of the values in `range`, with all pending updates applied, without modifying the sequence.
+    pub fn slice(&self, range: Range<usize>) -> ImplicitTreap<T, F> {
+        self.check_range(&range);
+        let mut values = Vec::with_capacity(range.len());
+        collect(self.root.as_deref(), &View::default(), range, &mut values);
+        values.into_iter().collect()
+    }
+
+    /// Splits the sequence in two at `at`, and returns the second part.
+    pub fn split_off(&mut self, at: usize) -> ImplicitTreap<T, F> {
+        assert!(at <= self.len(), "index out of bounds");
+        let (left, right) = treap::split_at(self.root.take(), at);
+        self.root = left;
+        ImplicitTreap { root: right }
+    }
+
+    /// Moves all values of `other` to the end of this sequence, leaving `other` empty.
+    pub fn append(&mut self, other: &mut ImplicitTreap<T, F>) {
+        self.root = treap::merge(self.root.take(), other.root.take());
+    }
+
+    /// Reverses the order of the values in `range`.
+    pub fn reverse(&mut self, range: Range<usize>) {
+        self.with_range(range, reverse);
+    }
+
+    /// Applies `action` to every value in `range`.
+    pub fn update(&mut self, range: Range<usize>, action: F) {
+        self.with_range(range, |node| apply(node, &action));
+    }
+
+    /// Returns the aggregate of the values in `range`, from left to right, or the identity for an empty range.
+    pub fn query(&self, range: Range<usize>) -> T {
+        self.check_range(&range);
+        fold(self.root.as_deref(), &View::default(), range)
+    }
+
+    /// Returns the largest `r` such that `pred(self.query(0..r))` holds.
+    ///
+    /// `pred` must hold for the identity, and once it fails on a prefix it must fail on all longer ones.
+    pub fn max_right<P: Fn(&T) -> bool>(&self, pred: P) -> usize {
+        debug_assert!(
+            pred(&T::identity()),
+            "the predicate must hold for the identity"
+        );
+        let mut current = self.root.as_deref();
+        let mut view = View::default();
+        let mut acc = T::identity();
+        let mut pos = 0;
+        while let Some(node) = current {
+            let (left, right) = view.children(node);
+            let child_view = view.descend(node);
+            if let Some(left) = left {
+                let with_left = acc.combine(&child_view.sum(left));
+                if !pred(&with_left) {
+                    current = Some(left);
+                    view = child_view;
+                    continue;
+                }
+                acc = with_left;
+                pos += left.size();
+            }
+            let with_node = acc.combine(&view.value(node));
+            if !pred(&with_node) {
+                return pos;
+            }
+            acc = with_node;
+            pos += 1;
+            current = right;
+            view = child_view;
+        }
+        pos
+    }
+
+    /// Returns the values in order, with all pending updates applied.
+    pub fn to_vec(&self) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.len());
+        collect(
+            self.root.as_deref(),
+            &View::default(),
+            0..self.len(),
+            &mut values,
+        );
+        values
+    }
+
+    fn check_range(&self, range: &Range<usize>) {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "range out of bounds"
+        );
+    }
+
+    /// Cuts out the subtree holding `range`, calls `f` on its root, and puts it back.
+    fn with_range<G: FnOnce(&mut Node<T, F>)>(&mut self, range: Range<usize>, f: G) {
+        let start = range.start;
+        let mut middle = self.remove_range(range);
+        if let Some(node) = middle.root.as_mut() {
+            f(node);
+        }
+        self.insert_all(start, middle);
+    }
+}
+
+impl<T: Monoid, F: Action<T>> From<Vec<T>> for ImplicitTreap<T, F> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: Monoid, F: Action<T>> FromIterator<T> for ImplicitTreap<T, F> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut sequence = ImplicitTreap::new();
+        for value in iter {
+            let node = Some(Box::new(Node::new((), value)));
+            sequence.root = treap::merge(sequence.root.take(), node);
+        }
+        sequence
+    }
+}
+
+/// The reversals and actions that ancestors have not pushed down yet, for read-only traversals.
+struct View<F> {
+    flip: bool,
+    action: Option<F>,
+}
+
+impl<F> Default for View<F> {
+    fn default() -> Self {
+        View {
+            flip: false,
+            action: None,
+        }
+    }
+}
+
+impl<F> View<F> {
+    /// Returns the children of `node` in their actual order.
+    fn children<'a, T>(&self, node: &'a Node<T, F>) -> Children<'a, T, F> {
+        let (left, right) = (node.left(), node.right());
+        if self.flip {
+            (right, left)
+        } else {
+            (left, right)
+        }
+    }
+
+    /// Returns the view for the children of `node`.
+    fn descend<T>(&self, node: &Node<T, F>) -> View<F>
+    where
+        F: Action<T>,
+    {
+        let fold = node.augment();
+        View {
+            flip: self.flip != fold.reversed,
+            action: match (&self.action, &fold.lazy) {
+                (Some(later), Some(earlier)) => Some(later.compose(earlier)),
+                (later, earlier) => later.as_ref().or(earlier.as_ref()).cloned(),
+            },
+        }
+    }
+
+    fn value<T: Clone>(&self, node: &Node<T, F>) -> T
+    where
+        F: Action<T>,
+    {
+        self.applied(node.value())
+    }
+
+    fn sum<T: Clone>(&self, node: &Node<T, F>) -> T
+    where
+        F: Action<T>,
+    {
+        let fold = node.augment();
+        self.applied(if self.flip { &fold.rev_sum } else { &fold.sum })
+    }
+
+    fn applied<T: Clone>(&self, value: &T) -> T
+    where
+        F: Action<T>,
+    {
+        match &self.action {
+            Some(action) => action.apply(value),
+            None => value.clone(),
+        }
+    }
+}
+
+/// Folds the positions in `range`, relative to the start of the subtree.
+fn fold<T: Monoid, F: Action<T>>(
+    tree: Option<&Node<T, F>>,
+    view: &View<F>,
+    range: Range<usize>,
+) -> T {
+    let Some(node) = tree else {
+        return T::identity();
+    };
+    if range.start >= range.end {
+        return T::identity();
+    }
+    if range.start == 0 && range.end >= node.size() {
+        return view.sum(node);
+    }
+    let (left, right) = view.children(node);
+    let child_view = view.descend(node);
+    let left_size = treap::size(left);
+    let mut result = T::identity();
+    if range.start < left_size {
+        result = fold(left, &child_view, range.start..min(range.end, left_size));
+    }
+    if range.start <= left_size && left_size < range.end {
+        result = result.combine(&view.value(node));
+    }
+    if range.end > left_size + 1 {
+        let start = range.start.saturating_sub(left_size + 1);
+        result = result.combine(&fold(right, &child_view, start..range.end - left_size - 1));
+    }
+    result
+}
+
+/// Pushes the values at the positions in `range`, relative to the start of the subtree, in order.
+fn collect<T: Monoid, F: Action<T>>(
+    tree: Option<&Node<T, F>>,
+    view: &View<F>,
+    range: Range<usize>,
+    out: &mut Vec<T>,
+) {
+    let Some(node) = tree else {
+        return;
+    };
+    if range.start >= range.end {
+        return;
+    }
+    let (left, right) = view.children(node);
+    let child_view = view.descend(node);
+    let left_size = treap::size(left);
+    if range.start < left_size {
+        collect(
+            left,
+            &child_view,
+            range.start..min(range.end, left_size),
+            out,
+        );
+    }
+    if range.start <= left_size && left_size < range.end {
+        out.push(view.value(node));
+    }
+    if range.end > left_size + 1 {
+        let start = range.start.saturating_sub(left_size + 1);
+        collect(right, &child_view, start..range.end - left_size - 1, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImplicitTreap;
+    use crate::data_structures::{Max, RangeAdd, RangeAssign, SumLen};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn values<F: super::Action<String>>(treap: &ImplicitTreap<String, F>) -> String {
+        treap.to_vec().concat()
+    }
+
+    fn letters(s: &str) -> ImplicitTreap<String> {
+        s.chars().map(String::from).collect()
+    }
+
+    #[test]
+    fn insert_remove_and_get() {
+        let mut treap = letters("acd");
+        treap.insert(1, "b".to_string());
+        treap.push("e".to_string());
+        treap.insert(0, "_".to_string());
+        assert_eq!(values(&treap), "_abcde");
+        assert_eq!(treap.remove(0), "_");
+        assert_eq!(treap.get(2), Some("c".to_string()));
+        assert_eq!(treap.get(5), None);
+        assert_eq!(treap.len(), 5);
+    }
+
+    #[test]
+    fn cut_copy_paste() {
+        let mut treap = letters("hello brave new world");
+        let cut = treap.remove_range(6..12);
+        assert_eq!(values(&cut), "brave ");
+        assert_eq!(values(&treap), "hello new world");
+        let copy = treap.slice(0..6);
+        assert_eq!(values(&treap), "hello new world");
+        treap.insert_all(treap.len(), copy);
+        let mut tail = treap.split_off(9);
+        assert_eq!(values(&treap), "hello new");
+        assert_eq!(values(&tail), " worldhello ");
+        treap.append(&mut tail);
+        assert!(tail.is_empty());
+        assert_eq!(treap.query(0..treap.len()), "hello new worldhello ");
+    }
+
+    #[test]
+    fn reverse_keeps_order_of_aggregates() {
+        let mut treap = letters("abcdefgh");
+        treap.reverse(2..6);
+        assert_eq!(values(&treap), "abfedcgh");
+        assert_eq!(treap.query(1..5), "bfed");
+        treap.reverse(0..8);
+        assert_eq!(values(&treap), "hgcdefba");
+        assert_eq!(treap.query(2..7), "cdefb");
+        treap.reverse(3..4);
+        treap.reverse(4..4);
+        assert_eq!(treap.get(3), Some("d".to_string()));
+    }
+
+    #[test]
+    fn lazy_updates_and_reversals() {
+        let mut naive: Vec<i64> = (0..200).map(|x| (x * 37) % 101).collect();
+        let mut treap: ImplicitTreap<SumLen<i64>, RangeAdd<i64>> =
+            naive.iter().map(|&x| SumLen::leaf(x)).collect();
+        let mut rng = StdRng::seed_from_u64(5);
+        for step in 0..500 {
+            let a = rng.gen_range(0..=naive.len());
+            let b = rng.gen_range(0..=naive.len());
+            let (l, r) = (a.min(b), a.max(b));
+            match step % 4 {
+                0 => {
+                    treap.update(l..r, RangeAdd(step as i64 - 250));
+                    naive[l..r].iter_mut().for_each(|x| *x += step as i64 - 250);
+                }
+                1 => {
+                    treap.reverse(l..r);
+                    naive[l..r].reverse();
+                }
+                2 => {
+                    let cut = treap.remove_range(l..r);
+                    treap.insert_all(0, cut);
+                    let moved: Vec<_> = naive.drain(l..r).collect();
+                    naive.splice(0..0, moved);
+                }
+                _ => {
+                    assert_eq!(treap.query(l..r).sum, naive[l..r].iter().sum::<i64>());
+                    assert_eq!(treap.get(l).map(|v| v.sum), naive.get(l).copied());
+                    let slice: Vec<i64> =
+                        treap.slice(l..r).to_vec().iter().map(|v| v.sum).collect();
+                    assert_eq!(slice, naive[l..r]);
+                }
+            }
+        }
+        let sums: Vec<i64> = treap.to_vec().iter().map(|v| v.sum).collect();
+        assert_eq!(sums, naive);
+    }
+
+    #[test]
+    fn max_right_over_prefix_maximum() {
+        let mut treap: ImplicitTreap<Max<i32>, RangeAssign<i32>> =
+            [3, 1, 4, 1, 5, 9, 2, 6].iter().map(|&x| Max(x)).collect();
+        assert_eq!(treap.max_right(|m| m.0 < 5), 4);
+        assert_eq!(treap.max_right(|m| m.0 < 100), 8);
+        treap.reverse(0..8);
+        assert_eq!(treap.max_right(|m| m.0 < 9), 2);
+        treap.update(0..3, RangeAssign(Some(0)));
+        assert_eq!(treap.max_right(|m| m.0 < 5), 3);
+    }
+
+    #[test]
+    fn long_sequences_stay_shallow() {
+        let mut treap: ImplicitTreap<SumLen<i64>> = ImplicitTreap::new();
+        for i in 0..100_000 {
+            treap.push(SumLen::leaf(i));
+        }
+        assert_eq!(treap.query(0..100_000).sum, 99_999 * 100_000 / 2);
+    }
+}
@@ -0,0 +1,326 @@
+// Indexed binary heap
+// A binary min-heap that also keeps the position of every element in the heap, so that an element can be found
+// from its handle in O(1), and have its priority changed or be removed in O(log n).
+
+use super::priority_queue::PriorityQueue;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_HEAP_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Refers to an element of an `IndexedHeap`, until the element leaves the heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    heap: u64,
+    slot: usize,
+    generation: u64,
+}
+
+struct Slot<P, T> {
+    entry: Option<(P, T)>,
+    /// The index of the slot in `IndexedHeap::heap`, while it holds an element.
+    position: usize,
+    /// Bumped whenever the slot is freed, so that old handles no longer match.
+    generation: u64,
+}
+
+pub struct IndexedHeap<P, T> {
+    /// Tells the handles of this heap from those of other heaps, whose slots may have the same index.
+    id: u64,
+    /// The slots of the elements, in heap order.
+    heap: Vec<usize>,
+    slots: Vec<Slot<P, T>>,
+    free: Vec<usize>,
+}
+
+impl<P: Ord, T> Default for IndexedHeap<P, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Ord, T> IndexedHeap<P, T> {
+    pub fn new() -> Self {
+        IndexedHeap {
+            id: NEXT_HEAP_ID.fetch_add(1, Ordering::Relaxed),
+            heap: Vec::new(),
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    fn priority(&self, position: usize) -> &P {
+        &self.slots[self.heap[position]].entry.as_ref().unwrap().0
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.slots[self.heap[a]].position = a;
+        self.slots[self.heap[b]].position = b;
+    }
+
+    fn sift_up(&mut self, mut position: usize) {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if self.priority(position) >= self.priority(parent) {
+                break;
+            }
+            self.swap(position, parent);
+            position = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut position: usize) {
+        loop {
+            let mut smallest = position;
+            for child in [2 * position + 1, 2 * position + 2] {
+                if child < self.len() && self.priority(child) < self.priority(smallest) {
+                    smallest = child;
+                }
+            }
+            if smallest == position {
+                break;
+            }
+            self.swap(position, smallest);
+            position = smallest;
+        }
+    }
+
+    pub fn push(&mut self, priority: P, value: T) -> Handle {
+        let position = self.heap.len();
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot].entry = Some((priority, value));
+                self.slots[slot].position = position;
+                slot
+            }
+            None => {
+                self.slots.push(Slot {
+                    entry: Some((priority, value)),
+                    position,
+                    generation: 0,
+                });
+                self.slots.len() - 1
+            }
+        };
+        self.heap.push(slot);
+        self.sift_up(position);
+        Handle {
+            heap: self.id,
+            slot,
+            generation: self.slots[slot].generation,
+        }
+    }
+
+    pub fn peek(&self) -> Option<(&P, &T)> {
+        let slot = *self.heap.first()?;
+        self.slots[slot]
+            .entry
+            .as_ref()
+            .map(|(priority, value)| (priority, value))
+    }
+
+    pub fn pop(&mut self) -> Option<(P, T)> {
+        let slot = *self.heap.first()?;
+        Some(self.remove_slot(slot))
+    }
+
+    /// Returns the position of the element of a handle, if it is still in the heap.
+    fn position(&self, handle: Handle) -> Option<usize> {
+        if handle.heap != self.id {
+            return None;
+        }
+        let slot = self.slots.get(handle.slot)?;
+        (slot.generation == handle.generation && slot.entry.is_some()).then_some(slot.position)
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.position(handle).is_some()
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<(&P, &T)> {
+        self.position(handle)?;
+        self.slots[handle.slot]
+            .entry
+            .as_ref()
+            .map(|(priority, value)| (priority, value))
+    }
+
+    /// Lowers the priority of an element.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the element is no longer in the heap, or if `priority` is greater than its current one.
+    pub fn decrease_key(&mut self, handle: Handle, priority: P) {
+        let position = self.position(handle).expect("stale handle");
+        assert!(
+            priority <= *self.priority(position),
+            "the new priority is greater than the current one"
+        );
+        self.change_priority(handle, priority);
+    }
+
+    /// Changes the priority of an element, up or down.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the element is no longer in the heap.
+    pub fn change_priority(&mut self, handle: Handle, priority: P) {
+        let position = self.position(handle).expect("stale handle");
+        self.slots[handle.slot].entry.as_mut().unwrap().0 = priority;
+        self.sift_up(position);
+        self.sift_down(self.slots[handle.slot].position);
+    }
+
+    /// Removes an element, and returns it if it was still in the heap.
+    pub fn remove(&mut self, handle: Handle) -> Option<(P, T)> {
+        self.position(handle)?;
+        Some(self.remove_slot(handle.slot))
+    }
+
+    fn remove_slot(&mut self, slot: usize) -> (P, T) {
+        let position = self.slots[slot].position;
+        let last = self.heap.len() - 1;
+        self.swap(position, last);
+        self.heap.pop();
+        if position < last {
+            self.sift_up(position);
+            self.sift_down(self.slots[self.heap[position]].position);
+        }
+        self.slots[slot].generation += 1;
+        self.free.push(slot);
+        self.slots[slot].entry.take().unwrap()
+    }
+}
+
+impl<P: Ord, T> PriorityQueue<P, T> for IndexedHeap<P, T> {
+    type Handle = Handle;
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn push(&mut self, priority: P, value: T) -> Handle {
+        self.push(priority, value)
+    }
+
+    fn peek(&self) -> Option<(&P, &T)> {
+        self.peek()
+    }
+
+    fn pop(&mut self) -> Option<(P, T)> {
+        self.pop()
+    }
+
+    fn decrease_key(&mut self, handle: Handle, priority: P) {
+        self.decrease_key(handle, priority);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IndexedHeap;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn pops_in_order() {
+        let mut heap = IndexedHeap::new();
+        for (i, priority) in [5, 3, 8, 1, 9, 2, 7].into_iter().enumerate() {
+            heap.push(priority, i);
+        }
+        assert_eq!(heap.peek(), Some((&1, &3)));
+        let order: Vec<i32> = std::iter::from_fn(|| heap.pop().map(|(p, _)| p)).collect();
+        assert_eq!(order, [1, 2, 3, 5, 7, 8, 9]);
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn changes_priorities_through_handles() {
+        let mut heap = IndexedHeap::new();
+        let a = heap.push(10, 'a');
+        let b = heap.push(20, 'b');
+        let c = heap.push(30, 'c');
+        heap.decrease_key(c, 5);
+        assert_eq!(heap.peek(), Some((&5, &'c')));
+        heap.change_priority(c, 40);
+        assert_eq!(heap.get(c), Some((&40, &'c')));
+        assert_eq!(heap.remove(a), Some((10, 'a')));
+        assert!(!heap.contains(a));
+        assert_eq!(heap.remove(a), None);
+        // the slot of `a` is reused, but its handle stays stale
+        let d = heap.push(1, 'd');
+        assert_eq!(heap.get(a), None);
+        assert_eq!(heap.pop(), Some((1, 'd')));
+        assert!(!heap.contains(d));
+        assert!(heap.contains(b));
+        assert_eq!(heap.pop(), Some((20, 'b')));
+        assert!(heap.contains(c));
+    }
+
+    #[test]
+    #[should_panic(expected = "stale handle")]
+    fn rejects_stale_handles() {
+        let mut heap = IndexedHeap::new();
+        let handle = heap.push(1, ());
+        heap.pop();
+        heap.decrease_key(handle, 0);
+    }
+
+    #[test]
+    fn ignores_handles_of_other_heaps() {
+        let mut first = IndexedHeap::new();
+        let mut second = IndexedHeap::new();
+        let handle = first.push(1, 'a');
+        second.push(2, 'b');
+        assert!(!second.contains(handle));
+        assert_eq!(second.get(handle), None);
+        assert_eq!(second.remove(handle), None);
+        assert_eq!(second.len(), 1);
+        assert!(first.contains(handle));
+    }
+
+    #[test]
+    fn matches_a_sorted_set() {
+        let mut heap = IndexedHeap::new();
+        let mut expected = BTreeSet::new();
+        let mut handles = Vec::new();
+        let mut rng = StdRng::seed_from_u64(11);
+        for id in 0..3000_u32 {
+            let priority = rng.gen_range(0..4096);
+            match rng.gen_range(0..5) {
+                0 | 1 => {
+                    handles.push((heap.push(priority, id), priority, id));
+                    expected.insert((priority, id));
+                }
+                2 if !handles.is_empty() => {
+                    let (handle, old, id) = handles.swap_remove(rng.gen_range(0..handles.len()));
+                    expected.remove(&(old, id));
+                    expected.insert((priority, id));
+                    heap.change_priority(handle, priority);
+                    handles.push((handle, priority, id));
+                }
+                3 if !handles.is_empty() => {
+                    let (handle, old, id) = handles.swap_remove(rng.gen_range(0..handles.len()));
+                    expected.remove(&(old, id));
+                    assert_eq!(heap.remove(handle), Some((old, id)));
+                }
+                _ => {
+                    if let Some((priority, id)) = heap.pop() {
+                        assert_eq!(expected.first().map(|e| e.0), Some(priority));
+                        expected.remove(&(priority, id));
+                        handles.retain(|h| h.2 != id);
+                    }
+                }
+            }
+            assert_eq!(heap.len(), expected.len());
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use super::Interval;
+use crate::data_structures::avl_tree::{self, AVLNode, Summary};
+use std::cmp::Ordering;
+use std::iter::FromIterator;
+
+/// The interval with the largest end in a subtree, which tells whether the subtree can overlap a query.
+struct MaxEnd<T>(Interval<T>);
+
+impl<T: PartialOrd + Clone, V> Summary<Interval<T>, V> for MaxEnd<T> {
+    fn summarize(key: &Interval<T>, _: &V, left: Option<&Self>, right: Option<&Self>) -> Self {
+        let mut max = key;
+        for child in [left, right].into_iter().flatten() {
+            if child.0.cmp_ends(max).is_gt() {
+                max = &child.0;
+            }
+        }
+        MaxEnd(max.clone())
+    }
+}
+
+type Node<T, V> = AVLNode<Interval<T>, V, MaxEnd<T>>;
+
+/// A map from intervals to values, which finds the intervals overlapping an interval or containing a point
+/// <https://en.wikipedia.org/wiki/Interval_tree#Augmented_tree>.
+///
+/// The intervals are the keys of an AVL tree ordered by start, whose nodes also keep the largest end in their
+/// subtree: a search skips the subtrees ending before the query, and stops at the first interval starting after
+/// it. Insertions and removals take O(log n) time, and a query reporting k intervals O(min(n, (k + 1) log n)).
+pub struct IntervalMap<T: PartialOrd, V> {
+    root: Option<Box<Node<T, V>>>,
+}
+
+impl<T: PartialOrd + Clone, V> IntervalMap<T, V> {
+    pub fn new() -> Self {
+        IntervalMap { root: None }
+    }
+
+    pub fn len(&self) -> usize {
+        avl_tree::size(self.root.as_deref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Maps an interval to a value, and returns the previous value of the interval.
+    pub fn insert(&mut self, interval: Interval<T>, value: V) -> Option<V> {
+        avl_tree::insert(&mut self.root, interval, value)
+    }
+
+    /// Removes an interval, and returns its value.
+    pub fn remove(&mut self, interval: &Interval<T>) -> Option<V> {
+        avl_tree::remove(&mut self.root, interval)
+    }
+
+    pub fn get(&self, interval: &Interval<T>) -> Option<&V> {
+        let mut current = self.root.as_deref();
+        while let Some(node) = current {
+            current = match interval.cmp(node.key()) {
+                Ordering::Equal => return Some(node.value()),
+                Ordering::Less => node.left(),
+                Ordering::Greater => node.right(),
+            }
+        }
+        None
+    }
+
+    pub fn contains(&self, interval: &Interval<T>) -> bool {
+        self.get(interval).is_some()
+    }
+
+    /// Returns an iterator over the entries, in the order of the intervals.
+    pub fn iter(&self) -> Iter<'_, T, V> {
+        Iter::new(self.root.as_deref(), None)
+    }
+
+    /// Returns an iterator over the entries whose interval shares a point with `query`, in the order of the
+    /// intervals.
+    pub fn overlapping(&self, query: impl Into<Interval<T>>) -> Iter<'_, T, V> {
+        Iter::new(self.root.as_deref(), Some(query.into()))
+    }
+
+    /// Returns an iterator over the entries whose interval contains `point`, in the order of the intervals.
+    pub fn stabbing(&self, point: T) -> Iter<'_, T, V> {
+        self.overlapping(Interval::closed(point.clone(), point))
+    }
+}
+
+impl<T: PartialOrd + Clone, V> Default for IntervalMap<T, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PartialOrd + Clone, V> FromIterator<(Interval<T>, V)> for IntervalMap<T, V> {
+    fn from_iter<I: IntoIterator<Item = (Interval<T>, V)>>(iter: I) -> Self {
+        let mut map = IntervalMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<T: PartialOrd + Clone, V> Extend<(Interval<T>, V)> for IntervalMap<T, V> {
+    fn extend<I: IntoIterator<Item = (Interval<T>, V)>>(&mut self, iter: I) {
+        for (interval, value) in iter {
+            self.insert(interval, value);
+        }
+    }
+}
+
+impl<'a, T: PartialOrd + Clone, V> IntoIterator for &'a IntervalMap<T, V> {
+    type Item = (&'a Interval<T>, &'a V);
+    type IntoIter = Iter<'a, T, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of an `IntervalMap`.
+///
+/// This struct is created by the `iter`, `overlapping` and `stabbing` methods of `IntervalMap`.
+pub struct Iter<'a, T: PartialOrd, V> {
+    /// The nodes left to visit, whose left subtrees were visited or skipped, with the next one on top.
+    stack: Vec<&'a Node<T, V>>,
+    /// The interval the entries must overlap, if any.
+    query: Option<Interval<T>>,
+}
+
+impl<'a, T: PartialOrd, V> Iter<'a, T, V> {
+    fn new(root: Option<&'a Node<T, V>>, query: Option<Interval<T>>) -> Self {
+        let mut iter = Iter {
+            stack: Vec::new(),
+            query,
+        };
+        iter.push_left_path(root);
+        iter
+    }
+
+    /// Pushes the nodes on the path to the first interval of a subtree, unless they end before the query.
+    fn push_left_path(&mut self, mut tree: Option<&'a Node<T, V>>) {
+        while let Some(node) = tree {
+            if let Some(query) = &self.query {
+                if !node.summary().0.ends_after(query.start()) {
+                    return;
+                }
+            }
+            self.stack.push(node);
+            tree = node.left();
+        }
+    }
+}
+
+impl<'a, T: PartialOrd, V> Iterator for Iter<'a, T, V> {
+    type Item = (&'a Interval<T>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.stack.pop()?;
+            let overlaps = match &self.query {
+                None => true,
+                Some(query) => {
+                    if !query.ends_after(node.key().start()) {
+                        // this interval and all the following ones start after the query
+                        self.stack.clear();
+                        return None;
+                    }
+                    node.key().ends_after(query.start())
+                }
+            };
+            self.push_left_path(node.right());
+            if overlaps {
+                return Some((node.key(), node.value()));
+            }
+        }
+    }
+}
+
+/// A set of intervals, which finds the intervals overlapping an interval or containing a point.
+///
+/// It is an `IntervalMap` to `()`.
+pub struct IntervalSet<T: PartialOrd> {
+    map: IntervalMap<T, ()>,
+}
+
+impl<T: PartialOrd + Clone> IntervalSet<T> {
+    pub fn new() -> Self {
+        IntervalSet {
+            map: IntervalMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Adds an interval, and returns `true` if it was not in the set.
+    pub fn insert(&mut self, interval: Interval<T>) -> bool {
+        self.map.insert(interval, ()).is_none()
+    }
+
+    /// Removes an interval, and returns `true` if it was in the set.
+    pub fn remove(&mut self, interval: &Interval<T>) -> bool {
+        self.map.remove(interval).is_some()
+    }
+
+    pub fn contains(&self, interval: &Interval<T>) -> bool {
+        self.map.contains(interval)
+    }
+
+    /// Returns an iterator over the intervals, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &Interval<T>> {
+        self.map.iter().map(|(interval, &())| interval)
+    }
+
+    /// Returns an iterator over the intervals that share a point with `query`, in order.
+    pub fn overlapping(&self, query: impl Into<Interval<T>>) -> impl Iterator<Item = &Interval<T>> {
+        self.map.overlapping(query).map(|(interval, &())| interval)
+    }
+
+    /// Returns an iterator over the intervals that contain `point`, in order.
+    pub fn stabbing(&self, point: T) -> impl Iterator<Item = &Interval<T>> {
+        self.map.stabbing(point).map(|(interval, &())| interval)
+    }
+}
+
+impl<T: PartialOrd + Clone> Default for IntervalSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PartialOrd + Clone> FromIterator<Interval<T>> for IntervalSet<T> {
+    fn from_iter<I: IntoIterator<Item = Interval<T>>>(iter: I) -> Self {
+        IntervalSet {
+            map: iter.into_iter().map(|interval| (interval, ())).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IntervalMap, IntervalSet};
+    use crate::data_structures::Interval;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng as _};
+
+    #[test]
+    fn finds_overlapping_reservations() {
+        let mut rooms = IntervalMap::new();
+        rooms.insert(Interval::new(9, 11), "standup");
+        rooms.insert(Interval::new(11, 12), "review");
+        rooms.insert(Interval::new(13, 17), "workshop");
+        rooms.insert(Interval::closed(10, 10), "call");
+        let names =
+            |iter: super::Iter<'_, i32, &'static str>| iter.map(|(_, v)| *v).collect::<Vec<_>>();
+
+        // half-open intervals touch without overlapping
+        assert_eq!(names(rooms.overlapping(11..13)), ["review"]);
+        assert_eq!(
+            names(rooms.overlapping(10..=11)),
+            ["standup", "call", "review"]
+        );
+        assert_eq!(names(rooms.overlapping(17..20)), Vec::<&str>::new());
+        assert_eq!(names(rooms.stabbing(10)), ["standup", "call"]);
+        assert_eq!(names(rooms.stabbing(12)), Vec::<&str>::new());
+        assert_eq!(rooms.insert(Interval::new(11, 12), "retro"), Some("review"));
+        assert_eq!(rooms.remove(&Interval::new(9, 11)), Some("standup"));
+        assert_eq!(rooms.remove(&Interval::new(9, 11)), None);
+        assert_eq!(rooms.get(&(11..12).into()), Some(&"retro"));
+        assert_eq!(names(rooms.iter()), ["call", "retro", "workshop"]);
+        assert_eq!(rooms.len(), 3);
+    }
+
+    #[test]
+    fn sets_of_float_intervals() {
+        let mut set: IntervalSet<f64> = [0.0..1.5, 1.0..2.0, 3.0..4.0]
+            .into_iter()
+            .map(Interval::from)
+            .collect();
+        assert!(!set.insert(Interval::new(1.0, 2.0)));
+        assert!(set.insert(Interval::closed(1.0, 2.0)));
+        assert_eq!(set.stabbing(1.25).count(), 3);
+        assert_eq!(
+            set.stabbing(2.0).collect::<Vec<_>>(),
+            [&Interval::closed(1.0, 2.0)]
+        );
+        assert!(set.remove(&Interval::new(0.0, 1.5)));
+        assert!(!set.contains(&Interval::new(0.0, 1.5)));
+        assert_eq!(set.overlapping(0.0..=1.0).count(), 2);
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn matches_a_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut map = IntervalMap::new();
+        let mut entries = Vec::new();
+        for step in 0..2000 {
+            let start = rng.gen_range(0..200);
+            let interval = if rng.gen_bool(0.5) {
+                Interval::new(start, start + rng.gen_range(1..30))
+            } else {
+                Interval::closed(start, start + rng.gen_range(0..30))
+            };
+            if rng.gen_bool(0.3) {
+                let removed = entries.iter().position(|(i, _)| *i == interval);
+                assert_eq!(
+                    map.remove(&interval),
+                    removed.map(|at| entries.remove(at).1)
+                );
+            } else {
+                let replaced = entries.iter_mut().find(|(i, _)| *i == interval);
+                let old = replaced.map(|(_, v)| std::mem::replace(v, step));
+                if old.is_none() {
+                    entries.push((interval, step));
+                }
+                assert_eq!(map.insert(interval, step), old);
+            }
+        }
+        entries.sort_unstable();
+        assert!(map
+            .iter()
+            .map(|(i, v)| (*i, *v))
+            .eq(entries.iter().copied()));
+        for _ in 0..200 {
+            let start = rng.gen_range(0..230);
+            let query = Interval::new(start, start + rng.gen_range(1..20));
+            let expected = entries.iter().filter(|(i, _)| i.overlaps(&query));
+            assert!(map
+                .overlapping(query)
+                .map(|(i, v)| (*i, *v))
+                .eq(expected.copied()));
+            let stabbed = entries.iter().filter(|(i, _)| i.contains(&start));
+            assert!(map
+                .stabbing(start)
+                .map(|(i, v)| (*i, *v))
+                .eq(stabbed.copied()));
+        }
+    }
+}
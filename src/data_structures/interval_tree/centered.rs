@@ -0,0 +1,217 @@
+use super::Interval;
+use std::cmp::Ordering;
+use std::iter::FromIterator;
+
+/// A node of a `CenteredIntervalTree`, which holds the intervals containing its center.
+struct Node<T> {
+    center: T,
+    /// The indices of the entries of the node, by increasing start.
+    by_start: Vec<usize>,
+    /// The indices of the entries of the node, by decreasing end.
+    by_end: Vec<usize>,
+    /// The node of the intervals ending before the center.
+    left: Option<usize>,
+    /// The node of the intervals starting after the center.
+    right: Option<usize>,
+}
+
+/// A static interval tree, built at once from all its entries
+/// <https://en.wikipedia.org/wiki/Interval_tree#Centered_interval_tree>.
+///
+/// Each node takes the median start of its intervals as center, keeps the intervals containing it sorted both by
+/// start and by end, and leaves the intervals before and after the center to its children. A query only scans the
+/// sorted intervals of a node while they overlap it, so it takes O(log n + k) time to report k intervals, without
+/// the comparisons of an `IntervalMap` on the intervals it skips. Building the tree takes O(n log n) time.
+///
+/// Unlike an `IntervalMap`, the tree keeps every entry, even with equal intervals, and reports them in no
+/// particular order.
+pub struct CenteredIntervalTree<T, V> {
+    entries: Vec<(Interval<T>, V)>,
+    nodes: Vec<Node<T>>,
+    root: Option<usize>,
+}
+
+impl<T: PartialOrd + Clone, V> CenteredIntervalTree<T, V> {
+    pub fn new(entries: Vec<(Interval<T>, V)>) -> Self {
+        let mut tree = CenteredIntervalTree {
+            entries,
+            nodes: Vec::new(),
+            root: None,
+        };
+        tree.root = tree.build((0..tree.entries.len()).collect());
+        tree
+    }
+
+    /// Builds the subtree of some entries, and returns its root.
+    fn build(&mut self, mut indices: Vec<usize>) -> Option<usize> {
+        if indices.is_empty() {
+            return None;
+        }
+        let entries = &self.entries;
+        let middle = indices.len() / 2;
+        indices.select_nth_unstable_by(middle, |&a, &b| entries[a].0.cmp(&entries[b].0));
+        // an interval contains its start, so the node keeps at least the median interval
+        let center = entries[indices[middle]].0.start().clone();
+        let (mut left, mut here, mut right) = (Vec::new(), Vec::new(), Vec::new());
+        for index in indices {
+            let interval = &entries[index].0;
+            if interval.contains(&center) {
+                here.push(index);
+            } else if *interval.start() > center {
+                right.push(index);
+            } else {
+                left.push(index);
+            }
+        }
+        let mut by_start = here.clone();
+        by_start.sort_unstable_by(|&a, &b| entries[a].0.cmp(&entries[b].0));
+        let mut by_end = here;
+        by_end.sort_unstable_by(|&a, &b| entries[b].0.cmp_ends(&entries[a].0));
+        let left = self.build(left);
+        let right = self.build(right);
+        self.nodes.push(Node {
+            center,
+            by_start,
+            by_end,
+            left,
+            right,
+        });
+        Some(self.nodes.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns an iterator over the entries, in the order they were given.
+    pub fn iter(&self) -> impl Iterator<Item = (&Interval<T>, &V)> {
+        self.entries
+            .iter()
+            .map(|(interval, value)| (interval, value))
+    }
+
+    /// Returns the entries whose interval shares a point with `query`.
+    pub fn overlapping(&self, query: impl Into<Interval<T>>) -> Vec<(&Interval<T>, &V)> {
+        let query = query.into();
+        let mut found = Vec::new();
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            let entry = |index: usize| {
+                let (interval, value) = &self.entries[index];
+                (interval, value)
+            };
+            // the intervals of the node contain the center, so they overlap a query before the center if they
+            // start early enough, and a query after the center if they end late enough
+            match query.start().partial_cmp(&node.center) {
+                Some(Ordering::Greater) => {
+                    let overlapping = node.by_end.iter().copied().map(entry);
+                    found.extend(overlapping.take_while(|(i, _)| i.ends_after(query.start())));
+                    stack.extend(node.right);
+                }
+                _ if !query.contains(&node.center) => {
+                    let overlapping = node.by_start.iter().copied().map(entry);
+                    found.extend(overlapping.take_while(|(i, _)| query.ends_after(i.start())));
+                    stack.extend(node.left);
+                }
+                _ => {
+                    found.extend(node.by_start.iter().copied().map(entry));
+                    if *query.start() < node.center {
+                        stack.extend(node.left);
+                    }
+                    if *query.end() > node.center {
+                        stack.extend(node.right);
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    /// Returns the entries whose interval contains `point`.
+    pub fn stabbing(&self, point: T) -> Vec<(&Interval<T>, &V)> {
+        self.overlapping(Interval::closed(point.clone(), point))
+    }
+}
+
+impl<T: PartialOrd + Clone, V> FromIterator<(Interval<T>, V)> for CenteredIntervalTree<T, V> {
+    fn from_iter<I: IntoIterator<Item = (Interval<T>, V)>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CenteredIntervalTree;
+    use crate::data_structures::Interval;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng as _};
+
+    #[test]
+    fn answers_queries() {
+        let tree: CenteredIntervalTree<_, _> = [
+            (Interval::new(1, 5), 'a'),
+            (Interval::new(5, 8), 'b'),
+            (Interval::closed(2, 3), 'c'),
+            (Interval::new(1, 5), 'd'),
+            (Interval::new(10, 12), 'e'),
+        ]
+        .into_iter()
+        .collect();
+        let values = |found: Vec<(&Interval<i32>, &char)>| {
+            let mut values: Vec<_> = found.into_iter().map(|(_, v)| *v).collect();
+            values.sort_unstable();
+            values
+        };
+        assert_eq!(tree.len(), 5);
+        assert_eq!(values(tree.stabbing(3)), ['a', 'c', 'd']);
+        assert_eq!(values(tree.stabbing(5)), ['b']);
+        assert_eq!(values(tree.stabbing(9)), []);
+        assert_eq!(values(tree.overlapping(4..11)), ['a', 'b', 'd', 'e']);
+        assert_eq!(values(tree.overlapping(8..=10)), ['e']);
+        assert!(CenteredIntervalTree::<i32, ()>::new(Vec::new())
+            .stabbing(0)
+            .is_empty());
+    }
+
+    #[test]
+    fn matches_a_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let entries: Vec<_> = (0..1000)
+            .map(|value| {
+                let start = rng.gen_range(0..500);
+                let interval = if rng.gen_bool(0.5) {
+                    Interval::new(start, start + rng.gen_range(1..40))
+                } else {
+                    Interval::closed(start, start + rng.gen_range(0..40))
+                };
+                (interval, value)
+            })
+            .collect();
+        let tree = CenteredIntervalTree::new(entries.clone());
+        for _ in 0..300 {
+            let start = rng.gen_range(0..550);
+            let query = if rng.gen_bool(0.5) {
+                Interval::new(start, start + rng.gen_range(1..30))
+            } else {
+                Interval::closed(start, start + rng.gen_range(0..30))
+            };
+            let mut found: Vec<_> = tree
+                .overlapping(query)
+                .into_iter()
+                .map(|(_, v)| *v)
+                .collect();
+            found.sort_unstable();
+            let expected: Vec<_> = entries
+                .iter()
+                .filter(|(i, _)| i.overlaps(&query))
+                .map(|(_, v)| *v)
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+}
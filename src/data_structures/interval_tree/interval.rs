@@ -0,0 +1,107 @@
+use std::cmp::Ordering;
+use std::ops::{Range, RangeInclusive};
+
+/// An interval of a totally ordered line, which includes its start and, if it is closed, its end.
+///
+/// Half-open intervals `[start, end)` suit durations such as reservations, which touch without overlapping, while
+/// closed intervals `[start, end]` suit extents such as the projection of a `geometry::Segment` onto an axis.
+/// The constructors reject empty intervals, and with them the floating-point NaN, so intervals of `f64` are totally
+/// ordered: first by start, then by end, a closed interval coming after the half-open one with the same ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval<T> {
+    start: T,
+    end: T,
+    closed: bool,
+}
+
+impl<T: PartialOrd> Interval<T> {
+    /// Creates the half-open interval `[start, end)`, which must not be empty.
+    pub fn new(start: T, end: T) -> Self {
+        assert!(
+            start < end,
+            "the start of an interval must be less than its end"
+        );
+        Interval {
+            start,
+            end,
+            closed: false,
+        }
+    }
+
+    /// Creates the closed interval `[start, end]`, which must not be empty.
+    pub fn closed(start: T, end: T) -> Self {
+        assert!(
+            start <= end,
+            "the start of an interval must not exceed its end"
+        );
+        Interval {
+            start,
+            end,
+            closed: true,
+        }
+    }
+
+    pub fn start(&self) -> &T {
+        &self.start
+    }
+
+    pub fn end(&self) -> &T {
+        &self.end
+    }
+
+    /// Returns `true` if the interval includes its end.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn contains(&self, point: &T) -> bool {
+        self.start <= *point && self.ends_after(point)
+    }
+
+    /// Returns `true` if the intervals share a point.
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.ends_after(&other.start) && other.ends_after(&self.start)
+    }
+
+    /// Returns `true` if the interval has a point at or after `point`.
+    pub(super) fn ends_after(&self, point: &T) -> bool {
+        *point < self.end || (self.closed && *point == self.end)
+    }
+
+    /// Compares the ends of two intervals, an included end coming after an excluded one.
+    pub(super) fn cmp_ends(&self, other: &Self) -> Ordering {
+        compare(&self.end, &other.end).then(self.closed.cmp(&other.closed))
+    }
+}
+
+/// Compares two endpoints, which the constructors of `Interval` made comparable.
+fn compare<T: PartialOrd>(a: &T, b: &T) -> Ordering {
+    a.partial_cmp(b).expect("interval endpoints are comparable")
+}
+
+impl<T: PartialOrd> Eq for Interval<T> {}
+
+impl<T: PartialOrd> PartialOrd for Interval<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: PartialOrd> Ord for Interval<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(&self.start, &other.start).then_with(|| self.cmp_ends(other))
+    }
+}
+
+impl<T: PartialOrd> From<Range<T>> for Interval<T> {
+    fn from(range: Range<T>) -> Self {
+        Interval::new(range.start, range.end)
+    }
+}
+
+impl<T: PartialOrd> From<RangeInclusive<T>> for Interval<T> {
+    fn from(range: RangeInclusive<T>) -> Self {
+        let (start, end) = range.into_inner();
+        Interval::closed(start, end)
+    }
+}
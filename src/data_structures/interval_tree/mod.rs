@@ -0,0 +1,12 @@
+//! Trees of intervals, which find the intervals overlapping an interval or containing a point.
+//!
+//! An `IntervalMap`, or an `IntervalSet`, is a balanced search tree that supports insertions and removals. A
+//! `CenteredIntervalTree` is built once from all its intervals, and answers queries with fewer comparisons.
+
+mod augmented;
+mod centered;
+mod interval;
+
+pub use self::augmented::{IntervalMap, IntervalSet, Iter};
+pub use self::centered::CenteredIntervalTree;
+pub use self::interval::Interval;
@@ -1,50 +1,249 @@
-use std::fmt::{Debug, Display};
-use std::ops::Add;
-use std::ops::AddAssign;
-use std::ops::Range;
+use super::{Max, Min, Monoid};
+use std::ops::{Add, Mul, Range};
 
-pub struct LazySegmentTree<T: Debug + Default + Ord + Copy + Display + AddAssign + Add<Output = T>>
-{
+/// A pending update that can be applied to a whole segment at once, in the "monoid + action" formulation.
+///
+/// The tree stores values of a [`Monoid`] `T`, and updates are elements of another monoid of functions on `T`.
+/// For the lazy propagation to be correct, applying an update to an aggregate must give the aggregate of the updated
+/// values: `f.apply(&a.combine(&b)) == f.apply(&a).combine(&f.apply(&b))`.
+pub trait Action<T>: Clone {
+    /// The update that changes nothing.
+    fn identity() -> Self;
+    /// The update doing `earlier` first, then `self`.
+    fn compose(&self, earlier: &Self) -> Self;
+    /// Applies the update to the aggregate of a segment.
+    fn apply(&self, value: &T) -> T;
+}
+
+/// No updates at all, for structures that are generic over an action but used without one.
+impl<T: Clone> Action<T> for () {
+    fn identity() -> Self {}
+
+    fn compose(&self, _earlier: &Self) -> Self {}
+
+    fn apply(&self, value: &T) -> T {
+        value.clone()
+    }
+}
+
+/// A sum together with the number of summed values, so that updates like range addition know how many values they
+/// change. Build leaves with [`SumLen::leaf`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SumLen<T> {
+    pub sum: T,
+    pub len: T,
+}
+
+impl<T: From<u8>> SumLen<T> {
+    pub fn leaf(value: T) -> Self {
+        SumLen {
+            sum: value,
+            len: T::from(1),
+        }
+    }
+}
+
+impl<T: Copy + From<u8> + Add<Output = T>> Monoid for SumLen<T> {
+    fn identity() -> Self {
+        SumLen {
+            sum: T::from(0),
+            len: T::from(0),
+        }
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        SumLen {
+            sum: self.sum + other.sum,
+            len: self.len + other.len,
+        }
+    }
+}
+
+/// Adds a constant to every value of a range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeAdd<T>(pub T);
+
+impl<T: Copy + From<u8> + Add<Output = T> + Mul<Output = T>> Action<SumLen<T>> for RangeAdd<T> {
+    fn identity() -> Self {
+        RangeAdd(T::from(0))
+    }
+
+    fn compose(&self, earlier: &Self) -> Self {
+        RangeAdd(self.0 + earlier.0)
+    }
+
+    fn apply(&self, value: &SumLen<T>) -> SumLen<T> {
+        SumLen {
+            sum: value.sum + self.0 * value.len,
+            len: value.len,
+        }
+    }
+}
+
+impl<T: Copy + From<u8> + Add<Output = T>> Action<Min<T>> for RangeAdd<T> {
+    fn identity() -> Self {
+        RangeAdd(T::from(0))
+    }
+
+    fn compose(&self, earlier: &Self) -> Self {
+        RangeAdd(self.0 + earlier.0)
+    }
+
+    fn apply(&self, value: &Min<T>) -> Min<T> {
+        Min(value.0 + self.0)
+    }
+}
+
+impl<T: Copy + From<u8> + Add<Output = T>> Action<Max<T>> for RangeAdd<T> {
+    fn identity() -> Self {
+        RangeAdd(T::from(0))
+    }
+
+    fn compose(&self, earlier: &Self) -> Self {
+        RangeAdd(self.0 + earlier.0)
+    }
+
+    fn apply(&self, value: &Max<T>) -> Max<T> {
+        Max(value.0 + self.0)
+    }
+}
+
+/// Sets every value of a range to a constant. `RangeAssign(None)` leaves the values unchanged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeAssign<T>(pub Option<T>);
+
+impl<T: Copy + Mul<Output = T>> Action<SumLen<T>> for RangeAssign<T> {
+    fn identity() -> Self {
+        RangeAssign(None)
+    }
+
+    fn compose(&self, earlier: &Self) -> Self {
+        RangeAssign(self.0.or(earlier.0))
+    }
+
+    fn apply(&self, value: &SumLen<T>) -> SumLen<T> {
+        match self.0 {
+            Some(x) => SumLen {
+                sum: x * value.len,
+                len: value.len,
+            },
+            None => *value,
+        }
+    }
+}
+
+impl<T: Copy> Action<Min<T>> for RangeAssign<T> {
+    fn identity() -> Self {
+        RangeAssign(None)
+    }
+
+    fn compose(&self, earlier: &Self) -> Self {
+        RangeAssign(self.0.or(earlier.0))
+    }
+
+    fn apply(&self, value: &Min<T>) -> Min<T> {
+        self.0.map_or(*value, Min)
+    }
+}
+
+impl<T: Copy> Action<Max<T>> for RangeAssign<T> {
+    fn identity() -> Self {
+        RangeAssign(None)
+    }
+
+    fn compose(&self, earlier: &Self) -> Self {
+        RangeAssign(self.0.or(earlier.0))
+    }
+
+    fn apply(&self, value: &Max<T>) -> Max<T> {
+        self.0.map_or(*value, Max)
+    }
+}
+
+/// Replaces every value `x` of a range with `mul * x + add`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeAffine<T> {
+    pub mul: T,
+    pub add: T,
+}
+
+impl<T: Copy + From<u8> + Add<Output = T> + Mul<Output = T>> Action<SumLen<T>> for RangeAffine<T> {
+    fn identity() -> Self {
+        RangeAffine {
+            mul: T::from(1),
+            add: T::from(0),
+        }
+    }
+
+    fn compose(&self, earlier: &Self) -> Self {
+        // a * (c * x + d) + b
+        RangeAffine {
+            mul: self.mul * earlier.mul,
+            add: self.mul * earlier.add + self.add,
+        }
+    }
+
+    fn apply(&self, value: &SumLen<T>) -> SumLen<T> {
+        SumLen {
+            sum: self.mul * value.sum + self.add * value.len,
+            len: value.len,
+        }
+    }
+}
+
+/// A segment tree supporting both range queries and range updates in O(log n).
+///
+/// Updates covering a whole node are not pushed to its children right away: they are stored as a pending tag on the
+/// node, and only composed into the children's tags once an operation needs to look inside the node.
+#[derive(Debug, Clone)]
+pub struct LazySegmentTree<T: Monoid, F: Action<T>> {
     len: usize,
     tree: Vec<T>,
-    lazy: Vec<Option<T>>,
-    merge: fn(T, T) -> T,
+    lazy: Vec<F>,
 }
 
-impl<T: Debug + Default + Ord + Copy + Display + AddAssign + Add<Output = T>> LazySegmentTree<T> {
-    pub fn from_vec(arr: &[T], merge: fn(T, T) -> T) -> Self {
+impl<T: Monoid, F: Action<T>> From<Vec<T>> for LazySegmentTree<T, F> {
+    fn from(arr: Vec<T>) -> Self {
         let len = arr.len();
         let mut sgtr = LazySegmentTree {
             len,
-            tree: vec![T::default(); 4 * len],
-            lazy: vec![None; 4 * len],
-            merge,
+            tree: vec![T::identity(); 4 * len],
+            lazy: vec![F::identity(); 4 * len],
         };
         if len != 0 {
-            sgtr.build_recursive(arr, 1, 0..len, merge);
+            sgtr.build_recursive(&arr, 1, 0..len);
         }
         sgtr
     }
+}
 
-    fn build_recursive(
-        &mut self,
-        arr: &[T],
-        idx: usize,
-        range: Range<usize>,
-        merge: fn(T, T) -> T,
-    ) {
+impl<T: Monoid, F: Action<T>> LazySegmentTree<T, F> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn build_recursive(&mut self, arr: &[T], idx: usize, range: Range<usize>) {
         if range.end - range.start == 1 {
-            self.tree[idx] = arr[range.start];
+            self.tree[idx] = arr[range.start].clone();
         } else {
             let mid = range.start + (range.end - range.start) / 2;
-            self.build_recursive(arr, 2 * idx, range.start..mid, merge);
-            self.build_recursive(arr, 2 * idx + 1, mid..range.end, merge);
-            self.tree[idx] = merge(self.tree[2 * idx], self.tree[2 * idx + 1]);
+            self.build_recursive(arr, 2 * idx, range.start..mid);
+            self.build_recursive(arr, 2 * idx + 1, mid..range.end);
+            self.tree[idx] = self.tree[2 * idx].combine(&self.tree[2 * idx + 1]);
         }
     }
 
+    /// Returns the aggregate of the values in `range`, from left to right, or the identity for an empty range.
+    /// Returns `None` if the range is out of the array's boundaries, like [`super::SegmentTree::query`].
     pub fn query(&mut self, range: Range<usize>) -> Option<T> {
-        self.query_recursive(1, 0..self.len, &range)
+        if range.start > range.end || range.end > self.len {
+            return None;
+        }
+        Some(self.query_recursive(1, 0..self.len, &range))
     }
 
     fn query_recursive(
@@ -52,29 +251,27 @@ impl<T: Debug + Default + Ord + Copy + Display + AddAssign + Add<Output = T>> La
         idx: usize,
         element_range: Range<usize>,
         query_range: &Range<usize>,
-    ) -> Option<T> {
+    ) -> T {
         if element_range.start >= query_range.end || element_range.end <= query_range.start {
-            return None;
-        }
-        if self.lazy[idx].is_some() {
-            self.propagation(idx, &element_range, T::default());
+            return T::identity();
         }
         if element_range.start >= query_range.start && element_range.end <= query_range.end {
-            return Some(self.tree[idx]);
+            return self.tree[idx].clone();
         }
+        self.push(idx);
         let mid = element_range.start + (element_range.end - element_range.start) / 2;
         let left = self.query_recursive(idx * 2, element_range.start..mid, query_range);
         let right = self.query_recursive(idx * 2 + 1, mid..element_range.end, query_range);
-        match (left, right) {
-            (None, None) => None,
-            (None, Some(r)) => Some(r),
-            (Some(l), None) => Some(l),
-            (Some(l), Some(r)) => Some((self.merge)(l, r)),
-        }
+        left.combine(&right)
     }
 
-    pub fn update(&mut self, target_range: Range<usize>, val: T) {
-        self.update_recursive(1, 0..self.len, &target_range, val);
+    /// Applies `action` to every value in `range`.
+    pub fn update(&mut self, range: Range<usize>, action: F) {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "range out of bounds"
+        );
+        self.update_recursive(1, 0..self.len, &range, &action);
     }
 
     fn update_recursive(
@@ -82,45 +279,35 @@ impl<T: Debug + Default + Ord + Copy + Display + AddAssign + Add<Output = T>> La
         idx: usize,
         element_range: Range<usize>,
         target_range: &Range<usize>,
-        val: T,
+        action: &F,
     ) {
         if element_range.start >= target_range.end || element_range.end <= target_range.start {
             return;
         }
-        if element_range.end - element_range.start == 1 {
-            self.tree[idx] += val;
-            return;
-        }
         if element_range.start >= target_range.start && element_range.end <= target_range.end {
-            self.lazy[idx] = match self.lazy[idx] {
-                Some(lazy) => Some(lazy + val),
-                None => Some(val),
-            };
+            self.apply_to_node(idx, action);
             return;
         }
-        if self.lazy[idx].is_some() && self.lazy[idx].unwrap() != T::default() {
-            self.propagation(idx, &element_range, T::default());
-        }
+        self.push(idx);
         let mid = element_range.start + (element_range.end - element_range.start) / 2;
-        self.update_recursive(idx * 2, element_range.start..mid, target_range, val);
-        self.update_recursive(idx * 2 + 1, mid..element_range.end, target_range, val);
-        self.tree[idx] = (self.merge)(self.tree[idx * 2], self.tree[idx * 2 + 1]);
-        self.lazy[idx] = Some(T::default());
+        self.update_recursive(idx * 2, element_range.start..mid, target_range, action);
+        self.update_recursive(idx * 2 + 1, mid..element_range.end, target_range, action);
+        self.tree[idx] = self.tree[idx * 2].combine(&self.tree[idx * 2 + 1]);
     }
 
-    fn propagation(&mut self, idx: usize, element_range: &Range<usize>, parent_lazy: T) {
-        if element_range.end - element_range.start == 1 {
-            self.tree[idx] += parent_lazy;
-            return;
+    fn apply_to_node(&mut self, idx: usize, action: &F) {
+        self.tree[idx] = action.apply(&self.tree[idx]);
+        // leaves have no children to pass the tag on to
+        if 2 * idx < self.lazy.len() {
+            self.lazy[idx] = action.compose(&self.lazy[idx]);
         }
+    }
 
-        let lazy = self.lazy[idx].unwrap_or_default();
-        self.lazy[idx] = None;
-
-        let mid = element_range.start + (element_range.end - element_range.start) / 2;
-        self.propagation(idx * 2, &(element_range.start..mid), parent_lazy + lazy);
-        self.propagation(idx * 2 + 1, &(mid..element_range.end), parent_lazy + lazy);
-        self.tree[idx] = (self.merge)(self.tree[idx * 2], self.tree[idx * 2 + 1]);
+    /// Hands the pending tag of an inner node down to its children.
+    fn push(&mut self, idx: usize) {
+        let lazy = std::mem::replace(&mut self.lazy[idx], F::identity());
+        self.apply_to_node(2 * idx, &lazy);
+        self.apply_to_node(2 * idx + 1, &lazy);
     }
 }
 
@@ -129,117 +316,211 @@ mod tests {
     use super::*;
     use quickcheck::TestResult;
     use quickcheck_macros::quickcheck;
-    use std::cmp::{max, min};
+
+    fn min_tree(vec: &[i32]) -> LazySegmentTree<Min<i32>, RangeAdd<i32>> {
+        LazySegmentTree::from(vec.iter().copied().map(Min).collect::<Vec<_>>())
+    }
+
+    fn max_tree(vec: &[i32]) -> LazySegmentTree<Max<i32>, RangeAdd<i32>> {
+        LazySegmentTree::from(vec.iter().copied().map(Max).collect::<Vec<_>>())
+    }
+
+    fn sum_tree<F: Action<SumLen<i64>>>(vec: &[i32]) -> LazySegmentTree<SumLen<i64>, F> {
+        LazySegmentTree::from(
+            vec.iter()
+                .map(|&x| SumLen::leaf(x as i64))
+                .collect::<Vec<_>>(),
+        )
+    }
 
     #[test]
     fn test_min_segments() {
         let vec = vec![-30, 2, -4, 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8];
-        let mut min_seg_tree = LazySegmentTree::from_vec(&vec, min);
+        let mut min_seg_tree = min_tree(&vec);
         // [-30, 2, -4, 7, (3, -5, 6), 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(-5), min_seg_tree.query(4..7));
+        assert_eq!(Some(Min(-5)), min_seg_tree.query(4..7));
         // [(-30, 2, -4, 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8)]
-        assert_eq!(Some(-30), min_seg_tree.query(0..vec.len()));
+        assert_eq!(Some(Min(-30)), min_seg_tree.query(0..vec.len()));
         // [(-30, 2), -4, 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(-30), min_seg_tree.query(0..2));
+        assert_eq!(Some(Min(-30)), min_seg_tree.query(0..2));
         // [-30, (2, -4), 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(-4), min_seg_tree.query(1..3));
+        assert_eq!(Some(Min(-4)), min_seg_tree.query(1..3));
         // [-30, (2, -4, 7, 3, -5, 6), 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(-5), min_seg_tree.query(1..7));
+        assert_eq!(Some(Min(-5)), min_seg_tree.query(1..7));
     }
 
     #[test]
     fn test_max_segments() {
         let vec = vec![-30, 2, -4, 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8];
-        let mut max_seg_tree = LazySegmentTree::from_vec(&vec, max);
+        let mut max_seg_tree = max_tree(&vec);
         // [-30, 2, -4, 7, (3, -5, 6), 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(6), max_seg_tree.query(4..7));
+        assert_eq!(Some(Max(6)), max_seg_tree.query(4..7));
         // [(-30, 2, -4, 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8)]
-        assert_eq!(Some(15), max_seg_tree.query(0..vec.len()));
+        assert_eq!(Some(Max(15)), max_seg_tree.query(0..vec.len()));
         // [(-30, 2), -4, 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(2), max_seg_tree.query(0..2));
+        assert_eq!(Some(Max(2)), max_seg_tree.query(0..2));
         // [-30, (2, -4), 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(2), max_seg_tree.query(1..3));
+        assert_eq!(Some(Max(2)), max_seg_tree.query(1..3));
         // [-30, (2, -4, 7, 3, -5, 6), 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(7), max_seg_tree.query(1..7));
+        assert_eq!(Some(Max(7)), max_seg_tree.query(1..7));
     }
 
     #[test]
     fn test_sum_segments() {
         let vec = vec![-30, 2, -4, 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8];
-        let mut max_seg_tree = LazySegmentTree::from_vec(&vec, |x, y| x + y);
+        let mut sum_seg_tree = sum_tree::<RangeAdd<i64>>(&vec);
         // [-30, 2, -4, 7, (3, -5, 6), 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(4), max_seg_tree.query(4..7));
+        assert_eq!(4, sum_seg_tree.query(4..7).unwrap().sum);
         // [(-30, 2, -4, 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8)]
-        assert_eq!(Some(7), max_seg_tree.query(0..vec.len()));
+        assert_eq!(7, sum_seg_tree.query(0..vec.len()).unwrap().sum);
         // [(-30, 2), -4, 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(-28), max_seg_tree.query(0..2));
+        assert_eq!(-28, sum_seg_tree.query(0..2).unwrap().sum);
         // [-30, (2, -4), 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(-2), max_seg_tree.query(1..3));
+        assert_eq!(-2, sum_seg_tree.query(1..3).unwrap().sum);
         // [-30, (2, -4, 7, 3, -5, 6), 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(9), max_seg_tree.query(1..7));
+        assert_eq!(9, sum_seg_tree.query(1..7).unwrap().sum);
     }
 
     #[test]
     fn test_update_segments_tiny() {
         let vec = vec![0, 0, 0, 0, 0];
-        let mut update_seg_tree = LazySegmentTree::from_vec(&vec, |x, y| x + y);
-        update_seg_tree.update(0..3, 3);
-        update_seg_tree.update(2..5, 3);
-        assert_eq!(Some(3), update_seg_tree.query(0..1));
-        assert_eq!(Some(3), update_seg_tree.query(1..2));
-        assert_eq!(Some(6), update_seg_tree.query(2..3));
-        assert_eq!(Some(3), update_seg_tree.query(3..4));
-        assert_eq!(Some(3), update_seg_tree.query(4..5));
+        let mut update_seg_tree = sum_tree(&vec);
+        update_seg_tree.update(0..3, RangeAdd(3));
+        update_seg_tree.update(2..5, RangeAdd(3));
+        assert_eq!(3, update_seg_tree.query(0..1).unwrap().sum);
+        assert_eq!(3, update_seg_tree.query(1..2).unwrap().sum);
+        assert_eq!(6, update_seg_tree.query(2..3).unwrap().sum);
+        assert_eq!(3, update_seg_tree.query(3..4).unwrap().sum);
+        assert_eq!(3, update_seg_tree.query(4..5).unwrap().sum);
     }
 
     #[test]
     fn test_update_segments() {
         let vec = vec![-30, 2, -4, 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8];
-        let mut update_seg_tree = LazySegmentTree::from_vec(&vec, |x, y| x + y);
+        let mut update_seg_tree = sum_tree(&vec);
         // -> [-30, (5, -1, 10, 6), -5, 6, 11, -20, 9, 14, 15, 5, 2, -8]
-        update_seg_tree.update(1..5, 3);
+        update_seg_tree.update(1..5, RangeAdd(3));
 
         // [-30, 5, -1, 10, (6 -5, 6), 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(7), update_seg_tree.query(4..7));
+        assert_eq!(7, update_seg_tree.query(4..7).unwrap().sum);
         // [(-30, 5, -1, 10, 6 , -5, 6, 11, -20, 9, 14, 15, 5, 2, -8)]
-        assert_eq!(Some(19), update_seg_tree.query(0..vec.len()));
+        assert_eq!(19, update_seg_tree.query(0..vec.len()).unwrap().sum);
         // [(-30, 5), -1, 10, 6, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(-25), update_seg_tree.query(0..2));
+        assert_eq!(-25, update_seg_tree.query(0..2).unwrap().sum);
         // [-30, (5, -1), 10, 6, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(4), update_seg_tree.query(1..3));
+        assert_eq!(4, update_seg_tree.query(1..3).unwrap().sum);
         // [-30, (5, -1, 10, 6, -5, 6), 11, -20, 9, 14, 15, 5, 2, -8]
-        assert_eq!(Some(21), update_seg_tree.query(1..7));
+        assert_eq!(21, update_seg_tree.query(1..7).unwrap().sum);
+    }
+
+    #[test]
+    fn test_assign() {
+        let mut tree = sum_tree(&[1, 2, 3, 4, 5, 6]);
+        tree.update(1..4, RangeAssign(Some(10)));
+        assert_eq!(tree.query(0..6).unwrap().sum, 1 + 30 + 5 + 6);
+        tree.update(3..6, RangeAssign(Some(-1)));
+        tree.update(0..6, RangeAssign(None));
+        assert_eq!(tree.query(2..5).unwrap().sum, 10 - 1 - 1);
+
+        let mut min = LazySegmentTree::<Min<i32>, RangeAssign<i32>>::from(vec![Min(5); 8]);
+        min.update(2..6, RangeAssign(Some(3)));
+        min.update(4..8, RangeAssign(Some(7)));
+        assert_eq!(min.query(0..8), Some(Min(3)));
+        assert_eq!(min.query(4..8), Some(Min(7)));
+    }
+
+    #[test]
+    fn test_affine_composition() {
+        let mut tree = sum_tree(&[1, 2, 3, 4]);
+        // x -> 2x + 1 on everything, then x -> 3x - 2 on the middle
+        tree.update(0..4, RangeAffine { mul: 2, add: 1 });
+        tree.update(1..3, RangeAffine { mul: 3, add: -2 });
+        // [3, 13, 19, 9]
+        assert_eq!(tree.query(0..1).unwrap().sum, 3);
+        assert_eq!(tree.query(1..2).unwrap().sum, 13);
+        assert_eq!(tree.query(0..4).unwrap().sum, 44);
+        // assignment is the affine map x -> 0x + c
+        tree.update(0..2, RangeAffine { mul: 0, add: 5 });
+        assert_eq!(tree.query(0..4).unwrap().sum, 5 + 5 + 19 + 9);
+
+        let f = RangeAffine { mul: 2, add: 3 };
+        let g = RangeAffine { mul: 5, add: 7 };
+        // g after f: 5 * (2x + 3) + 7
+        assert_eq!(g.compose(&f), RangeAffine { mul: 10, add: 22 });
+        assert_eq!(
+            Action::<SumLen<i32>>::compose(&f, &RangeAffine::identity()),
+            f
+        );
+    }
+
+    #[test]
+    fn test_float_values() {
+        let mut tree: LazySegmentTree<SumLen<f64>, RangeAffine<f64>> =
+            LazySegmentTree::from(vec![SumLen::leaf(1.0); 4]);
+        tree.update(
+            0..4,
+            RangeAffine {
+                mul: 0.5,
+                add: 0.25,
+            },
+        );
+        assert_eq!(tree.query(0..4).unwrap().sum, 3.0);
+    }
+
+    #[test]
+    fn test_query_out_of_bounds() {
+        let mut tree = min_tree(&[3, 1, 2]);
+        assert_eq!(tree.query(1..1), Some(Min(i32::MAX)));
+        assert_eq!(tree.query(2..4), None);
+        assert_eq!(tree.query(5..8), None);
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 2..1;
+        assert_eq!(tree.query(reversed), None);
     }
 
     // Some properties over segment trees:
     //  When asking for the range of the overall array, return the same as iter().min() or iter().max(), etc.
     //  When asking for an interval containing a single value, return this value, no matter the merge function
+    //  Any sequence of updates gives the same result as applying them one value at a time
 
     #[quickcheck]
     fn check_overall_interval_min(array: Vec<i32>) -> TestResult {
-        let mut seg_tree = LazySegmentTree::from_vec(&array, min);
-        TestResult::from_bool(array.iter().min().copied() == seg_tree.query(0..array.len()))
+        let mut seg_tree = min_tree(&array);
+        TestResult::from_bool(
+            array.iter().min().copied().unwrap_or(i32::MAX)
+                == seg_tree.query(0..array.len()).unwrap().0,
+        )
     }
 
     #[quickcheck]
     fn check_overall_interval_max(array: Vec<i32>) -> TestResult {
-        let mut seg_tree = LazySegmentTree::from_vec(&array, max);
-        TestResult::from_bool(array.iter().max().copied() == seg_tree.query(0..array.len()))
+        let mut seg_tree = max_tree(&array);
+        TestResult::from_bool(
+            array.iter().max().copied().unwrap_or(i32::MIN)
+                == seg_tree.query(0..array.len()).unwrap().0,
+        )
     }
 
     #[quickcheck]
     fn check_overall_interval_sum(array: Vec<i32>) -> TestResult {
-        let mut seg_tree = LazySegmentTree::from_vec(&array, max);
-        TestResult::from_bool(array.iter().max().copied() == seg_tree.query(0..array.len()))
+        let mut seg_tree = sum_tree::<RangeAdd<i64>>(&array);
+        TestResult::from_bool(
+            array.iter().map(|&x| x as i64).sum::<i64>()
+                == seg_tree.query(0..array.len()).unwrap().sum,
+        )
     }
 
     #[quickcheck]
     fn check_single_interval_min(array: Vec<i32>) -> TestResult {
-        let mut seg_tree = LazySegmentTree::from_vec(&array, min);
+        let mut seg_tree = min_tree(&array);
         for (i, value) in array.into_iter().enumerate() {
             let res = seg_tree.query(i..(i + 1));
-            if res != Some(value) {
-                return TestResult::error(format!("Expected {:?}, got {:?}", Some(value), res));
+            if res != Some(Min(value)) {
+                return TestResult::error(format!(
+                    "Expected {:?}, got {:?}",
+                    Some(Min(value)),
+                    res
+                ));
             }
         }
         TestResult::passed()
@@ -247,23 +528,73 @@ mod tests {
 
     #[quickcheck]
     fn check_single_interval_max(array: Vec<i32>) -> TestResult {
-        let mut seg_tree = LazySegmentTree::from_vec(&array, max);
+        let mut seg_tree = max_tree(&array);
         for (i, value) in array.into_iter().enumerate() {
             let res = seg_tree.query(i..(i + 1));
-            if res != Some(value) {
-                return TestResult::error(format!("Expected {:?}, got {:?}", Some(value), res));
+            if res != Some(Max(value)) {
+                return TestResult::error(format!(
+                    "Expected {:?}, got {:?}",
+                    Some(Max(value)),
+                    res
+                ));
             }
         }
         TestResult::passed()
     }
 
+    /// Integers modulo a prime, so that random affine maps cannot overflow.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Mod(u64);
+
+    const MODULUS: u64 = 998_244_353;
+
+    impl From<u8> for Mod {
+        fn from(value: u8) -> Self {
+            Mod(value as u64)
+        }
+    }
+
+    impl Add for Mod {
+        type Output = Mod;
+
+        fn add(self, other: Mod) -> Mod {
+            Mod((self.0 + other.0) % MODULUS)
+        }
+    }
+
+    impl Mul for Mod {
+        type Output = Mod;
+
+        fn mul(self, other: Mod) -> Mod {
+            Mod(self.0 * other.0 % MODULUS)
+        }
+    }
+
     #[quickcheck]
-    fn check_single_interval_sum(array: Vec<i32>) -> TestResult {
-        let mut seg_tree = LazySegmentTree::from_vec(&array, max);
-        for (i, value) in array.into_iter().enumerate() {
-            let res = seg_tree.query(i..(i + 1));
-            if res != Some(value) {
-                return TestResult::error(format!("Expected {:?}, got {:?}", Some(value), res));
+    fn check_affine_updates(array: Vec<u32>, updates: Vec<(usize, usize, u32, u32)>) -> TestResult {
+        if array.is_empty() {
+            return TestResult::discard();
+        }
+        let mut naive: Vec<Mod> = array.iter().map(|&x| Mod(x as u64 % MODULUS)).collect();
+        let mut seg_tree = LazySegmentTree::<SumLen<Mod>, RangeAffine<Mod>>::from(
+            naive.iter().map(|&x| SumLen::leaf(x)).collect::<Vec<_>>(),
+        );
+        for (a, b, mul, add) in updates {
+            let (a, b) = (a % array.len(), b % array.len());
+            let range = a.min(b)..a.max(b) + 1;
+            let action = RangeAffine {
+                mul: Mod(mul as u64),
+                add: Mod(add as u64),
+            };
+            for x in &mut naive[range.clone()] {
+                *x = action.mul * *x + action.add;
+            }
+            seg_tree.update(range, action);
+            for l in 0..array.len() {
+                let expected = naive[l..].iter().fold(Mod(0), |acc, &x| acc + x);
+                if seg_tree.query(l..array.len()).unwrap().sum != expected {
+                    return TestResult::failed();
+                }
             }
         }
         TestResult::passed()
@@ -1,47 +1,103 @@
 mod avl_tree;
 mod b_tree;
 mod binary_search_tree;
+mod cache;
+mod concurrent_skip_list;
+mod cuckoo_hash_map;
+mod disk_bplus_tree;
 mod fenwick_tree;
+mod fibonacci_heap;
 mod floyds_algorithm;
 pub mod graph;
 mod hash_table;
 mod heap;
+#[cfg(test)]
+mod identity_hasher;
+mod implicit_treap;
+mod indexed_heap;
+mod interval_tree;
 mod lazy_segment_tree;
 mod linked_list;
+mod monoid;
+mod node_pool;
+mod pairing_heap;
+mod persistent_array;
+mod persistent_segment_tree;
 mod postfix_evaluation;
+mod priority_queue;
 mod probabilistic;
 mod queue;
 mod range_minimum_query;
 mod rb_tree;
+mod robin_hood_hash_table;
+mod rope;
 mod segment_tree;
+mod segment_tree_beats;
 mod segment_tree_recursive;
+mod skip_list;
+mod sparse_table;
 mod stack_using_singly_linked_list;
 mod treap;
 mod trie;
 mod union_find;
 mod veb_tree;
 
-pub use self::avl_tree::AVLTree;
-pub use self::b_tree::BTree;
+pub use self::avl_tree::{AVLTree, Entry as AVLEntry};
+pub use self::b_tree::{BTree, BTreeViolation, Entry as BTreeEntry};
 pub use self::binary_search_tree::BinarySearchTree;
-pub use self::fenwick_tree::FenwickTree;
+pub use self::cache::{
+    AdaptiveReplacementCache, CacheStats as ReplacementCacheStats, Clock, EvictionCause, LfuCache,
+    LruCache, ManualClock, SystemClock,
+};
+pub use self::concurrent_skip_list::ConcurrentSkipList;
+pub use self::cuckoo_hash_map::CuckooHashMap;
+pub use self::disk_bplus_tree::{BPlusTree, BPlusTreeError, CacheStats, Scan};
+pub use self::fenwick_tree::{FenwickTree, FenwickTree2D, RangeFenwickTree};
+pub use self::fibonacci_heap::{FibonacciHeap, Handle as FibonacciHeapHandle};
 pub use self::floyds_algorithm::{detect_cycle, has_cycle};
 pub use self::graph::DirectedGraph;
 pub use self::graph::UndirectedGraph;
-pub use self::hash_table::HashTable;
+pub use self::hash_table::{Entry as HashTableEntry, HashTable, TableStats};
 pub use self::heap::Heap;
-pub use self::lazy_segment_tree::LazySegmentTree;
+pub use self::implicit_treap::ImplicitTreap;
+pub use self::indexed_heap::{Handle as IndexedHeapHandle, IndexedHeap};
+pub use self::interval_tree::{
+    CenteredIntervalTree, Interval, IntervalMap, IntervalSet, Iter as IntervalMapIter,
+};
+pub use self::lazy_segment_tree::{
+    Action, LazySegmentTree, RangeAdd, RangeAffine, RangeAssign, SumLen,
+};
 pub use self::linked_list::LinkedList;
+pub use self::monoid::{Max, Min, Monoid, Product, Sum};
+pub use self::pairing_heap::{Handle as PairingHeapHandle, PairingHeap};
+pub use self::persistent_array::PersistentArray;
+pub use self::persistent_segment_tree::{
+    MemoryReport, PersistentSegmentTree, RangeKthSmallest, Version,
+};
 pub use self::postfix_evaluation::evaluate_postfix;
+pub use self::priority_queue::PriorityQueue;
 pub use self::probabilistic::bloom_filter;
 pub use self::probabilistic::count_min_sketch;
+pub use self::probabilistic::counting_bloom_filter;
+pub use self::probabilistic::cuckoo_filter;
+pub use self::probabilistic::hyperloglog;
+pub use self::probabilistic::minhash;
+pub use self::probabilistic::scalable_bloom_filter;
 pub use self::queue::Queue;
 pub use self::range_minimum_query::RangeMinimumQuery;
-pub use self::rb_tree::RBTree;
+pub use self::rb_tree::{Entry as RBEntry, RBTree, RBTreeViolation};
+pub use self::robin_hood_hash_table::{Entry as RobinHoodEntry, RobinHoodHashTable};
+pub use self::rope::Rope;
 pub use self::segment_tree::SegmentTree;
+pub use self::segment_tree_beats::SegmentTreeBeats;
 pub use self::segment_tree_recursive::SegmentTree as SegmentTreeRecursive;
+pub use self::skip_list::{Iter as SkipListIter, SkipList};
+pub use self::sparse_table::{
+    BitAndOp, BitOrOp, Combine, DisjointSparseTable, GcdOp, Idempotent, MaxOp, MinOp, Operation,
+    RangeError, SparseTable,
+};
 pub use self::stack_using_singly_linked_list::Stack;
-pub use self::treap::Treap;
+pub use self::treap::{Entry as TreapEntry, Treap};
 pub use self::trie::Trie;
 pub use self::union_find::UnionFind;
 pub use self::veb_tree::VebTree;
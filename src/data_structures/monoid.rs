@@ -0,0 +1,158 @@
+//! Monoids: a set with an associative operation and an identity element.
+//!
+//! Range query structures such as [`SegmentTree`](super::SegmentTree) only
+//! need these two properties to combine the values of adjacent ranges. The
+//! operation does not have to be commutative: the left operand always covers
+//! the range that comes first, so string concatenation or matrix products work
+//! as well as sums.
+//!
+//! Implement [`Monoid`] on your own value type, or wrap numbers in [`Sum`],
+//! [`Product`], [`Min`] or [`Max`]. Pairs of monoids are monoids too, which
+//! lets one tree aggregate several values at once.
+
+use std::ops::Add;
+
+pub trait Monoid: Clone {
+    /// The element `e` such that `e.combine(x) == x.combine(&e) == x`.
+    fn identity() -> Self;
+    /// Must be associative: `a.combine(&b.combine(&c)) == a.combine(&b).combine(&c)`.
+    fn combine(&self, other: &Self) -> Self;
+}
+
+/// Addition, with zero as given by `Default`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Sum<T>(pub T);
+
+impl<T: Clone + Default + Add<Output = T>> Monoid for Sum<T> {
+    fn identity() -> Self {
+        Sum(T::default())
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Sum(self.0.clone() + other.0.clone())
+    }
+}
+
+/// Multiplication.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Product<T>(pub T);
+
+/// Minimum, with the largest value of the type as identity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Min<T>(pub T);
+
+/// Maximum, with the smallest value of the type as identity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Max<T>(pub T);
+
+macro_rules! impl_numeric_monoids {
+    ($($t:ty: $one:expr, $lowest:expr, $highest:expr;)*) => {$(
+        impl Monoid for Product<$t> {
+            fn identity() -> Self {
+                Product($one)
+            }
+
+            fn combine(&self, other: &Self) -> Self {
+                Product(self.0 * other.0)
+            }
+        }
+
+        impl Monoid for Min<$t> {
+            fn identity() -> Self {
+                Min($highest)
+            }
+
+            fn combine(&self, other: &Self) -> Self {
+                if other.0 < self.0 { *other } else { *self }
+            }
+        }
+
+        impl Monoid for Max<$t> {
+            fn identity() -> Self {
+                Max($lowest)
+            }
+
+            fn combine(&self, other: &Self) -> Self {
+                if other.0 > self.0 { *other } else { *self }
+            }
+        }
+    )*};
+}
+
+impl_numeric_monoids! {
+    i8: 1, i8::MIN, i8::MAX;
+    i16: 1, i16::MIN, i16::MAX;
+    i32: 1, i32::MIN, i32::MAX;
+    i64: 1, i64::MIN, i64::MAX;
+    i128: 1, i128::MIN, i128::MAX;
+    isize: 1, isize::MIN, isize::MAX;
+    u8: 1, u8::MIN, u8::MAX;
+    u16: 1, u16::MIN, u16::MAX;
+    u32: 1, u32::MIN, u32::MAX;
+    u64: 1, u64::MIN, u64::MAX;
+    u128: 1, u128::MIN, u128::MAX;
+    usize: 1, usize::MIN, usize::MAX;
+    f32: 1.0, f32::NEG_INFINITY, f32::INFINITY;
+    f64: 1.0, f64::NEG_INFINITY, f64::INFINITY;
+}
+
+/// Concatenation, the textbook non-commutative monoid.
+impl Monoid for String {
+    fn identity() -> Self {
+        String::new()
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        result.push_str(other);
+        result
+    }
+}
+
+impl<A: Monoid, B: Monoid> Monoid for (A, B) {
+    fn identity() -> Self {
+        (A::identity(), B::identity())
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        (self.0.combine(&other.0), self.1.combine(&other.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_identity<M: Monoid + PartialEq + std::fmt::Debug>(values: &[M]) {
+        for value in values {
+            assert_eq!(M::identity().combine(value), *value);
+            assert_eq!(value.combine(&M::identity()), *value);
+        }
+    }
+
+    #[test]
+    fn identities() {
+        check_identity(&[Sum(3), Sum(-7)]);
+        check_identity(&[Sum(0.5), Sum(-2.25)]);
+        check_identity(&[Product(3u64), Product(0)]);
+        check_identity(&[Min(i32::MIN), Min(4), Min(i32::MAX)]);
+        check_identity(&[Max(f64::MIN), Max(-1e300)]);
+        check_identity(&["ab".to_string()]);
+        check_identity(&[(Sum(1), Max(2u8))]);
+    }
+
+    #[test]
+    fn combine() {
+        assert_eq!(Min(3).combine(&Min(-2)), Min(-2));
+        assert_eq!(Max(3).combine(&Max(-2)), Max(3));
+        assert_eq!(Product(3).combine(&Product(-2)), Product(-6));
+        assert_eq!(
+            "ab".to_string().combine(&"cd".to_string()),
+            "abcd".to_string()
+        );
+        assert_eq!(
+            (Sum(1), Min(5)).combine(&(Sum(2), Min(4))),
+            (Sum(3), Min(4))
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! The node storage of the pointer-based heaps, with handles that stay safe to use after their node is removed.
+//!
+//! Slots are only deallocated with the pool: removing a node puts its slot on a free list and bumps its generation, so
+//! the handles of the old node no longer match. A pool also accepts the handles of the pools merged into it, which is
+//! what lets heaps meld in constant time: merging only links the slot lists together.
+
+use std::collections::HashSet;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_POOL_ID: AtomicU64 = AtomicU64::new(0);
+
+pub struct Slot<N> {
+    node: Option<N>,
+    generation: u64,
+    /// The next slot allocated by the pool, live or free.
+    next_allocated: *mut Slot<N>,
+    next_free: *mut Slot<N>,
+}
+
+/// Refers to an element of a heap, until the element leaves the heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    pool: u64,
+    address: usize,
+    generation: u64,
+}
+
+/// A singly linked list of slots, with its tail for constant-time concatenation.
+struct List<N> {
+    head: *mut Slot<N>,
+    tail: *mut Slot<N>,
+}
+
+pub struct NodePool<N> {
+    id: u64,
+    /// The ids of this pool and of the pools merged into it.
+    ids: HashSet<u64>,
+    allocated: List<N>,
+    free: List<N>,
+    marker: PhantomData<Box<Slot<N>>>,
+}
+
+impl<N> Default for NodePool<N> {
+    fn default() -> Self {
+        let id = NEXT_POOL_ID.fetch_add(1, Ordering::Relaxed);
+        NodePool {
+            id,
+            ids: HashSet::from([id]),
+            allocated: List {
+                head: ptr::null_mut(),
+                tail: ptr::null_mut(),
+            },
+            free: List {
+                head: ptr::null_mut(),
+                tail: ptr::null_mut(),
+            },
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<N> NodePool<N> {
+    /// Stores a node, and returns its slot, which stays valid until the node is removed.
+    pub fn insert(&mut self, node: N) -> *mut Slot<N> {
+        let slot = self.free.head;
+        if slot.is_null() {
+            let slot = Box::into_raw(Box::new(Slot {
+                node: Some(node),
+                generation: 0,
+                next_allocated: ptr::null_mut(),
+                next_free: ptr::null_mut(),
+            }));
+            if self.allocated.head.is_null() {
+                self.allocated.head = slot;
+            } else {
+                // SAFETY: the tail is a slot of this pool
+                unsafe {
+                    (*self.allocated.tail).next_allocated = slot;
+                }
+            }
+            self.allocated.tail = slot;
+            return slot;
+        }
+        // SAFETY: free slots belong to this pool
+        unsafe {
+            self.free.head = (*slot).next_free;
+            if self.free.head.is_null() {
+                self.free.tail = ptr::null_mut();
+            }
+            (*slot).node = Some(node);
+        }
+        slot
+    }
+
+    /// Removes the node of a live slot of this pool.
+    ///
+    /// # Safety
+    ///
+    /// `slot` must be a live slot of this pool.
+    pub unsafe fn remove(&mut self, slot: *mut Slot<N>) -> N {
+        let node = (*slot).node.take().unwrap();
+        (*slot).generation += 1;
+        (*slot).next_free = ptr::null_mut();
+        if self.free.head.is_null() {
+            self.free.head = slot;
+        } else {
+            (*self.free.tail).next_free = slot;
+        }
+        self.free.tail = slot;
+        node
+    }
+
+    /// Returns the node of a live slot.
+    ///
+    /// # Safety
+    ///
+    /// `slot` must be a live slot of this pool, and the node must not be borrowed mutably for `'a`.
+    pub unsafe fn node<'a>(slot: *mut Slot<N>) -> &'a N {
+        (*slot).node.as_ref().unwrap()
+    }
+
+    /// Returns the node of a live slot, mutably.
+    ///
+    /// # Safety
+    ///
+    /// `slot` must be a live slot of this pool, and the node must not be borrowed elsewhere for `'a`.
+    pub unsafe fn node_mut<'a>(slot: *mut Slot<N>) -> &'a mut N {
+        (*slot).node.as_mut().unwrap()
+    }
+
+    pub fn handle(&self, slot: *mut Slot<N>) -> Handle {
+        Handle {
+            pool: self.id,
+            address: slot.expose_provenance(),
+            // SAFETY: slots stay allocated as long as the pool
+            generation: unsafe { (*slot).generation },
+        }
+    }
+
+    /// Returns the slot of a handle, if its node is still in this pool.
+    pub fn find(&self, handle: Handle) -> Option<*mut Slot<N>> {
+        if !self.ids.contains(&handle.pool) {
+            return None;
+        }
+        let slot: *mut Slot<N> = ptr::with_exposed_provenance_mut(handle.address);
+        // SAFETY: the handle was created by this pool or one merged into it, whose slots are only freed with this pool
+        let live = unsafe { (*slot).generation == handle.generation && (*slot).node.is_some() };
+        live.then_some(slot)
+    }
+
+    /// Takes over all slots of `other`, whose handles become valid in this pool.
+    pub fn merge(&mut self, mut other: NodePool<N>) {
+        if self.ids.len() < other.ids.len() {
+            mem::swap(&mut self.ids, &mut other.ids);
+        }
+        self.ids.extend(other.ids.drain());
+        // SAFETY: the tails are slots of their pools, and the lists are disjoint
+        unsafe {
+            append(&mut self.allocated, &mut other.allocated, |slot| {
+                &raw mut (*slot).next_allocated
+            });
+            append(&mut self.free, &mut other.free, |slot| {
+                &raw mut (*slot).next_free
+            });
+        }
+    }
+}
+
+/// Moves the slots of `other` to the end of `list`, where `next` gives the link field of a slot.
+unsafe fn append<N>(
+    list: &mut List<N>,
+    other: &mut List<N>,
+    next: impl Fn(*mut Slot<N>) -> *mut *mut Slot<N>,
+) {
+    if other.head.is_null() {
+        return;
+    }
+    if list.head.is_null() {
+        list.head = other.head;
+    } else {
+        *next(list.tail) = other.head;
+    }
+    list.tail = other.tail;
+    other.head = ptr::null_mut();
+    other.tail = ptr::null_mut();
+}
+
+impl<N> Drop for NodePool<N> {
+    fn drop(&mut self) {
+        let mut slot = self.allocated.head;
+        while !slot.is_null() {
+            // SAFETY: every allocated slot is in the list once, and was allocated with `Box`
+            let boxed = unsafe { Box::from_raw(slot) };
+            slot = boxed.next_allocated;
+        }
+    }
+}
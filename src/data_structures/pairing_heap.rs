@@ -0,0 +1,323 @@
+// Pairing heap
+// A heap-ordered multiway tree, where every node keeps its children in a linked list. Pushing and melding link two
+// roots in O(1); popping the root pairs up its children, then links the pairs from right to left, in O(log n)
+// amortized. Decreasing a key cuts the node's subtree and links it with the root.
+// https://en.wikipedia.org/wiki/Pairing_heap
+
+pub use super::node_pool::Handle;
+use super::node_pool::{NodePool, Slot};
+use super::priority_queue::PriorityQueue;
+use std::ptr;
+
+struct Node<P, T> {
+    priority: P,
+    value: T,
+    /// The leftmost child.
+    child: *mut Slot<Node<P, T>>,
+    /// The next sibling to the right.
+    sibling: *mut Slot<Node<P, T>>,
+    /// The previous sibling, or the parent of a leftmost child.
+    prev: *mut Slot<Node<P, T>>,
+}
+
+type Link<P, T> = *mut Slot<Node<P, T>>;
+
+pub struct PairingHeap<P, T> {
+    pool: NodePool<Node<P, T>>,
+    root: Link<P, T>,
+    len: usize,
+}
+
+// The unsafe blocks below only follow links between the live nodes of the heap's pool.
+unsafe fn node<'a, P, T>(slot: Link<P, T>) -> &'a Node<P, T> {
+    NodePool::node(slot)
+}
+
+unsafe fn node_mut<'a, P, T>(slot: Link<P, T>) -> &'a mut Node<P, T> {
+    NodePool::node_mut(slot)
+}
+
+impl<P: Ord, T> Default for PairingHeap<P, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Ord, T> PairingHeap<P, T> {
+    pub fn new() -> Self {
+        PairingHeap {
+            pool: NodePool::default(),
+            root: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, priority: P, value: T) -> Handle {
+        let slot = self.pool.insert(Node {
+            priority,
+            value,
+            child: ptr::null_mut(),
+            sibling: ptr::null_mut(),
+            prev: ptr::null_mut(),
+        });
+        self.root = unsafe { link(self.root, slot) };
+        self.len += 1;
+        self.pool.handle(slot)
+    }
+
+    pub fn peek(&self) -> Option<(&P, &T)> {
+        if self.root.is_null() {
+            return None;
+        }
+        let root = unsafe { node(self.root) };
+        Some((&root.priority, &root.value))
+    }
+
+    pub fn pop(&mut self) -> Option<(P, T)> {
+        if self.root.is_null() {
+            return None;
+        }
+        let root = self.root;
+        unsafe {
+            self.root = merge_pairs(node(root).child);
+            Some(self.take(root))
+        }
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<(&P, &T)> {
+        let slot = self.pool.find(handle)?;
+        let node = unsafe { node(slot) };
+        Some((&node.priority, &node.value))
+    }
+
+    /// Lowers the priority of an element.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the element is no longer in the heap, or if `priority` is greater than its current one.
+    pub fn decrease_key(&mut self, handle: Handle, priority: P) {
+        let slot = self.pool.find(handle).expect("stale handle");
+        unsafe {
+            assert!(
+                priority <= node(slot).priority,
+                "the new priority is greater than the current one"
+            );
+            node_mut(slot).priority = priority;
+            if slot != self.root {
+                detach(slot);
+                self.root = link(self.root, slot);
+            }
+        }
+    }
+
+    /// Removes an element, and returns it if it was still in the heap.
+    pub fn remove(&mut self, handle: Handle) -> Option<(P, T)> {
+        let slot = self.pool.find(handle)?;
+        if slot == self.root {
+            return self.pop();
+        }
+        unsafe {
+            detach(slot);
+            self.root = link(self.root, merge_pairs(node(slot).child));
+            Some(self.take(slot))
+        }
+    }
+
+    /// Moves all elements of `other` into this heap in O(1). The handles of `other` stay valid in this heap.
+    pub fn meld(&mut self, other: Self) {
+        self.root = unsafe { link(self.root, other.root) };
+        self.len += other.len;
+        self.pool.merge(other.pool);
+    }
+
+    /// Frees a node that is no longer linked to the heap.
+    unsafe fn take(&mut self, slot: Link<P, T>) -> (P, T) {
+        self.len -= 1;
+        let node = self.pool.remove(slot);
+        (node.priority, node.value)
+    }
+}
+
+/// Links two roots, either of which may be null, and returns the new root.
+unsafe fn link<P: Ord, T>(a: Link<P, T>, b: Link<P, T>) -> Link<P, T> {
+    if a.is_null() {
+        return b;
+    }
+    if b.is_null() {
+        return a;
+    }
+    let (parent, child) = if node(b).priority < node(a).priority {
+        (b, a)
+    } else {
+        (a, b)
+    };
+    let first = node(parent).child;
+    if !first.is_null() {
+        node_mut(first).prev = child;
+    }
+    let child_node = node_mut(child);
+    child_node.sibling = first;
+    child_node.prev = parent;
+    node_mut(parent).child = child;
+    parent
+}
+
+/// Unlinks a non-root node, with its subtree, from its parent and siblings.
+unsafe fn detach<P, T>(slot: Link<P, T>) {
+    let Node { prev, sibling, .. } = *node(slot);
+    if node(prev).child == slot {
+        node_mut(prev).child = sibling;
+    } else {
+        node_mut(prev).sibling = sibling;
+    }
+    if !sibling.is_null() {
+        node_mut(sibling).prev = prev;
+    }
+    let node = node_mut(slot);
+    node.prev = ptr::null_mut();
+    node.sibling = ptr::null_mut();
+}
+
+/// Links a list of siblings into one tree: in pairs from left to right, then the pairs from right to left.
+unsafe fn merge_pairs<P: Ord, T>(mut first: Link<P, T>) -> Link<P, T> {
+    let mut pairs = Vec::new();
+    while !first.is_null() {
+        let second = node(first).sibling;
+        let next = if second.is_null() {
+            ptr::null_mut()
+        } else {
+            node(second).sibling
+        };
+        for slot in [first, second] {
+            if !slot.is_null() {
+                let node = node_mut(slot);
+                node.prev = ptr::null_mut();
+                node.sibling = ptr::null_mut();
+            }
+        }
+        pairs.push(link(first, second));
+        first = next;
+    }
+    pairs
+        .into_iter()
+        .rev()
+        .fold(ptr::null_mut(), |root, pair| link(pair, root))
+}
+
+impl<P: Ord, T> PriorityQueue<P, T> for PairingHeap<P, T> {
+    type Handle = Handle;
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn push(&mut self, priority: P, value: T) -> Handle {
+        self.push(priority, value)
+    }
+
+    fn peek(&self) -> Option<(&P, &T)> {
+        self.peek()
+    }
+
+    fn pop(&mut self) -> Option<(P, T)> {
+        self.pop()
+    }
+
+    fn decrease_key(&mut self, handle: Handle, priority: P) {
+        self.decrease_key(handle, priority);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PairingHeap;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn pops_in_order() {
+        let mut heap = PairingHeap::new();
+        for (i, priority) in [5, 3, 8, 1, 9, 2, 7].into_iter().enumerate() {
+            heap.push(priority, i);
+        }
+        assert_eq!(heap.peek(), Some((&1, &3)));
+        let order: Vec<i32> = std::iter::from_fn(|| heap.pop().map(|(p, _)| p)).collect();
+        assert_eq!(order, [1, 2, 3, 5, 7, 8, 9]);
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn melds_and_keeps_handles() {
+        let mut a = PairingHeap::new();
+        let mut b = PairingHeap::new();
+        let x = a.push(10, "x");
+        let y = b.push(20, "y");
+        b.push(5, "z");
+        a.meld(b);
+        assert_eq!(a.len(), 3);
+        assert_eq!(a.peek(), Some((&5, &"z")));
+        a.decrease_key(y, 1);
+        assert_eq!(a.pop(), Some((1, "y")));
+        assert_eq!(a.get(y), None);
+        assert_eq!(a.remove(x), Some((10, "x")));
+        assert_eq!(a.pop(), Some((5, "z")));
+        assert_eq!(a.pop(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "stale handle")]
+    fn rejects_handles_of_other_heaps() {
+        let mut a = PairingHeap::new();
+        let mut b = PairingHeap::new();
+        let handle = a.push(1, ());
+        b.push(1, ());
+        b.decrease_key(handle, 0);
+    }
+
+    #[test]
+    fn matches_a_sorted_set() {
+        let mut heap = PairingHeap::new();
+        let mut expected = BTreeSet::new();
+        let mut handles = Vec::new();
+        let mut rng = StdRng::seed_from_u64(7);
+        for id in 0..3000_u32 {
+            let priority = rng.gen_range(0..4096);
+            match rng.gen_range(0..5) {
+                0 | 1 => {
+                    handles.push((heap.push(priority, id), priority, id));
+                    expected.insert((priority, id));
+                }
+                2 if !handles.is_empty() => {
+                    let index = rng.gen_range(0..handles.len());
+                    let (handle, old, id) = handles[index];
+                    let priority = old.min(priority);
+                    expected.remove(&(old, id));
+                    expected.insert((priority, id));
+                    heap.decrease_key(handle, priority);
+                    handles[index].1 = priority;
+                }
+                3 if !handles.is_empty() => {
+                    let (handle, old, id) = handles.swap_remove(rng.gen_range(0..handles.len()));
+                    expected.remove(&(old, id));
+                    assert_eq!(heap.remove(handle), Some((old, id)));
+                }
+                _ => {
+                    if let Some((priority, id)) = heap.pop() {
+                        assert_eq!(expected.first().map(|e| e.0), Some(priority));
+                        expected.remove(&(priority, id));
+                        handles.retain(|h| h.2 != id);
+                    }
+                }
+            }
+            assert_eq!(heap.len(), expected.len());
+        }
+    }
+}
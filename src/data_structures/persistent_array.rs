@@ -0,0 +1,133 @@
+use super::persistent_segment_tree::{MemoryReport, PathCopyingTree, Version};
+
+/// An array where every `set` creates a new version, leaving the old ones readable.
+///
+/// It is the path copying tree of a [`super::PersistentSegmentTree`] without aggregates: reads and writes take
+/// O(log n), and a write allocates O(log n) nodes instead of copying the whole array. Since inner nodes keep no
+/// summary, only the written value is stored and nothing else is cloned. A clone of the array shares the versions
+/// created before it, but not those created after.
+#[derive(Debug, Clone)]
+pub struct PersistentArray<T> {
+    tree: PathCopyingTree<T, ()>,
+}
+
+impl<T> From<Vec<T>> for PersistentArray<T> {
+    fn from(arr: Vec<T>) -> Self {
+        PersistentArray {
+            tree: PathCopyingTree::from(arr),
+        }
+    }
+}
+
+impl<T> PersistentArray<T> {
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The version built from the initial array.
+    pub fn initial(&self) -> Version {
+        self.tree.initial()
+    }
+
+    /// The most recently created version.
+    pub fn latest(&self) -> Version {
+        self.tree.latest()
+    }
+
+    pub fn get(&self, version: Version, idx: usize) -> &T {
+        self.tree.get(version, idx)
+    }
+
+    /// Returns a new version where the value at `idx` is `val`.
+    pub fn set(&mut self, version: Version, idx: usize, val: T) -> Version {
+        self.tree.set(version, idx, val)
+    }
+
+    /// Copies the values of `version` out into a vector.
+    pub fn to_vec(&self, version: Version) -> Vec<T>
+    where
+        T: Clone,
+    {
+        (0..self.len())
+            .map(|idx| self.get(version, idx).clone())
+            .collect()
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        self.tree.memory_report()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_versions_stay_readable() {
+        let mut arr = PersistentArray::from(vec!["a", "b", "c"]);
+        let v0 = arr.initial();
+        let v1 = arr.set(v0, 1, "x");
+        let v2 = arr.set(v1, 2, "y");
+        let v3 = arr.set(v0, 0, "z");
+        assert_eq!(arr.to_vec(v0), ["a", "b", "c"]);
+        assert_eq!(arr.to_vec(v1), ["a", "x", "c"]);
+        assert_eq!(arr.to_vec(v2), ["a", "x", "y"]);
+        assert_eq!(arr.to_vec(v3), ["z", "b", "c"]);
+        assert_eq!(*arr.get(v2, 1), "x");
+        assert_eq!(arr.latest(), v3);
+        assert_eq!(arr.len(), 3);
+    }
+
+    #[test]
+    fn clones_share_earlier_versions() {
+        let mut arr = PersistentArray::from(vec![1, 2, 3]);
+        let v1 = arr.set(arr.initial(), 0, 10);
+        let mut copy = arr.clone();
+        let v2 = copy.set(v1, 1, 20);
+        assert_eq!(copy.to_vec(arr.initial()), [1, 2, 3]);
+        assert_eq!(copy.to_vec(v1), [10, 2, 3]);
+        assert_eq!(copy.to_vec(v2), [10, 20, 3]);
+        assert_eq!(arr.to_vec(v1), [10, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "version of another persistent structure")]
+    fn rejects_versions_created_after_cloning() {
+        let mut arr = PersistentArray::from(vec![1, 2, 3]);
+        let copy = arr.clone();
+        let v1 = arr.set(arr.initial(), 0, 10);
+        copy.get(v1, 0);
+    }
+
+    #[test]
+    fn versions_share_memory() {
+        let n = 10_000;
+        let mut arr = PersistentArray::from(vec![0u64; n]);
+        let mut version = arr.initial();
+        for i in 0..1000 {
+            version = arr.set(version, i * 37 % n, i as u64);
+        }
+        let report = arr.memory_report();
+        assert_eq!(report.versions, 1001);
+        // a full copy per version would need hundreds of times more nodes
+        assert!(report.nodes * 300 < report.nodes_without_sharing);
+        assert_eq!(*arr.get(version, 999 * 37 % n), 999);
+        assert_eq!(*arr.get(arr.initial(), 999 * 37 % n), 0);
+    }
+
+    #[test]
+    fn never_clones_values() {
+        #[derive(Debug, PartialEq)]
+        struct Unique(u32);
+
+        let mut arr = PersistentArray::from((0..5).map(Unique).collect::<Vec<_>>());
+        let v1 = arr.set(arr.initial(), 4, Unique(40));
+        assert_eq!(*arr.get(v1, 4), Unique(40));
+        assert_eq!(*arr.get(arr.initial(), 4), Unique(4));
+        assert!(PersistentArray::<Unique>::from(Vec::new()).is_empty());
+    }
+}
@@ -0,0 +1,588 @@
+use super::{Monoid, Sum};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_OWNER_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A handle to one version of a [`PersistentSegmentTree`] or a [`super::PersistentArray`].
+///
+/// Versions are never modified, so a handle stays valid forever and can be queried or updated again at any time, by
+/// the structure that created it and by its clones. Passing it to any other structure panics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Version {
+    owner: u64,
+    index: usize,
+}
+
+/// The roots of the versions of a persistent structure, which hands out and checks their [`Version`]s.
+#[derive(Debug)]
+struct Roots {
+    /// Tells the versions created by this structure from those of other structures.
+    owner: u64,
+    /// The structures this one was cloned from, oldest first, with the number of their versions it shares.
+    ancestors: Vec<(u64, usize)>,
+    /// The root node of each version.
+    nodes: Vec<usize>,
+}
+
+impl Roots {
+    fn new() -> Self {
+        Roots {
+            owner: NEXT_OWNER_ID.fetch_add(1, Ordering::Relaxed),
+            ancestors: Vec::new(),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Adds the root of a new version, and returns that version.
+    fn push(&mut self, root: usize) -> Version {
+        self.nodes.push(root);
+        self.version(self.len() - 1)
+    }
+
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns the root of `version`, which must belong to this structure.
+    fn get(&self, version: Version) -> usize {
+        assert!(
+            version.index < self.len() && self.owner_of(version.index) == version.owner,
+            "version of another persistent structure"
+        );
+        self.nodes[version.index]
+    }
+
+    fn version(&self, index: usize) -> Version {
+        Version {
+            owner: self.owner_of(index),
+            index,
+        }
+    }
+
+    /// The structure that created the `index`-th version.
+    fn owner_of(&self, index: usize) -> u64 {
+        self.ancestors
+            .iter()
+            .find(|&&(_, shared)| index < shared)
+            .map_or(self.owner, |&(owner, _)| owner)
+    }
+}
+
+impl Clone for Roots {
+    /// A clone shares the versions created so far, while the versions either copy creates from now on are
+    /// foreign to the other one.
+    fn clone(&self) -> Self {
+        let mut ancestors = self.ancestors.clone();
+        ancestors.push((self.owner, self.len()));
+        Roots {
+            ancestors,
+            nodes: self.nodes.clone(),
+            ..Roots::new()
+        }
+    }
+}
+
+/// How much memory the versions of a persistent structure share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// Number of versions created so far.
+    pub versions: usize,
+    /// Number of tree nodes actually allocated for all of them.
+    pub nodes: usize,
+    /// Bytes used by those nodes.
+    pub bytes: usize,
+    /// Number of nodes needed if every version were a full copy.
+    pub nodes_without_sharing: usize,
+}
+
+/// A node of a [`PathCopyingTree`]: the values are in the leaves, and inner nodes link two subtrees together
+/// with a summary of them.
+#[derive(Debug, Clone)]
+pub(super) enum Node<T, S> {
+    Leaf(T),
+    Inner {
+        summary: S,
+        left: usize,
+        right: usize,
+    },
+}
+
+/// What an inner node of a [`PathCopyingTree`] keeps about its subtree, computed from its two children.
+pub(super) trait Summary<T>: Sized {
+    fn summarize(left: &Node<T, Self>, right: &Node<T, Self>) -> Self;
+}
+
+/// No summary, for trees that only store values.
+impl<T> Summary<T> for () {
+    fn summarize(_: &Node<T, Self>, _: &Node<T, Self>) -> Self {}
+}
+
+/// The aggregate of the values of the subtree.
+impl<T: Monoid> Summary<T> for T {
+    fn summarize(left: &Node<T, T>, right: &Node<T, T>) -> T {
+        aggregate(left).combine(aggregate(right))
+    }
+}
+
+/// The value of a leaf, or the aggregate of an inner node.
+fn aggregate<T>(node: &Node<T, T>) -> &T {
+    match node {
+        Node::Leaf(value) => value,
+        Node::Inner { summary, .. } => summary,
+    }
+}
+
+/// A balanced binary tree over the indices `0..len`, updated by path copying: an update copies the O(log n) nodes
+/// on the path from the root to the updated leaf, and the copies point to the untouched subtrees of the old
+/// version. Every version stays readable in O(log n), while an update only allocates O(log n) nodes.
+///
+/// All nodes live in one arena, and versions are indices of their roots. It is the core of both
+/// [`PersistentSegmentTree`] and [`super::PersistentArray`], which differ in the summary `S` of their inner nodes.
+#[derive(Debug, Clone)]
+pub(super) struct PathCopyingTree<T, S> {
+    len: usize,
+    nodes: Vec<Node<T, S>>,
+    roots: Roots,
+}
+
+impl<T, S: Summary<T>> From<Vec<T>> for PathCopyingTree<T, S> {
+    /// Builds the first version from an array. An empty tree has no nodes: its root is never read.
+    fn from(arr: Vec<T>) -> Self {
+        let mut tree = PathCopyingTree {
+            len: arr.len(),
+            nodes: Vec::new(),
+            roots: Roots::new(),
+        };
+        let root = if arr.is_empty() {
+            usize::MAX
+        } else {
+            let mut values = arr.into_iter();
+            tree.build(&mut values, 0..tree.len)
+        };
+        tree.roots.push(root);
+        tree
+    }
+}
+
+impl<T, S: Summary<T>> PathCopyingTree<T, S> {
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(super) fn initial(&self) -> Version {
+        self.roots.version(0)
+    }
+
+    pub(super) fn latest(&self) -> Version {
+        self.roots.version(self.roots.len() - 1)
+    }
+
+    pub(super) fn versions(&self) -> impl Iterator<Item = Version> + '_ {
+        (0..self.roots.len()).map(|index| self.roots.version(index))
+    }
+
+    /// The root node of `version`.
+    pub(super) fn root(&self, version: Version) -> usize {
+        self.roots.get(version)
+    }
+
+    pub(super) fn node(&self, idx: usize) -> &Node<T, S> {
+        &self.nodes[idx]
+    }
+
+    fn push_node(&mut self, node: Node<T, S>) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn inner(&mut self, left: usize, right: usize) -> usize {
+        let summary = S::summarize(&self.nodes[left], &self.nodes[right]);
+        self.push_node(Node::Inner {
+            summary,
+            left,
+            right,
+        })
+    }
+
+    /// Builds the subtree of `range`, taking its values from `values` in order.
+    fn build(&mut self, values: &mut impl Iterator<Item = T>, range: Range<usize>) -> usize {
+        if range.end - range.start == 1 {
+            let value = values.next().unwrap();
+            return self.push_node(Node::Leaf(value));
+        }
+        let mid = range.start + (range.end - range.start) / 2;
+        let left = self.build(values, range.start..mid);
+        let right = self.build(values, mid..range.end);
+        self.inner(left, right)
+    }
+
+    /// Returns the nodes on the path from the root of `version` to the leaf of `idx`, with the range of each.
+    fn path(
+        &self,
+        version: Version,
+        idx: usize,
+    ) -> impl Iterator<Item = (usize, Range<usize>)> + '_ {
+        assert!(idx < self.len, "index out of bounds");
+        let start = Some((self.root(version), 0..self.len));
+        std::iter::successors(start, move |(node, range)| match self.nodes[*node] {
+            Node::Leaf(_) => None,
+            Node::Inner { left, right, .. } => {
+                let mid = range.start + (range.end - range.start) / 2;
+                Some(if idx < mid {
+                    (left, range.start..mid)
+                } else {
+                    (right, mid..range.end)
+                })
+            }
+        })
+    }
+
+    pub(super) fn get(&self, version: Version, idx: usize) -> &T {
+        let (leaf, _) = self.path(version, idx).last().unwrap();
+        match &self.nodes[leaf] {
+            Node::Leaf(value) => value,
+            Node::Inner { .. } => unreachable!("paths end at a leaf"),
+        }
+    }
+
+    /// Creates a new version equal to `version`, except that the value at `idx` is `val`.
+    pub(super) fn set(&mut self, version: Version, idx: usize, val: T) -> Version {
+        let path: Vec<(usize, Range<usize>)> = self.path(version, idx).collect();
+        // copy the path bottom up, each copy pointing to the copy below it and to the untouched sibling
+        let mut copy = self.push_node(Node::Leaf(val));
+        for (node, range) in path.into_iter().rev().skip(1) {
+            let Node::Inner { left, right, .. } = self.nodes[node] else {
+                unreachable!("only the last node of a path is a leaf")
+            };
+            let mid = range.start + (range.end - range.start) / 2;
+            copy = if idx < mid {
+                self.inner(copy, right)
+            } else {
+                self.inner(left, copy)
+            };
+        }
+        self.roots.push(copy)
+    }
+
+    pub(super) fn memory_report(&self) -> MemoryReport {
+        let nodes_per_version = (2 * self.len).max(2) - 1;
+        let versions = self.roots.len();
+        MemoryReport {
+            versions,
+            nodes: self.nodes.len(),
+            bytes: self.nodes.len() * std::mem::size_of::<Node<T, S>>(),
+            nodes_without_sharing: versions * nodes_per_version,
+        }
+    }
+}
+
+/// A functional segment tree, where every update returns a new [`Version`].
+///
+/// Updates never modify a node: they copy the O(log n) nodes on the path from the root to the updated leaf. The copies point to the untouched subtrees of the old version, so
+/// every version can still be queried in O(log n), while an update only allocates O(log n) nodes.
+///
+/// All nodes live in one arena, and versions are indices of their roots. A clone of the tree shares the versions
+/// created before it, but not those created after.
+#[derive(Debug, Clone)]
+pub struct PersistentSegmentTree<T: Monoid> {
+    tree: PathCopyingTree<T, T>,
+}
+
+impl<T: Monoid> From<Vec<T>> for PersistentSegmentTree<T> {
+    /// Builds the first version, [`PersistentSegmentTree::initial`], from an array.
+    fn from(arr: Vec<T>) -> Self {
+        PersistentSegmentTree {
+            tree: PathCopyingTree::from(arr),
+        }
+    }
+}
+
+impl<T: Monoid> PersistentSegmentTree<T> {
+    /// Builds a tree over `len` identity values.
+    pub fn new(len: usize) -> Self {
+        Self::from(vec![T::identity(); len])
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The version built from the initial array.
+    pub fn initial(&self) -> Version {
+        self.tree.initial()
+    }
+
+    /// The most recently created version.
+    pub fn latest(&self) -> Version {
+        self.tree.latest()
+    }
+
+    /// All versions, in order of creation.
+    pub fn versions(&self) -> impl Iterator<Item = Version> + '_ {
+        self.tree.versions()
+    }
+
+    /// Returns the aggregate of `range` in `version`, from left to right, or the identity for an empty range.
+    pub fn query(&self, version: Version, range: Range<usize>) -> T {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "range out of bounds"
+        );
+        let root = self.tree.root(version);
+        self.query_recursive(root, 0..self.len(), &range)
+    }
+
+    fn query_recursive(
+        &self,
+        idx: usize,
+        element_range: Range<usize>,
+        query_range: &Range<usize>,
+    ) -> T {
+        if element_range.start >= query_range.end || element_range.end <= query_range.start {
+            return T::identity();
+        }
+        let node = self.tree.node(idx);
+        if element_range.start >= query_range.start && element_range.end <= query_range.end {
+            return aggregate(node).clone();
+        }
+        let Node::Inner { left, right, .. } = *node else {
+            unreachable!("a leaf is either inside or outside of the query")
+        };
+        let mid = element_range.start + (element_range.end - element_range.start) / 2;
+        let left = self.query_recursive(left, element_range.start..mid, query_range);
+        let right = self.query_recursive(right, mid..element_range.end, query_range);
+        left.combine(&right)
+    }
+
+    /// Returns the value at `idx` in `version`.
+    pub fn get(&self, version: Version, idx: usize) -> &T {
+        self.tree.get(version, idx)
+    }
+
+    /// Creates a new version equal to `version`, except that the value at `idx` is `val`. `version` itself is left
+    /// unchanged.
+    pub fn update(&mut self, version: Version, idx: usize, val: T) -> Version {
+        self.tree.set(version, idx, val)
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        self.tree.memory_report()
+    }
+}
+
+/// Answers "what is the k-th smallest value in `a[l..r]`" in O(log n), for a fixed array.
+///
+/// The values are replaced by their ranks, and version `i` of a persistent segment tree counts how many times each
+/// rank occurs in the prefix `a[..i]`. Subtracting the counts of versions `l` and `r` gives the counts of `a[l..r]`,
+/// and the k-th smallest is found by walking down both versions at once.
+#[derive(Debug, Clone)]
+pub struct RangeKthSmallest<T> {
+    /// Distinct values, sorted: rank `i` stands for `sorted[i]`.
+    sorted: Vec<T>,
+    counts: PersistentSegmentTree<Sum<usize>>,
+    /// `prefixes[i]` holds the counts of `a[..i]`.
+    prefixes: Vec<Version>,
+}
+
+impl<T: Ord + Clone> RangeKthSmallest<T> {
+    pub fn new(arr: &[T]) -> Self {
+        let mut sorted = arr.to_vec();
+        sorted.sort();
+        sorted.dedup();
+        let mut counts = PersistentSegmentTree::<Sum<usize>>::new(sorted.len());
+        let mut prefixes = vec![counts.initial()];
+        for value in arr {
+            let rank = sorted.binary_search(value).unwrap();
+            let previous = *prefixes.last().unwrap();
+            let count = counts.get(previous, rank).0;
+            prefixes.push(counts.update(previous, rank, Sum(count + 1)));
+        }
+        RangeKthSmallest {
+            sorted,
+            counts,
+            prefixes,
+        }
+    }
+
+    /// Returns the `k`-th smallest value of `range`, counting from 0, or `None` if the range holds `k` values or
+    /// fewer.
+    pub fn kth_smallest(&self, range: Range<usize>, k: usize) -> Option<&T> {
+        assert!(
+            range.start <= range.end && range.end < self.prefixes.len(),
+            "range out of bounds"
+        );
+        if self.sorted.is_empty() {
+            return None;
+        }
+        let tree = &self.counts.tree;
+        let mut before = tree.root(self.prefixes[range.start]);
+        let mut after = tree.root(self.prefixes[range.end]);
+        let count = |before: usize, after: usize| {
+            aggregate(tree.node(after)).0 - aggregate(tree.node(before)).0
+        };
+        if count(before, after) <= k {
+            return None;
+        }
+        let mut k = k;
+        let mut ranks = 0..self.sorted.len();
+        while let (
+            &Node::Inner {
+                left: left_before,
+                right: right_before,
+                ..
+            },
+            &Node::Inner {
+                left: left_after,
+                right: right_after,
+                ..
+            },
+        ) = (tree.node(before), tree.node(after))
+        {
+            let mid = ranks.start + (ranks.end - ranks.start) / 2;
+            let left_count = count(left_before, left_after);
+            if k < left_count {
+                (before, after) = (left_before, left_after);
+                ranks.end = mid;
+            } else {
+                k -= left_count;
+                (before, after) = (right_before, right_after);
+                ranks.start = mid;
+            }
+        }
+        Some(&self.sorted[ranks.start])
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        self.counts.memory_report()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Min;
+    use super::*;
+    use quickcheck::TestResult;
+    use quickcheck_macros::quickcheck;
+
+    #[test]
+    fn versions_are_independent() {
+        let mut tree = PersistentSegmentTree::from(vec![Sum(1), Sum(2), Sum(3), Sum(4)]);
+        let v0 = tree.initial();
+        let v1 = tree.update(v0, 1, Sum(20));
+        let v2 = tree.update(v1, 3, Sum(40));
+        // branching off an old version
+        let v3 = tree.update(v0, 0, Sum(-1));
+
+        assert_eq!(tree.query(v0, 0..4), Sum(10));
+        assert_eq!(tree.query(v1, 0..4), Sum(28));
+        assert_eq!(tree.query(v2, 0..4), Sum(64));
+        assert_eq!(tree.query(v3, 0..4), Sum(8));
+        assert_eq!(tree.query(v2, 1..3), Sum(23));
+        assert_eq!(*tree.get(v1, 3), Sum(4));
+        assert_eq!(*tree.get(v2, 3), Sum(40));
+        assert_eq!(tree.latest(), v3);
+        assert_eq!(tree.versions().count(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "version of another persistent structure")]
+    fn rejects_versions_of_another_tree() {
+        let first = PersistentSegmentTree::from(vec![Sum(1), Sum(2)]);
+        let second = PersistentSegmentTree::from(vec![Sum(3), Sum(4)]);
+        // both trees have a version 0, but they are not the same version
+        second.query(first.initial(), 0..2);
+    }
+
+    #[test]
+    fn updates_share_structure() {
+        let n = 1 << 10;
+        let mut tree = PersistentSegmentTree::<Min<i32>>::new(n);
+        let mut version = tree.initial();
+        for i in 0..100 {
+            version = tree.update(version, i * 7 % n, Min(i as i32));
+        }
+        let report = tree.memory_report();
+        assert_eq!(report.versions, 101);
+        // the initial tree, then one root-to-leaf path of 11 nodes per update
+        assert_eq!(report.nodes, 2 * n - 1 + 100 * 11);
+        assert_eq!(report.nodes_without_sharing, 101 * (2 * n - 1));
+        assert!(report.bytes >= report.nodes * std::mem::size_of::<Min<i32>>());
+        assert_eq!(tree.query(version, 0..n), Min(0));
+    }
+
+    #[test]
+    fn empty_tree() {
+        let tree = PersistentSegmentTree::<Sum<i32>>::new(0);
+        assert!(tree.is_empty());
+        assert_eq!(tree.query(tree.initial(), 0..0), Sum(0));
+        let kth = RangeKthSmallest::<i32>::new(&[]);
+        assert_eq!(kth.kth_smallest(0..0, 0), None);
+    }
+
+    #[test]
+    fn kth_smallest() {
+        let arr = [5, 1, 4, 1, 5, 9, 2, 6, 5, 3];
+        let kth = RangeKthSmallest::new(&arr);
+        assert_eq!(kth.kth_smallest(0..10, 0), Some(&1));
+        assert_eq!(kth.kth_smallest(0..10, 1), Some(&1));
+        assert_eq!(kth.kth_smallest(0..10, 9), Some(&9));
+        assert_eq!(kth.kth_smallest(0..10, 10), None);
+        // [4, 1, 5, 9]
+        assert_eq!(kth.kth_smallest(2..6, 2), Some(&5));
+        assert_eq!(kth.kth_smallest(6..6, 0), None);
+        assert_eq!(kth.memory_report().versions, arr.len() + 1);
+
+        let words = RangeKthSmallest::new(&["pear", "apple", "fig", "kiwi"]);
+        assert_eq!(words.kth_smallest(1..4, 1), Some(&"fig"));
+        assert_eq!(words.clone().kth_smallest(1..4, 1), Some(&"fig"));
+    }
+
+    #[quickcheck]
+    fn check_kth_smallest(arr: Vec<i8>, queries: Vec<(usize, usize, usize)>) -> TestResult {
+        let kth = RangeKthSmallest::new(&arr);
+        for (a, b, k) in queries {
+            let (a, b) = (a % (arr.len() + 1), b % (arr.len() + 1));
+            let range = a.min(b)..a.max(b);
+            let mut sorted = arr[range.clone()].to_vec();
+            sorted.sort();
+            let k = k % (sorted.len() + 1);
+            if kth.kth_smallest(range, k) != sorted.get(k) {
+                return TestResult::failed();
+            }
+        }
+        TestResult::passed()
+    }
+
+    #[quickcheck]
+    fn check_all_versions(arr: Vec<i32>, updates: Vec<(usize, usize, i32)>) -> TestResult {
+        if arr.is_empty() {
+            return TestResult::discard();
+        }
+        let mut tree =
+            PersistentSegmentTree::from(arr.iter().map(|&x| Sum(x as i64)).collect::<Vec<_>>());
+        let mut snapshots = vec![arr.clone()];
+        let mut versions = vec![tree.initial()];
+        for (version, idx, val) in updates {
+            let version = version % snapshots.len();
+            let idx = idx % arr.len();
+            let mut snapshot = snapshots[version].clone();
+            snapshot[idx] = val;
+            snapshots.push(snapshot);
+            versions.push(tree.update(versions[version], idx, Sum(val as i64)));
+        }
+        for (version, snapshot) in tree.versions().zip(&snapshots) {
+            for l in 0..arr.len() {
+                let expected = snapshot[l..].iter().map(|&x| x as i64).sum::<i64>();
+                if tree.query(version, l..arr.len()).0 != expected {
+                    return TestResult::failed();
+                }
+            }
+        }
+        TestResult::passed()
+    }
+}
@@ -0,0 +1,30 @@
+/// A min-priority queue whose elements can be reached through handles, to lower their priority while they wait.
+///
+/// This is what Dijkstra's and Prim's algorithms need: every vertex waits in the queue once, and its priority drops
+/// whenever a cheaper path or edge to it is found. `IndexedHeap`, `PairingHeap` and `FibonacciHeap` implement it.
+pub trait PriorityQueue<P: Ord, T>: Default {
+    /// Refers to an element until it leaves the queue.
+    type Handle: Copy;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds an element, and returns its handle.
+    fn push(&mut self, priority: P, value: T) -> Self::Handle;
+
+    /// Returns an element with the smallest priority.
+    fn peek(&self) -> Option<(&P, &T)>;
+
+    /// Removes an element with the smallest priority.
+    fn pop(&mut self) -> Option<(P, T)>;
+
+    /// Lowers the priority of an element.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the element is no longer in the queue, or if `priority` is greater than its current one.
+    fn decrease_key(&mut self, handle: Self::Handle, priority: P);
+}
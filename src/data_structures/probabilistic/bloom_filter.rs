@@ -1,4 +1,6 @@
-use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fmt;
 use std::hash::{BuildHasher, Hash, Hasher};
 
 /// A Bloom Filter <https://en.wikipedia.org/wiki/Bloom_filter> is a probabilistic data structure testing whether an element belongs to a set or not
@@ -99,19 +101,29 @@ impl<T: Hash> BloomFilter<T> for SingleBinaryBloomFilter {
 /// Then when looking for a value, if **ANY** of the tests (`hash` then `AND`) returns 0 then this means the value is missing from the set, otherwise it would have returned 1
 /// If it returns `1`, it **may** be that the item is present, but could also be a collision
 /// This is what a Bloom Filter is about: returning `false` means the value is necessarily absent, and returning true means it may be present
+///
+/// Every hash function is a `SeededState` with its own seed (see below), rather than a `RandomState`:
+/// two filters built with the same seed and dimensions hash every item to the same bits, so they can be combined, saved, and loaded in another process.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MultiBinaryBloomFilter {
     filter_size: usize,
     bytes: Vec<u8>,
-    hash_builders: Vec<RandomState>,
+    seed: u64,
+    hash_builders: Vec<SeededState>,
 }
 
 impl MultiBinaryBloomFilter {
     pub fn with_dimensions(filter_size: usize, hash_count: usize) -> Self {
-        let bytes_count = filter_size / 8 + if filter_size % 8 > 0 { 1 } else { 0 }; // we need 8 times less entries in the array, since we are using bytes. Careful that we have at least one element though
+        Self::with_dimensions_and_seed(filter_size, hash_count, 0)
+    }
+
+    pub fn with_dimensions_and_seed(filter_size: usize, hash_count: usize, seed: u64) -> Self {
+        let bytes_count = filter_size.div_ceil(8); // we need 8 times less entries in the array, since we are using bytes. Careful that we have at least one element though
         Self {
             filter_size,
             bytes: vec![0; bytes_count],
-            hash_builders: vec![RandomState::new(); hash_count],
+            seed,
+            hash_builders: SeededState::family(seed, hash_count),
         }
     }
 
@@ -119,23 +131,71 @@ impl MultiBinaryBloomFilter {
         estimated_count_of_items: usize,
         max_false_positive_probability: f64,
     ) -> Self {
-        // Check Wikipedia for these formulae
-        let optimal_filter_size = (-(estimated_count_of_items as f64)
-            * max_false_positive_probability.ln()
-            / (2.0_f64.ln().powi(2)))
-        .ceil() as usize;
-        let optimal_hash_count = ((optimal_filter_size as f64 / estimated_count_of_items as f64)
-            * 2.0_f64.ln())
-        .ceil() as usize;
+        let (optimal_filter_size, optimal_hash_count) =
+            optimal_dimensions(estimated_count_of_items, max_false_positive_probability);
         Self::with_dimensions(optimal_filter_size, optimal_hash_count)
     }
+
+    /// Returns a filter containing the items of both filters.
+    pub fn union(&self, other: &Self) -> Result<Self, BloomFilterError> {
+        self.combine(other, |a, b| a | b)
+    }
+
+    /// Returns a filter containing the items present in both filters.
+    /// It may report more false positives than a filter built from these items directly.
+    pub fn intersection(&self, other: &Self) -> Result<Self, BloomFilterError> {
+        self.combine(other, |a, b| a & b)
+    }
+
+    fn combine(&self, other: &Self, op: impl Fn(u8, u8) -> u8) -> Result<Self, BloomFilterError> {
+        if (self.filter_size, self.seed, self.hash_builders.len())
+            != (other.filter_size, other.seed, other.hash_builders.len())
+        {
+            return Err(BloomFilterError::Incompatible);
+        }
+        let mut combined = self.clone();
+        for (byte, &other) in combined.bytes.iter_mut().zip(&other.bytes) {
+            *byte = op(*byte, other);
+        }
+        Ok(combined)
+    }
+
+    /// Encodes the filter in the format `from_bytes` reads:
+    ///
+    /// ```text
+    /// header (kind 1) | filter size: u64 | hash count: u32 | bits
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = encode_header(MULTI_BINARY, self.seed);
+        buf.extend_from_slice(&(self.filter_size as u64).to_le_bytes());
+        buf.extend_from_slice(&(self.hash_builders.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&self.bytes);
+        buf
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BloomFilterError> {
+        let mut reader = ByteReader::new(bytes);
+        let seed = reader.header(MULTI_BINARY)?;
+        let filter_size = reader.usize()?;
+        let hash_count = reader.hash_count()?;
+        if filter_size == 0 {
+            return Err(BloomFilterError::Corrupt("empty filter"));
+        }
+        // read the bits before allocating anything, so a forged size can't allocate more than the input holds
+        let bytes = reader.bytes(filter_size.div_ceil(8))?.to_vec();
+        reader.finish()?;
+        Ok(MultiBinaryBloomFilter {
+            filter_size,
+            bytes,
+            seed,
+            hash_builders: SeededState::family(seed, hash_count),
+        })
+    }
 }
 
 impl<Item: Hash> BloomFilter<Item> for MultiBinaryBloomFilter {
     fn insert(&mut self, item: Item) {
         for builder in &self.hash_builders {
-            let mut hasher = builder.build_hasher();
-            item.hash(&mut hasher);
             let hash = builder.hash_one(&item);
             let index = hash % self.filter_size as u64;
             let byte_index = index as usize / 8; // this is this byte that we need to modify
@@ -146,8 +206,6 @@ impl<Item: Hash> BloomFilter<Item> for MultiBinaryBloomFilter {
 
     fn contains(&self, item: &Item) -> bool {
         for builder in &self.hash_builders {
-            let mut hasher = builder.build_hasher();
-            item.hash(&mut hasher);
             let hash = builder.hash_one(item);
             let index = hash % self.filter_size as u64;
             let byte_index = index as usize / 8; // this is this byte that we need to modify
@@ -160,14 +218,213 @@ impl<Item: Hash> BloomFilter<Item> for MultiBinaryBloomFilter {
     }
 }
 
+/// Returns the number of bits and of hash functions of a filter holding `items` with the given false positive probability.
+pub(super) fn optimal_dimensions(items: usize, false_positive_probability: f64) -> (usize, usize) {
+    // Check Wikipedia for these formulae
+    let size = (-(items as f64) * false_positive_probability.ln() / (2.0_f64.ln().powi(2))).ceil();
+    let hash_count = ((size / items as f64) * 2.0_f64.ln()).ceil();
+    (size as usize, hash_count as usize)
+}
+
+/// The hashes of `RandomState` change with every process, and even those of `DefaultHasher` may change with the Rust version.
+///
+/// A filter meant to be saved needs hash functions that never change: `SeededHasher` is FNV-1a (<https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function>)
+/// started from a seed, with the bits mixed at the end so that every output bit depends on every input bit.
+/// Integers are hashed as little-endian, and `usize` as `u64`, so hashes are also the same on every platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SeededState {
+    seed: u64,
+}
+
+impl SeededState {
+    pub fn new(seed: u64) -> Self {
+        SeededState { seed }
+    }
+
+    /// Returns `count` hash functions derived from one seed.
+    pub fn family(seed: u64, count: usize) -> Vec<SeededState> {
+        (0..count as u64)
+            .map(|i| SeededState::new(mix(seed.wrapping_add(i))))
+            .collect()
+    }
+}
+
+impl BuildHasher for SeededState {
+    type Hasher = SeededHasher;
+
+    fn build_hasher(&self) -> SeededHasher {
+        SeededHasher {
+            state: FNV_OFFSET ^ self.seed,
+        }
+    }
+}
+
+pub struct SeededHasher {
+    state: u64,
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// The splitmix64 finalizer.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl Hasher for SeededHasher {
+    fn finish(&self) -> u64 {
+        mix(self.state)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state = (self.state ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
+
+/// The error returned when combining filters of different dimensions or too many items, or decoding invalid bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BloomFilterError {
+    /// The filters have different sizes, hash functions or kinds.
+    Incompatible,
+    /// The union holds more items than one of the slices of a scalable filter.
+    Full,
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for BloomFilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BloomFilterError::Incompatible => write!(f, "the filters are not compatible"),
+            BloomFilterError::Full => write!(f, "the union holds too many items"),
+            BloomFilterError::Corrupt(reason) => write!(f, "corrupt filter: {reason}"),
+        }
+    }
+}
+
+impl Error for BloomFilterError {}
+
+/// Every encoded filter starts with this header:
+///
+/// ```text
+/// magic "BLOOMFLT" | kind: u8 | seed: u64
+/// ```
+const MAGIC: &[u8; 8] = b"BLOOMFLT";
+
+/// The most hash functions a decoded filter may use: a rate of 2^-64 only takes 64 of them, but the slices of a
+/// scalable filter need one more each.
+pub(super) const MAX_HASH_COUNT: usize = 1024;
+
+pub(super) const MULTI_BINARY: u8 = 1;
+pub(super) const COUNTING: u8 = 2;
+pub(super) const PARTITIONED: u8 = 3;
+pub(super) const SCALABLE: u8 = 4;
+
+pub(super) fn encode_header(kind: u8, seed: u64) -> Vec<u8> {
+    let mut buf = MAGIC.to_vec();
+    buf.push(kind);
+    buf.extend_from_slice(&seed.to_le_bytes());
+    buf
+}
+
+/// Reads little-endian fields of an encoded filter, failing on truncated data instead of panicking.
+pub(super) struct ByteReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    pub(super) fn new(buf: &'a [u8]) -> Self {
+        ByteReader { buf }
+    }
+
+    /// Checks the header of a filter of the given kind, and returns its seed.
+    pub(super) fn header(&mut self, kind: u8) -> Result<u64, BloomFilterError> {
+        if self.bytes(MAGIC.len())? != MAGIC {
+            return Err(BloomFilterError::Corrupt("not a Bloom filter"));
+        }
+        if self.bytes(1)?[0] != kind {
+            return Err(BloomFilterError::Incompatible);
+        }
+        self.u64()
+    }
+
+    pub(super) fn bytes(&mut self, len: usize) -> Result<&'a [u8], BloomFilterError> {
+        if len > self.buf.len() {
+            return Err(BloomFilterError::Corrupt("truncated data"));
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    pub(super) fn u32(&mut self) -> Result<u32, BloomFilterError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Reads a number of hash functions, which is at most `MAX_HASH_COUNT`.
+    pub(super) fn hash_count(&mut self) -> Result<usize, BloomFilterError> {
+        let count = self.u32()? as usize;
+        if count > MAX_HASH_COUNT {
+            return Err(BloomFilterError::Corrupt("too many hash functions"));
+        }
+        Ok(count)
+    }
+
+    pub(super) fn u64(&mut self) -> Result<u64, BloomFilterError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub(super) fn usize(&mut self) -> Result<usize, BloomFilterError> {
+        usize::try_from(self.u64()?).map_err(|_| BloomFilterError::Corrupt("size out of range"))
+    }
+
+    pub(super) fn f64(&mut self) -> Result<f64, BloomFilterError> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    /// Fails if bytes are left over.
+    pub(super) fn finish(&self) -> Result<(), BloomFilterError> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(BloomFilterError::Corrupt("trailing data"))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::data_structures::probabilistic::bloom_filter::{
-        BasicBloomFilter, BloomFilter, MultiBinaryBloomFilter, SingleBinaryBloomFilter,
+        encode_header, BasicBloomFilter, BloomFilter, BloomFilterError, MultiBinaryBloomFilter,
+        SeededState, SingleBinaryBloomFilter, MULTI_BINARY,
     };
     use quickcheck::{Arbitrary, Gen};
     use quickcheck_macros::quickcheck;
     use std::collections::HashSet;
+    use std::hash::BuildHasher as _;
 
     #[derive(Debug, Clone)]
     struct TestSet {
@@ -265,4 +522,67 @@ mod tests {
         let fp_rate = false_positives as f64 / tests as f64;
         assert!(fp_rate < 1.0); // This isn't really a test, but so that you have the `fp_rate` variable to print out, or evaluate
     }
+
+    #[test]
+    fn seeded_hashes_never_change() {
+        // saved filters depend on these values
+        assert_eq!(
+            SeededState::new(42).hash_one("bloom"),
+            1_491_554_696_723_071_946
+        );
+        assert_eq!(
+            SeededState::new(42).hash_one(7_usize),
+            8_718_785_269_080_554_627
+        );
+        assert_ne!(
+            SeededState::new(1).hash_one("bloom"),
+            SeededState::new(2).hash_one("bloom")
+        );
+    }
+
+    #[test]
+    fn multi_binary_filters_combine_and_serialize() {
+        let mut low = MultiBinaryBloomFilter::from_estimate(100, 0.01);
+        let mut high = MultiBinaryBloomFilter::from_estimate(100, 0.01);
+        for i in 0..100 {
+            low.insert(i);
+            high.insert(i + 50);
+        }
+        let union = low.union(&high).unwrap();
+        assert!((0..150).all(|i| union.contains(&i)));
+        let intersection = low.intersection(&high).unwrap();
+        assert!((50..100).all(|i| intersection.contains(&i)));
+        let false_positives = (0..50).filter(|i| intersection.contains(i)).count();
+        assert!(false_positives < 10, "{false_positives}");
+
+        let bytes = union.to_bytes();
+        assert_eq!(MultiBinaryBloomFilter::from_bytes(&bytes), Ok(union));
+        assert_eq!(
+            MultiBinaryBloomFilter::from_bytes(&bytes[..20]),
+            Err(BloomFilterError::Corrupt("truncated data"))
+        );
+        let other_seed = MultiBinaryBloomFilter::with_dimensions_and_seed(959, 7, 1);
+        assert_eq!(low.union(&other_seed), Err(BloomFilterError::Incompatible));
+    }
+
+    #[test]
+    fn rejects_forged_dimensions() {
+        let forged = |filter_size: u64, hash_count: u32| {
+            let mut bytes = encode_header(MULTI_BINARY, 0);
+            bytes.extend_from_slice(&filter_size.to_le_bytes());
+            bytes.extend_from_slice(&hash_count.to_le_bytes());
+            bytes.push(0);
+            MultiBinaryBloomFilter::from_bytes(&bytes)
+        };
+        assert!(forged(8, 3).is_ok());
+        assert_eq!(
+            forged(u64::MAX >> 1, 3),
+            Err(BloomFilterError::Corrupt("truncated data"))
+        );
+        assert_eq!(
+            forged(8, u32::MAX),
+            Err(BloomFilterError::Corrupt("too many hash functions"))
+        );
+        assert_eq!(forged(0, 3), Err(BloomFilterError::Corrupt("empty filter")));
+    }
 }
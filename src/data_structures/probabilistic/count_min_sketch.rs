@@ -1,6 +1,8 @@
-use std::collections::hash_map::RandomState;
-use std::fmt::{Debug, Formatter};
-use std::hash::{BuildHasher, Hash};
+use super::bloom_filter::SeededState;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{BuildHasher as _, Hash};
 
 /// A probabilistic data structure holding an approximate count for diverse items efficiently (using constant space)
 ///
@@ -88,12 +90,28 @@ pub trait CountMinSketch {
 ///
 /// This could potentially be overestimated if we have a huge number of entries and a lot of collisions.
 /// But an interesting property is that the count we return for "TEST" cannot be underestimated
+///
+/// The hash functions are `SeededState`s derived from a seed, so two sketches with the same seed and shape put every item in the same columns:
+/// adding their counts gives the sketch of both streams, which lets us count parts of a stream separately (on different machines for instance) and `merge` the results.
 pub struct HashCountMinSketch<Item: Hash, const WIDTH: usize, const DEPTH: usize> {
     phantom: std::marker::PhantomData<Item>, // just a marker for Item to be used
     counts: [[usize; WIDTH]; DEPTH],
-    hashers: [RandomState; DEPTH],
+    seed: u64,
+    hashers: [SeededState; DEPTH],
 }
 
+/// The error returned when merging sketches built with different seeds or dimensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IncompatibleSketches;
+
+impl Display for IncompatibleSketches {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the sketches are not compatible")
+    }
+}
+
+impl Error for IncompatibleSketches {}
+
 impl<Item: Hash, const WIDTH: usize, const DEPTH: usize> Debug
     for HashCountMinSketch<Item, WIDTH, DEPTH>
 {
@@ -106,12 +124,52 @@ impl<T: Hash, const WIDTH: usize, const DEPTH: usize> Default
     for HashCountMinSketch<T, WIDTH, DEPTH>
 {
     fn default() -> Self {
-        let hashers = std::array::from_fn(|_| RandomState::new());
+        Self::with_seed(0)
+    }
+}
+
+impl<Item: Hash, const WIDTH: usize, const DEPTH: usize> HashCountMinSketch<Item, WIDTH, DEPTH> {
+    pub fn with_seed(seed: u64) -> Self {
+        let hashers = SeededState::family(seed, DEPTH);
 
         Self {
             phantom: Default::default(),
             counts: [[0; WIDTH]; DEPTH],
-            hashers,
+            seed,
+            hashers: std::array::from_fn(|row| hashers[row]),
+        }
+    }
+
+    fn column(&self, row: usize, item: &Item) -> usize {
+        (self.hashers[row].hash_one(item) % WIDTH as u64) as usize
+    }
+
+    /// Adds the counts of another sketch, as if its items had been counted by this one.
+    pub fn merge(&mut self, other: &Self) -> Result<(), IncompatibleSketches> {
+        if self.seed != other.seed {
+            return Err(IncompatibleSketches);
+        }
+        for (row, other_row) in self.counts.iter_mut().zip(&other.counts) {
+            for (count, other_count) in row.iter_mut().zip(other_row) {
+                *count += other_count;
+            }
+        }
+        Ok(())
+    }
+
+    /// Increments the count of an item like `increment_by`, but only raises the counters that would otherwise end up under the new estimate of the item.
+    ///
+    /// Every counter of the item is an overestimate of its count, so the new count is at most `get_count(item) + count`, and setting a counter higher would only add error for other items sharing it.
+    /// This "conservative update" makes estimates noticeably more accurate, but the counts can no longer be decremented.
+    pub fn conservative_increment_by(&mut self, item: Item, count: usize) {
+        let columns: [usize; DEPTH] = std::array::from_fn(|row| self.column(row, &item));
+        let estimate = (0..DEPTH)
+            .map(|row| self.counts[row][columns[row]])
+            .min()
+            .unwrap_or(0)
+            + count;
+        for (row, &col) in columns.iter().enumerate() {
+            self.counts[row][col] = self.counts[row][col].max(estimate);
         }
     }
 }
@@ -126,35 +184,87 @@ impl<Item: Hash, const WIDTH: usize, const DEPTH: usize> CountMinSketch
     }
 
     fn increment_by(&mut self, item: Self::Item, count: usize) {
-        for (row, r) in self.hashers.iter_mut().enumerate() {
-            let mut h = r.build_hasher();
-            item.hash(&mut h);
-            let hashed = r.hash_one(&item);
-            let col = (hashed % WIDTH as u64) as usize;
+        for row in 0..DEPTH {
+            let col = self.column(row, &item);
             self.counts[row][col] += count;
         }
     }
 
     fn get_count(&self, item: Self::Item) -> usize {
-        self.hashers
-            .iter()
-            .enumerate()
-            .map(|(row, r)| {
-                let mut h = r.build_hasher();
-                item.hash(&mut h);
-                let hashed = r.hash_one(&item);
-                let col = (hashed % WIDTH as u64) as usize;
-                self.counts[row][col]
-            })
+        (0..DEPTH)
+            .map(|row| self.counts[row][self.column(row, &item)])
             .min()
             .unwrap()
     }
 }
 
+/// A common use of a count-min sketch is finding the most frequent items of a stream ("heavy hitters"), without counting every item exactly.
+///
+/// Along with the sketch, we keep the `k` items with the highest estimates seen so far:
+///     * when an item is counted, we fetch its new estimate from the sketch
+///     * if it's already among the top `k`, we update its estimate
+///     * otherwise it replaces the item with the lowest estimate, if its own estimate is higher
+/// The sketch never underestimates, so a frequent item will make its way in the top `k` as soon as it's counted again.
+pub struct HeavyHitters<Item: Hash + Eq + Clone, const WIDTH: usize, const DEPTH: usize> {
+    sketch: HashCountMinSketch<Item, WIDTH, DEPTH>,
+    k: usize,
+    top: HashMap<Item, usize>,
+}
+
+impl<Item: Hash + Eq + Clone, const WIDTH: usize, const DEPTH: usize>
+    HeavyHitters<Item, WIDTH, DEPTH>
+{
+    pub fn new(k: usize) -> Self {
+        HeavyHitters {
+            sketch: HashCountMinSketch::default(),
+            k,
+            top: HashMap::with_capacity(k + 1),
+        }
+    }
+
+    pub fn increment(&mut self, item: Item) {
+        self.increment_by(item, 1);
+    }
+
+    pub fn increment_by(&mut self, item: Item, count: usize) {
+        self.sketch.conservative_increment_by(item.clone(), count);
+        let estimate = self.sketch.get_count(item.clone());
+        if let Some(top) = self.top.get_mut(&item) {
+            *top = estimate;
+            return;
+        }
+        self.top.insert(item, estimate);
+        if self.top.len() > self.k {
+            let (lowest, _) = self
+                .top
+                .iter()
+                .min_by_key(|(_, &count)| count)
+                .map(|(item, &count)| (item.clone(), count))
+                .unwrap();
+            self.top.remove(&lowest);
+        }
+    }
+
+    /// Returns the (at most) `k` most frequent items with their estimated counts, the most frequent first.
+    pub fn top_k(&self) -> Vec<(&Item, usize)> {
+        let mut top: Vec<(&Item, usize)> = self
+            .top
+            .iter()
+            .map(|(item, &count)| (item, count))
+            .collect();
+        top.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        top
+    }
+
+    pub fn sketch(&self) -> &HashCountMinSketch<Item, WIDTH, DEPTH> {
+        &self.sketch
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::data_structures::probabilistic::count_min_sketch::{
-        CountMinSketch, HashCountMinSketch,
+        CountMinSketch, HashCountMinSketch, HeavyHitters, IncompatibleSketches,
     };
     use quickcheck::{Arbitrary, Gen};
     use std::collections::HashSet;
@@ -244,4 +354,62 @@ mod tests {
             assert!(exact_ratio > 0.7); // the proof is quite hard, but this should be OK
         }
     }
+
+    #[test]
+    fn merged_sketches_add_counts() {
+        let mut left: HashCountMinSketch<&str, 50, 5> = HashCountMinSketch::default();
+        let mut right: HashCountMinSketch<&str, 50, 5> = HashCountMinSketch::default();
+        left.increment_by("apple", 3);
+        right.increment_by("apple", 4);
+        right.increment("pear");
+        left.merge(&right).unwrap();
+        assert!(left.get_count("apple") >= 7);
+        assert!(left.get_count("pear") >= 1);
+        assert_eq!(
+            left.merge(&HashCountMinSketch::with_seed(1)),
+            Err(IncompatibleSketches)
+        );
+    }
+
+    #[test]
+    fn conservative_update_is_at_least_as_accurate() {
+        let mut plain: HashCountMinSketch<u32, 64, 4> = HashCountMinSketch::default();
+        let mut conservative: HashCountMinSketch<u32, 64, 4> = HashCountMinSketch::default();
+        for i in 0..2000 {
+            let item = i % 300;
+            plain.increment(item);
+            conservative.conservative_increment_by(item, 1);
+        }
+        let error = |sketch: &HashCountMinSketch<u32, 64, 4>| -> usize {
+            (0..300)
+                .map(|item| {
+                    let exact = (2000 - item as usize).div_ceil(300);
+                    let estimate = sketch.get_count(item);
+                    assert!(estimate >= exact);
+                    estimate - exact
+                })
+                .sum()
+        };
+        assert!(error(&conservative) <= error(&plain));
+    }
+
+    #[test]
+    fn finds_heavy_hitters() {
+        let mut hitters: HeavyHitters<u32, 256, 5> = HeavyHitters::new(3);
+        for round in 0..100 {
+            // 10, 20 and 30 come 10, 5 and 3 times as often as the other items
+            for item in [
+                10, 10, 10, 10, 10, 10, 10, 10, 10, 10, 20, 20, 20, 20, 20, 30, 30, 30,
+            ] {
+                hitters.increment(item);
+            }
+            for item in 0..50 {
+                hitters.increment(1000 + round * 50 + item);
+            }
+        }
+        let top: Vec<u32> = hitters.top_k().into_iter().map(|(item, _)| *item).collect();
+        assert_eq!(top, [10, 20, 30]);
+        assert!(hitters.top_k()[0].1 >= 1000);
+        assert!(hitters.sketch().get_count(20) >= 500);
+    }
 }
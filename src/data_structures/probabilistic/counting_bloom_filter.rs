@@ -0,0 +1,179 @@
+use super::bloom_filter::{
+    encode_header, optimal_dimensions, BloomFilter, BloomFilterError, ByteReader, SeededState,
+    COUNTING,
+};
+use std::hash::{BuildHasher as _, Hash};
+
+/// A Bloom filter can't forget an item: clearing its bits may clear the bits of other items too, which would then be reported absent.
+///
+/// A counting Bloom filter replaces every bit by a small counter <https://en.wikipedia.org/wiki/Counting_Bloom_filter>.
+/// Inserting an item increments its counters, and removing it decrements them, so a counter is only back to 0 once every item using it is removed.
+/// An item is present if all its counters are positive, exactly like all its bits being set.
+///
+/// Counters are bytes. One that reaches 255 stays there for good, since it may count more items than it can tell: this costs a false positive, never a false negative.
+/// Only remove items that were inserted: removing another one decrements counters of items still in the filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountingBloomFilter {
+    counters: Vec<u8>,
+    seed: u64,
+    hash_builders: Vec<SeededState>,
+}
+
+impl CountingBloomFilter {
+    pub fn with_dimensions(filter_size: usize, hash_count: usize) -> Self {
+        Self::with_dimensions_and_seed(filter_size, hash_count, 0)
+    }
+
+    pub fn with_dimensions_and_seed(filter_size: usize, hash_count: usize, seed: u64) -> Self {
+        CountingBloomFilter {
+            counters: vec![0; filter_size],
+            seed,
+            hash_builders: SeededState::family(seed, hash_count),
+        }
+    }
+
+    pub fn from_estimate(
+        estimated_count_of_items: usize,
+        max_false_positive_probability: f64,
+    ) -> Self {
+        let (filter_size, hash_count) =
+            optimal_dimensions(estimated_count_of_items, max_false_positive_probability);
+        Self::with_dimensions(filter_size, hash_count)
+    }
+
+    fn indices<'a, Item: Hash>(&'a self, item: &'a Item) -> impl Iterator<Item = usize> + 'a {
+        self.hash_builders
+            .iter()
+            .map(move |builder| (builder.hash_one(item) % self.counters.len() as u64) as usize)
+    }
+
+    /// Removes an item, and returns whether it may have been present.
+    pub fn remove<Item: Hash>(&mut self, item: &Item) -> bool {
+        if !self.contains(item) {
+            return false;
+        }
+        let indices: Vec<usize> = self.indices(item).collect();
+        for index in indices {
+            if self.counters[index] != u8::MAX {
+                self.counters[index] -= 1;
+            }
+        }
+        true
+    }
+
+    /// Returns a filter containing the items of both filters, as if they had all been inserted into one.
+    pub fn union(&self, other: &Self) -> Result<Self, BloomFilterError> {
+        self.combine(other, u8::saturating_add)
+    }
+
+    /// Returns a filter containing the items present in both filters.
+    /// It may report more false positives than a filter built from these items directly.
+    pub fn intersection(&self, other: &Self) -> Result<Self, BloomFilterError> {
+        self.combine(other, u8::min)
+    }
+
+    fn combine(&self, other: &Self, op: impl Fn(u8, u8) -> u8) -> Result<Self, BloomFilterError> {
+        if (self.counters.len(), self.seed, self.hash_builders.len())
+            != (other.counters.len(), other.seed, other.hash_builders.len())
+        {
+            return Err(BloomFilterError::Incompatible);
+        }
+        let mut combined = self.clone();
+        for (counter, &other) in combined.counters.iter_mut().zip(&other.counters) {
+            *counter = op(*counter, other);
+        }
+        Ok(combined)
+    }
+
+    /// Encodes the filter in the format `from_bytes` reads:
+    ///
+    /// ```text
+    /// header (kind 2) | filter size: u64 | hash count: u32 | counters
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = encode_header(COUNTING, self.seed);
+        buf.extend_from_slice(&(self.counters.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&(self.hash_builders.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&self.counters);
+        buf
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BloomFilterError> {
+        let mut reader = ByteReader::new(bytes);
+        let seed = reader.header(COUNTING)?;
+        let filter_size = reader.usize()?;
+        let hash_count = reader.hash_count()?;
+        if filter_size == 0 {
+            return Err(BloomFilterError::Corrupt("empty filter"));
+        }
+        let counters = reader.bytes(filter_size)?.to_vec();
+        reader.finish()?;
+        let mut filter = Self::with_dimensions_and_seed(0, hash_count, seed);
+        filter.counters = counters;
+        Ok(filter)
+    }
+}
+
+impl<Item: Hash> BloomFilter<Item> for CountingBloomFilter {
+    fn insert(&mut self, item: Item) {
+        let indices: Vec<usize> = self.indices(&item).collect();
+        for index in indices {
+            self.counters[index] = self.counters[index].saturating_add(1);
+        }
+    }
+
+    fn contains(&self, item: &Item) -> bool {
+        self.indices(item).all(|index| self.counters[index] > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BloomFilter, BloomFilterError, CountingBloomFilter};
+
+    #[test]
+    fn removes_items() {
+        let mut filter = CountingBloomFilter::from_estimate(1000, 0.01);
+        for i in 0..1000 {
+            filter.insert(i);
+        }
+        for i in (0..1000).step_by(2) {
+            assert!(filter.remove(&i));
+        }
+        assert!((1..1000).step_by(2).all(|i| filter.contains(&i)));
+        let false_positives = (0..1000).step_by(2).filter(|i| filter.contains(i)).count();
+        assert!(false_positives < 50, "{false_positives}");
+        // removing the rest empties the filter
+        for i in (1..1000).step_by(2) {
+            assert!(filter.remove(&i));
+        }
+        assert_eq!(filter, CountingBloomFilter::from_estimate(1000, 0.01));
+    }
+
+    #[test]
+    fn combines_and_serializes() {
+        let mut evens = CountingBloomFilter::with_dimensions(4096, 5);
+        let mut small = CountingBloomFilter::with_dimensions(4096, 5);
+        for i in 0..200 {
+            evens.insert(2 * i);
+            small.insert(i);
+        }
+        let union = evens.union(&small).unwrap();
+        assert!((0..200).all(|i| union.contains(&(2 * i)) && union.contains(&i)));
+        let intersection = evens.intersection(&small).unwrap();
+        assert!((0..100).all(|i| intersection.contains(&(2 * i))));
+
+        let bytes = union.to_bytes();
+        assert_eq!(CountingBloomFilter::from_bytes(&bytes), Ok(union));
+        assert!(CountingBloomFilter::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let empty = CountingBloomFilter::with_dimensions(0, 5).to_bytes();
+        assert_eq!(
+            CountingBloomFilter::from_bytes(&empty),
+            Err(BloomFilterError::Corrupt("empty filter"))
+        );
+        assert_eq!(
+            evens.union(&CountingBloomFilter::with_dimensions(4096, 4)),
+            Err(BloomFilterError::Incompatible)
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use super::bloom_filter::BloomFilter;
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+/// The number of fingerprints a bucket holds.
+const BUCKET_SIZE: usize = 4;
+
+/// The load the filter is sized for: with buckets of 4, cuckoo filters fill up around 95%.
+const TARGET_LOAD: f64 = 0.95;
+
+/// How many fingerprints may be moved in a row before the filter counts as full.
+const MAX_KICKS: usize = 500;
+
+/// A filter that answers set membership like a Bloom filter, but also supports deletion, by storing a short
+/// fingerprint of every item instead of setting bits.
+///
+/// Every item has two candidate buckets, and its fingerprint is stored in either one.
+/// The trick ("partial-key cuckoo hashing") is that the second bucket is computed from the first one and the fingerprint alone:
+///     i1 = hash(item)
+///     i2 = i1 XOR hash(fingerprint)
+/// so a fingerprint can be moved to its other bucket without knowing the item it came from, since `i1 = i2 XOR hash(fingerprint)` as well.
+///
+/// Inserting into two full buckets evicts a random fingerprint of one of them, which moves to its other bucket, possibly evicting another one, and so on.
+/// If this goes on for too long, the filter is full: the last evicted fingerprint is kept aside (the "victim") so that nothing is forgotten, and further insertions fail.
+///
+/// Looking an item up compares its fingerprint with the 2 * 4 fingerprints of its buckets, and with the victim if there is one, so a different item with the same fingerprint in one of them is a false positive.
+/// With `f`-bit fingerprints, this happens with a probability of at most `1 - (1 - 1 / (2^f - 1))^9`, roughly `9 / 2^f`.
+///
+/// Removing an item removes one copy of its fingerprint. Only remove items that were inserted, or another item sharing the fingerprint may be removed instead.
+pub struct CuckooFilter<S = RandomState> {
+    /// Fingerprints, 0 meaning an empty slot.
+    buckets: Vec<[u16; BUCKET_SIZE]>,
+    fingerprint_bits: u32,
+    len: usize,
+    /// A fingerprint and one of its buckets, when the filter is full.
+    victim: Option<(usize, u16)>,
+    /// The state of the generator picking which fingerprint to evict.
+    rng: u64,
+    hasher: S,
+}
+
+/// The error returned when inserting into a full [`CuckooFilter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CuckooFilterFull;
+
+impl fmt::Display for CuckooFilterFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the cuckoo filter is full")
+    }
+}
+
+impl Error for CuckooFilterFull {}
+
+impl CuckooFilter {
+    /// Creates a filter holding about `capacity` items, with fingerprints of `fingerprint_bits` bits (between 4 and 16).
+    pub fn with_capacity(capacity: usize, fingerprint_bits: u32) -> Self {
+        CuckooFilter::with_capacity_and_hasher(capacity, fingerprint_bits, RandomState::new())
+    }
+
+    /// Creates a filter holding about `capacity` items, with fingerprints long enough for a false positive rate of at most `false_positive_rate`.
+    pub fn from_estimate(capacity: usize, false_positive_rate: f64) -> Self {
+        // a full filter also compares with its victim
+        let bits = ((2 * BUCKET_SIZE + 1) as f64 / false_positive_rate)
+            .log2()
+            .ceil();
+        CuckooFilter::with_capacity(capacity, (bits as u32).clamp(4, 16))
+    }
+}
+
+impl<S: BuildHasher> CuckooFilter<S> {
+    pub fn with_capacity_and_hasher(capacity: usize, fingerprint_bits: u32, hasher: S) -> Self {
+        assert!(
+            (4..=16).contains(&fingerprint_bits),
+            "fingerprints must have between 4 and 16 bits"
+        );
+        let buckets = (capacity as f64 / (BUCKET_SIZE as f64 * TARGET_LOAD)).ceil() as usize;
+        CuckooFilter {
+            buckets: vec![[0; BUCKET_SIZE]; buckets.next_power_of_two()],
+            fingerprint_bits,
+            len: 0,
+            victim: None,
+            rng: 0x853c_49e6_748f_ea9b,
+            hasher,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of fingerprints the buckets hold.
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * BUCKET_SIZE
+    }
+
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.capacity() as f64
+    }
+
+    /// Returns an upper bound of the probability that `contains` reports an item that was not inserted.
+    pub fn false_positive_bound(&self) -> f64 {
+        let fingerprints = (1u64 << self.fingerprint_bits) - 1;
+        let compared = 2 * BUCKET_SIZE + usize::from(self.victim.is_some());
+        1.0 - (1.0 - 1.0 / fingerprints as f64).powi(compared as i32)
+    }
+
+    /// The fingerprint and the first bucket of an item.
+    fn locate<T: Hash + ?Sized>(&self, item: &T) -> (usize, u16) {
+        let hash = self.hasher.hash_one(item);
+        let fingerprints = (1 << self.fingerprint_bits) - 1;
+        // 0 marks empty slots, so fingerprints are spread evenly over the other values
+        let fingerprint = ((hash >> 32) % fingerprints + 1) as u16;
+        (hash as usize & (self.buckets.len() - 1), fingerprint)
+    }
+
+    /// The other bucket of a fingerprint stored in `bucket`.
+    fn alternate(&self, bucket: usize, fingerprint: u16) -> usize {
+        let hash = u64::from(fingerprint).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32;
+        (bucket ^ hash as usize) & (self.buckets.len() - 1)
+    }
+
+    /// Stores the fingerprint in a free slot of the bucket, if it has one.
+    fn put(&mut self, bucket: usize, fingerprint: u16) -> bool {
+        match self.buckets[bucket].iter_mut().find(|slot| **slot == 0) {
+            Some(slot) => {
+                *slot = fingerprint;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Inserts an item, or fails if the filter is full.
+    pub fn try_insert<T: Hash + ?Sized>(&mut self, item: &T) -> Result<(), CuckooFilterFull> {
+        if self.victim.is_some() {
+            return Err(CuckooFilterFull);
+        }
+        let (first, mut fingerprint) = self.locate(item);
+        let second = self.alternate(first, fingerprint);
+        self.len += 1;
+        if self.put(first, fingerprint) || self.put(second, fingerprint) {
+            return Ok(());
+        }
+        let mut bucket = if self.next_random() & 1 == 0 {
+            first
+        } else {
+            second
+        };
+        for _ in 0..MAX_KICKS {
+            let slot = self.next_random() as usize % BUCKET_SIZE;
+            std::mem::swap(&mut self.buckets[bucket][slot], &mut fingerprint);
+            bucket = self.alternate(bucket, fingerprint);
+            if self.put(bucket, fingerprint) {
+                return Ok(());
+            }
+        }
+        // the item is in, but some fingerprint has no room left
+        self.victim = Some((bucket, fingerprint));
+        Ok(())
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let (first, fingerprint) = self.locate(item);
+        let second = self.alternate(first, fingerprint);
+        self.buckets[first].contains(&fingerprint)
+            || self.buckets[second].contains(&fingerprint)
+            || self.victim.is_some_and(|(bucket, victim)| {
+                victim == fingerprint && (bucket == first || bucket == second)
+            })
+    }
+
+    /// Removes one copy of an item, and returns whether it was found.
+    pub fn remove<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        let (first, fingerprint) = self.locate(item);
+        let second = self.alternate(first, fingerprint);
+        if self.victim.is_some_and(|(bucket, victim)| {
+            victim == fingerprint && (bucket == first || bucket == second)
+        }) {
+            self.victim = None;
+            self.len -= 1;
+            return true;
+        }
+        for bucket in [first, second] {
+            if let Some(slot) = self.buckets[bucket]
+                .iter_mut()
+                .find(|slot| **slot == fingerprint)
+            {
+                *slot = 0;
+                self.len -= 1;
+                // the victim may fit in the freed slot
+                if let Some((bucket, victim)) = self.victim.take() {
+                    let other = self.alternate(bucket, victim);
+                    if !(self.put(bucket, victim) || self.put(other, victim)) {
+                        self.victim = Some((bucket, victim));
+                    }
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    /// A xorshift generator, so that evictions are reproducible.
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+}
+
+/// Inserting into a full filter panics, as it would otherwise forget the item: use `try_insert` when the filter may fill up.
+impl<Item: Hash, S: BuildHasher> BloomFilter<Item> for CuckooFilter<S> {
+    fn insert(&mut self, item: Item) {
+        self.try_insert(&item)
+            .expect("inserting into a full cuckoo filter");
+    }
+
+    fn contains(&self, item: &Item) -> bool {
+        CuckooFilter::contains(self, item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BloomFilter, CuckooFilter, CuckooFilterFull};
+    use quickcheck::{Arbitrary, Gen};
+    use quickcheck_macros::quickcheck;
+    use std::collections::HashSet;
+
+    #[derive(Debug, Clone)]
+    struct TestSet {
+        to_insert: HashSet<i32>,
+        to_test: Vec<i32>,
+    }
+
+    impl Arbitrary for TestSet {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let mut qty = usize::arbitrary(g) % 500;
+            qty += 50; // at least 50 elements to insert
+            let mut to_insert = HashSet::with_capacity(qty);
+            let mut to_test = Vec::with_capacity(qty);
+            for _ in 0..qty {
+                to_insert.insert(i32::arbitrary(g));
+                to_test.push(i32::arbitrary(g));
+            }
+            TestSet { to_insert, to_test }
+        }
+    }
+
+    #[quickcheck]
+    #[cfg_attr(miri, ignore)]
+    fn no_false_negatives(TestSet { to_insert, to_test }: TestSet) {
+        let mut filter = CuckooFilter::from_estimate(to_insert.len(), 0.01);
+        for item in &to_insert {
+            filter.insert(*item);
+        }
+        for other in to_test {
+            if !filter.contains(&other) {
+                assert!(!to_insert.contains(&other))
+            }
+        }
+    }
+
+    #[test]
+    fn removes_items() {
+        let mut filter = CuckooFilter::with_capacity(1000, 12);
+        for i in 0..1000 {
+            filter.insert(i);
+        }
+        assert_eq!(filter.len(), 1000);
+        for i in (0..1000).step_by(2) {
+            assert!(filter.remove(&i));
+        }
+        assert_eq!(filter.len(), 500);
+        assert!((1..1000).step_by(2).all(|i| filter.contains(&i)));
+        // duplicates are stored as separate copies
+        filter.insert(1);
+        assert!(filter.remove(&1));
+        assert!(filter.contains(&1));
+    }
+
+    #[test]
+    fn false_positives_stay_under_the_bound() {
+        let mut filter = CuckooFilter::with_capacity(10_000, 8);
+        for i in 0..10_000 {
+            filter.insert(i);
+        }
+        let false_positives = (10_000..110_000).filter(|i| filter.contains(i)).count();
+        let rate = false_positives as f64 / 100_000.0;
+        assert!(rate <= filter.false_positive_bound(), "{rate}");
+        assert!((0.0..=0.04).contains(&filter.false_positive_bound()));
+    }
+
+    #[test]
+    fn fills_up_without_forgetting() {
+        let mut filter = CuckooFilter::with_capacity(64, 16);
+        let inserted: Vec<u32> = (0..).take_while(|i| filter.try_insert(i).is_ok()).collect();
+        assert!(filter.load_factor() > 0.8, "{}", filter.load_factor());
+        assert_eq!(filter.try_insert(&u32::MAX), Err(CuckooFilterFull));
+        assert!(inserted.iter().all(|i| filter.contains(i)));
+        // lookups now also compare with the victim
+        let empty = CuckooFilter::with_capacity(64, 16);
+        assert!(filter.false_positive_bound() > empty.false_positive_bound());
+        assert_eq!(filter.len(), inserted.len());
+        // removing every item, the victim included, empties the filter
+        assert!(inserted.iter().all(|i| filter.remove(i)));
+        assert!(filter.is_empty());
+        assert!(filter.try_insert(&u32::MAX).is_ok());
+    }
+}
@@ -0,0 +1,442 @@
+use super::bloom_filter::SeededState;
+use crate::string::{jaro_winkler_distance, optimized_levenshtein_distance};
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher as _, Hash};
+
+/// The Mersenne prime `2^61 - 1`, the modulus of the permutations of `MinHasher`.
+const PRIME: u64 = (1 << 61) - 1;
+
+/// Returns the hashes of the sequences of `k` consecutive characters of `text`.
+///
+/// To find near-duplicate documents, we first turn every document into a set of "shingles": its overlapping substrings of `k` characters (or `k` words).
+/// Documents differing by a few edits share most of their shingles, and the similarity of two sets is measured by their Jaccard index `|A ∩ B| / |A ∪ B|`.
+///
+/// Shingles are hashed to 64 bits right away, since only their identity matters.
+pub fn char_shingles(text: &str, k: usize) -> HashSet<u64> {
+    assert!(k > 0, "shingles must have at least one character");
+    let chars: Vec<char> = text.chars().collect();
+    let hasher = SeededState::new(0);
+    if chars.len() <= k {
+        return HashSet::from([hasher.hash_one(&chars)]);
+    }
+    chars
+        .windows(k)
+        .map(|shingle| hasher.hash_one(shingle))
+        .collect()
+}
+
+/// Returns the hashes of the sequences of `k` consecutive words of `text`.
+pub fn word_shingles(text: &str, k: usize) -> HashSet<u64> {
+    assert!(k > 0, "shingles must have at least one word");
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let hasher = SeededState::new(0);
+    if words.len() <= k {
+        return HashSet::from([hasher.hash_one(&words)]);
+    }
+    words
+        .windows(k)
+        .map(|shingle| hasher.hash_one(shingle))
+        .collect()
+}
+
+/// Returns the exact Jaccard index of two sets, 1 for two empty sets.
+pub fn jaccard<T: Hash + Eq>(a: &HashSet<T>, b: &HashSet<T>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+/// Computing the Jaccard index of every pair of documents is way too slow for millions of documents, and needs all their shingles.
+///
+/// MinHash <https://en.wikipedia.org/wiki/MinHash> summarizes every set by a short signature instead.
+///
+/// Apply a random permutation to all shingles, and keep the smallest one: it is equally likely to be any element of `A ∪ B`,
+/// so the smallest shingles of `A` and `B` are the same with probability exactly `|A ∩ B| / |A ∪ B|`.
+/// A signature holds the minimums of `k` independent permutations, and the fraction of equal positions of two signatures estimates their Jaccard index, with a standard error of `sqrt(J(1 - J) / k)`.
+///
+/// Applying `k` permutations costs `k` operations per shingle. "One permutation hashing" <https://arxiv.org/abs/1208.1259> applies a single one,
+/// splits its range into `k` bins, and keeps the minimum of every bin instead, which gives estimates about as accurate in `1` operation per shingle.
+/// Small sets leave some bins empty: those borrow the value of the next non-empty bin, rehashed with the distance to it ("densification"), so that two sets still agree on a bin with the right probability.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinHasher {
+    scheme: Scheme,
+    len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Scheme {
+    /// The coefficients `(a, b)` of the permutations `x -> (a * x + b) mod PRIME`.
+    KPermutation(Vec<(u64, u64)>),
+    OnePermutation(SeededState),
+}
+
+/// The MinHash signature of a set, comparable with the signatures of the same `MinHasher`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Signature(Vec<u64>);
+
+impl MinHasher {
+    /// Creates a hasher with `len` independent permutations.
+    pub fn k_permutation(len: usize, seed: u64) -> Self {
+        assert!(len > 0, "signatures must not be empty");
+        let mut state = seed;
+        let mut next = move || {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            SeededState::new(0).hash_one(state) % PRIME
+        };
+        let coefficients = std::iter::repeat_with(|| (next().max(1), next()))
+            .take(len)
+            .collect();
+        MinHasher {
+            scheme: Scheme::KPermutation(coefficients),
+            len,
+        }
+    }
+
+    /// Creates a hasher splitting a single permutation into `len` bins.
+    pub fn one_permutation(len: usize, seed: u64) -> Self {
+        assert!(len > 0, "signatures must not be empty");
+        MinHasher {
+            scheme: Scheme::OnePermutation(SeededState::new(seed)),
+            len,
+        }
+    }
+
+    /// Returns the length of the signatures.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the signature of a set of shingle hashes. All empty sets have the same signature.
+    pub fn signature(&self, shingles: &HashSet<u64>) -> Signature {
+        match &self.scheme {
+            Scheme::KPermutation(coefficients) => Signature(
+                coefficients
+                    .iter()
+                    .map(|&(a, b)| {
+                        shingles
+                            .iter()
+                            .map(|&x| permute(a, b, x))
+                            .min()
+                            .unwrap_or(u64::MAX)
+                    })
+                    .collect(),
+            ),
+            Scheme::OnePermutation(hasher) => {
+                let mut bins = vec![u64::MAX; self.len];
+                for &shingle in shingles {
+                    let hash = hasher.hash_one(shingle);
+                    let bin = ((u128::from(hash) * self.len as u128) >> 64) as usize;
+                    bins[bin] = bins[bin].min(hash);
+                }
+                if shingles.is_empty() {
+                    return Signature(bins);
+                }
+                let filled = bins.clone();
+                for (bin, value) in bins.iter_mut().enumerate() {
+                    if *value == u64::MAX {
+                        let (distance, borrowed) = (1..self.len)
+                            .map(|distance| (distance, filled[(bin + distance) % self.len]))
+                            .find(|&(_, value)| value != u64::MAX)
+                            .unwrap();
+                        *value = hasher.hash_one((borrowed, distance));
+                    }
+                }
+                Signature(bins)
+            }
+        }
+    }
+}
+
+/// Returns `(a * x + b) mod PRIME`.
+fn permute(a: u64, b: u64, x: u64) -> u64 {
+    ((u128::from(a) * u128::from(x % PRIME) + u128::from(b)) % u128::from(PRIME)) as u64
+}
+
+impl Signature {
+    pub fn values(&self) -> &[u64] {
+        &self.0
+    }
+
+    /// Estimates the Jaccard index of the sets of two signatures.
+    pub fn jaccard(&self, other: &Signature) -> f64 {
+        assert_eq!(
+            self.0.len(),
+            other.0.len(),
+            "signatures of different lengths"
+        );
+        let equal = self.0.iter().zip(&other.0).filter(|(a, b)| a == b).count();
+        equal as f64 / self.0.len() as f64
+    }
+}
+
+/// Comparing signatures is faster than comparing sets, but still needs every pair. Locality-sensitive hashing finds the similar pairs without looking at the others.
+///
+/// Signatures of `bands * rows` values are cut into `bands` bands of `rows` values, and every band is hashed into a table of its own.
+/// Two signatures land in the same bucket of a band if the whole band is equal, which happens with probability `J^rows`, so they share at least one bucket with probability `1 - (1 - J^rows)^bands`.
+/// This is an S-curve: pairs above the threshold `(1 / bands)^(1 / rows)` are very likely candidates, and pairs well below it very unlikely.
+/// More rows make the curve steeper, more bands move it towards lower similarities.
+#[derive(Debug, Clone)]
+pub struct LshIndex {
+    bands: usize,
+    rows: usize,
+    /// For every band, the ids of the signatures by the hash of their band.
+    buckets: Vec<HashMap<u64, Vec<usize>>>,
+    len: usize,
+}
+
+impl LshIndex {
+    pub fn new(bands: usize, rows: usize) -> Self {
+        assert!(bands > 0 && rows > 0, "bands and rows must not be empty");
+        LshIndex {
+            bands,
+            rows,
+            buckets: vec![HashMap::new(); bands],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the similarity at which a pair is a candidate with probability about 1/2.
+    pub fn threshold(&self) -> f64 {
+        (1.0 / self.bands as f64).powf(1.0 / self.rows as f64)
+    }
+
+    /// Returns the probability that two signatures with Jaccard index `similarity` share a bucket.
+    pub fn candidate_probability(&self, similarity: f64) -> f64 {
+        1.0 - (1.0 - similarity.powi(self.rows as i32)).powi(self.bands as i32)
+    }
+
+    fn band_hashes<'a>(&'a self, signature: &'a Signature) -> impl Iterator<Item = u64> + 'a {
+        assert_eq!(
+            signature.0.len(),
+            self.bands * self.rows,
+            "signatures must have bands * rows values"
+        );
+        signature
+            .0
+            .chunks(self.rows)
+            .map(|band| SeededState::new(0).hash_one(band))
+    }
+
+    /// Adds a signature, and returns its id: the number of signatures added before it.
+    pub fn insert(&mut self, signature: &Signature) -> usize {
+        let id = self.len;
+        let hashes: Vec<u64> = self.band_hashes(signature).collect();
+        for (band, hash) in hashes.into_iter().enumerate() {
+            self.buckets[band].entry(hash).or_default().push(id);
+        }
+        self.len += 1;
+        id
+    }
+
+    /// Returns the ids of the signatures sharing a bucket with `signature`, sorted.
+    pub fn query(&self, signature: &Signature) -> Vec<usize> {
+        let mut candidates: Vec<usize> = self
+            .band_hashes(signature)
+            .enumerate()
+            .filter_map(|(band, hash)| self.buckets[band].get(&hash))
+            .flatten()
+            .copied()
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+    }
+
+    /// Returns every pair of ids `(i, j)` with `i < j` sharing a bucket, sorted.
+    ///
+    /// A bucket of `b` ids yields `b * (b - 1) / 2` pairs in every band, so this takes quadratic time when many signatures are
+    /// identical, or nearly so: remove exact duplicates before inserting them, and map every kept id back to its duplicates.
+    pub fn candidate_pairs(&self) -> Vec<(usize, usize)> {
+        let pairs: HashSet<(usize, usize)> = self
+            .buckets
+            .iter()
+            .flat_map(HashMap::values)
+            .flat_map(|ids| {
+                ids.iter()
+                    .enumerate()
+                    .flat_map(move |(i, &a)| ids[i + 1..].iter().map(move |&b| (a, b)))
+            })
+            .collect();
+        let mut pairs: Vec<(usize, usize)> = pairs.into_iter().collect();
+        pairs.sort_unstable();
+        pairs
+    }
+}
+
+/// How candidate pairs are checked before being reported, since some of them are false positives of the index.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verification {
+    /// Keep every candidate.
+    None,
+    /// Keep pairs whose shingle sets have at least this exact Jaccard index.
+    Jaccard(f64),
+    /// Keep pairs with at least this Jaro-Winkler similarity.
+    JaroWinkler(f64),
+    /// Keep pairs at most this Levenshtein distance apart.
+    Levenshtein(usize),
+}
+
+/// Returns the pairs of near-duplicate documents, as indices `(i, j)` with `i < j`, sorted.
+///
+/// Documents are shingled into sequences of `shingle_len` characters, signed by `hasher`, indexed by an `LshIndex` with `bands` bands
+/// (whose signatures must have a multiple of `bands` values), and the candidate pairs are checked by `verification`.
+pub fn near_duplicates(
+    documents: &[&str],
+    shingle_len: usize,
+    hasher: &MinHasher,
+    bands: usize,
+    verification: Verification,
+) -> Vec<(usize, usize)> {
+    let shingles: Vec<HashSet<u64>> = documents
+        .iter()
+        .map(|document| char_shingles(document, shingle_len))
+        .collect();
+    let mut index = LshIndex::new(bands, hasher.len() / bands);
+    for set in &shingles {
+        index.insert(&hasher.signature(set));
+    }
+    index
+        .candidate_pairs()
+        .into_iter()
+        .filter(|&(i, j)| match verification {
+            Verification::None => true,
+            Verification::Jaccard(threshold) => jaccard(&shingles[i], &shingles[j]) >= threshold,
+            Verification::JaroWinkler(threshold) => {
+                jaro_winkler_distance(documents[i], documents[j]) >= threshold
+            }
+            Verification::Levenshtein(max_distance) => {
+                optimized_levenshtein_distance(documents[i], documents[j]) <= max_distance
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        char_shingles, jaccard, near_duplicates, word_shingles, LshIndex, MinHasher, Verification,
+    };
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+    use std::collections::HashSet;
+
+    /// Deterministic pseudo-random sentences of `words` words.
+    fn sentences(count: usize, words: usize) -> Vec<String> {
+        const WORDS: [&str; 16] = [
+            "alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel", "india",
+            "juliet", "kilo", "lima", "mike", "november", "oscar", "papa",
+        ];
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..count)
+            .map(|_| {
+                (0..words)
+                    .map(|_| *WORDS.choose(&mut rng).unwrap())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shingles() {
+        assert_eq!(char_shingles("abcab", 2).len(), 3); // ab, bc, ca
+        assert_eq!(char_shingles("ab", 5).len(), 1);
+        assert_eq!(word_shingles("a rose is a rose is a rose", 3).len(), 3);
+        let a: HashSet<u32> = (0..100).collect();
+        let b: HashSet<u32> = (50..150).collect();
+        assert!((jaccard(&a, &b) - 1.0 / 3.0).abs() < 1e-12);
+        assert_eq!(jaccard::<u32>(&HashSet::new(), &HashSet::new()), 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "shingles must have at least one character")]
+    fn rejects_empty_shingles() {
+        char_shingles("abc", 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "signatures must not be empty")]
+    fn rejects_empty_signatures() {
+        MinHasher::k_permutation(0, 1);
+    }
+
+    #[test]
+    fn signatures_estimate_jaccard() {
+        let a: HashSet<u64> = (0..1000).collect();
+        let b: HashSet<u64> = (500..1500).collect();
+        let few: HashSet<u64> = (0..10).collect();
+        let few_more: HashSet<u64> = (0..12).collect();
+        for hasher in [
+            MinHasher::k_permutation(256, 1),
+            MinHasher::one_permutation(256, 1),
+        ] {
+            let estimate = hasher.signature(&a).jaccard(&hasher.signature(&b));
+            assert!((estimate - 1.0 / 3.0).abs() < 0.1, "{estimate}");
+            assert_eq!(hasher.signature(&a).jaccard(&hasher.signature(&a)), 1.0);
+            // sets smaller than the signature use densified bins
+            let estimate = hasher.signature(&few).jaccard(&hasher.signature(&few_more));
+            assert!((estimate - 10.0 / 12.0).abs() < 0.15, "{estimate}");
+        }
+    }
+
+    #[test]
+    fn index_finds_similar_signatures() {
+        let hasher = MinHasher::one_permutation(64, 7);
+        let mut index = LshIndex::new(16, 4);
+        assert!((index.threshold() - 0.5).abs() < 1e-12);
+        assert!(index.candidate_probability(0.9) > 0.99);
+        assert!(index.candidate_probability(0.1) < 0.01);
+        let base: HashSet<u64> = (0..500).collect();
+        let close: HashSet<u64> = (10..510).collect();
+        let far: HashSet<u64> = (400..900).collect();
+        for set in [&base, &close, &far] {
+            index.insert(&hasher.signature(set));
+        }
+        assert_eq!(index.query(&hasher.signature(&base)), [0, 1]);
+        assert_eq!(index.candidate_pairs(), [(0, 1)]);
+    }
+
+    #[test]
+    fn finds_near_duplicate_documents() {
+        let mut documents = sentences(200, 12);
+        documents[150] = documents[3].replacen("a", "e", 2);
+        documents[170] = format!("{} papa", documents[42]);
+        let documents: Vec<&str> = documents.iter().map(String::as_str).collect();
+        let hasher = MinHasher::k_permutation(60, 3);
+        for verification in [
+            Verification::Jaccard(0.7),
+            Verification::JaroWinkler(0.9),
+            Verification::Levenshtein(5),
+        ] {
+            assert_eq!(
+                near_duplicates(&documents, 5, &hasher, 15, verification),
+                [(3, 150), (42, 170)],
+                "{verification:?}"
+            );
+        }
+        let candidates = near_duplicates(&documents, 5, &hasher, 15, Verification::None);
+        assert!(candidates.contains(&(3, 150)) && candidates.contains(&(42, 170)));
+        let accented = [
+            "crème brûlée à la française",
+            "crème brûlée à la francaise",
+            "œuf",
+        ];
+        assert_eq!(
+            near_duplicates(&accented, 3, &hasher, 15, Verification::JaroWinkler(0.9)),
+            [(0, 1)]
+        );
+    }
+}
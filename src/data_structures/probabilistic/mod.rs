@@ -1,2 +1,7 @@
 pub mod bloom_filter;
 pub mod count_min_sketch;
+pub mod counting_bloom_filter;
+pub mod cuckoo_filter;
+pub mod hyperloglog;
+pub mod minhash;
+pub mod scalable_bloom_filter;
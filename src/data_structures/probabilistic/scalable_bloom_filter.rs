@@ -0,0 +1,485 @@
+use super::bloom_filter::{
+    encode_header, BloomFilter, BloomFilterError, ByteReader, SeededState, PARTITIONED, SCALABLE,
+};
+use std::hash::{BuildHasher as _, Hash};
+
+/// A Bloom filter is sized for a number of items: past it, its false positive rate keeps growing until every item looks present.
+///
+/// When that number isn't known in advance, a scalable Bloom filter <https://doi.org/10.1016/j.ipl.2006.10.007> starts small, and adds a larger filter (a "slice") whenever the last one is full.
+/// An item is inserted into the last slice only, and is present if any slice contains it.
+///
+/// Every new slice holds `growth` times more items than the previous one, so there are only logarithmically many of them.
+/// Looking up an item that was never inserted may hit a false positive in any slice, so their rates must shrink too: the rate of every slice is `tightening` times the rate of the previous one.
+/// The overall rate is then bounded by a geometric series: `p0 * (1 + r + r^2 + ...) = p0 / (1 - r)`. Starting at `p0 = P * (1 - r)` keeps it under the target `P`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalableBloomFilter {
+    slices: Vec<PartitionedBloomFilter>,
+    initial_capacity: usize,
+    false_positive_rate: f64,
+    growth: usize,
+    tightening: f64,
+    seed: u64,
+}
+
+/// The slices of a scalable filter are partitioned Bloom filters: each of the `k` hash functions sets one bit in its own partition of `m / k` bits.
+///
+/// No two hash functions of an item can then pick the same bit, which makes the false positive rate of a filter with `n` items easy to bound: every partition has at most `n` bits set,
+/// so it is at most `(n / partition_bits)^k`, and the filter counts as full once this reaches the rate it was built for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionedBloomFilter {
+    partition_bits: usize,
+    bytes: Vec<u8>,
+    /// The number of items inserted, which may count an item twice.
+    len: usize,
+    seed: u64,
+    hash_builders: Vec<SeededState>,
+}
+
+impl PartitionedBloomFilter {
+    pub fn with_dimensions(partition_bits: usize, hash_count: usize) -> Self {
+        Self::with_dimensions_and_seed(partition_bits, hash_count, 0)
+    }
+
+    pub fn with_dimensions_and_seed(partition_bits: usize, hash_count: usize, seed: u64) -> Self {
+        PartitionedBloomFilter {
+            partition_bits,
+            bytes: vec![0; (partition_bits * hash_count).div_ceil(8)],
+            len: 0,
+            seed,
+            hash_builders: SeededState::family(seed, hash_count),
+        }
+    }
+
+    /// Creates a filter holding `capacity` items with a false positive rate of at most `false_positive_rate`.
+    pub fn from_estimate(capacity: usize, false_positive_rate: f64) -> Self {
+        Self::from_estimate_and_seed(capacity, false_positive_rate, 0)
+    }
+
+    fn from_estimate_and_seed(capacity: usize, false_positive_rate: f64, seed: u64) -> Self {
+        assert!(
+            0.0 < false_positive_rate && false_positive_rate < 1.0,
+            "the false positive rate must be between 0 and 1"
+        );
+        // with log2(1 / rate) hash functions, the filter is full with about half of every partition set, which uses the bits best
+        let hash_count = (1.0 / false_positive_rate).log2().ceil().max(1.0) as usize;
+        let fill = false_positive_rate.powf(1.0 / hash_count as f64);
+        let partition_bits = (capacity.max(1) as f64 / fill).ceil() as usize;
+        Self::with_dimensions_and_seed(partition_bits, hash_count, seed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of items the filter holds before its false positive rate may exceed `false_positive_rate`.
+    pub fn capacity(&self, false_positive_rate: f64) -> usize {
+        let fill = false_positive_rate.powf(1.0 / self.hash_builders.len() as f64);
+        (fill * self.partition_bits as f64) as usize
+    }
+
+    /// Returns an upper bound of the false positive rate, from the number of bits set in every partition.
+    pub fn false_positive_bound(&self) -> f64 {
+        (0..self.hash_builders.len())
+            .map(|partition| {
+                let start = partition * self.partition_bits;
+                let set = (start..start + self.partition_bits)
+                    .filter(|&bit| self.bytes[bit / 8] & (1 << (bit % 8)) != 0)
+                    .count();
+                set as f64 / self.partition_bits as f64
+            })
+            .product()
+    }
+
+    fn bits<'a, Item: Hash>(&'a self, item: &'a Item) -> impl Iterator<Item = usize> + 'a {
+        self.hash_builders
+            .iter()
+            .enumerate()
+            .map(move |(partition, builder)| {
+                let offset = builder.hash_one(item) % self.partition_bits as u64;
+                partition * self.partition_bits + offset as usize
+            })
+    }
+
+    /// Returns a filter containing the items of both filters.
+    pub fn union(&self, other: &Self) -> Result<Self, BloomFilterError> {
+        let mut union = self.combine(other, |a, b| a | b)?;
+        union.len = self.len + other.len;
+        Ok(union)
+    }
+
+    /// Returns a filter containing the items present in both filters.
+    /// It may report more false positives than a filter built from these items directly.
+    pub fn intersection(&self, other: &Self) -> Result<Self, BloomFilterError> {
+        let mut intersection = self.combine(other, |a, b| a & b)?;
+        intersection.len = self.len.min(other.len);
+        Ok(intersection)
+    }
+
+    fn combine(&self, other: &Self, op: impl Fn(u8, u8) -> u8) -> Result<Self, BloomFilterError> {
+        if (self.partition_bits, self.seed, self.hash_builders.len())
+            != (other.partition_bits, other.seed, other.hash_builders.len())
+        {
+            return Err(BloomFilterError::Incompatible);
+        }
+        let mut combined = self.clone();
+        for (byte, &other) in combined.bytes.iter_mut().zip(&other.bytes) {
+            *byte = op(*byte, other);
+        }
+        Ok(combined)
+    }
+
+    /// Encodes the filter in the format `from_bytes` reads:
+    ///
+    /// ```text
+    /// header (kind 3) | partition bits: u64 | hash count: u32 | len: u64 | bits
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = encode_header(PARTITIONED, self.seed);
+        self.encode_body(&mut buf);
+        buf
+    }
+
+    fn encode_body(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.partition_bits as u64).to_le_bytes());
+        buf.extend_from_slice(&(self.hash_builders.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&(self.len as u64).to_le_bytes());
+        buf.extend_from_slice(&self.bytes);
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BloomFilterError> {
+        let mut reader = ByteReader::new(bytes);
+        let seed = reader.header(PARTITIONED)?;
+        let filter = Self::decode_body(&mut reader, seed)?;
+        reader.finish()?;
+        Ok(filter)
+    }
+
+    fn decode_body(reader: &mut ByteReader, seed: u64) -> Result<Self, BloomFilterError> {
+        let partition_bits = reader.usize()?;
+        let hash_count = reader.hash_count()?;
+        let len = reader.usize()?;
+        if partition_bits == 0 {
+            return Err(BloomFilterError::Corrupt("empty partitions"));
+        }
+        let bytes = partition_bits
+            .checked_mul(hash_count)
+            .ok_or(BloomFilterError::Corrupt("size out of range"))?
+            .div_ceil(8);
+        let bytes = reader.bytes(bytes)?.to_vec();
+        Ok(PartitionedBloomFilter {
+            partition_bits,
+            bytes,
+            len,
+            seed,
+            hash_builders: SeededState::family(seed, hash_count),
+        })
+    }
+}
+
+impl<Item: Hash> BloomFilter<Item> for PartitionedBloomFilter {
+    fn insert(&mut self, item: Item) {
+        let bits: Vec<usize> = self.bits(&item).collect();
+        for bit in bits {
+            self.bytes[bit / 8] |= 1 << (bit % 8);
+        }
+        self.len += 1;
+    }
+
+    fn contains(&self, item: &Item) -> bool {
+        self.bits(item)
+            .all(|bit| self.bytes[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+}
+
+impl ScalableBloomFilter {
+    /// Creates a filter whose first slice holds `initial_capacity` items, keeping the false positive rate under `false_positive_rate` however many items are inserted.
+    /// Slices grow 2 times larger, and their rates 2 times lower.
+    pub fn new(initial_capacity: usize, false_positive_rate: f64) -> Self {
+        Self::with_parameters(initial_capacity, false_positive_rate, 2, 0.5, 0)
+    }
+
+    pub fn with_parameters(
+        initial_capacity: usize,
+        false_positive_rate: f64,
+        growth: usize,
+        tightening: f64,
+        seed: u64,
+    ) -> Self {
+        assert!(
+            0.0 < false_positive_rate && false_positive_rate < 1.0,
+            "the false positive rate must be between 0 and 1"
+        );
+        assert!(growth >= 1, "slices must not shrink");
+        assert!(
+            0.0 < tightening && tightening < 1.0,
+            "the tightening ratio must be between 0 and 1"
+        );
+        ScalableBloomFilter {
+            slices: Vec::new(),
+            initial_capacity: initial_capacity.max(1),
+            false_positive_rate,
+            growth,
+            tightening,
+            seed,
+        }
+    }
+
+    /// Returns the number of items inserted, which may count an item twice if it was a false positive when inserted the first time.
+    pub fn len(&self) -> usize {
+        self.slices.iter().map(PartitionedBloomFilter::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn slices(&self) -> &[PartitionedBloomFilter] {
+        &self.slices
+    }
+
+    /// The capacity and false positive rate of the slice at `index`.
+    fn slice_dimensions(&self, index: usize) -> (usize, f64) {
+        let capacity = self
+            .initial_capacity
+            .saturating_mul(self.growth.saturating_pow(index as u32));
+        let rate =
+            self.false_positive_rate * (1.0 - self.tightening) * self.tightening.powi(index as i32);
+        (capacity, rate)
+    }
+
+    /// Returns an upper bound of the false positive rate, from the bits set in every slice. It stays under the target rate.
+    pub fn false_positive_bound(&self) -> f64 {
+        1.0 - self
+            .slices
+            .iter()
+            .map(|slice| 1.0 - slice.false_positive_bound())
+            .product::<f64>()
+    }
+
+    /// Returns a filter containing the items of both filters. Slices are combined pairwise, so the filters must have been created with the same parameters,
+    /// and every pair of slices must fit in the capacity of one: otherwise the union could exceed the target rate, and fails with `BloomFilterError::Full`.
+    pub fn union(&self, other: &Self) -> Result<Self, BloomFilterError> {
+        if (
+            self.initial_capacity,
+            self.false_positive_rate.to_bits(),
+            self.growth,
+            self.tightening.to_bits(),
+            self.seed,
+        ) != (
+            other.initial_capacity,
+            other.false_positive_rate.to_bits(),
+            other.growth,
+            other.tightening.to_bits(),
+            other.seed,
+        ) {
+            return Err(BloomFilterError::Incompatible);
+        }
+        let (longer, shorter) = if self.slices.len() >= other.slices.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let mut union = longer.clone();
+        for (index, (slice, other)) in union.slices.iter_mut().zip(&shorter.slices).enumerate() {
+            let (capacity, _) = self.slice_dimensions(index);
+            if slice.len() + other.len() > capacity {
+                return Err(BloomFilterError::Full);
+            }
+            *slice = slice.union(other)?;
+        }
+        Ok(union)
+    }
+
+    /// Encodes the filter in the format `from_bytes` reads:
+    ///
+    /// ```text
+    /// header (kind 4) | initial capacity: u64 | rate: f64 | growth: u64 | tightening: f64 | slice count: u32 | slices
+    /// ```
+    ///
+    /// where every slice is encoded as a partitioned filter without its header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = encode_header(SCALABLE, self.seed);
+        buf.extend_from_slice(&(self.initial_capacity as u64).to_le_bytes());
+        buf.extend_from_slice(&self.false_positive_rate.to_le_bytes());
+        buf.extend_from_slice(&(self.growth as u64).to_le_bytes());
+        buf.extend_from_slice(&self.tightening.to_le_bytes());
+        buf.extend_from_slice(&(self.slices.len() as u32).to_le_bytes());
+        for slice in &self.slices {
+            slice.encode_body(&mut buf);
+        }
+        buf
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BloomFilterError> {
+        let mut reader = ByteReader::new(bytes);
+        let seed = reader.header(SCALABLE)?;
+        let initial_capacity = reader.usize()?;
+        let false_positive_rate = reader.f64()?;
+        let growth = reader.usize()?;
+        let tightening = reader.f64()?;
+        if initial_capacity == 0
+            || growth == 0
+            || !(0.0 < false_positive_rate && false_positive_rate < 1.0)
+            || !(0.0 < tightening && tightening < 1.0)
+        {
+            return Err(BloomFilterError::Corrupt("invalid parameters"));
+        }
+        let mut filter = Self::with_parameters(
+            initial_capacity,
+            false_positive_rate,
+            growth,
+            tightening,
+            seed,
+        );
+        for index in 0..reader.u32()? {
+            let slice =
+                PartitionedBloomFilter::decode_body(&mut reader, slice_seed(seed, index as usize))?;
+            filter.slices.push(slice);
+        }
+        reader.finish()?;
+        Ok(filter)
+    }
+}
+
+/// Every slice hashes with its own seed, so that items colliding in one slice don't collide in the others.
+fn slice_seed(seed: u64, index: usize) -> u64 {
+    seed.wrapping_add((index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+}
+
+impl<Item: Hash> BloomFilter<Item> for ScalableBloomFilter {
+    fn insert(&mut self, item: Item) {
+        // inserting an item again would only fill the filter up faster
+        if self.contains(&item) {
+            return;
+        }
+        let index = self.slices.len();
+        let full = self.slices.last().is_none_or(|last| {
+            let (capacity, _) = self.slice_dimensions(index - 1);
+            last.len() >= capacity
+        });
+        if full {
+            let (capacity, rate) = self.slice_dimensions(index);
+            self.slices
+                .push(PartitionedBloomFilter::from_estimate_and_seed(
+                    capacity,
+                    rate,
+                    slice_seed(self.seed, index),
+                ));
+        }
+        self.slices.last_mut().unwrap().insert(item);
+    }
+
+    fn contains(&self, item: &Item) -> bool {
+        self.slices.iter().any(|slice| slice.contains(item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BloomFilter, BloomFilterError, PartitionedBloomFilter, ScalableBloomFilter};
+
+    #[test]
+    fn partitioned_filter_keeps_its_rate() {
+        let mut filter = PartitionedBloomFilter::from_estimate(1000, 0.01);
+        assert_eq!(filter.capacity(0.01), 1000);
+        for i in 0..1000 {
+            filter.insert(i);
+        }
+        assert!((0..1000).all(|i| filter.contains(&i)));
+        let bound = filter.false_positive_bound();
+        assert!(bound <= 0.01, "{bound}");
+        let false_positives = (1000..101_000).filter(|i| filter.contains(i)).count();
+        assert!(false_positives as f64 / 100_000.0 <= 2.0 * bound);
+
+        let bytes = filter.to_bytes();
+        assert_eq!(PartitionedBloomFilter::from_bytes(&bytes), Ok(filter));
+        assert_eq!(
+            ScalableBloomFilter::from_bytes(&bytes),
+            Err(BloomFilterError::Incompatible)
+        );
+    }
+
+    #[test]
+    fn scalable_filter_grows_under_its_rate() {
+        let mut filter = ScalableBloomFilter::new(100, 0.01);
+        for i in 0..10_000 {
+            filter.insert(i);
+        }
+        assert!((0..10_000).all(|i| filter.contains(&i)));
+        // 100 + 200 + ... + 6400 < 10_000 <= 12_700
+        assert_eq!(filter.slices().len(), 7);
+        assert!(filter.false_positive_bound() <= 0.01);
+        let false_positives = (10_000..110_000).filter(|i| filter.contains(i)).count();
+        assert!(false_positives < 1000, "{false_positives}");
+    }
+
+    #[test]
+    fn scalable_filters_combine_and_serialize() {
+        let mut small = ScalableBloomFilter::new(10, 0.001);
+        let mut large = ScalableBloomFilter::new(10, 0.001);
+        for i in 0..20 {
+            small.insert(i);
+        }
+        for i in 1000..1015 {
+            large.insert(i);
+        }
+        // the first slices hold 10 items each, which is more than one slice can take
+        assert_eq!(small.union(&large), Err(BloomFilterError::Full));
+        let (mut first, mut second) = (
+            ScalableBloomFilter::new(10, 0.001),
+            ScalableBloomFilter::new(10, 0.001),
+        );
+        for i in 0..5 {
+            first.insert(i);
+            second.insert(i + 2000);
+        }
+        let union = first.union(&second).unwrap();
+        assert!((0..5).chain(2000..2005).all(|i| union.contains(&i)));
+        assert_eq!(union.slices().len(), 1);
+        assert_eq!(
+            small.union(&ScalableBloomFilter::new(10, 0.01)),
+            Err(BloomFilterError::Incompatible)
+        );
+
+        let bytes = small.to_bytes();
+        let decoded = ScalableBloomFilter::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, small);
+        assert!((0..20).all(|i| decoded.contains(&i)));
+        assert!(ScalableBloomFilter::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(ScalableBloomFilter::from_bytes(b"not a filter").is_err());
+    }
+
+    #[test]
+    fn rejects_forged_parameters() {
+        let empty = PartitionedBloomFilter::with_dimensions(0, 3).to_bytes();
+        assert_eq!(
+            PartitionedBloomFilter::from_bytes(&empty),
+            Err(BloomFilterError::Corrupt("empty partitions"))
+        );
+        // the hash count follows the 17 bytes of the header and the partition size
+        let mut hashes = empty;
+        hashes[25..29].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            PartitionedBloomFilter::from_bytes(&hashes),
+            Err(BloomFilterError::Corrupt("too many hash functions"))
+        );
+        for rate in [0.0, 1.0, f64::NAN] {
+            let mut filter = ScalableBloomFilter::new(10, 0.01);
+            filter.false_positive_rate = rate;
+            assert_eq!(
+                ScalableBloomFilter::from_bytes(&filter.to_bytes()),
+                Err(BloomFilterError::Corrupt("invalid parameters"))
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "the false positive rate must be between 0 and 1")]
+    fn rejects_a_zero_false_positive_rate() {
+        ScalableBloomFilter::new(10, 0.0);
+    }
+}
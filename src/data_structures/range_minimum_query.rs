@@ -9,79 +9,34 @@
     Wikipedia: <https://en.wikipedia.org/wiki/Range_minimum_query>
 */
 
-use std::cmp::PartialOrd;
-use std::fmt;
+use super::sparse_table::{MinOp, RangeError, SparseTable};
 
-/// Custom error for invalid range
-#[derive(Debug, PartialEq)]
-pub struct RangeError;
-
-impl fmt::Display for RangeError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Invalid range")
-    }
-}
-
-pub struct RangeMinimumQuery<T: PartialOrd + Copy> {
-    // the current version makes a copy of the input array, but this could be changed
-    // to references if needed (in that case, we dont need T to implement the Copy trait)
-    array: Vec<T>,
-    sparse_table: Vec<Vec<usize>>,
-}
-
-impl<T: PartialOrd + Copy> RangeMinimumQuery<T> {
-    pub fn new(input: &[T]) -> RangeMinimumQuery<T> {
-        RangeMinimumQuery {
-            array: input.to_vec(),
-            sparse_table: build_sparse_table(input),
-        }
-    }
+/// A sparse table over `min`; see [`SparseTable`] for other idempotent operations.
+pub type RangeMinimumQuery<T> = SparseTable<T, MinOp>;
 
+impl<T: PartialOrd + Copy> SparseTable<T, MinOp> {
     pub fn get_range_min(&self, start: usize, end: usize) -> Result<T, RangeError> {
-        if start >= end || start >= self.array.len() || end > self.array.len() {
-            return Err(RangeError);
-        }
-        let loglen = (end - start).ilog2() as usize;
-        let idx: usize = end - (1 << loglen);
-        let a = self.sparse_table[loglen][start];
-        let b = self.sparse_table[loglen][idx];
-        if self.array[a] < self.array[b] {
-            return Ok(self.array[a]);
-        }
-        Ok(self.array[b])
-    }
-}
-
-fn build_sparse_table<T: PartialOrd>(array: &[T]) -> Vec<Vec<usize>> {
-    let mut table: Vec<Vec<usize>> = vec![(0..array.len()).collect()];
-    let len = array.len();
-
-    for loglen in 1..=len.ilog2() {
-        let mut row = Vec::new();
-        for i in 0..=len - (1 << loglen) {
-            let a = table[table.len() - 1][i];
-            let b = table[table.len() - 1][i + (1 << (loglen - 1))];
-            if array[a] < array[b] {
-                row.push(a);
-            } else {
-                row.push(b);
-            }
-        }
-        table.push(row);
+        self.query(start..end)
     }
-    table
 }
 
 #[cfg(test)]
 mod tests {
-    use super::build_sparse_table;
+    use super::RangeMinimumQuery;
+    use crate::data_structures::RangeError;
+    // `expected[k][i]` is the index of the minimum of the 2^k elements starting at `i`
     macro_rules! test_build_sparse_table {
         ($($name:ident: $inputs:expr,)*) => {
         $(
             #[test]
             fn $name() {
-                let (array, expected) = $inputs;
-                assert_eq!(build_sparse_table(&array), expected);
+                let (array, expected): (_, Vec<Vec<usize>>) = $inputs;
+                let rmq = RangeMinimumQuery::new(&array);
+                for (k, row) in expected.iter().enumerate() {
+                    for (i, &idx) in row.iter().enumerate() {
+                        assert_eq!(rmq.get_range_min(i, i + (1 << k)), Ok(array[idx]));
+                    }
+                }
             }
         )*
         }
@@ -119,6 +74,10 @@ mod tests {
         assert!(sparse_v1.get_range_min(4, 3).is_err());
         assert!(sparse_v1.get_range_min(0, 1000).is_err());
         assert!(sparse_v1.get_range_min(1000, 1001).is_err());
+        assert_eq!(
+            sparse_v1.get_range_min(5, 5),
+            Err(RangeError::Empty { start: 5, end: 5 })
+        );
     }
 
     #[test]
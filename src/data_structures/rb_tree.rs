@@ -1,6 +1,9 @@
 use std::boxed::Box;
 use std::cmp::{Ord, Ordering};
-use std::iter::Iterator;
+use std::iter::{FromIterator, Iterator};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
 use std::ptr::null_mut;
 
 #[derive(Copy, Clone)]
@@ -33,6 +36,9 @@ impl<K: Ord, V> RBNode<K, V> {
 
 pub struct RBTree<K: Ord, V> {
     root: *mut RBNode<K, V>,
+    len: usize,
+    /// The tree owns its nodes through raw pointers.
+    marker: PhantomData<Box<RBNode<K, V>>>,
 }
 
 impl<K: Ord, V> Default for RBTree<K, V> {
@@ -43,24 +49,62 @@ impl<K: Ord, V> Default for RBTree<K, V> {
 
 impl<K: Ord, V> RBTree<K, V> {
     pub fn new() -> RBTree<K, V> {
-        RBTree::<K, V> { root: null_mut() }
+        RBTree::<K, V> {
+            root: null_mut(),
+            len: 0,
+            marker: PhantomData,
+        }
     }
 
-    pub fn find(&self, key: &K) -> Option<&V> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the node holding `key`, or null.
+    fn find_node(&self, key: &K) -> *mut RBNode<K, V> {
         unsafe {
             let mut node = self.root;
             while !node.is_null() {
                 node = match (*node).key.cmp(key) {
                     Ordering::Less => (*node).right,
-                    Ordering::Equal => return Some(&(*node).value),
+                    Ordering::Equal => return node,
                     Ordering::Greater => (*node).left,
                 }
             }
         }
-        None
+        null_mut()
     }
 
-    pub fn insert(&mut self, key: K, value: V) {
+    pub fn find(&self, key: &K) -> Option<&V> {
+        let node = self.find_node(key);
+        unsafe { node.as_ref().map(|node| &node.value) }
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let node = self.find_node(key);
+        unsafe { node.as_mut().map(|node| &mut node.value) }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        !self.find_node(key).is_null()
+    }
+
+    /// Inserts a key-value pair, and returns the previous value of the key.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let (node, old) = self.insert_node(key, value);
+        if old.is_none() {
+            unsafe { insert_fixup(self, node) }
+        }
+        old
+    }
+
+    /// Inserts a key-value pair without restoring the red-black invariants, and returns the node holding the
+    /// key. Nodes never move in memory, so the pointer stays valid through the rotations of the fixup.
+    fn insert_node(&mut self, key: K, value: V) -> (*mut RBNode<K, V>, Option<V>) {
         unsafe {
             let mut parent = null_mut();
             let mut node = self.root;
@@ -69,8 +113,7 @@ impl<K: Ord, V> RBTree<K, V> {
                 node = match (*node).key.cmp(&key) {
                     Ordering::Less => (*node).right,
                     Ordering::Equal => {
-                        (*node).value = value;
-                        return;
+                        return (node, Some(mem::replace(&mut (*node).value, value)));
                     }
                     Ordering::Greater => (*node).left,
                 }
@@ -86,155 +129,492 @@ impl<K: Ord, V> RBTree<K, V> {
                 self.root = node;
             }
             (*node).parent = parent;
-            insert_fixup(self, node);
+            self.len += 1;
+            (node, None)
         }
     }
 
     pub fn delete(&mut self, key: &K) {
-        unsafe {
-            let mut parent = null_mut();
-            let mut node = self.root;
-            while !node.is_null() {
-                node = match (*node).key.cmp(key) {
-                    Ordering::Less => {
-                        parent = node;
-                        (*node).right
-                    }
-                    Ordering::Equal => break,
-                    Ordering::Greater => {
-                        parent = node;
-                        (*node).left
-                    }
-                };
+        self.remove(key);
+    }
+
+    /// Removes a key, and returns its value.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let node = self.find_node(key);
+        if node.is_null() {
+            return None;
+        }
+        Some(unsafe { self.remove_node(node) }.1)
+    }
+
+    /// Unlinks `node` from the tree, restores the red-black invariants, and returns its contents.
+    ///
+    /// # Safety
+    ///
+    /// `node` must be a node of this tree.
+    unsafe fn remove_node(&mut self, node: *mut RBNode<K, V>) -> (K, V) {
+        let mut parent = (*node).parent;
+        /* cl and cr denote left and right child of node, respectively. */
+        let cl = (*node).left;
+        let cr = (*node).right;
+        let mut deleted_color;
+
+        if cl.is_null() {
+            replace_node(self, parent, node, cr);
+            if cr.is_null() {
+                /*
+                 * Case 1 - cl and cr are both NULL
+                 * (n could be either color here)
+                 *
+                 *     (n)             NULL
+                 *    /   \    -->
+                 *  NULL  NULL
+                 */
+
+                deleted_color = (*node).color;
+            } else {
+                /*
+                 * Case 2 - cl is NULL and cr is not NULL
+                 *
+                 *     N             Cr
+                 *    / \    -->    /  \
+                 *  NULL cr       NULL NULL
+                 */
+
+                (*cr).parent = parent;
+                (*cr).color = Color::Black;
+                deleted_color = Color::Red;
             }
+        } else if cr.is_null() {
+            /*
+             * Case 3 - cl is not NULL and cr is NULL
+             *
+             *     N             Cl
+             *    / \    -->    /  \
+             *  cl  NULL      NULL NULL
+             */
 
-            if node.is_null() {
-                return;
+            replace_node(self, parent, node, cl);
+            (*cl).parent = parent;
+            (*cl).color = Color::Black;
+            deleted_color = Color::Red;
+        } else {
+            let mut victim = (*node).right;
+            while !(*victim).left.is_null() {
+                victim = (*victim).left;
             }
+            if victim == (*node).right {
+                /* Case 4 - victim is the right child of node
+                 *
+                 *     N         N         n
+                 *    / \       / \       / \
+                 *  (cl) cr   (cl) Cr    Cl  Cr
+                 *
+                 *     N         n
+                 *    / \       / \
+                 *  (cl) Cr    Cl  Cr
+                 *         \         \
+                 *         crr       crr
+                 */
 
-            /* cl and cr denote left and right child of node, respectively. */
-            let cl = (*node).left;
-            let cr = (*node).right;
-            let mut deleted_color;
-
-            if cl.is_null() {
-                replace_node(self, parent, node, cr);
-                if cr.is_null() {
-                    /*
-                     * Case 1 - cl and cr are both NULL
-                     * (n could be either color here)
-                     *
-                     *     (n)             NULL
-                     *    /   \    -->
-                     *  NULL  NULL
-                     */
-
-                    deleted_color = (*node).color;
+                replace_node(self, parent, node, victim);
+                (*victim).parent = parent;
+                deleted_color = (*victim).color;
+                (*victim).color = (*node).color;
+                (*victim).left = cl;
+                (*cl).parent = victim;
+                if (*victim).right.is_null() {
+                    parent = victim;
                 } else {
-                    /*
-                     * Case 2 - cl is NULL and cr is not NULL
-                     *
-                     *     N             Cr
-                     *    / \    -->    /  \
-                     *  NULL cr       NULL NULL
-                     */
-
-                    (*cr).parent = parent;
-                    (*cr).color = Color::Black;
                     deleted_color = Color::Red;
+                    (*(*victim).right).color = Color::Black;
                 }
-            } else if cr.is_null() {
+            } else {
                 /*
-                 * Case 3 - cl is not NULL and cr is NULL
-                 *
-                 *     N             Cl
-                 *    / \    -->    /  \
-                 *  cl  NULL      NULL NULL
+                 * Case 5 - victim is not the right child of node
                  */
 
-                replace_node(self, parent, node, cl);
-                (*cl).parent = parent;
-                (*cl).color = Color::Black;
-                deleted_color = Color::Red;
-            } else {
-                let mut victim = (*node).right;
-                while !(*victim).left.is_null() {
-                    victim = (*victim).left;
-                }
-                if victim == (*node).right {
-                    /* Case 4 - victim is the right child of node
-                     *
-                     *     N         N         n
-                     *    / \       / \       / \
-                     *  (cl) cr   (cl) Cr    Cl  Cr
-                     *
-                     *     N         n
-                     *    / \       / \
-                     *  (cl) Cr    Cl  Cr
-                     *         \         \
-                     *         crr       crr
-                     */
-
-                    replace_node(self, parent, node, victim);
-                    (*victim).parent = parent;
+                /* vp and vr denote parent and right child of victim, respectively. */
+                let vp = (*victim).parent;
+                let vr = (*victim).right;
+                (*vp).left = vr;
+                if vr.is_null() {
                     deleted_color = (*victim).color;
-                    (*victim).color = (*node).color;
-                    (*victim).left = cl;
-                    (*cl).parent = victim;
-                    if (*victim).right.is_null() {
-                        parent = victim;
-                    } else {
-                        deleted_color = Color::Red;
-                        (*(*victim).right).color = Color::Black;
-                    }
                 } else {
-                    /*
-                     * Case 5 - victim is not the right child of node
-                     */
-
-                    /* vp and vr denote parent and right child of victim, respectively. */
-                    let vp = (*victim).parent;
-                    let vr = (*victim).right;
-                    (*vp).left = vr;
-                    if vr.is_null() {
-                        deleted_color = (*victim).color;
-                    } else {
-                        deleted_color = Color::Red;
-                        (*vr).parent = vp;
-                        (*vr).color = Color::Black;
-                    }
-                    replace_node(self, parent, node, victim);
-                    (*victim).parent = parent;
-                    (*victim).color = (*node).color;
-                    (*victim).left = cl;
-                    (*victim).right = cr;
-                    (*cl).parent = victim;
-                    (*cr).parent = victim;
-                    parent = vp;
+                    deleted_color = Color::Red;
+                    (*vr).parent = vp;
+                    (*vr).color = Color::Black;
                 }
+                replace_node(self, parent, node, victim);
+                (*victim).parent = parent;
+                (*victim).color = (*node).color;
+                (*victim).left = cl;
+                (*victim).right = cr;
+                (*cl).parent = victim;
+                (*cr).parent = victim;
+                parent = vp;
             }
+        }
+
+        self.len -= 1;
+        let removed = Box::from_raw(node);
+        if matches!(deleted_color, Color::Black) {
+            delete_fixup(self, parent);
+        }
+        (removed.key, removed.value)
+    }
+
+    /// Gets the entry for a key, to inspect or update it in place.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        let node = self.find_node(&key);
+        if node.is_null() {
+            Entry::Vacant(VacantEntry { tree: self, key })
+        } else {
+            Entry::Occupied(OccupiedEntry { tree: self, node })
+        }
+    }
 
-            /* release resource */
-            drop(Box::from_raw(node));
-            if matches!(deleted_color, Color::Black) {
-                delete_fixup(self, parent);
+    /// Keeps only the entries for which `f` returns `true`.
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        let mut doomed = Vec::new();
+        unsafe {
+            let mut node = leftmost(self.root);
+            while !node.is_null() {
+                if !f(&(*node).key, &mut (*node).value) {
+                    doomed.push(node);
+                }
+                node = successor(node);
+            }
+            // removals relink nodes but never move them, so the pointers stay valid
+            for node in doomed {
+                self.remove_node(node);
             }
         }
     }
 
-    pub fn iter<'a>(&self) -> RBTreeIterator<'a, K, V> {
-        let mut iterator = RBTreeIterator { stack: Vec::new() };
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        unsafe {
+            leftmost(self.root)
+                .as_ref()
+                .map(|node| (&node.key, &node.value))
+        }
+    }
+
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        unsafe {
+            rightmost(self.root)
+                .as_ref()
+                .map(|node| (&node.key, &node.value))
+        }
+    }
+
+    /// Returns a double-ended iterator over the entries in ascending order of keys.
+    pub fn iter(&self) -> RBTreeIterator<'_, K, V> {
+        RBTreeIterator {
+            front: leftmost(self.root),
+            back: rightmost(self.root),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns a double-ended iterator over the entries whose keys lie in `range`.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> RBTreeIterator<'_, K, V> {
+        let front = self.bound_node(range.start_bound(), true);
+        let back = self.bound_node(range.end_bound(), false);
+        let empty = front.is_null() || back.is_null() || unsafe { (*front).key > (*back).key };
+        RBTreeIterator {
+            front: if empty { null_mut() } else { front },
+            back: if empty { null_mut() } else { back },
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the first node inside a start bound (`lower`), or the last node inside an end bound.
+    fn bound_node(&self, bound: Bound<&K>, lower: bool) -> *mut RBNode<K, V> {
+        let inside = |key: &K| match bound {
+            Bound::Included(b) if lower => key >= b,
+            Bound::Excluded(b) if lower => key > b,
+            Bound::Included(b) => key <= b,
+            Bound::Excluded(b) => key < b,
+            Bound::Unbounded => true,
+        };
+        let mut best = null_mut();
         let mut node = self.root;
         unsafe {
             while !node.is_null() {
-                iterator.stack.push(&*node);
-                node = (*node).left;
+                if inside(&(*node).key) {
+                    best = node;
+                    // look for a node closer to the bound
+                    node = if lower { (*node).left } else { (*node).right };
+                } else {
+                    node = if lower { (*node).right } else { (*node).left };
+                }
+            }
+        }
+        best
+    }
+
+    /// Checks the binary search tree order, the parent links, the length and the red-black invariants: the
+    /// root is black, red nodes have no red children, and all paths from the root to a leaf have as many black
+    /// nodes.
+    pub fn validate(&self) -> Result<(), RBTreeViolation> {
+        unsafe {
+            if !self.root.is_null() {
+                if !(*self.root).parent.is_null() {
+                    return Err(RBTreeViolation::BrokenParentLink);
+                }
+                if matches!((*self.root).color, Color::Red) {
+                    return Err(RBTreeViolation::RedRoot);
+                }
+            }
+            let mut count = 0;
+            validate_subtree(self.root, &mut count)?;
+            if count != self.len {
+                return Err(RBTreeViolation::WrongLength);
+            }
+            let mut node = leftmost(self.root);
+            while !node.is_null() {
+                let next = successor(node);
+                if !next.is_null() && (*node).key >= (*next).key {
+                    return Err(RBTreeViolation::KeysOutOfOrder);
+                }
+                node = next;
             }
         }
-        iterator
+        Ok(())
     }
 }
 
+/// A broken invariant found by `RBTree::validate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RBTreeViolation {
+    RedRoot,
+    RedNodeWithRedChild,
+    UnequalBlackHeights,
+    BrokenParentLink,
+    KeysOutOfOrder,
+    WrongLength,
+}
+
+/// Checks the subtree rooted at `node`, and returns its black height.
+unsafe fn validate_subtree<K: Ord, V>(
+    node: *mut RBNode<K, V>,
+    count: &mut usize,
+) -> Result<usize, RBTreeViolation> {
+    if node.is_null() {
+        return Ok(1);
+    }
+    *count += 1;
+    for child in [(*node).left, (*node).right] {
+        if child.is_null() {
+            continue;
+        }
+        if (*child).parent != node {
+            return Err(RBTreeViolation::BrokenParentLink);
+        }
+        if matches!((*node).color, Color::Red) && matches!((*child).color, Color::Red) {
+            return Err(RBTreeViolation::RedNodeWithRedChild);
+        }
+    }
+    let left = validate_subtree((*node).left, count)?;
+    let right = validate_subtree((*node).right, count)?;
+    if left != right {
+        return Err(RBTreeViolation::UnequalBlackHeights);
+    }
+    Ok(left + usize::from(matches!((*node).color, Color::Black)))
+}
+
+impl<K: Ord, V> Drop for RBTree<K, V> {
+    fn drop(&mut self) {
+        // free the nodes in post-order, walking back up through the parent links
+        let mut node = self.root;
+        unsafe {
+            while !node.is_null() {
+                if !(*node).left.is_null() {
+                    node = (*node).left;
+                } else if !(*node).right.is_null() {
+                    node = (*node).right;
+                } else {
+                    let parent = (*node).parent;
+                    if !parent.is_null() {
+                        if (*parent).left == node {
+                            (*parent).left = null_mut();
+                        } else {
+                            (*parent).right = null_mut();
+                        }
+                    }
+                    drop(Box::from_raw(node));
+                    node = parent;
+                }
+            }
+        }
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for RBTree<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut tree = RBTree::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for RBTree<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<'a, K: Ord, V> IntoIterator for &'a RBTree<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = RBTreeIterator<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A view into a single entry of an `RBTree`, which may be vacant or occupied.
+pub enum Entry<'a, K: Ord, V> {
+    Vacant(VacantEntry<'a, K, V>),
+    Occupied(OccupiedEntry<'a, K, V>),
+}
+
+pub struct VacantEntry<'a, K: Ord, V> {
+    tree: &'a mut RBTree<K, V>,
+    key: K,
+}
+
+pub struct OccupiedEntry<'a, K: Ord, V> {
+    tree: &'a mut RBTree<K, V>,
+    node: *mut RBNode<K, V>,
+}
+
+impl<'a, K: Ord, V> Entry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Vacant(entry) => &entry.key,
+            Entry::Occupied(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant, and returns the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of `default` if the entry is vacant, and returns the value.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default()),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    /// Calls `f` on the value if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Ord, V: Default> Entry<'a, K, V> {
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: Ord, V> VacantEntry<'a, K, V> {
+    pub fn insert(self, value: V) -> &'a mut V {
+        let (node, _) = self.tree.insert_node(self.key, value);
+        unsafe {
+            insert_fixup(self.tree, node);
+            &mut (*node).value
+        }
+    }
+}
+
+impl<'a, K: Ord, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        unsafe { &(*self.node).key }
+    }
+
+    pub fn get(&self) -> &V {
+        unsafe { &(*self.node).value }
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        unsafe { &mut (*self.node).value }
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        unsafe { &mut (*self.node).value }
+    }
+
+    /// Replaces the value, and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry, and returns its key and value.
+    pub fn remove_entry(self) -> (K, V) {
+        unsafe { self.tree.remove_node(self.node) }
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+}
+
+fn leftmost<K: Ord, V>(mut node: *mut RBNode<K, V>) -> *mut RBNode<K, V> {
+    unsafe {
+        while !node.is_null() && !(*node).left.is_null() {
+            node = (*node).left;
+        }
+    }
+    node
+}
+
+fn rightmost<K: Ord, V>(mut node: *mut RBNode<K, V>) -> *mut RBNode<K, V> {
+    unsafe {
+        while !node.is_null() && !(*node).right.is_null() {
+            node = (*node).right;
+        }
+    }
+    node
+}
+
+/// Returns the node after `node` in key order, or null.
+unsafe fn successor<K: Ord, V>(mut node: *mut RBNode<K, V>) -> *mut RBNode<K, V> {
+    if !(*node).right.is_null() {
+        return leftmost((*node).right);
+    }
+    // climb until we come up from a left child
+    let mut parent = (*node).parent;
+    while !parent.is_null() && (*parent).right == node {
+        node = parent;
+        parent = (*node).parent;
+    }
+    parent
+}
+
+/// Returns the node before `node` in key order, or null.
+unsafe fn predecessor<K: Ord, V>(mut node: *mut RBNode<K, V>) -> *mut RBNode<K, V> {
+    if !(*node).left.is_null() {
+        return rightmost((*node).left);
+    }
+    let mut parent = (*node).parent;
+    while !parent.is_null() && (*parent).left == node {
+        node = parent;
+        parent = (*node).parent;
+    }
+    parent
+}
+
 #[inline]
 unsafe fn insert_fixup<K: Ord, V>(tree: &mut RBTree<K, V>, mut node: *mut RBNode<K, V>) {
     let mut parent: *mut RBNode<K, V> = (*node).parent;
@@ -372,10 +752,13 @@ unsafe fn delete_fixup<K: Ord, V>(tree: &mut RBTree<K, V>, mut parent: *mut RBNo
         /*
          * Loop invariants:
          * - node is black (or null on first iteration)
-         * - node is not the root (so parent is not null)
          * - All leaf paths going through parent and node have a
          *   black node count that is 1 lower than other leaf paths.
          */
+        if parent.is_null() {
+            /* node is the root, so every path lost the same black node */
+            break;
+        }
         sibling = (*parent).right;
         if node != sibling {
             /* node = (*parent).left */
@@ -461,7 +844,7 @@ unsafe fn delete_fixup<K: Ord, V>(tree: &mut RBTree<K, V>, mut parent: *mut RBNo
                 right_rotate(tree, parent);
                 (*parent).color = Color::Red;
                 (*sibling).color = Color::Black;
-                sibling = (*parent).right;
+                sibling = (*parent).left;
             }
             sl = (*sibling).left;
             sr = (*sibling).right;
@@ -586,32 +969,62 @@ unsafe fn replace_node<K: Ord, V>(
     }
 }
 
+/// An iterator over the entries of an `RBTree` between two nodes, in both directions.
 pub struct RBTreeIterator<'a, K: Ord, V> {
-    stack: Vec<&'a RBNode<K, V>>,
+    /// The next node from the front, or null once the iterator is exhausted.
+    front: *mut RBNode<K, V>,
+    /// The next node from the back, or null once the iterator is exhausted.
+    back: *mut RBNode<K, V>,
+    marker: PhantomData<&'a RBNode<K, V>>,
+}
+
+impl<'a, K: Ord, V> RBTreeIterator<'a, K, V> {
+    /// Yields `node`, and ends the iteration if both ends met there.
+    fn take(&mut self, node: *mut RBNode<K, V>) -> (&'a K, &'a V) {
+        if self.front == self.back {
+            self.front = null_mut();
+            self.back = null_mut();
+        }
+        unsafe { (&(*node).key, &(*node).value) }
+    }
 }
 
 impl<'a, K: Ord, V> Iterator for RBTreeIterator<'a, K, V> {
-    type Item = &'a RBNode<K, V>;
+    type Item = (&'a K, &'a V);
+
     fn next(&mut self) -> Option<Self::Item> {
-        match self.stack.pop() {
-            Some(node) => {
-                let mut next = node.right;
-                unsafe {
-                    while !next.is_null() {
-                        self.stack.push(&*next);
-                        next = (*next).left;
-                    }
-                }
-                Some(node)
-            }
-            None => None,
+        let node = self.front;
+        if node.is_null() {
+            return None;
         }
+        let item = Some(self.take(node));
+        if !self.front.is_null() {
+            self.front = unsafe { successor(node) };
+        }
+        item
+    }
+}
+
+impl<K: Ord, V> DoubleEndedIterator for RBTreeIterator<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node = self.back;
+        if node.is_null() {
+            return None;
+        }
+        let item = Some(self.take(node));
+        if !self.back.is_null() {
+            self.back = unsafe { predecessor(node) };
+        }
+        item
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::RBTree;
+    use super::{Entry, RBTree};
+    use quickcheck::TestResult;
+    use quickcheck_macros::quickcheck;
+    use std::collections::BTreeMap;
 
     #[test]
     fn find() {
@@ -631,7 +1044,7 @@ mod tests {
         for (k, v) in "hello, world!".chars().enumerate() {
             tree.insert(k, v);
         }
-        let s: String = tree.iter().map(|x| x.value).collect();
+        let s: String = tree.iter().map(|(_, v)| *v).collect();
         assert_eq!(s, "hello, world!");
     }
 
@@ -646,7 +1059,125 @@ mod tests {
         tree.delete(&5);
         tree.delete(&7);
         tree.delete(&11);
-        let s: String = tree.iter().map(|x| x.value).collect();
+        let s: String = tree.iter().map(|(_, v)| *v).collect();
         assert_eq!(s, "hlo orl!");
     }
+
+    #[test]
+    fn map_api() {
+        let mut tree: RBTree<i32, String> = (0..10).map(|k| (k, k.to_string())).collect();
+        assert_eq!(tree.len(), 10);
+        assert_eq!(tree.insert(3, "three".to_string()), Some("3".to_string()));
+        tree.get_mut(&4).unwrap().push('!');
+        assert_eq!(tree.find(&4).map(String::as_str), Some("4!"));
+        assert_eq!(tree.remove(&5), Some("5".to_string()));
+        assert_eq!(tree.remove(&5), None);
+        assert!(!tree.contains_key(&5));
+        assert_eq!(tree.first_key_value(), Some((&0, &"0".to_string())));
+        assert_eq!(tree.last_key_value(), Some((&9, &"9".to_string())));
+        tree.extend([(20, "twenty".to_string())]);
+        assert_eq!(tree.len(), 10);
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(RBTree::<i32, ()>::new().is_empty());
+    }
+
+    #[test]
+    fn entries() {
+        let mut counts = RBTree::new();
+        for word in "the quick fox jumps over the lazy dog the end".split(' ') {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(counts.find(&"the"), Some(&3));
+        counts.entry("fox").and_modify(|n| *n += 10).or_default();
+        assert_eq!(counts.find(&"fox"), Some(&11));
+        match counts.entry("dog") {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), 1),
+            Entry::Vacant(_) => unreachable!(),
+        }
+        assert_eq!(counts.entry("dog").key(), &"dog");
+        assert!(!counts.contains_key(&"dog"));
+        assert_eq!(counts.validate(), Ok(()));
+    }
+
+    #[test]
+    fn ranges_and_reverse_iteration() {
+        let tree: RBTree<i32, ()> = (0..20).map(|k| (2 * k, ())).collect();
+        let keys = |it: super::RBTreeIterator<i32, ()>| it.map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys(tree.range(5..11)), [6, 8, 10]);
+        assert_eq!(keys(tree.range(6..=10)), [6, 8, 10]);
+        assert_eq!(keys(tree.range(..3)), [0, 2]);
+        assert_eq!(keys(tree.range(35..)), [36, 38]);
+        assert!(keys(tree.range(7..8)).is_empty());
+        assert_eq!(
+            tree.range(10..20)
+                .rev()
+                .map(|(k, _)| *k)
+                .collect::<Vec<_>>(),
+            [18, 16, 14, 12, 10]
+        );
+        // both ends meet in the middle without yielding an entry twice
+        let mut iter = tree.range(0..6);
+        assert_eq!(iter.next(), Some((&0, &())));
+        assert_eq!(iter.next_back(), Some((&4, &())));
+        assert_eq!(iter.next(), Some((&2, &())));
+        assert_eq!(iter.next_back(), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn retain_even_keys() {
+        let mut tree: RBTree<u32, u32> = (0..100).map(|k| (k, k * k)).collect();
+        tree.retain(|k, v| {
+            *v += 1;
+            k % 2 == 0
+        });
+        assert_eq!(tree.len(), 50);
+        assert_eq!(tree.find(&10), Some(&101));
+        assert_eq!(tree.find(&11), None);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn small_sequence_for_miri() {
+        // short enough to run under Miri, which checks the raw pointer handling and that drop frees every node
+        let mut tree = RBTree::new();
+        for k in [5, 2, 8, 1, 9, 3, 7, 4, 6, 0] {
+            tree.insert(k, Box::new(k));
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        for k in [2, 5, 0, 9, 4] {
+            assert_eq!(tree.remove(&k).as_deref(), Some(&k));
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        let keys: Vec<_> = tree.iter().rev().map(|(k, _)| *k).collect();
+        assert_eq!(keys, [8, 7, 6, 3, 1]);
+    }
+
+    #[quickcheck]
+    #[cfg_attr(miri, ignore)]
+    fn matches_btree_map(ops: Vec<(bool, u8, u16)>) -> TestResult {
+        let mut tree = RBTree::new();
+        let mut expected = BTreeMap::new();
+        for (insert, key, value) in ops {
+            if insert {
+                if tree.insert(key, value) != expected.insert(key, value) {
+                    return TestResult::failed();
+                }
+            } else if tree.remove(&key) != expected.remove(&key) {
+                return TestResult::failed();
+            }
+            if tree.validate().is_err() {
+                return TestResult::failed();
+            }
+        }
+        let (low, high) = (64, 192);
+        TestResult::from_bool(
+            tree.len() == expected.len()
+                && tree.iter().eq(expected.iter())
+                && tree.iter().rev().eq(expected.iter().rev())
+                && tree.range(low..high).eq(expected.range(low..high))
+                && tree.first_key_value() == expected.first_key_value()
+                && tree.last_key_value() == expected.last_key_value(),
+        )
+    }
 }
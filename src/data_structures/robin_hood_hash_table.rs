@@ -0,0 +1,465 @@
+//! A hash map with open addressing and Robin Hood hashing.
+//!
+//! Every entry is stored in the slots array itself, at or after its home slot (its hash modulo the
+//! capacity), probing linearly. The distance from the home slot is the entry's probe length. Robin Hood
+//! hashing keeps those distances even: an entry being inserted takes the slot of any entry that is closer
+//! to its home than the new one is to its own, and the displaced entry continues probing in its place. A
+//! lookup can then stop as soon as it meets an entry closer to home than the key would be at that slot.
+//!
+//! Removal uses backward shifting instead of tombstones: the entries after the removed one move back a
+//! slot until one is empty or already at home, which keeps the table as if the removed key was never
+//! inserted.
+//!
+//! See also <https://programming.guide/robin-hood-hashing.html>.
+
+use super::TableStats;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+/// The number of slots allocated by the first insertion.
+const INITIAL_SLOTS: usize = 8;
+
+struct Slot<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+pub struct RobinHoodHashTable<K, V, S = RandomState> {
+    slots: Vec<Option<Slot<K, V>>>,
+    len: usize,
+    hasher: S,
+}
+
+impl<K, V> RobinHoodHashTable<K, V> {
+    pub fn new() -> Self {
+        RobinHoodHashTable::with_hasher(RandomState::new())
+    }
+
+    /// Creates a table that holds `capacity` entries without growing.
+    pub fn with_capacity(capacity: usize) -> Self {
+        RobinHoodHashTable::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S: Default> Default for RobinHoodHashTable<K, V, S> {
+    fn default() -> Self {
+        RobinHoodHashTable::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> RobinHoodHashTable<K, V, S> {
+    pub fn with_hasher(hasher: S) -> Self {
+        RobinHoodHashTable {
+            slots: Vec::new(),
+            len: 0,
+            hasher,
+        }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        let mut table = RobinHoodHashTable::with_hasher(hasher);
+        if capacity > 0 {
+            let count = (capacity * 8).div_ceil(7).next_power_of_two();
+            table.slots = empty_slots(count.max(INITIAL_SLOTS));
+        }
+        table
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hasher
+    }
+
+    /// Removes all entries, keeping the slots.
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| *slot = None);
+        self.len = 0;
+    }
+
+    /// Returns an iterator over the entries, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|slot| (&slot.key, &slot.value))
+    }
+
+    /// Returns an iterator over the entries with mutable values, in no particular order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.slots
+            .iter_mut()
+            .flatten()
+            .map(|slot| (&slot.key, &mut slot.value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Returns the load factor and the probe lengths.
+    pub fn stats(&self) -> TableStats {
+        TableStats::from_probe_lengths(
+            self.slots.len(),
+            self.slots.iter().enumerate().filter_map(|(index, slot)| {
+                slot.as_ref()
+                    .map(|slot| self.distance(slot.hash, index) + 1)
+            }),
+        )
+    }
+
+    fn mask(&self) -> usize {
+        self.slots.len() - 1
+    }
+
+    /// The distance from the home slot of `hash` to `index`.
+    fn distance(&self, hash: u64, index: usize) -> usize {
+        index.wrapping_sub(hash as usize) & self.mask()
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> RobinHoodHashTable<K, V, S> {
+    /// Returns the slot holding `key`.
+    fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.slots.is_empty() {
+            return None;
+        }
+        let hash = self.hasher.hash_one(key);
+        let mut index = hash as usize & self.mask();
+        for distance in 0.. {
+            let slot = self.slots[index].as_ref()?;
+            if self.distance(slot.hash, index) < distance {
+                // the key would have taken this slot
+                return None;
+            }
+            if slot.hash == hash && slot.key.borrow() == key {
+                return Some(index);
+            }
+            index = (index + 1) & self.mask();
+        }
+        unreachable!()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        self.slots[index].as_ref().map(|slot| &slot.value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        self.slots[index].as_mut().map(|slot| &mut slot.value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Inserts a key-value pair, and returns the previous value of the key.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    /// Removes a key, and returns its value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        Some(self.remove_at(index).value)
+    }
+
+    /// Gets the entry for a key, to inspect or update it in place.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        match self.find(&key) {
+            Some(index) => Entry::Occupied(OccupiedEntry { table: self, index }),
+            None => Entry::Vacant(VacantEntry { table: self, key }),
+        }
+    }
+
+    /// Empties a slot, and shifts the entries after it back until one is empty or at home.
+    fn remove_at(&mut self, mut index: usize) -> Slot<K, V> {
+        let removed = self.slots[index].take().unwrap();
+        self.len -= 1;
+        loop {
+            let next = (index + 1) & self.mask();
+            match &self.slots[next] {
+                Some(slot) if self.distance(slot.hash, next) > 0 => {
+                    self.slots[index] = self.slots[next].take();
+                    index = next;
+                }
+                _ => return removed,
+            }
+        }
+    }
+
+    /// Inserts a key that is not in the table, and returns the slot where it ended up.
+    fn insert_new(&mut self, hash: u64, key: K, value: V) -> usize {
+        // doubles the slots if one more entry would exceed a load factor of 7/8
+        if 8 * (self.len + 1) > 7 * self.slots.len() {
+            let count = (2 * self.slots.len()).max(INITIAL_SLOTS);
+            let old = mem::replace(&mut self.slots, empty_slots(count));
+            for slot in old.into_iter().flatten() {
+                self.place(slot);
+            }
+        }
+        self.len += 1;
+        self.place(Slot { hash, key, value })
+    }
+
+    /// Puts an entry in the first free slot of its probe sequence, swapping it with every entry closer to home
+    /// on the way, and returns the slot where the entry ended up.
+    fn place(&mut self, mut carried: Slot<K, V>) -> usize {
+        let mask = self.mask();
+        let mut index = carried.hash as usize & mask;
+        let mut distance = 0;
+        let mut placed = None;
+        loop {
+            match &mut self.slots[index] {
+                empty @ None => {
+                    *empty = Some(carried);
+                    return placed.unwrap_or(index);
+                }
+                Some(slot) => {
+                    let slot_distance = index.wrapping_sub(slot.hash as usize) & mask;
+                    if slot_distance < distance {
+                        // take from the rich: the resident is closer to home, so it moves on instead
+                        mem::swap(slot, &mut carried);
+                        placed.get_or_insert(index);
+                        distance = slot_distance;
+                    }
+                }
+            }
+            index = (index + 1) & mask;
+            distance += 1;
+        }
+    }
+}
+
+fn empty_slots<K, V>(count: usize) -> Vec<Option<Slot<K, V>>> {
+    std::iter::repeat_with(|| None).take(count).collect()
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Default> FromIterator<(K, V)>
+    for RobinHoodHashTable<K, V, S>
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut table = RobinHoodHashTable::default();
+        table.extend(iter);
+        table
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> Extend<(K, V)> for RobinHoodHashTable<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for RobinHoodHashTable<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// A view into a single entry of a `RobinHoodHashTable`, which may be vacant or occupied.
+pub enum Entry<'a, K, V, S> {
+    Vacant(VacantEntry<'a, K, V, S>),
+    Occupied(OccupiedEntry<'a, K, V, S>),
+}
+
+pub struct VacantEntry<'a, K, V, S> {
+    table: &'a mut RobinHoodHashTable<K, V, S>,
+    key: K,
+}
+
+pub struct OccupiedEntry<'a, K, V, S> {
+    table: &'a mut RobinHoodHashTable<K, V, S>,
+    index: usize,
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> Entry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Vacant(entry) => &entry.key,
+            Entry::Occupied(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant, and returns the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of `default` if the entry is vacant, and returns the value.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default()),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    /// Calls `f` on the value if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Hash + Eq, V: Default, S: BuildHasher> Entry<'a, K, V, S> {
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> VacantEntry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        let table = self.table;
+        let hash = table.hasher.hash_one(&self.key);
+        let index = table.insert_new(hash, self.key, value);
+        &mut table.slots[index].as_mut().unwrap().value
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> OccupiedEntry<'a, K, V, S> {
+    fn slot(&self) -> &Slot<K, V> {
+        self.table.slots[self.index].as_ref().unwrap()
+    }
+
+    pub fn key(&self) -> &K {
+        &self.slot().key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.slot().value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.table.slots[self.index].as_mut().unwrap().value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.table.slots[self.index].as_mut().unwrap().value
+    }
+
+    /// Replaces the value, and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry, and returns its key and value.
+    pub fn remove_entry(self) -> (K, V) {
+        let slot = self.table.remove_at(self.index);
+        (slot.key, slot.value)
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, RobinHoodHashTable};
+    use crate::data_structures::identity_hasher::Identity;
+
+    #[test]
+    fn entries_and_removal() {
+        let mut counts = RobinHoodHashTable::new();
+        for word in "a b c a b a".split(' ') {
+            *counts.entry(word).or_default() += 1;
+        }
+        assert_eq!(counts.get("a"), Some(&3));
+        assert_eq!(counts.insert("c", 10), Some(1));
+        assert_eq!(counts.remove("b"), Some(2));
+        assert!(!counts.contains_key("b"));
+        match counts.entry("c") {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), 10),
+            Entry::Vacant(_) => unreachable!(),
+        }
+        assert_eq!(counts.len(), 1);
+        assert_eq!(format!("{counts:?}"), r#"{"a": 3}"#);
+    }
+
+    #[test]
+    fn robin_hood_displacement() {
+        let mut table: RobinHoodHashTable<u64, &str, Identity> =
+            RobinHoodHashTable::with_capacity_and_hasher(7, Identity::default());
+        // 0 and 8 both start at slot 0, and 1 is pushed past its home by them
+        table.insert(1, "one");
+        table.insert(0, "zero");
+        table.insert(8, "eight");
+        let stats = table.stats();
+        assert_eq!(stats.capacity, 8);
+        assert_eq!(stats.max_probe_length, 2);
+        assert_eq!(table.get(&1), Some(&"one"));
+        assert_eq!(table.get(&16), None);
+        // removing 0 shifts 8 and 1 back to their homes
+        assert_eq!(table.remove(&0), Some("zero"));
+        let stats = table.stats();
+        assert_eq!(stats.max_probe_length, 1);
+        assert_eq!(table.get(&8), Some(&"eight"));
+        assert_eq!(table.get(&1), Some(&"one"));
+    }
+
+    #[test]
+    fn grows_under_load() {
+        let mut table: RobinHoodHashTable<u64, u64> = (0..1000).map(|i| (i, i * i)).collect();
+        assert_eq!(table.len(), 1000);
+        assert!(table.stats().load_factor <= 0.875);
+        for (key, value) in table.iter_mut() {
+            *value -= key * key;
+        }
+        assert!(table.values().all(|&value| value == 0));
+        for i in (0..1000).step_by(2) {
+            assert_eq!(table.remove(&i), Some(0));
+        }
+        assert!((0..1000).all(|i| table.contains_key(&i) == (i % 2 == 1)));
+        table.clear();
+        assert!(table.is_empty());
+    }
+}
@@ -0,0 +1,272 @@
+use super::{ImplicitTreap, Monoid};
+use std::fmt;
+use std::ops::Range;
+
+/// One character, or the summary of a run of text.
+///
+/// Every node of the rope holds a single character, and the treap keeps for every subtree how many characters,
+/// bytes and line breaks it contains. `ch` is only meaningful for single characters.
+#[derive(Debug, Clone, PartialEq)]
+struct Glyph {
+    ch: char,
+    chars: usize,
+    bytes: usize,
+    newlines: usize,
+}
+
+impl Glyph {
+    fn new(ch: char) -> Self {
+        Glyph {
+            ch,
+            chars: 1,
+            bytes: ch.len_utf8(),
+            newlines: usize::from(ch == '\n'),
+        }
+    }
+}
+
+impl Monoid for Glyph {
+    fn identity() -> Self {
+        Glyph {
+            ch: '\0',
+            chars: 0,
+            bytes: 0,
+            newlines: 0,
+        }
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Glyph {
+            ch: other.ch,
+            chars: self.chars + other.chars,
+            bytes: self.bytes + other.bytes,
+            newlines: self.newlines + other.newlines,
+        }
+    }
+}
+
+/// A UTF-8 text buffer for editors, where edits anywhere in the text take O(log n).
+///
+/// The text is an [`ImplicitTreap`] of characters, so inserting or removing text at a character position,
+/// slicing and concatenating only split and merge trees. Positions are counted in characters (Unicode scalar
+/// values), never in bytes, so edits can't cut a character in half. The counts of bytes and line breaks kept in
+/// every subtree also convert between characters, bytes and lines in O(log n).
+///
+/// This trades memory for simplicity: every character takes a tree node, where production ropes store chunks of
+/// text in their leaves.
+#[derive(Clone, Default)]
+pub struct Rope {
+    text: ImplicitTreap<Glyph>,
+}
+
+impl Rope {
+    pub fn new() -> Self {
+        Rope::default()
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.text.len()
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        self.summary(0..self.len_chars()).bytes
+    }
+
+    /// Returns the number of lines, which is one more than the number of line breaks.
+    pub fn len_lines(&self) -> usize {
+        self.summary(0..self.len_chars()).newlines + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Returns the character at `char_idx`.
+    pub fn char(&self, char_idx: usize) -> Option<char> {
+        self.text.get(char_idx).map(|glyph| glyph.ch)
+    }
+
+    /// Inserts `text` before the character at `char_idx`.
+    pub fn insert(&mut self, char_idx: usize, text: &str) {
+        self.text.insert_all(char_idx, Rope::from(text).text);
+    }
+
+    /// Removes the characters in `range`.
+    pub fn remove(&mut self, range: Range<usize>) {
+        self.text.remove_range(range);
+    }
+
+    /// Splits the rope in two at `char_idx`, and returns the second part.
+    pub fn split_off(&mut self, char_idx: usize) -> Rope {
+        Rope {
+            text: self.text.split_off(char_idx),
+        }
+    }
+
+    /// Moves the text of `other` to the end of this rope.
+    pub fn append(&mut self, mut other: Rope) {
+        self.text.append(&mut other.text);
+    }
+
+    /// Returns the characters in `range` as a `String`.
+    pub fn slice(&self, range: Range<usize>) -> String {
+        let part = self.text.slice(range);
+        part.to_vec().iter().map(|glyph| glyph.ch).collect()
+    }
+
+    /// Returns the byte offset of the character at `char_idx`.
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        self.summary(0..char_idx).bytes
+    }
+
+    /// Returns the index of the character that starts at or contains the byte at `byte_idx`.
+    pub fn byte_to_char(&self, byte_idx: usize) -> usize {
+        self.text.max_right(|glyph| glyph.bytes <= byte_idx)
+    }
+
+    /// Returns the line, counting from 0, of the character at `char_idx`.
+    pub fn char_to_line(&self, char_idx: usize) -> usize {
+        self.summary(0..char_idx).newlines
+    }
+
+    /// Returns the index of the first character of `line`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the text has fewer lines.
+    pub fn line_to_char(&self, line: usize) -> usize {
+        assert!(line < self.len_lines(), "line out of bounds");
+        if line == 0 {
+            return 0;
+        }
+        // the line starts after its `line`-th line break
+        self.text.max_right(|glyph| glyph.newlines < line) + 1
+    }
+
+    /// Returns the text of `line`, including its line break if it has one.
+    pub fn line(&self, line: usize) -> String {
+        let start = self.line_to_char(line);
+        let end = if line + 1 < self.len_lines() {
+            self.line_to_char(line + 1)
+        } else {
+            self.len_chars()
+        };
+        self.slice(start..end)
+    }
+
+    fn summary(&self, range: Range<usize>) -> Glyph {
+        self.text.query(range)
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Self {
+        Rope {
+            text: text.chars().map(Glyph::new).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text: String = self.text.to_vec().iter().map(|glyph| glyph.ch).collect();
+        f.write_str(&text)
+    }
+}
+
+impl fmt::Debug for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Rope({:?})", self.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rope;
+    use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+    #[test]
+    fn edits() {
+        let mut rope = Rope::from("Hello world");
+        rope.insert(5, ", dear");
+        assert_eq!(rope.to_string(), "Hello, dear world");
+        rope.remove(5..11);
+        assert_eq!(rope.to_string(), "Hello world");
+        rope.append(Rope::from("!"));
+        let tail = rope.split_off(6);
+        assert_eq!(rope.to_string(), "Hello ");
+        assert_eq!(tail.to_string(), "world!");
+        assert_eq!(rope.slice(1..4), "ell");
+        assert_eq!(rope.char(4), Some('o'));
+        assert!(Rope::new().is_empty());
+    }
+
+    #[test]
+    fn multibyte_text() {
+        let mut rope = Rope::from("naïve café ☕");
+        assert_eq!(rope.len_chars(), 12);
+        assert_eq!(rope.len_bytes(), "naïve café ☕".len());
+        assert_eq!(rope.char_to_byte(3), 4);
+        assert_eq!(rope.byte_to_char(4), 3);
+        // a byte inside 'ï' belongs to it
+        assert_eq!(rope.byte_to_char(3), 2);
+        rope.insert(11, "🍰 ");
+        assert_eq!(rope.to_string(), "naïve café 🍰 ☕");
+        assert_eq!(rope.char(11), Some('🍰'));
+    }
+
+    #[test]
+    fn line_index() {
+        let mut rope = Rope::from("first\nsecond\n\nfourth");
+        assert_eq!(rope.len_lines(), 4);
+        assert_eq!(rope.line_to_char(1), 6);
+        assert_eq!(rope.line_to_char(3), 14);
+        assert_eq!(rope.char_to_line(0), 0);
+        assert_eq!(rope.char_to_line(6), 1);
+        assert_eq!(rope.char_to_line(13), 2);
+        assert_eq!(rope.line(1), "second\n");
+        assert_eq!(rope.line(2), "\n");
+        assert_eq!(rope.line(3), "fourth");
+        rope.insert(6, "inserted\n");
+        assert_eq!(rope.len_lines(), 5);
+        assert_eq!(rope.line(2), "second\n");
+        rope.remove(0..rope.line_to_char(3));
+        assert_eq!(rope.to_string(), "\nfourth");
+        assert_eq!(Rope::from("trailing\n").len_lines(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "line out of bounds")]
+    fn line_out_of_bounds() {
+        Rope::from("one\ntwo").line_to_char(2);
+    }
+
+    #[test]
+    fn matches_string_edits() {
+        let mut rope = Rope::new();
+        let mut naive: Vec<char> = Vec::new();
+        let alphabet: Vec<char> = "ab\nçé🌍 ".chars().collect();
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..300 {
+            let at = rng.gen_range(0..=naive.len());
+            if rng.gen_ratio(1, 4) && at < naive.len() {
+                let end = (at + rng.gen_range(0..5)).min(naive.len());
+                rope.remove(at..end);
+                naive.drain(at..end);
+            } else {
+                let len = rng.gen_range(1..=16);
+                let text: String = std::iter::repeat_with(|| *alphabet.choose(&mut rng).unwrap())
+                    .take(len)
+                    .collect();
+                rope.insert(at, &text);
+                naive.splice(at..at, text.chars());
+            }
+        }
+        let expected: String = naive.iter().collect();
+        assert_eq!(rope.to_string(), expected);
+        assert_eq!(rope.len_bytes(), expected.len());
+        for (line, text) in expected.split_inclusive('\n').enumerate() {
+            assert_eq!(rope.line(line), text);
+        }
+    }
+}
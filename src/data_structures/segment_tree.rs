@@ -1,159 +1,390 @@
-use std::cmp::min;
-use std::fmt::Debug;
+use super::Monoid;
 use std::ops::Range;
 
 /// This data structure implements a segment-tree that can efficiently answer range (interval) queries on arrays.
+///
 /// It represents this array as a binary tree of merged intervals. From top to bottom: [aggregated value for the overall array], then [left-hand half, right hand half], etc. until [each individual value, ...]
-/// It is generic over a reduction function for each segment or interval: basically, to describe how we merge two intervals together.
-/// Note that this function should be commutative and associative
-///     It could be `std::cmp::min(interval_1, interval_2)` or `std::cmp::max(interval_1, interval_2)`, or `|a, b| a + b`, `|a, b| a * b`
-pub struct SegmentTree<T: Debug + Default + Ord + Copy> {
-    len: usize,           // length of the represented
+/// It is generic over a [`Monoid`]: how we merge two intervals together, and the value of an empty interval.
+/// The merge only has to be associative, not commutative: the left-hand interval is always the left operand.
+///     It could be `Min`, `Max`, `Sum` or `Product` of numbers, string concatenation, matrix products, or your own aggregate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentTree<T: Monoid> {
+    len: usize,   // length of the represented array
+    size: usize,  // number of leaves: the smallest power of two >= len
     tree: Vec<T>, // represents a binary tree of intervals as an array (as a BinaryHeap does, for instance)
-    merge: fn(T, T) -> T, // how we merge two values together
 }
 
-impl<T: Debug + Default + Ord + Copy> SegmentTree<T> {
-    /// Builds a SegmentTree from an array and a merge function
-    pub fn from_vec(arr: &[T], merge: fn(T, T) -> T) -> Self {
+impl<T: Monoid> From<Vec<T>> for SegmentTree<T> {
+    /// Builds a SegmentTree from an array in O(n).
+    fn from(arr: Vec<T>) -> Self {
         let len = arr.len();
-        let mut buf: Vec<T> = vec![T::default(); 2 * len];
+        let size = len.next_power_of_two();
+        let mut tree = vec![T::identity(); size];
+        // the last `size` positions are the bottom of the tree -> every individual value, padded with the identity
+        tree.extend(arr);
+        tree.resize(2 * size, T::identity());
         // Populate the tree bottom-up, from right to left
-        buf[len..(2 * len)].clone_from_slice(&arr[0..len]); // last len pos is the bottom of the tree -> every individual value
-        for i in (1..len).rev() {
+        for i in (1..size).rev() {
             // a nice property of this "flat" representation of a tree: the parent of an element at index i is located at index i/2
-            buf[i] = merge(buf[2 * i], buf[2 * i + 1]);
-        }
-        SegmentTree {
-            len,
-            tree: buf,
-            merge,
+            tree[i] = tree[2 * i].combine(&tree[2 * i + 1]);
         }
+        SegmentTree { len, size, tree }
+    }
+}
+
+impl<T: Monoid> SegmentTree<T> {
+    /// Builds a SegmentTree over `len` identity values.
+    pub fn new(len: usize) -> Self {
+        Self::from(vec![T::identity(); len])
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the value at index `idx` in the original array.
+    pub fn get(&self, idx: usize) -> &T {
+        assert!(idx < self.len, "index out of bounds");
+        &self.tree[idx + self.size]
+    }
+
+    /// Returns `true` if `range` is a range of the original array, possibly empty.
+    pub(super) fn in_bounds(&self, range: &Range<usize>) -> bool {
+        range.start <= range.end && range.end <= self.len
+    }
+
+    /// The number of leaves, whose root covers `0..leaves()`.
+    pub(super) fn leaves(&self) -> usize {
+        self.size
+    }
+
+    /// The node at `idx`, where the root is at 1 and the children of `i` are at `2 * i` and `2 * i + 1`.
+    pub(super) fn node(&self, idx: usize) -> &T {
+        &self.tree[idx]
+    }
+
+    pub(super) fn node_mut(&mut self, idx: usize) -> &mut T {
+        &mut self.tree[idx]
     }
 
     /// Query the range (exclusive)
     /// returns None if the range is out of the array's boundaries (eg: if start is after the end of the array, or start > end, etc.)
-    /// return the aggregate of values over this range otherwise
+    /// return the aggregate of values over this range otherwise, from left to right, or the identity for an empty range.
     pub fn query(&self, range: Range<usize>) -> Option<T> {
-        let mut l = range.start + self.len;
-        let mut r = min(self.len, range.end) + self.len;
-        let mut res = None;
+        if !self.in_bounds(&range) {
+            return None;
+        }
+        let mut l = range.start + self.size;
+        let mut r = range.end + self.size;
+        // the left and right parts are accumulated separately, so that the merge order is kept
+        let mut left = T::identity();
+        let mut right = T::identity();
         // Check Wikipedia or other detailed explanations here for how to navigate the tree bottom-up to limit the number of operations
         while l < r {
             if l % 2 == 1 {
-                res = Some(match res {
-                    None => self.tree[l],
-                    Some(old) => (self.merge)(old, self.tree[l]),
-                });
+                left = left.combine(&self.tree[l]);
                 l += 1;
             }
             if r % 2 == 1 {
                 r -= 1;
-                res = Some(match res {
-                    None => self.tree[r],
-                    Some(old) => (self.merge)(old, self.tree[r]),
-                });
+                right = self.tree[r].combine(&right);
             }
             l /= 2;
             r /= 2;
         }
-        res
+        Some(left.combine(&right))
     }
 
-    /// Updates the value at index `idx` in the original array with a new value `val`
+    /// Updates the value at index `idx` in the original array with a new value `val`.
     pub fn update(&mut self, idx: usize, val: T) {
+        assert!(idx < self.len, "index out of bounds");
         // change every value where `idx` plays a role, bottom -> up
         // 1: change in the right-hand side of the tree (bottom row)
-        let mut idx = idx + self.len;
+        let mut idx = idx + self.size;
         self.tree[idx] = val;
 
         // 2: then bubble up
         idx /= 2;
         while idx != 0 {
-            self.tree[idx] = (self.merge)(self.tree[2 * idx], self.tree[2 * idx + 1]);
+            self.tree[idx] = self.tree[2 * idx].combine(&self.tree[2 * idx + 1]);
             idx /= 2;
         }
     }
+
+    /// Returns the largest `r` such that `pred(query(l..r))` holds, i.e. the first index where the predicate fails.
+    /// `pred` must hold for the identity, and must be monotone: once it fails for a range, it fails for every longer one.
+    /// Runs in O(log n), e.g. to find how far a prefix sum stays below a bound.
+    pub fn max_right<P: Fn(&T) -> bool>(&self, l: usize, pred: P) -> usize {
+        assert!(l <= self.len, "index out of bounds");
+        debug_assert!(pred(&T::identity()));
+        if l == self.len {
+            return self.len;
+        }
+        let mut l = l + self.size;
+        let mut acc = T::identity();
+        loop {
+            // climb while `l` is a left child: its parent covers the same start
+            while l.is_multiple_of(2) {
+                l /= 2;
+            }
+            let next = acc.combine(&self.tree[l]);
+            if !pred(&next) {
+                // the answer is inside this node: walk down towards it
+                while l < self.size {
+                    l *= 2;
+                    let next = acc.combine(&self.tree[l]);
+                    if pred(&next) {
+                        acc = next;
+                        l += 1;
+                    }
+                }
+                return (l - self.size).min(self.len);
+            }
+            acc = next;
+            l += 1;
+            if l.is_power_of_two() {
+                // went past the right end of the tree
+                return self.len;
+            }
+        }
+    }
+
+    /// Returns the smallest `l` such that `pred(query(l..r))` holds.
+    /// `pred` must hold for the identity, and must be monotone: once it fails for a range, it fails for every longer one.
+    pub fn min_left<P: Fn(&T) -> bool>(&self, r: usize, pred: P) -> usize {
+        assert!(r <= self.len, "index out of bounds");
+        debug_assert!(pred(&T::identity()));
+        if r == 0 {
+            return 0;
+        }
+        let mut r = r + self.size;
+        let mut acc = T::identity();
+        loop {
+            r -= 1;
+            // climb while `r` is a right child: its parent covers the same end
+            while r > 1 && r % 2 == 1 {
+                r /= 2;
+            }
+            let next = self.tree[r].combine(&acc);
+            if !pred(&next) {
+                while r < self.size {
+                    r = 2 * r + 1;
+                    let next = self.tree[r].combine(&acc);
+                    if pred(&next) {
+                        acc = next;
+                        r -= 1;
+                    }
+                }
+                return r + 1 - self.size;
+            }
+            acc = next;
+            if r.is_power_of_two() {
+                return 0;
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::{Max, Min, Sum};
     use super::*;
     use quickcheck::TestResult;
     use quickcheck_macros::quickcheck;
-    use std::cmp::{max, min};
+
+    fn min_tree(vec: &[i32]) -> SegmentTree<Min<i32>> {
+        SegmentTree::from(vec.iter().copied().map(Min).collect::<Vec<_>>())
+    }
+
+    fn max_tree(vec: &[i32]) -> SegmentTree<Max<i32>> {
+        SegmentTree::from(vec.iter().copied().map(Max).collect::<Vec<_>>())
+    }
+
+    fn sum_tree(vec: &[i32]) -> SegmentTree<Sum<i64>> {
+        SegmentTree::from(vec.iter().map(|&x| Sum(x as i64)).collect::<Vec<_>>())
+    }
 
     #[test]
     fn test_min_segments() {
         let vec = vec![-30, 2, -4, 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8];
-        let min_seg_tree = SegmentTree::from_vec(&vec, min);
-        assert_eq!(Some(-5), min_seg_tree.query(4..7));
-        assert_eq!(Some(-30), min_seg_tree.query(0..vec.len()));
-        assert_eq!(Some(-30), min_seg_tree.query(0..2));
-        assert_eq!(Some(-4), min_seg_tree.query(1..3));
-        assert_eq!(Some(-5), min_seg_tree.query(1..7));
+        let min_seg_tree = min_tree(&vec);
+        assert_eq!(Some(Min(-5)), min_seg_tree.query(4..7));
+        assert_eq!(Some(Min(-30)), min_seg_tree.query(0..vec.len()));
+        assert_eq!(Some(Min(-30)), min_seg_tree.query(0..2));
+        assert_eq!(Some(Min(-4)), min_seg_tree.query(1..3));
+        assert_eq!(Some(Min(-5)), min_seg_tree.query(1..7));
+        assert_eq!(Some(Min(i32::MAX)), min_seg_tree.query(3..3));
     }
 
     #[test]
     fn test_max_segments() {
         let val_at_6 = 6;
         let vec = vec![1, 2, -4, 7, 3, -5, val_at_6, 11, -20, 9, 14, 15, 5, 2, -8];
-        let mut max_seg_tree = SegmentTree::from_vec(&vec, max);
-        assert_eq!(Some(15), max_seg_tree.query(0..vec.len()));
+        let mut max_seg_tree = max_tree(&vec);
+        assert_eq!(Some(Max(15)), max_seg_tree.query(0..vec.len()));
         let max_4_to_6 = 6;
-        assert_eq!(Some(max_4_to_6), max_seg_tree.query(4..7));
+        assert_eq!(Some(Max(max_4_to_6)), max_seg_tree.query(4..7));
         let delta = 2;
-        max_seg_tree.update(6, val_at_6 + delta);
-        assert_eq!(Some(val_at_6 + delta), max_seg_tree.query(4..7));
+        max_seg_tree.update(6, Max(val_at_6 + delta));
+        assert_eq!(Some(Max(val_at_6 + delta)), max_seg_tree.query(4..7));
     }
 
     #[test]
     fn test_sum_segments() {
         let val_at_6 = 6;
         let vec = vec![1, 2, -4, 7, 3, -5, val_at_6, 11, -20, 9, 14, 15, 5, 2, -8];
-        let mut sum_seg_tree = SegmentTree::from_vec(&vec, |a, b| a + b);
+        let mut sum_seg_tree = sum_tree(&vec);
         for (i, val) in vec.iter().enumerate() {
-            assert_eq!(Some(*val), sum_seg_tree.query(i..(i + 1)));
+            assert_eq!(Some(Sum(*val as i64)), sum_seg_tree.query(i..(i + 1)));
         }
-        let sum_4_to_6 = sum_seg_tree.query(4..7);
-        assert_eq!(Some(4), sum_4_to_6);
+        let sum_4_to_6 = sum_seg_tree.query(4..7).unwrap();
+        assert_eq!(Sum(4), sum_4_to_6);
         let delta = 3;
-        sum_seg_tree.update(6, val_at_6 + delta);
+        sum_seg_tree.update(6, Sum((val_at_6 + delta) as i64));
         assert_eq!(
-            sum_4_to_6.unwrap() + delta,
-            sum_seg_tree.query(4..7).unwrap()
+            sum_4_to_6.0 + delta as i64,
+            sum_seg_tree.query(4..7).unwrap().0
         );
     }
 
+    #[test]
+    fn test_float_sums() {
+        let mut tree = SegmentTree::from(vec![Sum(0.5), Sum(1.25), Sum(-2.0), Sum(4.0)]);
+        assert_eq!(tree.query(0..3), Some(Sum(-0.25)));
+        tree.update(2, Sum(0.25));
+        assert_eq!(tree.query(1..4), Some(Sum(5.5)));
+        assert_eq!(*tree.get(0), Sum(0.5));
+        assert_eq!(tree.len(), 4);
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Matrix([[i64; 2]; 2]);
+
+    impl Monoid for Matrix {
+        fn identity() -> Self {
+            Matrix([[1, 0], [0, 1]])
+        }
+
+        fn combine(&self, other: &Self) -> Self {
+            let (a, b) = (self.0, other.0);
+            Matrix([
+                [
+                    a[0][0] * b[0][0] + a[0][1] * b[1][0],
+                    a[0][0] * b[0][1] + a[0][1] * b[1][1],
+                ],
+                [
+                    a[1][0] * b[0][0] + a[1][1] * b[1][0],
+                    a[1][0] * b[0][1] + a[1][1] * b[1][1],
+                ],
+            ])
+        }
+    }
+
+    #[test]
+    fn test_non_commutative() {
+        let words: Vec<String> = ["a", "b", "c", "d", "e", "f", "g"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut tree = SegmentTree::from(words);
+        assert_eq!(tree.query(0..7).unwrap(), "abcdefg");
+        assert_eq!(tree.query(1..6).unwrap(), "bcdef");
+        tree.update(3, "XY".to_string());
+        assert_eq!(tree.query(2..5).unwrap(), "cXYe");
+
+        let shear = Matrix([[1, 1], [0, 1]]);
+        let swap = Matrix([[0, 1], [1, 0]]);
+        let tree = SegmentTree::from(vec![shear, swap, shear, shear, swap]);
+        for l in 0..5 {
+            for r in l..=5 {
+                let expected = tree
+                    .tree
+                    .iter()
+                    .skip(tree.size + l)
+                    .take(r - l)
+                    .fold(Matrix::identity(), |acc, m| acc.combine(m));
+                assert_eq!(tree.query(l..r).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_right_min_left() {
+        let vec = vec![3, 1, 4, 1, 5, 9, 2, 6, 5, 3];
+        let tree = sum_tree(&vec);
+        // how far can we go from 2 with a sum <= 10: 4 + 1 + 5 = 10
+        assert_eq!(tree.max_right(2, |s| s.0 <= 10), 5);
+        assert_eq!(tree.max_right(0, |s| s.0 <= 100), vec.len());
+        assert_eq!(tree.max_right(5, |s| s.0 < 9), 5);
+        assert_eq!(tree.max_right(vec.len(), |_| true), vec.len());
+        // how far back from 7 with a sum <= 11: 9 + 2 = 11
+        assert_eq!(tree.min_left(7, |s| s.0 <= 11), 5);
+        assert_eq!(tree.min_left(vec.len(), |s| s.0 <= 100), 0);
+        assert_eq!(tree.min_left(0, |_| true), 0);
+
+        let max = max_tree(&vec);
+        // first index from 0 holding a value >= 5
+        assert_eq!(max.max_right(0, |m| m.0 < 5), 4);
+        assert_eq!(max.min_left(10, |m| m.0 < 6), 8);
+    }
+
+    #[test]
+    fn test_query_out_of_bounds() {
+        let tree = sum_tree(&[1, 2, 3]);
+        assert_eq!(tree.query(1..4), None);
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 2..1;
+        assert_eq!(tree.query(reversed), None);
+        assert_eq!(tree.query(3..3), Some(Sum(0)));
+    }
+
     // Some properties over segment trees:
     //  When asking for the range of the overall array, return the same as iter().min() or iter().max(), etc.
     //  When asking for an interval containing a single value, return this value, no matter the merge function
+    //  max_right and min_left agree with a linear scan
 
     #[quickcheck]
     fn check_overall_interval_min(array: Vec<i32>) -> TestResult {
-        let seg_tree = SegmentTree::from_vec(&array, min);
-        TestResult::from_bool(array.iter().min().copied() == seg_tree.query(0..array.len()))
+        let seg_tree = min_tree(&array);
+        TestResult::from_bool(
+            array.iter().min().copied().unwrap_or(i32::MAX)
+                == seg_tree.query(0..array.len()).unwrap().0,
+        )
     }
 
     #[quickcheck]
     fn check_overall_interval_max(array: Vec<i32>) -> TestResult {
-        let seg_tree = SegmentTree::from_vec(&array, max);
-        TestResult::from_bool(array.iter().max().copied() == seg_tree.query(0..array.len()))
+        let seg_tree = max_tree(&array);
+        TestResult::from_bool(
+            array.iter().max().copied().unwrap_or(i32::MIN)
+                == seg_tree.query(0..array.len()).unwrap().0,
+        )
     }
 
     #[quickcheck]
     fn check_overall_interval_sum(array: Vec<i32>) -> TestResult {
-        let seg_tree = SegmentTree::from_vec(&array, max);
-        TestResult::from_bool(array.iter().max().copied() == seg_tree.query(0..array.len()))
+        let seg_tree = sum_tree(&array);
+        TestResult::from_bool(
+            array.iter().map(|&x| x as i64).sum::<i64>()
+                == seg_tree.query(0..array.len()).unwrap().0,
+        )
     }
 
     #[quickcheck]
     fn check_single_interval_min(array: Vec<i32>) -> TestResult {
-        let seg_tree = SegmentTree::from_vec(&array, min);
+        let seg_tree = min_tree(&array);
         for (i, value) in array.into_iter().enumerate() {
             let res = seg_tree.query(i..(i + 1));
-            if res != Some(value) {
-                return TestResult::error(format!("Expected {:?}, got {:?}", Some(value), res));
+            if res != Some(Min(value)) {
+                return TestResult::error(format!(
+                    "Expected {:?}, got {:?}",
+                    Some(Min(value)),
+                    res
+                ));
             }
         }
         TestResult::passed()
@@ -161,11 +392,15 @@ mod tests {
 
     #[quickcheck]
     fn check_single_interval_max(array: Vec<i32>) -> TestResult {
-        let seg_tree = SegmentTree::from_vec(&array, max);
+        let seg_tree = max_tree(&array);
         for (i, value) in array.into_iter().enumerate() {
             let res = seg_tree.query(i..(i + 1));
-            if res != Some(value) {
-                return TestResult::error(format!("Expected {:?}, got {:?}", Some(value), res));
+            if res != Some(Max(value)) {
+                return TestResult::error(format!(
+                    "Expected {:?}, got {:?}",
+                    Some(Max(value)),
+                    res
+                ));
             }
         }
         TestResult::passed()
@@ -173,11 +408,37 @@ mod tests {
 
     #[quickcheck]
     fn check_single_interval_sum(array: Vec<i32>) -> TestResult {
-        let seg_tree = SegmentTree::from_vec(&array, max);
+        let seg_tree = sum_tree(&array);
         for (i, value) in array.into_iter().enumerate() {
             let res = seg_tree.query(i..(i + 1));
-            if res != Some(value) {
-                return TestResult::error(format!("Expected {:?}, got {:?}", Some(value), res));
+            if res != Some(Sum(value as i64)) {
+                return TestResult::error(format!(
+                    "Expected {:?}, got {:?}",
+                    Some(Sum(value as i64)),
+                    res
+                ));
+            }
+        }
+        TestResult::passed()
+    }
+
+    #[quickcheck]
+    fn check_max_right_min_left(array: Vec<u8>, bound: u16) -> TestResult {
+        let seg_tree = SegmentTree::from(array.iter().map(|&x| Sum(x as u32)).collect::<Vec<_>>());
+        let bound = bound as u32 % 1000;
+        for i in 0..=array.len() {
+            let mut r = i;
+            while r < array.len() && seg_tree.query(i..r + 1).unwrap().0 <= bound {
+                r += 1;
+            }
+            let mut l = i;
+            while l > 0 && seg_tree.query(l - 1..i).unwrap().0 <= bound {
+                l -= 1;
+            }
+            if seg_tree.max_right(i, |s| s.0 <= bound) != r
+                || seg_tree.min_left(i, |s| s.0 <= bound) != l
+            {
+                return TestResult::failed();
             }
         }
         TestResult::passed()
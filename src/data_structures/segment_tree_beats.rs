@@ -0,0 +1,400 @@
+use std::ops::Range;
+
+/// Segment Tree Beats (Ji driver segment tree): range chmin, range chmax and range add, with range sum, max and min
+/// queries, in O(log² n) amortized per operation.
+///
+/// A plain lazy segment tree cannot do range chmin (`a[i] = min(a[i], x)`) with sum queries, because the new sum
+/// depends on how many values exceed `x`. Beats keeps, for every node, its maximum, the number of times it occurs and
+/// the strictly second largest value. If `x` lies between the second maximum and the maximum, only the maximal values
+/// change, and the sum can be updated from their count. Otherwise the update recurses further; an amortized analysis
+/// shows that this does not happen too often. Chmax works the same way on the minimum side.
+///
+/// See <https://codeforces.com/blog/entry/57319>.
+#[derive(Debug, Clone)]
+pub struct SegmentTreeBeats {
+    len: usize,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Node {
+    sum: i64,
+    max: i64,
+    second_max: i64,
+    max_count: i64,
+    min: i64,
+    second_min: i64,
+    min_count: i64,
+    /// Pending addition for the children.
+    add: i64,
+}
+
+impl Node {
+    fn leaf(value: i64) -> Self {
+        Node {
+            sum: value,
+            max: value,
+            second_max: i64::MIN,
+            max_count: 1,
+            min: value,
+            second_min: i64::MAX,
+            min_count: 1,
+            add: 0,
+        }
+    }
+
+    fn merge(left: &Node, right: &Node) -> Node {
+        let (max, max_count, second_max) = match left.max.cmp(&right.max) {
+            std::cmp::Ordering::Greater => {
+                (left.max, left.max_count, left.second_max.max(right.max))
+            }
+            std::cmp::Ordering::Less => {
+                (right.max, right.max_count, right.second_max.max(left.max))
+            }
+            std::cmp::Ordering::Equal => (
+                left.max,
+                left.max_count + right.max_count,
+                left.second_max.max(right.second_max),
+            ),
+        };
+        let (min, min_count, second_min) = match left.min.cmp(&right.min) {
+            std::cmp::Ordering::Less => (left.min, left.min_count, left.second_min.min(right.min)),
+            std::cmp::Ordering::Greater => {
+                (right.min, right.min_count, right.second_min.min(left.min))
+            }
+            std::cmp::Ordering::Equal => (
+                left.min,
+                left.min_count + right.min_count,
+                left.second_min.min(right.second_min),
+            ),
+        };
+        Node {
+            sum: left.sum + right.sum,
+            max,
+            second_max,
+            max_count,
+            min,
+            second_min,
+            min_count,
+            add: 0,
+        }
+    }
+
+    /// Lowers the maximal values to `x`, which must be above the second maximum.
+    fn apply_chmin(&mut self, x: i64) {
+        if self.max <= x {
+            return;
+        }
+        self.sum -= (self.max - x) * self.max_count;
+        // the minimum side may hold the same values
+        if self.min == self.max {
+            self.min = x;
+        } else if self.second_min == self.max {
+            self.second_min = x;
+        }
+        self.max = x;
+    }
+
+    /// Raises the minimal values to `x`, which must be below the second minimum.
+    fn apply_chmax(&mut self, x: i64) {
+        if self.min >= x {
+            return;
+        }
+        self.sum += (x - self.min) * self.min_count;
+        if self.max == self.min {
+            self.max = x;
+        } else if self.second_max == self.min {
+            self.second_max = x;
+        }
+        self.min = x;
+    }
+
+    fn apply_add(&mut self, x: i64, len: usize) {
+        self.sum += x * len as i64;
+        self.max += x;
+        if self.second_max != i64::MIN {
+            self.second_max += x;
+        }
+        self.min += x;
+        if self.second_min != i64::MAX {
+            self.second_min += x;
+        }
+        self.add += x;
+    }
+}
+
+impl From<Vec<i64>> for SegmentTreeBeats {
+    fn from(arr: Vec<i64>) -> Self {
+        let len = arr.len();
+        let mut tree = SegmentTreeBeats {
+            len,
+            nodes: vec![Node::default(); 4 * len],
+        };
+        if len != 0 {
+            tree.build_recursive(&arr, 1, 0..len);
+        }
+        tree
+    }
+}
+
+impl SegmentTreeBeats {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn build_recursive(&mut self, arr: &[i64], idx: usize, range: Range<usize>) {
+        if range.end - range.start == 1 {
+            self.nodes[idx] = Node::leaf(arr[range.start]);
+        } else {
+            let mid = range.start + (range.end - range.start) / 2;
+            self.build_recursive(arr, 2 * idx, range.start..mid);
+            self.build_recursive(arr, 2 * idx + 1, mid..range.end);
+            self.pull(idx);
+        }
+    }
+
+    fn pull(&mut self, idx: usize) {
+        self.nodes[idx] = Node::merge(&self.nodes[2 * idx], &self.nodes[2 * idx + 1]);
+    }
+
+    /// Hands the pending updates of a node down to its children: first the addition, then the clamping of the
+    /// children's extremes to the node's own.
+    fn push(&mut self, idx: usize, range: &Range<usize>) {
+        let mid = range.start + (range.end - range.start) / 2;
+        let node = self.nodes[idx];
+        for (child, len) in [(2 * idx, mid - range.start), (2 * idx + 1, range.end - mid)] {
+            if node.add != 0 {
+                self.nodes[child].apply_add(node.add, len);
+            }
+            self.nodes[child].apply_chmin(node.max);
+            self.nodes[child].apply_chmax(node.min);
+        }
+        self.nodes[idx].add = 0;
+    }
+
+    fn check_range(&self, range: &Range<usize>) {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "range out of bounds"
+        );
+    }
+
+    /// Sets every `a[i]` in `range` to `min(a[i], x)`.
+    pub fn chmin(&mut self, range: Range<usize>, x: i64) {
+        self.check_range(&range);
+        self.chmin_recursive(1, 0..self.len, &range, x);
+    }
+
+    fn chmin_recursive(
+        &mut self,
+        idx: usize,
+        element_range: Range<usize>,
+        target_range: &Range<usize>,
+        x: i64,
+    ) {
+        if element_range.start >= target_range.end
+            || element_range.end <= target_range.start
+            || self.nodes[idx].max <= x
+        {
+            return;
+        }
+        if element_range.start >= target_range.start
+            && element_range.end <= target_range.end
+            && self.nodes[idx].second_max < x
+        {
+            self.nodes[idx].apply_chmin(x);
+            return;
+        }
+        self.push(idx, &element_range);
+        let mid = element_range.start + (element_range.end - element_range.start) / 2;
+        self.chmin_recursive(idx * 2, element_range.start..mid, target_range, x);
+        self.chmin_recursive(idx * 2 + 1, mid..element_range.end, target_range, x);
+        self.pull(idx);
+    }
+
+    /// Sets every `a[i]` in `range` to `max(a[i], x)`.
+    pub fn chmax(&mut self, range: Range<usize>, x: i64) {
+        self.check_range(&range);
+        self.chmax_recursive(1, 0..self.len, &range, x);
+    }
+
+    fn chmax_recursive(
+        &mut self,
+        idx: usize,
+        element_range: Range<usize>,
+        target_range: &Range<usize>,
+        x: i64,
+    ) {
+        if element_range.start >= target_range.end
+            || element_range.end <= target_range.start
+            || self.nodes[idx].min >= x
+        {
+            return;
+        }
+        if element_range.start >= target_range.start
+            && element_range.end <= target_range.end
+            && self.nodes[idx].second_min > x
+        {
+            self.nodes[idx].apply_chmax(x);
+            return;
+        }
+        self.push(idx, &element_range);
+        let mid = element_range.start + (element_range.end - element_range.start) / 2;
+        self.chmax_recursive(idx * 2, element_range.start..mid, target_range, x);
+        self.chmax_recursive(idx * 2 + 1, mid..element_range.end, target_range, x);
+        self.pull(idx);
+    }
+
+    /// Adds `x` to every value in `range`.
+    pub fn add(&mut self, range: Range<usize>, x: i64) {
+        self.check_range(&range);
+        self.add_recursive(1, 0..self.len, &range, x);
+    }
+
+    fn add_recursive(
+        &mut self,
+        idx: usize,
+        element_range: Range<usize>,
+        target_range: &Range<usize>,
+        x: i64,
+    ) {
+        if element_range.start >= target_range.end || element_range.end <= target_range.start {
+            return;
+        }
+        if element_range.start >= target_range.start && element_range.end <= target_range.end {
+            self.nodes[idx].apply_add(x, element_range.end - element_range.start);
+            return;
+        }
+        self.push(idx, &element_range);
+        let mid = element_range.start + (element_range.end - element_range.start) / 2;
+        self.add_recursive(idx * 2, element_range.start..mid, target_range, x);
+        self.add_recursive(idx * 2 + 1, mid..element_range.end, target_range, x);
+        self.pull(idx);
+    }
+
+    /// Returns the node aggregating `range`, or `None` for an empty range.
+    fn query(&mut self, range: Range<usize>) -> Option<Node> {
+        self.check_range(&range);
+        self.query_recursive(1, 0..self.len, &range)
+    }
+
+    fn query_recursive(
+        &mut self,
+        idx: usize,
+        element_range: Range<usize>,
+        query_range: &Range<usize>,
+    ) -> Option<Node> {
+        if element_range.start >= query_range.end || element_range.end <= query_range.start {
+            return None;
+        }
+        if element_range.start >= query_range.start && element_range.end <= query_range.end {
+            return Some(self.nodes[idx]);
+        }
+        self.push(idx, &element_range);
+        let mid = element_range.start + (element_range.end - element_range.start) / 2;
+        let left = self.query_recursive(idx * 2, element_range.start..mid, query_range);
+        let right = self.query_recursive(idx * 2 + 1, mid..element_range.end, query_range);
+        match (left, right) {
+            (Some(l), Some(r)) => Some(Node::merge(&l, &r)),
+            (l, r) => l.or(r),
+        }
+    }
+
+    /// Sum of the values in `range`, 0 if it is empty.
+    pub fn sum(&mut self, range: Range<usize>) -> i64 {
+        self.query(range).map_or(0, |node| node.sum)
+    }
+
+    /// Largest value in `range`, `None` if it is empty.
+    pub fn max(&mut self, range: Range<usize>) -> Option<i64> {
+        self.query(range).map(|node| node.max)
+    }
+
+    /// Smallest value in `range`, `None` if it is empty.
+    pub fn min(&mut self, range: Range<usize>) -> Option<i64> {
+        self.query(range).map(|node| node.min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::TestResult;
+    use quickcheck_macros::quickcheck;
+
+    #[test]
+    fn chmin_chmax_add() {
+        let mut tree = SegmentTreeBeats::from(vec![1, 2, 3, 4, 5]);
+        assert_eq!(tree.sum(0..5), 15);
+        tree.chmin(0..5, 2);
+        // [1, 2, 2, 2, 2]
+        assert_eq!(tree.sum(0..5), 9);
+        assert_eq!(tree.max(1..5), Some(2));
+        tree.chmax(2..5, 3);
+        // [1, 2, 3, 3, 3]
+        assert_eq!(tree.sum(0..5), 12);
+        tree.add(0..2, 10);
+        // [11, 12, 3, 3, 3]
+        assert_eq!(tree.max(0..5), Some(12));
+        assert_eq!(tree.min(0..5), Some(3));
+        tree.chmin(1..4, 0);
+        // [11, 0, 0, 0, 3]
+        assert_eq!(tree.sum(0..5), 14);
+        assert_eq!(tree.sum(1..4), 0);
+        assert_eq!(tree.sum(2..2), 0);
+        assert_eq!(tree.max(2..2), None);
+    }
+
+    #[test]
+    fn empty_tree() {
+        let mut tree = SegmentTreeBeats::from(Vec::new());
+        assert!(tree.is_empty());
+        assert_eq!(tree.sum(0..0), 0);
+        tree.chmin(0..0, 3);
+    }
+
+    #[quickcheck]
+    fn check_against_naive(
+        array: Vec<i16>,
+        operations: Vec<(u8, usize, usize, i16)>,
+    ) -> TestResult {
+        if array.is_empty() {
+            return TestResult::discard();
+        }
+        let mut naive: Vec<i64> = array.iter().map(|&x| x as i64).collect();
+        let mut tree = SegmentTreeBeats::from(naive.clone());
+        for (kind, a, b, x) in operations {
+            let (a, b) = (a % (naive.len() + 1), b % (naive.len() + 1));
+            let range = a.min(b)..a.max(b);
+            let x = x as i64;
+            let slice = &mut naive[range.clone()];
+            match kind % 3 {
+                0 => {
+                    tree.chmin(range.clone(), x);
+                    slice.iter_mut().for_each(|v| *v = (*v).min(x));
+                }
+                1 => {
+                    tree.chmax(range.clone(), x);
+                    slice.iter_mut().for_each(|v| *v = (*v).max(x));
+                }
+                _ => {
+                    tree.add(range.clone(), x);
+                    slice.iter_mut().for_each(|v| *v += x);
+                }
+            }
+            let slice = &naive[range.clone()];
+            if tree.sum(range.clone()) != slice.iter().sum::<i64>()
+                || tree.max(range.clone()) != slice.iter().max().copied()
+                || tree.min(range.clone()) != slice.iter().min().copied()
+                || tree.sum(0..naive.len()) != naive.iter().sum::<i64>()
+            {
+                return TestResult::failed();
+            }
+        }
+        TestResult::passed()
+    }
+}
@@ -1,48 +1,54 @@
-use std::fmt::Debug;
+use super::Monoid;
 use std::ops::Range;
 
-pub struct SegmentTree<T: Debug + Default + Ord + Copy> {
-    len: usize,           // length of the represented
-    tree: Vec<T>, // represents a binary tree of intervals as an array (as a BinaryHeap does, for instance)
-    merge: fn(T, T) -> T, // how we merge two values together
+/// The same structure as [`SegmentTree`](super::SegmentTree), written top-down.
+///
+/// It shares the array of nodes of the bottom-up tree, and how it is built: only the walks differ. Every operation
+/// starts at the root, which covers the whole array, and recurses into the halves that intersect the range of interest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentTree<T: Monoid> {
+    nodes: super::SegmentTree<T>,
 }
 
-impl<T: Debug + Default + Ord + Copy> SegmentTree<T> {
-    pub fn from_vec(arr: &[T], merge: fn(T, T) -> T) -> Self {
-        let len = arr.len();
-        let mut sgtr = SegmentTree {
-            len,
-            tree: vec![T::default(); 4 * len],
-            merge,
-        };
-        if len != 0 {
-            sgtr.build_recursive(arr, 1, 0..len, merge);
+impl<T: Monoid> From<Vec<T>> for SegmentTree<T> {
+    fn from(arr: Vec<T>) -> Self {
+        SegmentTree {
+            nodes: super::SegmentTree::from(arr),
         }
-        sgtr
     }
+}
 
-    fn build_recursive(
-        &mut self,
-        arr: &[T],
-        idx: usize,
-        range: Range<usize>,
-        merge: fn(T, T) -> T,
-    ) {
-        if range.end - range.start == 1 {
-            self.tree[idx] = arr[range.start];
-        } else {
-            let mid = range.start + (range.end - range.start) / 2;
-            self.build_recursive(arr, 2 * idx, range.start..mid, merge);
-            self.build_recursive(arr, 2 * idx + 1, mid..range.end, merge);
-            self.tree[idx] = merge(self.tree[2 * idx], self.tree[2 * idx + 1]);
-        }
+impl<T: Monoid> SegmentTree<T> {
+    pub fn new(len: usize) -> Self {
+        Self::from(vec![T::identity(); len])
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the value at index `idx` in the original array.
+    pub fn get(&self, idx: usize) -> &T {
+        self.nodes.get(idx)
+    }
+
+    /// The range covered by the root: the array, padded with identities up to a power of two.
+    fn root_range(&self) -> Range<usize> {
+        0..self.nodes.leaves()
     }
 
     /// Query the range (exclusive)
     /// returns None if the range is out of the array's boundaries (eg: if start is after the end of the array, or start > end, etc.)
-    /// return the aggregate of values over this range otherwise
+    /// return the aggregate of values over this range otherwise, from left to right, or the identity for an empty range.
     pub fn query(&self, range: Range<usize>) -> Option<T> {
-        self.query_recursive(1, 0..self.len, &range)
+        if !self.nodes.in_bounds(&range) {
+            return None;
+        }
+        Some(self.query_recursive(1, self.root_range(), &range))
     }
 
     fn query_recursive(
@@ -50,27 +56,23 @@ impl<T: Debug + Default + Ord + Copy> SegmentTree<T> {
         idx: usize,
         element_range: Range<usize>,
         query_range: &Range<usize>,
-    ) -> Option<T> {
+    ) -> T {
         if element_range.start >= query_range.end || element_range.end <= query_range.start {
-            return None;
+            return T::identity();
         }
         if element_range.start >= query_range.start && element_range.end <= query_range.end {
-            return Some(self.tree[idx]);
+            return self.nodes.node(idx).clone();
         }
         let mid = element_range.start + (element_range.end - element_range.start) / 2;
         let left = self.query_recursive(idx * 2, element_range.start..mid, query_range);
         let right = self.query_recursive(idx * 2 + 1, mid..element_range.end, query_range);
-        match (left, right) {
-            (None, None) => None,
-            (None, Some(r)) => Some(r),
-            (Some(l), None) => Some(l),
-            (Some(l), Some(r)) => Some((self.merge)(l, r)),
-        }
+        left.combine(&right)
     }
 
-    /// Updates the value at index `idx` in the original array with a new value `val`
+    /// Updates the value at index `idx` in the original array with a new value `val`.
     pub fn update(&mut self, idx: usize, val: T) {
-        self.update_recursive(1, 0..self.len, idx, val);
+        assert!(idx < self.len(), "index out of bounds");
+        self.update_recursive(1, self.root_range(), idx, val);
     }
 
     fn update_recursive(
@@ -80,100 +82,223 @@ impl<T: Debug + Default + Ord + Copy> SegmentTree<T> {
         target_idx: usize,
         val: T,
     ) {
-        println!("{element_range:?}");
-        if element_range.start > target_idx || element_range.end <= target_idx {
+        if element_range.end - element_range.start == 1 {
+            *self.nodes.node_mut(idx) = val;
             return;
         }
-        if element_range.end - element_range.start <= 1 && element_range.start == target_idx {
-            println!("{element_range:?}");
-            self.tree[idx] = val;
-            return;
+        let mid = element_range.start + (element_range.end - element_range.start) / 2;
+        if target_idx < mid {
+            self.update_recursive(idx * 2, element_range.start..mid, target_idx, val);
+        } else {
+            self.update_recursive(idx * 2 + 1, mid..element_range.end, target_idx, val);
+        }
+        *self.nodes.node_mut(idx) = self
+            .nodes
+            .node(idx * 2)
+            .combine(self.nodes.node(idx * 2 + 1));
+    }
+
+    /// Returns the largest `r` such that `pred(query(l..r))` holds, i.e. the first index where the predicate fails.
+    /// `pred` must hold for the identity, and must be monotone: once it fails for a range, it fails for every longer one.
+    pub fn max_right<P: Fn(&T) -> bool>(&self, l: usize, pred: P) -> usize {
+        assert!(l <= self.len(), "index out of bounds");
+        let mut acc = T::identity();
+        // the padding holds identities, on which the predicate keeps holding, so it is never where the predicate fails
+        self.max_right_recursive(1, self.root_range(), l, &pred, &mut acc)
+            .unwrap_or(self.len())
+    }
+
+    /// Returns the index where the predicate fails inside `element_range`, if any, after extending `acc` with
+    /// all the elements from `l` that come before it.
+    fn max_right_recursive<P: Fn(&T) -> bool>(
+        &self,
+        idx: usize,
+        element_range: Range<usize>,
+        l: usize,
+        pred: &P,
+        acc: &mut T,
+    ) -> Option<usize> {
+        if element_range.end <= l {
+            return None;
+        }
+        if element_range.start >= l {
+            let next = acc.combine(self.nodes.node(idx));
+            if pred(&next) {
+                // the whole node fits
+                *acc = next;
+                return None;
+            }
+            if element_range.end - element_range.start == 1 {
+                return Some(element_range.start);
+            }
+        }
+        let mid = element_range.start + (element_range.end - element_range.start) / 2;
+        self.max_right_recursive(idx * 2, element_range.start..mid, l, pred, acc)
+            .or_else(|| self.max_right_recursive(idx * 2 + 1, mid..element_range.end, l, pred, acc))
+    }
+
+    /// Returns the smallest `l` such that `pred(query(l..r))` holds.
+    /// `pred` must hold for the identity, and must be monotone: once it fails for a range, it fails for every longer one.
+    pub fn min_left<P: Fn(&T) -> bool>(&self, r: usize, pred: P) -> usize {
+        assert!(r <= self.len(), "index out of bounds");
+        let mut acc = T::identity();
+        self.min_left_recursive(1, self.root_range(), r, &pred, &mut acc)
+            .map_or(0, |failing| failing + 1)
+    }
+
+    fn min_left_recursive<P: Fn(&T) -> bool>(
+        &self,
+        idx: usize,
+        element_range: Range<usize>,
+        r: usize,
+        pred: &P,
+        acc: &mut T,
+    ) -> Option<usize> {
+        if element_range.start >= r {
+            return None;
+        }
+        if element_range.end <= r {
+            let next = self.nodes.node(idx).combine(acc);
+            if pred(&next) {
+                *acc = next;
+                return None;
+            }
+            if element_range.end - element_range.start == 1 {
+                return Some(element_range.start);
+            }
         }
         let mid = element_range.start + (element_range.end - element_range.start) / 2;
-        self.update_recursive(idx * 2, element_range.start..mid, target_idx, val);
-        self.update_recursive(idx * 2 + 1, mid..element_range.end, target_idx, val);
-        self.tree[idx] = (self.merge)(self.tree[idx * 2], self.tree[idx * 2 + 1]);
+        self.min_left_recursive(idx * 2 + 1, mid..element_range.end, r, pred, acc)
+            .or_else(|| self.min_left_recursive(idx * 2, element_range.start..mid, r, pred, acc))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::{Max, Min, Sum};
     use super::*;
     use quickcheck::TestResult;
     use quickcheck_macros::quickcheck;
-    use std::cmp::{max, min};
+
+    fn min_tree(vec: &[i32]) -> SegmentTree<Min<i32>> {
+        SegmentTree::from(vec.iter().copied().map(Min).collect::<Vec<_>>())
+    }
+
+    fn max_tree(vec: &[i32]) -> SegmentTree<Max<i32>> {
+        SegmentTree::from(vec.iter().copied().map(Max).collect::<Vec<_>>())
+    }
+
+    fn sum_tree(vec: &[i32]) -> SegmentTree<Sum<i64>> {
+        SegmentTree::from(vec.iter().map(|&x| Sum(x as i64)).collect::<Vec<_>>())
+    }
 
     #[test]
     fn test_min_segments() {
         let vec = vec![-30, 2, -4, 7, 3, -5, 6, 11, -20, 9, 14, 15, 5, 2, -8];
-        let min_seg_tree = SegmentTree::from_vec(&vec, min);
-        assert_eq!(Some(-5), min_seg_tree.query(4..7));
-        assert_eq!(Some(-30), min_seg_tree.query(0..vec.len()));
-        assert_eq!(Some(-30), min_seg_tree.query(0..2));
-        assert_eq!(Some(-4), min_seg_tree.query(1..3));
-        assert_eq!(Some(-5), min_seg_tree.query(1..7));
+        let min_seg_tree = min_tree(&vec);
+        assert_eq!(Some(Min(-5)), min_seg_tree.query(4..7));
+        assert_eq!(Some(Min(-30)), min_seg_tree.query(0..vec.len()));
+        assert_eq!(Some(Min(-30)), min_seg_tree.query(0..2));
+        assert_eq!(Some(Min(-4)), min_seg_tree.query(1..3));
+        assert_eq!(Some(Min(-5)), min_seg_tree.query(1..7));
     }
 
     #[test]
     fn test_max_segments() {
         let val_at_6 = 6;
         let vec = vec![1, 2, -4, 7, 3, -5, val_at_6, 11, -20, 9, 14, 15, 5, 2, -8];
-        let mut max_seg_tree = SegmentTree::from_vec(&vec, max);
-        assert_eq!(Some(15), max_seg_tree.query(0..vec.len()));
+        let mut max_seg_tree = max_tree(&vec);
+        assert_eq!(Some(Max(15)), max_seg_tree.query(0..vec.len()));
         let max_4_to_6 = 6;
-        assert_eq!(Some(max_4_to_6), max_seg_tree.query(4..7));
+        assert_eq!(Some(Max(max_4_to_6)), max_seg_tree.query(4..7));
         let delta = 2;
-        max_seg_tree.update(6, val_at_6 + delta);
-        assert_eq!(Some(val_at_6 + delta), max_seg_tree.query(4..7));
+        max_seg_tree.update(6, Max(val_at_6 + delta));
+        assert_eq!(Some(Max(val_at_6 + delta)), max_seg_tree.query(4..7));
     }
 
     #[test]
     fn test_sum_segments() {
         let val_at_6 = 6;
         let vec = vec![1, 2, -4, 7, 3, -5, val_at_6, 11, -20, 9, 14, 15, 5, 2, -8];
-        let mut sum_seg_tree = SegmentTree::from_vec(&vec, |a, b| a + b);
+        let mut sum_seg_tree = sum_tree(&vec);
         for (i, val) in vec.iter().enumerate() {
-            assert_eq!(Some(*val), sum_seg_tree.query(i..(i + 1)));
+            assert_eq!(Some(Sum(*val as i64)), sum_seg_tree.query(i..(i + 1)));
         }
-        let sum_4_to_6 = sum_seg_tree.query(4..7);
-        assert_eq!(Some(4), sum_4_to_6);
+        let sum_4_to_6 = sum_seg_tree.query(4..7).unwrap();
+        assert_eq!(Sum(4), sum_4_to_6);
         let delta = 3;
-        sum_seg_tree.update(6, val_at_6 + delta);
+        sum_seg_tree.update(6, Sum((val_at_6 + delta) as i64));
         assert_eq!(
-            sum_4_to_6.unwrap() + delta,
-            sum_seg_tree.query(4..7).unwrap()
+            sum_4_to_6.0 + delta as i64,
+            sum_seg_tree.query(4..7).unwrap().0
         );
     }
 
+    #[test]
+    fn test_non_commutative() {
+        let words: Vec<String> = "segment".chars().map(String::from).collect();
+        let mut tree = SegmentTree::from(words);
+        assert_eq!(tree.query(0..7).unwrap(), "segment");
+        assert_eq!(tree.query(2..5).unwrap(), "gme");
+        tree.update(0, "fr".to_string());
+        assert_eq!(tree.query(0..3).unwrap(), "freg");
+    }
+
+    #[test]
+    fn test_max_right_min_left() {
+        let tree = sum_tree(&[3, 1, 4, 1, 5, 9, 2, 6, 5, 3]);
+        assert_eq!(tree.max_right(2, |s| s.0 <= 10), 5);
+        assert_eq!(tree.max_right(0, |s| s.0 <= 100), 10);
+        assert_eq!(tree.min_left(7, |s| s.0 <= 11), 5);
+        assert_eq!(tree.min_left(10, |s| s.0 <= 100), 0);
+        assert_eq!(SegmentTree::<Sum<i32>>::new(0).max_right(0, |_| true), 0);
+        assert_eq!(tree.query(8..11), None);
+        assert_eq!(*tree.get(4), Sum(5));
+    }
+
     // Some properties over segment trees:
     //  When asking for the range of the overall array, return the same as iter().min() or iter().max(), etc.
     //  When asking for an interval containing a single value, return this value, no matter the merge function
+    //  It behaves the same as the bottom-up implementation
 
     #[quickcheck]
     fn check_overall_interval_min(array: Vec<i32>) -> TestResult {
-        let seg_tree = SegmentTree::from_vec(&array, min);
-        TestResult::from_bool(array.iter().min().copied() == seg_tree.query(0..array.len()))
+        let seg_tree = min_tree(&array);
+        TestResult::from_bool(
+            array.iter().min().copied().unwrap_or(i32::MAX)
+                == seg_tree.query(0..array.len()).unwrap().0,
+        )
     }
 
     #[quickcheck]
     fn check_overall_interval_max(array: Vec<i32>) -> TestResult {
-        let seg_tree = SegmentTree::from_vec(&array, max);
-        TestResult::from_bool(array.iter().max().copied() == seg_tree.query(0..array.len()))
+        let seg_tree = max_tree(&array);
+        TestResult::from_bool(
+            array.iter().max().copied().unwrap_or(i32::MIN)
+                == seg_tree.query(0..array.len()).unwrap().0,
+        )
     }
 
     #[quickcheck]
     fn check_overall_interval_sum(array: Vec<i32>) -> TestResult {
-        let seg_tree = SegmentTree::from_vec(&array, max);
-        TestResult::from_bool(array.iter().max().copied() == seg_tree.query(0..array.len()))
+        let seg_tree = sum_tree(&array);
+        TestResult::from_bool(
+            array.iter().map(|&x| x as i64).sum::<i64>()
+                == seg_tree.query(0..array.len()).unwrap().0,
+        )
     }
 
     #[quickcheck]
     fn check_single_interval_min(array: Vec<i32>) -> TestResult {
-        let seg_tree = SegmentTree::from_vec(&array, min);
+        let seg_tree = min_tree(&array);
         for (i, value) in array.into_iter().enumerate() {
             let res = seg_tree.query(i..(i + 1));
-            if res != Some(value) {
-                return TestResult::error(format!("Expected {:?}, got {:?}", Some(value), res));
+            if res != Some(Min(value)) {
+                return TestResult::error(format!(
+                    "Expected {:?}, got {:?}",
+                    Some(Min(value)),
+                    res
+                ));
             }
         }
         TestResult::passed()
@@ -181,11 +306,15 @@ mod tests {
 
     #[quickcheck]
     fn check_single_interval_max(array: Vec<i32>) -> TestResult {
-        let seg_tree = SegmentTree::from_vec(&array, max);
+        let seg_tree = max_tree(&array);
         for (i, value) in array.into_iter().enumerate() {
             let res = seg_tree.query(i..(i + 1));
-            if res != Some(value) {
-                return TestResult::error(format!("Expected {:?}, got {:?}", Some(value), res));
+            if res != Some(Max(value)) {
+                return TestResult::error(format!(
+                    "Expected {:?}, got {:?}",
+                    Some(Max(value)),
+                    res
+                ));
             }
         }
         TestResult::passed()
@@ -193,11 +322,34 @@ mod tests {
 
     #[quickcheck]
     fn check_single_interval_sum(array: Vec<i32>) -> TestResult {
-        let seg_tree = SegmentTree::from_vec(&array, max);
+        let seg_tree = sum_tree(&array);
         for (i, value) in array.into_iter().enumerate() {
             let res = seg_tree.query(i..(i + 1));
-            if res != Some(value) {
-                return TestResult::error(format!("Expected {:?}, got {:?}", Some(value), res));
+            if res != Some(Sum(value as i64)) {
+                return TestResult::error(format!(
+                    "Expected {:?}, got {:?}",
+                    Some(Sum(value as i64)),
+                    res
+                ));
+            }
+        }
+        TestResult::passed()
+    }
+
+    #[quickcheck]
+    fn check_same_as_bottom_up(array: Vec<u8>, bound: u16) -> TestResult {
+        let values: Vec<Sum<u32>> = array.iter().map(|&x| Sum(x as u32)).collect();
+        let recursive = SegmentTree::from(values.clone());
+        let bottom_up = super::super::SegmentTree::from(values);
+        let bound = bound as u32 % 1000;
+        for i in 0..=array.len() {
+            if recursive.query(i..array.len()) != bottom_up.query(i..array.len())
+                || recursive.max_right(i, |s| s.0 <= bound)
+                    != bottom_up.max_right(i, |s| s.0 <= bound)
+                || recursive.min_left(i, |s| s.0 <= bound)
+                    != bottom_up.min_left(i, |s| s.0 <= bound)
+            {
+                return TestResult::failed();
             }
         }
         TestResult::passed()
@@ -0,0 +1,379 @@
+use rand::{rngs::StdRng, Rng, SeedableRng as _};
+use std::{
+    cmp::Ordering,
+    fmt::{self, Debug},
+    iter::FromIterator,
+    mem,
+    ops::{Bound, RangeBounds},
+};
+
+/// The most levels a node can have: enough for `2^32` keys with probability 1/2.
+pub(super) const MAX_LEVEL: usize = 32;
+
+/// The index of the missing node past the end of every level.
+const NIL: usize = usize::MAX;
+
+/// The index of the head node, which holds no entry and links the first node of every level.
+const HEAD: usize = 0;
+
+/// A node of a `SkipList`: the head, a live entry, or a free slot.
+struct Node<K, V> {
+    entry: Option<(K, V)>,
+    /// The next node of every level the node is on.
+    next: Vec<usize>,
+}
+
+/// An ordered map based on a skip list <https://en.wikipedia.org/wiki/Skip_list>.
+///
+/// All entries are in a sorted linked list, and every node is also on the next level with probability `p`: the level
+/// above skips about `1 / p` nodes at a time. A search starts on the highest level, and goes down a level when the
+/// next key is too large, which takes `O(log n)` expected steps for any keys: the levels are random, not derived from
+/// the keys. The levels are drawn from a seeded generator, so that a list built the same way has the same shape.
+pub struct SkipList<K, V> {
+    nodes: Vec<Node<K, V>>,
+    free: Vec<usize>,
+    len: usize,
+    /// The number of levels in use.
+    height: usize,
+    probability: f64,
+    rng: StdRng,
+}
+
+/// Draws the number of levels of a new node: every level is kept with probability `probability`.
+pub(super) fn random_level<R: Rng>(rng: &mut R, probability: f64) -> usize {
+    let mut level = 1;
+    while level < MAX_LEVEL && rng.gen_bool(probability) {
+        level += 1;
+    }
+    level
+}
+
+impl<K: Ord, V> SkipList<K, V> {
+    /// Creates a list where every node is on the next level with probability 1/2.
+    pub fn new() -> Self {
+        Self::with_parameters(0.5, 0)
+    }
+
+    /// Creates a list where every node is on the next level with probability `probability`, between 0 and 1
+    /// exclusive, and whose levels are drawn from a generator seeded with `seed`.
+    ///
+    /// A lower probability makes fewer levels, which saves links but takes more steps per search.
+    pub fn with_parameters(probability: f64, seed: u64) -> Self {
+        assert!(
+            probability > 0.0 && probability < 1.0,
+            "the probability must be between 0 and 1"
+        );
+        SkipList {
+            nodes: vec![Node {
+                entry: None,
+                next: vec![NIL; MAX_LEVEL],
+            }],
+            free: Vec::new(),
+            len: 0,
+            height: 1,
+            probability,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of levels in use.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn key(&self, node: usize) -> &K {
+        &self.nodes[node].entry.as_ref().unwrap().0
+    }
+
+    /// Returns the last node on every level whose key is less than `key`, or at most `key` if `inclusive`.
+    fn predecessors(&self, key: &K, inclusive: bool) -> [usize; MAX_LEVEL] {
+        let mut predecessors = [HEAD; MAX_LEVEL];
+        let mut node = HEAD;
+        for level in (0..self.height).rev() {
+            loop {
+                let next = self.nodes[node].next[level];
+                if next == NIL {
+                    break;
+                }
+                match self.key(next).cmp(key) {
+                    Ordering::Less => node = next,
+                    Ordering::Equal if inclusive => node = next,
+                    _ => break,
+                }
+            }
+            predecessors[level] = node;
+        }
+        predecessors
+    }
+
+    /// Returns the node of a key.
+    fn find(&self, key: &K) -> Option<usize> {
+        let node = self.nodes[self.predecessors(key, false)[0]].next[0];
+        (node != NIL && self.key(node) == key).then_some(node)
+    }
+
+    /// Returns `true` if the list contains a key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Returns a reference to the value stored under a key.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let node = self.find(key)?;
+        self.nodes[node].entry.as_ref().map(|(_, value)| value)
+    }
+
+    /// Returns a mutable reference to the value stored under a key.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let node = self.find(key)?;
+        self.nodes[node].entry.as_mut().map(|(_, value)| value)
+    }
+
+    /// Inserts a key-value pair, and returns the previous value stored under the key.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let predecessors = self.predecessors(&key, false);
+        let next = self.nodes[predecessors[0]].next[0];
+        if next != NIL && *self.key(next) == key {
+            let (_, old) = self.nodes[next].entry.as_mut().unwrap();
+            return Some(mem::replace(old, value));
+        }
+
+        let level = random_level(&mut self.rng, self.probability);
+        // the predecessors on the levels above the height are the head
+        let next = (0..level)
+            .map(|i| self.nodes[predecessors[i]].next[i])
+            .collect();
+        let node = Node {
+            entry: Some((key, value)),
+            next,
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        for (level, &predecessor) in predecessors[..level].iter().enumerate() {
+            self.nodes[predecessor].next[level] = index;
+        }
+        self.height = self.height.max(level);
+        self.len += 1;
+        None
+    }
+
+    /// Removes a key from the list, and returns its value.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let predecessors = self.predecessors(key, false);
+        let node = self.nodes[predecessors[0]].next[0];
+        if node == NIL || self.key(node) != key {
+            return None;
+        }
+        let next = mem::take(&mut self.nodes[node].next);
+        for (level, next) in next.into_iter().enumerate() {
+            self.nodes[predecessors[level]].next[level] = next;
+        }
+        while self.height > 1 && self.nodes[HEAD].next[self.height - 1] == NIL {
+            self.height -= 1;
+        }
+        self.free.push(node);
+        self.len -= 1;
+        self.nodes[node].entry.take().map(|(_, value)| value)
+    }
+
+    /// Returns the entry with the smallest key.
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.iter().next()
+    }
+
+    /// Returns an iterator that visits the entries in ascending order of keys.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            list: self,
+            node: self.nodes[HEAD].next[0],
+            end: NIL,
+        }
+    }
+
+    /// Returns an iterator over the entries whose keys lie in `range`, in ascending order.
+    ///
+    /// A range whose start lies after its end is empty.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, K, V> {
+        let first_after =
+            |key: &K, inclusive: bool| self.nodes[self.predecessors(key, inclusive)[0]].next[0];
+        let start = match range.start_bound() {
+            Bound::Included(key) => first_after(key, false),
+            Bound::Excluded(key) => first_after(key, true),
+            Bound::Unbounded => self.nodes[HEAD].next[0],
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => first_after(key, true),
+            Bound::Excluded(key) => first_after(key, false),
+            Bound::Unbounded => NIL,
+        };
+        // the end node comes before the start node when the start lies after the end
+        let empty = start == NIL || (end != NIL && self.key(start) > self.key(end));
+        Iter {
+            list: self,
+            node: if empty { end } else { start },
+            end,
+        }
+    }
+}
+
+/// An iterator over the entries of a `SkipList`, in ascending order of keys.
+pub struct Iter<'a, K, V> {
+    list: &'a SkipList<K, V>,
+    node: usize,
+    /// The first node not to visit.
+    end: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.node == self.end {
+            return None;
+        }
+        let node = &self.list.nodes[self.node];
+        self.node = node.next[0];
+        node.entry.as_ref().map(|(key, value)| (key, value))
+    }
+}
+
+impl<'a, K: Ord, V> IntoIterator for &'a SkipList<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: Ord, V> Default for SkipList<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for SkipList<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut list = SkipList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for SkipList<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: Ord + Debug, V: Debug> Debug for SkipList<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SkipList;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+
+    #[test]
+    fn inserts_and_removes() {
+        let mut list = SkipList::new();
+        assert_eq!(list.insert(3, "c"), None);
+        assert_eq!(list.insert(1, "a"), None);
+        assert_eq!(list.insert(2, "b"), None);
+        assert_eq!(list.insert(2, "B"), Some("b"));
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.get(&2), Some(&"B"));
+        *list.get_mut(&3).unwrap() = "C";
+        assert_eq!(list.first(), Some((&1, &"a")));
+        assert_eq!(list.remove(&1), Some("a"));
+        assert_eq!(list.remove(&1), None);
+        assert!(!list.contains_key(&1));
+        assert_eq!(format!("{list:?}"), r#"{2: "B", 3: "C"}"#);
+    }
+
+    #[test]
+    fn ranges() {
+        let list: SkipList<i32, i32> = (0..100).map(|i| (i * 2, i)).collect();
+        let keys = |range: (Bound<i32>, Bound<i32>)| -> Vec<i32> {
+            list.range(range).map(|(k, _)| *k).collect()
+        };
+        assert_eq!(
+            list.range(10..16).map(|(k, _)| *k).collect::<Vec<_>>(),
+            [10, 12, 14]
+        );
+        assert_eq!(
+            list.range(11..=16).map(|(k, _)| *k).collect::<Vec<_>>(),
+            [12, 14, 16]
+        );
+        assert_eq!(keys((Bound::Excluded(10), Bound::Included(14))), [12, 14]);
+        assert_eq!(
+            list.range(195..).map(|(k, _)| *k).collect::<Vec<_>>(),
+            [196, 198]
+        );
+        assert_eq!(list.range(..3).count(), 2);
+        assert_eq!(list.range(..).count(), 100);
+        assert_eq!(list.range(500..).count(), 0);
+        assert_eq!(keys((Bound::Included(20), Bound::Excluded(10))), []);
+        assert_eq!(keys((Bound::Excluded(10), Bound::Excluded(12))), []);
+    }
+
+    #[test]
+    fn same_seed_same_shape() {
+        let build = |seed| {
+            let mut list = SkipList::with_parameters(0.25, seed);
+            for i in 0..1000 {
+                list.insert(i, ());
+            }
+            list
+        };
+        let levels = |list: &SkipList<i32, ()>| -> Vec<usize> {
+            list.nodes.iter().map(|n| n.next.len()).collect()
+        };
+        assert_eq!(levels(&build(7)), levels(&build(7)));
+        assert_ne!(levels(&build(7)), levels(&build(8)));
+        // with p = 1/4, about log4(1000) = 5 levels
+        assert!((3..=10).contains(&build(7).height()));
+    }
+
+    #[test]
+    fn matches_a_btree_map() {
+        let mut list = SkipList::with_parameters(0.5, 42);
+        let mut expected = BTreeMap::new();
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..5000 {
+            let key = rng.gen_range(0..500);
+            if rng.gen_ratio(1, 3) {
+                assert_eq!(list.remove(&key), expected.remove(&key));
+            } else {
+                let value: u32 = rng.gen();
+                assert_eq!(list.insert(key, value), expected.insert(key, value));
+            }
+            assert_eq!(list.len(), expected.len());
+        }
+        assert!(list.iter().eq(expected.iter()));
+        assert!(list.range(100..300).eq(expected.range(100..300)));
+    }
+}
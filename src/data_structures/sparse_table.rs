@@ -0,0 +1,351 @@
+//! Sparse tables answer range queries over a static array in O(1) after an
+//! O(n log n) precomputation.
+//!
+//! [`SparseTable`] stores, for every power of two `2^k` and every start `i`,
+//! the aggregate of `a[i..i + 2^k]`. Any range is covered by two such blocks,
+//! which may overlap, so the operation has to be idempotent (`op(x, x) == x`),
+//! like min, max, gcd or bitwise and/or.
+//!
+//! [`DisjointSparseTable`] lifts that restriction: on level `k`, the array is
+//! split into blocks of `2^(k+1)` elements, and each element stores the
+//! aggregate from itself to the middle of its block. A range `l..=r` is then
+//! split at the middle of the smallest block containing both ends, found from
+//! the highest bit of `l ^ r`, and the two halves never overlap. This works for
+//! any associative operation, such as sums, products or matrix multiplication.
+//!
+//! See also <https://en.wikipedia.org/wiki/Range_minimum_query>.
+
+use super::Monoid;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{BitAnd, BitOr, Range, Rem};
+
+/// An associative binary operation on `T`, chosen by a marker type so that
+/// tables built with different operations have different types.
+pub trait Operation<T> {
+    /// Must be associative: `op(a, op(b, c)) == op(op(a, b), c)`.
+    fn op(a: &T, b: &T) -> T;
+}
+
+/// An operation where combining a value with itself changes nothing.
+///
+/// This marker promises `op(x, x) == x`, which [`SparseTable`] relies on to
+/// answer a query with two overlapping blocks.
+pub trait Idempotent<T>: Operation<T> {}
+
+/// Minimum under `PartialOrd`; ties keep the left operand.
+#[derive(Debug, Clone, Copy)]
+pub struct MinOp;
+
+/// Maximum under `PartialOrd`; ties keep the left operand.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxOp;
+
+/// Greatest common divisor of non-negative integers.
+#[derive(Debug, Clone, Copy)]
+pub struct GcdOp;
+
+/// Bitwise and.
+#[derive(Debug, Clone, Copy)]
+pub struct BitAndOp;
+
+/// Bitwise or.
+#[derive(Debug, Clone, Copy)]
+pub struct BitOrOp;
+
+/// [`Monoid::combine`], for use with [`DisjointSparseTable`].
+#[derive(Debug, Clone, Copy)]
+pub struct Combine;
+
+impl<T: PartialOrd + Clone> Operation<T> for MinOp {
+    fn op(a: &T, b: &T) -> T {
+        if b < a {
+            b.clone()
+        } else {
+            a.clone()
+        }
+    }
+}
+
+impl<T: PartialOrd + Clone> Idempotent<T> for MinOp {}
+
+impl<T: PartialOrd + Clone> Operation<T> for MaxOp {
+    fn op(a: &T, b: &T) -> T {
+        if b > a {
+            b.clone()
+        } else {
+            a.clone()
+        }
+    }
+}
+
+impl<T: PartialOrd + Clone> Idempotent<T> for MaxOp {}
+
+impl<T: Rem<Output = T> + PartialEq + Default + Clone> Operation<T> for GcdOp {
+    fn op(a: &T, b: &T) -> T {
+        let (mut a, mut b) = (a.clone(), b.clone());
+        while b != T::default() {
+            let r = a % b.clone();
+            a = b;
+            b = r;
+        }
+        a
+    }
+}
+
+impl<T: Rem<Output = T> + PartialEq + Default + Clone> Idempotent<T> for GcdOp {}
+
+impl<T: BitAnd<Output = T> + Clone> Operation<T> for BitAndOp {
+    fn op(a: &T, b: &T) -> T {
+        a.clone() & b.clone()
+    }
+}
+
+impl<T: BitAnd<Output = T> + Clone> Idempotent<T> for BitAndOp {}
+
+impl<T: BitOr<Output = T> + Clone> Operation<T> for BitOrOp {
+    fn op(a: &T, b: &T) -> T {
+        a.clone() | b.clone()
+    }
+}
+
+impl<T: BitOr<Output = T> + Clone> Idempotent<T> for BitOrOp {}
+
+impl<T: Monoid> Operation<T> for Combine {
+    fn op(a: &T, b: &T) -> T {
+        a.combine(b)
+    }
+}
+
+/// Error returned for a query range that has no answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeError {
+    /// The range contains no elements, so there is nothing to aggregate.
+    Empty { start: usize, end: usize },
+    /// The range ends past the last element.
+    OutOfBounds { end: usize, len: usize },
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RangeError::Empty { start, end } => write!(f, "empty range {start}..{end}"),
+            RangeError::OutOfBounds { end, len } => {
+                write!(f, "range end {end} is out of bounds for length {len}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RangeError {}
+
+fn check_range(range: &Range<usize>, len: usize) -> Result<(), RangeError> {
+    if range.start >= range.end {
+        return Err(RangeError::Empty {
+            start: range.start,
+            end: range.end,
+        });
+    }
+    if range.end > len {
+        return Err(RangeError::OutOfBounds {
+            end: range.end,
+            len,
+        });
+    }
+    Ok(())
+}
+
+/// Range queries for an idempotent operation in O(1), using O(n log n) memory.
+#[derive(Debug, Clone)]
+pub struct SparseTable<T, Op> {
+    // table[k][i] is the aggregate of the 2^k elements starting at i
+    table: Vec<Vec<T>>,
+    op: PhantomData<Op>,
+}
+
+impl<T: Clone, Op: Idempotent<T>> SparseTable<T, Op> {
+    pub fn new(values: &[T]) -> Self {
+        let mut table = vec![values.to_vec()];
+        let mut half = 1;
+        while 2 * half <= values.len() {
+            let prev = &table[table.len() - 1];
+            let row = (0..=values.len() - 2 * half)
+                .map(|i| Op::op(&prev[i], &prev[i + half]))
+                .collect();
+            table.push(row);
+            half *= 2;
+        }
+        SparseTable {
+            table,
+            op: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.table[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Aggregate of the elements in `range`, from the two blocks of `2^k`
+    /// elements that start at `range.start` and end at `range.end`.
+    pub fn query(&self, range: Range<usize>) -> Result<T, RangeError> {
+        check_range(&range, self.len())?;
+        let k = (range.end - range.start).ilog2() as usize;
+        let row = &self.table[k];
+        Ok(Op::op(&row[range.start], &row[range.end - (1 << k)]))
+    }
+}
+
+/// Range queries for any associative operation in O(1), using O(n log n)
+/// memory.
+#[derive(Debug, Clone)]
+pub struct DisjointSparseTable<T, Op> {
+    values: Vec<T>,
+    // levels[k][i] aggregates from i to the middle of its block of 2^(k+1)
+    // elements: a[i..mid] on the left half, a[mid..=i] on the right half
+    levels: Vec<Vec<T>>,
+    op: PhantomData<Op>,
+}
+
+impl<T: Clone, Op: Operation<T>> DisjointSparseTable<T, Op> {
+    pub fn new(values: &[T]) -> Self {
+        let n = values.len();
+        let mut levels = Vec::new();
+        let mut half = 1;
+        while half < n {
+            let mut row = values.to_vec();
+            for start in (0..n).step_by(2 * half) {
+                let mid = start + half;
+                if mid >= n {
+                    // no query can cross this midpoint
+                    break;
+                }
+                for i in (start..mid - 1).rev() {
+                    row[i] = Op::op(&values[i], &row[i + 1]);
+                }
+                for i in mid + 1..n.min(mid + half) {
+                    row[i] = Op::op(&row[i - 1], &values[i]);
+                }
+            }
+            levels.push(row);
+            half *= 2;
+        }
+        DisjointSparseTable {
+            values: values.to_vec(),
+            levels,
+            op: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Aggregate of the elements in `range`, in order.
+    pub fn query(&self, range: Range<usize>) -> Result<T, RangeError> {
+        check_range(&range, self.len())?;
+        let (l, r) = (range.start, range.end - 1);
+        if l == r {
+            return Ok(self.values[l].clone());
+        }
+        let row = &self.levels[(l ^ r).ilog2() as usize];
+        Ok(Op::op(&row[l], &row[r]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_structures::{Max, Sum};
+    use quickcheck::TestResult;
+    use quickcheck_macros::quickcheck;
+
+    #[test]
+    fn idempotent_operations() {
+        let values = [12u32, 18, 6, 30, 45, 9, 27, 8];
+        let min = SparseTable::<_, MinOp>::new(&values);
+        let max = SparseTable::<_, MaxOp>::new(&values);
+        let gcd = SparseTable::<_, GcdOp>::new(&values);
+        let and = SparseTable::<_, BitAndOp>::new(&values);
+        let or = SparseTable::<_, BitOrOp>::new(&values);
+        assert_eq!(min.query(0..8), Ok(6));
+        assert_eq!(max.query(1..4), Ok(30));
+        assert_eq!(gcd.query(0..4), Ok(6));
+        assert_eq!(gcd.query(3..6), Ok(3));
+        assert_eq!(gcd.query(0..8), Ok(1));
+        assert_eq!(and.query(5..7), Ok(9 & 27));
+        assert_eq!(or.query(6..8), Ok(27 | 8));
+        assert_eq!(gcd.query(4..5), Ok(45));
+    }
+
+    #[test]
+    fn range_errors() {
+        let table = SparseTable::<_, MinOp>::new(&[3, 1, 2]);
+        assert_eq!(
+            table.query(2..2),
+            Err(RangeError::Empty { start: 2, end: 2 })
+        );
+        assert_eq!(
+            table.query(1..4),
+            Err(RangeError::OutOfBounds { end: 4, len: 3 })
+        );
+        assert_eq!(
+            RangeError::OutOfBounds { end: 4, len: 3 }.to_string(),
+            "range end 4 is out of bounds for length 3"
+        );
+        let empty = DisjointSparseTable::<Sum<i32>, Combine>::new(&[]);
+        assert!(empty.is_empty());
+        assert_eq!(
+            empty.query(0..1),
+            Err(RangeError::OutOfBounds { end: 1, len: 0 })
+        );
+    }
+
+    #[test]
+    fn disjoint_table_keeps_order() {
+        let words = ["a", "b", "c", "d", "e", "f", "g"].map(String::from);
+        let table = DisjointSparseTable::<_, Combine>::new(&words);
+        for start in 0..words.len() {
+            for end in start + 1..=words.len() {
+                assert_eq!(table.query(start..end), Ok(words[start..end].concat()));
+            }
+        }
+    }
+
+    #[test]
+    fn disjoint_table_with_monoids() {
+        let values: Vec<_> = [4, -2, 7, 1, -9, 3]
+            .iter()
+            .map(|&x| (Sum(x), Max(x)))
+            .collect();
+        let table = DisjointSparseTable::<_, Combine>::new(&values);
+        assert_eq!(table.query(1..5), Ok((Sum(-3), Max(7))));
+        assert_eq!(table.query(3..6), Ok((Sum(-5), Max(3))));
+    }
+
+    #[quickcheck]
+    fn tables_match_naive(values: Vec<i32>, start: usize, end: usize) -> TestResult {
+        if values.is_empty() {
+            return TestResult::discard();
+        }
+        let (start, end) = (start % values.len(), end % values.len() + 1);
+        if start >= end {
+            return TestResult::discard();
+        }
+        let values: Vec<i64> = values.into_iter().map(i64::from).collect();
+        let min = SparseTable::<_, MinOp>::new(&values);
+        let sums: Vec<_> = values.iter().map(|&x| Sum(x)).collect();
+        let sum = DisjointSparseTable::<_, Combine>::new(&sums);
+        TestResult::from_bool(
+            min.query(start..end) == Ok(*values[start..end].iter().min().unwrap())
+                && sum.query(start..end) == Ok(Sum(values[start..end].iter().sum())),
+        )
+    }
+}
@@ -2,26 +2,52 @@ use std::{
     cmp::Ordering,
     iter::FromIterator,
     mem,
-    ops::Not,
+    ops::{Bound, Not, RangeBounds},
+    sync::atomic::{AtomicU64, Ordering as AtomicOrdering},
     time::{SystemTime, UNIX_EPOCH},
 };
 
-/// An internal node of an `Treap`.
-struct TreapNode<T: Ord> {
-    value: T,
+/// An internal node of a `Treap`, or of another treap like an `ImplicitTreap`.
+#[derive(Clone)]
+pub(super) struct TreapNode<K: Ord, V, A = ()> {
+    key: K,
+    value: V,
     priority: usize,
-    left: Option<Box<TreapNode<T>>>,
-    right: Option<Box<TreapNode<T>>>,
+    /// The number of nodes in the subtree rooted here, for order statistics.
+    size: usize,
+    /// What the treap keeps about the subtree rooted here besides its size.
+    augment: A,
+    left: Link<K, V, A>,
+    right: Link<K, V, A>,
 }
 
-/// A set based on a Treap (Randomized Binary Search Tree).
+/// Data about a subtree that a node computes from its own entry and the data of its children,
+/// along with updates of the subtree that the node has not passed on to its children yet.
+///
+/// `split_at` and `merge` push the pending updates of every node they restructure before
+/// recomputing it, so `summarize` never sees updates pending at the node it summarizes.
+pub(super) trait Augment<K: Ord, V>: Sized {
+    fn summarize(key: &K, value: &V, left: Option<&Self>, right: Option<&Self>) -> Self;
+
+    /// Passes the updates pending at `node` on to its children.
+    fn push(_node: &mut TreapNode<K, V, Self>) {}
+}
+
+impl<K: Ord, V> Augment<K, V> for () {
+    fn summarize(_: &K, _: &V, _: Option<&Self>, _: Option<&Self>) -> Self {}
+}
+
+/// An ordered map based on a Treap (Randomized Binary Search Tree).
 ///
 /// A Treap is a self-balancing binary search tree. It matains a priority value for each node, such
 /// that for every node, its children will have lower priority than itself. So, by just looking at
 /// the priority, it is like a heap, and this is where the name, Treap, comes from, Tree + Heap.
-pub struct Treap<T: Ord> {
-    root: Option<Box<TreapNode<T>>>,
-    length: usize,
+///
+/// Because the shape of a treap only depends on its keys and priorities, two treaps whose keys do
+/// not interleave can be merged, and a treap can be split at a key, in O(log n) expected time.
+/// Every node also stores the size of its subtree for order statistics (`rank`, `select`).
+pub struct Treap<K: Ord, V> {
+    root: Option<Box<TreapNode<K, V>>>,
 }
 
 /// Refers to the left or right subtree of a `Treap`.
@@ -31,147 +57,487 @@ enum Side {
     Right,
 }
 
-impl<T: Ord> Treap<T> {
-    pub fn new() -> Treap<T> {
-        Treap {
-            root: None,
-            length: 0,
-        }
+impl<K: Ord, V> Treap<K, V> {
+    pub fn new() -> Treap<K, V> {
+        Treap { root: None }
+    }
+
+    /// Returns `true` if the tree contains a key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
     }
 
-    /// Returns `true` if the tree contains a value.
-    pub fn contains(&self, value: &T) -> bool {
+    /// Returns a reference to the value stored under a key.
+    pub fn get(&self, key: &K) -> Option<&V> {
         let mut current = &self.root;
         while let Some(node) = current {
-            current = match value.cmp(&node.value) {
-                Ordering::Equal => return true,
+            current = match key.cmp(&node.key) {
+                Ordering::Equal => return Some(&node.value),
                 Ordering::Less => &node.left,
                 Ordering::Greater => &node.right,
             }
         }
-        false
+        None
+    }
+
+    /// Returns a mutable reference to the value stored under a key.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let mut current = &mut self.root;
+        while let Some(node) = current {
+            current = match key.cmp(&node.key) {
+                Ordering::Equal => return Some(&mut node.value),
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => &mut node.right,
+            }
+        }
+        None
     }
 
-    /// Adds a value to the tree
+    /// Adds a key-value pair to the tree.
     ///
-    /// Returns `true` if the tree did not yet contain the value.
-    pub fn insert(&mut self, value: T) -> bool {
-        let inserted = insert(&mut self.root, value);
-        if inserted {
-            self.length += 1;
+    /// Returns the previous value if the tree already contained the key; the key itself is
+    /// not updated.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        insert(&mut self.root, key, value)
+    }
+
+    /// Removes a key from the tree.
+    ///
+    /// Returns the value that was stored under the key.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        remove(&mut self.root, key)
+    }
+
+    /// Gets the entry for a key, to inspect or update it in place.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        let index = self.rank(&key);
+        if self.select(index).is_some_and(|(k, _)| *k == key) {
+            Entry::Occupied(OccupiedEntry {
+                tree: self,
+                key,
+                index,
+            })
+        } else {
+            Entry::Vacant(VacantEntry {
+                tree: self,
+                key,
+                index,
+            })
         }
-        inserted
     }
 
-    /// Removes a value from the tree.
+    /// Splits the tree into the entries with keys less than `key`, and those with keys greater
+    /// than or equal to it.
+    pub fn split(self, key: &K) -> (Treap<K, V>, Treap<K, V>) {
+        let (left, right) = split(self.root, key);
+        (Treap { root: left }, Treap { root: right })
+    }
+
+    /// Joins two trees, where every key of `self` is less than every key of `other`.
+    ///
+    /// # Panics
     ///
-    /// Returns `true` if the tree contained the value.
-    pub fn remove(&mut self, value: &T) -> bool {
-        let removed = remove(&mut self.root, value);
-        if removed {
-            self.length -= 1;
+    /// Panics if the keys of the two trees interleave.
+    pub fn merge(self, other: Treap<K, V>) -> Treap<K, V> {
+        if let (Some((last, _)), Some((first, _))) = (self.last(), other.first()) {
+            assert!(last < first, "the keys of the merged treaps interleave");
+        }
+        Treap {
+            root: merge(self.root, other.root),
         }
-        removed
     }
 
-    /// Returns the number of values in the tree.
+    /// Returns the number of entries in the tree.
     pub fn len(&self) -> usize {
-        self.length
+        size(self.root.as_deref())
     }
 
-    /// Returns `true` if the tree contains no values.
+    /// Returns `true` if the tree contains no entries.
     pub fn is_empty(&self) -> bool {
-        self.length == 0
+        self.root.is_none()
     }
 
-    /// Returns an iterator that visits the nodes in the tree in order.
-    fn node_iter(&self) -> NodeIter<T> {
+    /// Returns the entry with the smallest key.
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.select(0)
+    }
+
+    /// Returns the entry with the largest key.
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.select(self.len().checked_sub(1)?)
+    }
+
+    /// Removes and returns the entry with the smallest key.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        take_extreme(&mut self.root, Side::Left).map(|node| (node.key, node.value))
+    }
+
+    /// Removes and returns the entry with the largest key.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        take_extreme(&mut self.root, Side::Right).map(|node| (node.key, node.value))
+    }
+
+    /// Returns the entry with the largest key less than or equal to `key`.
+    pub fn floor(&self, key: &K) -> Option<(&K, &V)> {
+        let mut current = &self.root;
+        let mut best = None;
+        while let Some(node) = current {
+            current = match key.cmp(&node.key) {
+                Ordering::Equal => return Some((&node.key, &node.value)),
+                Ordering::Less => &node.left,
+                Ordering::Greater => {
+                    best = Some((&node.key, &node.value));
+                    &node.right
+                }
+            }
+        }
+        best
+    }
+
+    /// Returns the entry with the smallest key greater than or equal to `key`.
+    pub fn ceiling(&self, key: &K) -> Option<(&K, &V)> {
+        let mut current = &self.root;
+        let mut best = None;
+        while let Some(node) = current {
+            current = match key.cmp(&node.key) {
+                Ordering::Equal => return Some((&node.key, &node.value)),
+                Ordering::Greater => &node.right,
+                Ordering::Less => {
+                    best = Some((&node.key, &node.value));
+                    &node.left
+                }
+            }
+        }
+        best
+    }
+
+    /// Returns the number of keys strictly less than `key`.
+    pub fn rank(&self, key: &K) -> usize {
+        self.count_below(key, false)
+    }
+
+    /// Returns the number of keys less than `key`, or less than or equal to it if
+    /// `inclusive` is set.
+    fn count_below(&self, key: &K, inclusive: bool) -> usize {
+        let mut current = &self.root;
+        let mut count = 0;
+        while let Some(node) = current {
+            let go_right = match key.cmp(&node.key) {
+                Ordering::Less => false,
+                Ordering::Equal => inclusive,
+                Ordering::Greater => true,
+            };
+            current = if go_right {
+                count += size(node.left.as_deref()) + 1;
+                &node.right
+            } else {
+                &node.left
+            };
+        }
+        count
+    }
+
+    /// Returns the entry with the `index`-th smallest key, counting from 0.
+    pub fn select(&self, index: usize) -> Option<(&K, &V)> {
+        let mut current = &self.root;
+        let mut index = index;
+        while let Some(node) = current {
+            let left = size(node.left.as_deref());
+            current = match index.cmp(&left) {
+                Ordering::Equal => return Some((&node.key, &node.value)),
+                Ordering::Less => &node.left,
+                Ordering::Greater => {
+                    index -= left + 1;
+                    &node.right
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns a mutable reference to the value of the `index`-th smallest key.
+    fn select_mut(&mut self, index: usize) -> Option<&mut V> {
+        let mut current = &mut self.root;
+        let mut index = index;
+        while let Some(node) = current {
+            let left = size(node.left.as_deref());
+            current = match index.cmp(&left) {
+                Ordering::Equal => return Some(&mut node.value),
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => {
+                    index -= left + 1;
+                    &mut node.right
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns an iterator over the nodes from the `index`-th smallest one, in order.
+    fn node_iter_from(&self, index: usize) -> NodeIter<'_, K, V> {
         let mut node_iter = NodeIter { stack: Vec::new() };
-        // Initialize stack with path to leftmost child
+        // Initialize stack with the nodes on the search path that come at or after `index`
         let mut child = &self.root;
+        let mut index = index;
         while let Some(node) = child {
-            node_iter.stack.push(node.as_ref());
-            child = &node.left;
+            let left = size(node.left.as_deref());
+            if index <= left {
+                node_iter.stack.push(node.as_ref());
+                child = &node.left;
+            } else {
+                index -= left + 1;
+                child = &node.right;
+            }
         }
         node_iter
     }
 
-    /// Returns an iterator that visits the values in the tree in ascending order.
-    pub fn iter(&self) -> Iter<T> {
+    /// Returns an iterator that visits the nodes in the tree in order.
+    fn node_iter(&self) -> NodeIter<'_, K, V> {
+        self.node_iter_from(0)
+    }
+
+    /// Returns an iterator that visits the entries in the tree in ascending order of keys.
+    pub fn iter(&self) -> Iter<'_, K, V> {
         Iter {
             node_iter: self.node_iter(),
+            remaining: self.len(),
+        }
+    }
+
+    /// Returns an iterator over the keys in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Returns an iterator over the values in ascending order of keys.
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// Returns an iterator over the entries whose keys lie in `range`, in ascending order.
+    ///
+    /// A range whose start lies after its end is empty.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, K, V> {
+        let start = match range.start_bound() {
+            Bound::Included(key) => self.count_below(key, false),
+            Bound::Excluded(key) => self.count_below(key, true),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => self.count_below(key, true),
+            Bound::Excluded(key) => self.count_below(key, false),
+            Bound::Unbounded => self.len(),
+        };
+        Iter {
+            node_iter: self.node_iter_from(start),
+            remaining: end.saturating_sub(start),
         }
     }
 }
 
 /// Generating random number, should use rand::Rng if possible.
+///
+/// Successive timestamps are close to each other, and a treap built from increasing priorities
+/// degenerates into a list, so they are mixed with a counter through the splitmix64 finalizer.
 fn rand() -> usize {
-    SystemTime::now()
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
-        .subsec_nanos() as usize
+        .subsec_nanos();
+    let mut z = COUNTER
+        .fetch_add(0x9e37_79b9_7f4a_7c15, AtomicOrdering::Relaxed)
+        .wrapping_add(u64::from(nanos));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)) as usize
+}
+
+/// Returns the number of nodes in a subtree.
+pub(super) fn size<K: Ord, V, A>(tree: Option<&TreapNode<K, V, A>>) -> usize {
+    tree.map_or(0, |n| n.size)
 }
 
 /// Recursive helper function for `Treap` insertion.
-fn insert<T: Ord>(tree: &mut Option<Box<TreapNode<T>>>, value: T) -> bool {
+fn insert<K: Ord, V>(tree: &mut Option<Box<TreapNode<K, V>>>, key: K, value: V) -> Option<V> {
     if let Some(node) = tree {
-        let inserted = match value.cmp(&node.value) {
-            Ordering::Equal => false,
-            Ordering::Less => insert(&mut node.left, value),
-            Ordering::Greater => insert(&mut node.right, value),
+        let old = match key.cmp(&node.key) {
+            Ordering::Equal => return Some(mem::replace(&mut node.value, value)),
+            Ordering::Less => insert(&mut node.left, key, value),
+            Ordering::Greater => insert(&mut node.right, key, value),
         };
-        if inserted {
+        if old.is_none() {
             node.rebalance();
         }
-        inserted
+        old
     } else {
-        *tree = Some(Box::new(TreapNode {
-            value,
-            priority: rand(),
-            left: None,
-            right: None,
-        }));
-        true
+        *tree = Some(Box::new(TreapNode::new(key, value)));
+        None
     }
 }
 
-/// Recursive helper function for `Treap` deletion
-fn remove<T: Ord>(tree: &mut Option<Box<TreapNode<T>>>, value: &T) -> bool {
-    if let Some(node) = tree {
-        let removed = match value.cmp(&node.value) {
-            Ordering::Less => remove(&mut node.left, value),
-            Ordering::Greater => remove(&mut node.right, value),
-            Ordering::Equal => {
-                *tree = match (node.left.take(), node.right.take()) {
-                    (None, None) => None,
-                    (Some(b), None) | (None, Some(b)) => Some(b),
-                    (Some(left), Some(right)) => {
-                        let side = match left.priority.cmp(&right.priority) {
-                            Ordering::Greater => Side::Right,
-                            _ => Side::Left,
-                        };
-                        node.left = Some(left);
-                        node.right = Some(right);
-                        node.rotate(side);
-                        remove(node.child_mut(side), value);
-                        Some(tree.take().unwrap())
-                    }
-                };
-                return true;
+/// Recursive helper function for `Treap` deletion.
+fn remove<K: Ord, V>(tree: &mut Option<Box<TreapNode<K, V>>>, key: &K) -> Option<V> {
+    let node = tree.as_mut()?;
+    let removed = match key.cmp(&node.key) {
+        Ordering::Less => remove(&mut node.left, key),
+        Ordering::Greater => remove(&mut node.right, key),
+        Ordering::Equal => {
+            let mut node = tree.take().unwrap();
+            *tree = merge(node.left.take(), node.right.take());
+            return Some(node.value);
+        }
+    };
+    if removed.is_some() {
+        node.update();
+    }
+    removed
+}
+
+/// An optional subtree.
+pub(super) type Link<K, V, A = ()> = Option<Box<TreapNode<K, V, A>>>;
+
+/// Splits a tree into the nodes with keys less than `key`, and the rest.
+fn split<K: Ord, V>(tree: Option<Box<TreapNode<K, V>>>, key: &K) -> (Link<K, V>, Link<K, V>) {
+    let Some(mut node) = tree else {
+        return (None, None);
+    };
+    if node.key < *key {
+        // The node and its left subtree go left; the right subtree is split further
+        let (left, right) = split(node.right.take(), key);
+        node.right = left;
+        node.update();
+        (Some(node), right)
+    } else {
+        let (left, right) = split(node.left.take(), key);
+        node.left = right;
+        node.update();
+        (left, Some(node))
+    }
+}
+
+/// Splits a tree into its first `k` nodes and the rest.
+pub(super) fn split_at<K: Ord, V, A: Augment<K, V>>(
+    tree: Link<K, V, A>,
+    k: usize,
+) -> (Link<K, V, A>, Link<K, V, A>) {
+    let Some(mut node) = tree else {
+        return (None, None);
+    };
+    A::push(&mut node);
+    let left_size = size(node.left.as_deref());
+    if k <= left_size {
+        let (left, right) = split_at(node.left.take(), k);
+        node.left = right;
+        node.update();
+        (left, Some(node))
+    } else {
+        let (left, right) = split_at(node.right.take(), k - left_size - 1);
+        node.right = left;
+        node.update();
+        (Some(node), right)
+    }
+}
+
+/// Merges two trees, where all nodes of `left` come before those of `right`, and returns the
+/// root of the merged tree.
+pub(super) fn merge<K: Ord, V, A: Augment<K, V>>(
+    left: Link<K, V, A>,
+    right: Link<K, V, A>,
+) -> Link<K, V, A> {
+    match (left, right) {
+        (None, tree) | (tree, None) => tree,
+        (Some(mut left), Some(mut right)) => {
+            // The root with the higher priority stays on top
+            if left.priority > right.priority {
+                A::push(&mut left);
+                left.right = merge(left.right.take(), Some(right));
+                left.update();
+                Some(left)
+            } else {
+                A::push(&mut right);
+                right.left = merge(Some(left), right.left.take());
+                right.update();
+                Some(right)
             }
-        };
-        if removed {
-            node.rebalance();
         }
-        removed
+    }
+}
+
+/// Removes the smallest (`Side::Left`) or largest (`Side::Right`) node from the tree, if
+/// one exists.
+fn take_extreme<K: Ord, V>(
+    tree: &mut Option<Box<TreapNode<K, V>>>,
+    side: Side,
+) -> Option<Box<TreapNode<K, V>>> {
+    let mut node = tree.take()?;
+    if let Some(extreme) = take_extreme(node.child_mut(side), side) {
+        node.update();
+        *tree = Some(node);
+        Some(extreme)
     } else {
-        false
+        *tree = node.child_mut(!side).take();
+        node.size = 1;
+        Some(node)
+    }
+}
+
+impl<K: Ord, V, A> TreapNode<K, V, A> {
+    pub(super) fn value(&self) -> &V {
+        &self.value
+    }
+
+    pub(super) fn value_mut(&mut self) -> &mut V {
+        &mut self.value
+    }
+
+    pub(super) fn into_value(self) -> V {
+        self.value
+    }
+
+    pub(super) fn size(&self) -> usize {
+        self.size
+    }
+
+    pub(super) fn augment(&self) -> &A {
+        &self.augment
+    }
+
+    pub(super) fn augment_mut(&mut self) -> &mut A {
+        &mut self.augment
+    }
+
+    pub(super) fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+
+    pub(super) fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
+    }
+
+    /// Returns mutable references to both children, left first.
+    pub(super) fn children_mut(&mut self) -> [&mut Link<K, V, A>; 2] {
+        [&mut self.left, &mut self.right]
     }
 }
 
-impl<T: Ord> TreapNode<T> {
+impl<K: Ord, V, A: Augment<K, V>> TreapNode<K, V, A> {
+    /// Creates a node without children, with a random priority.
+    pub(super) fn new(key: K, value: V) -> Self {
+        TreapNode {
+            augment: A::summarize(&key, &value, None, None),
+            key,
+            value,
+            priority: rand(),
+            size: 1,
+            left: None,
+            right: None,
+        }
+    }
+
     /// Returns a reference to the left or right child.
-    fn child(&self, side: Side) -> &Option<Box<TreapNode<T>>> {
+    fn child(&self, side: Side) -> &Link<K, V, A> {
         match side {
             Side::Left => &self.left,
             Side::Right => &self.right,
@@ -179,7 +545,7 @@ impl<T: Ord> TreapNode<T> {
     }
 
     /// Returns a mutable reference to the left or right child.
-    fn child_mut(&mut self, side: Side) -> &mut Option<Box<TreapNode<T>>> {
+    fn child_mut(&mut self, side: Side) -> &mut Link<K, V, A> {
         match side {
             Side::Left => &mut self.left,
             Side::Right => &mut self.right,
@@ -191,7 +557,18 @@ impl<T: Ord> TreapNode<T> {
         self.child(side).as_ref().map_or(0, |n| n.priority)
     }
 
-    /// Performs a left or right rotation
+    /// Recomputes the `size` and `augment` fields.
+    fn update(&mut self) {
+        self.size = 1 + size(self.left.as_deref()) + size(self.right.as_deref());
+        self.augment = A::summarize(
+            &self.key,
+            &self.value,
+            self.left.as_deref().map(|n| &n.augment),
+            self.right.as_deref().map(|n| &n.augment),
+        );
+    }
+
+    /// Performs a left or right rotation.
     fn rotate(&mut self, side: Side) {
         if self.child_mut(!side).is_none() {
             return;
@@ -199,14 +576,17 @@ impl<T: Ord> TreapNode<T> {
 
         let mut subtree = self.child_mut(!side).take().unwrap();
         *self.child_mut(!side) = subtree.child_mut(side).take();
+        self.update();
         // Swap root and child nodes in memory
         mem::swap(self, subtree.as_mut());
         // Set old root (subtree) as child of new root (self)
         *self.child_mut(side) = Some(subtree);
+        self.update();
     }
 
     /// Performs left or right tree rotations to balance this node.
     fn rebalance(&mut self) {
+        self.update();
         match (
             self.priority,
             self.priority(Side::Left),
@@ -215,16 +595,18 @@ impl<T: Ord> TreapNode<T> {
             (v, p, q) if p >= q && p > v => self.rotate(Side::Right),
             (v, p, q) if p < q && q > v => self.rotate(Side::Left),
             _ => (),
-        };
+        }
     }
 
     #[cfg(test)]
     fn is_valid(&self) -> bool {
-        self.priority >= self.priority(Side::Left) && self.priority >= self.priority(Side::Right)
+        self.priority >= self.priority(Side::Left)
+            && self.priority >= self.priority(Side::Right)
+            && self.size == 1 + size(self.left.as_deref()) + size(self.right.as_deref())
     }
 }
 
-impl<T: Ord> Default for Treap<T> {
+impl<K: Ord, V> Default for Treap<K, V> {
     fn default() -> Self {
         Self::new()
     }
@@ -241,25 +623,147 @@ impl Not for Side {
     }
 }
 
-impl<T: Ord> FromIterator<T> for Treap<T> {
-    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+impl<K: Ord, V> FromIterator<(K, V)> for Treap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
         let mut tree = Treap::new();
-        for value in iter {
-            tree.insert(value);
-        }
+        tree.extend(iter);
         tree
     }
 }
 
+impl<K: Ord, V> Extend<(K, V)> for Treap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<'a, K: Ord, V> IntoIterator for &'a Treap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A view into a single entry of a `Treap`, which may be vacant or occupied.
+///
+/// This enum is created by the `entry` method of `Treap`.
+pub enum Entry<'a, K: Ord, V> {
+    Vacant(VacantEntry<'a, K, V>),
+    Occupied(OccupiedEntry<'a, K, V>),
+}
+
+/// An entry of a `Treap` whose key is not in the tree.
+pub struct VacantEntry<'a, K: Ord, V> {
+    tree: &'a mut Treap<K, V>,
+    key: K,
+    /// The rank the key will have once inserted.
+    index: usize,
+}
+
+/// An entry of a `Treap` whose key is in the tree.
+pub struct OccupiedEntry<'a, K: Ord, V> {
+    tree: &'a mut Treap<K, V>,
+    key: K,
+    /// The rank of the key, which locates the entry without comparing keys.
+    index: usize,
+}
+
+impl<'a, K: Ord, V> Entry<'a, K, V> {
+    /// Returns the key of this entry.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Vacant(entry) => entry.key(),
+            Entry::Occupied(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant, and returns the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of `default` if the entry is vacant, and returns the value.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default()),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    /// Calls `f` on the value if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Ord, V: Default> Entry<'a, K, V> {
+    /// Inserts the default value if the entry is vacant, and returns the value.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: Ord, V> VacantEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts the value under this entry's key, and returns a reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.tree.insert(self.key, value);
+        self.tree.select_mut(self.index).unwrap()
+    }
+}
+
+impl<'a, K: Ord, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> &V {
+        self.tree.select(self.index).unwrap().1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.tree.select_mut(self.index).unwrap()
+    }
+
+    /// Converts the entry into a reference to its value, with the lifetime of the tree.
+    pub fn into_mut(self) -> &'a mut V {
+        self.tree.select_mut(self.index).unwrap()
+    }
+
+    /// Replaces the value of the entry, and returns the old value.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the tree, and returns its value.
+    pub fn remove(self) -> V {
+        self.tree.remove(&self.key).unwrap()
+    }
+}
+
 /// An iterator over the nodes of an `Treap`.
 ///
 /// This struct is created by the `node_iter` method of `Treap`.
-struct NodeIter<'a, T: Ord> {
-    stack: Vec<&'a TreapNode<T>>,
+struct NodeIter<'a, K: Ord, V> {
+    stack: Vec<&'a TreapNode<K, V>>,
 }
 
-impl<'a, T: Ord> Iterator for NodeIter<'a, T> {
-    type Item = &'a TreapNode<T>;
+impl<'a, K: Ord, V> Iterator for NodeIter<'a, K, V> {
+    type Item = &'a TreapNode<K, V>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(node) = self.stack.pop() {
@@ -276,77 +780,172 @@ impl<'a, T: Ord> Iterator for NodeIter<'a, T> {
     }
 }
 
-/// An iterator over the items of an `Treap`.
+/// An iterator over the entries of an `Treap`.
 ///
-/// This struct is created by the `iter` method of `Treap`.
-pub struct Iter<'a, T: Ord> {
-    node_iter: NodeIter<'a, T>,
+/// This struct is created by the `iter` and `range` methods of `Treap`.
+pub struct Iter<'a, K: Ord, V> {
+    node_iter: NodeIter<'a, K, V>,
+    remaining: usize,
 }
 
-impl<'a, T: Ord> Iterator for Iter<'a, T> {
-    type Item = &'a T;
+impl<'a, K: Ord, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
 
-    fn next(&mut self) -> Option<&'a T> {
-        match self.node_iter.next() {
-            Some(node) => Some(&node.value),
-            None => None,
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
         }
+        self.remaining -= 1;
+        self.node_iter.next().map(|node| (&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
     }
 }
 
+impl<K: Ord, V> ExactSizeIterator for Iter<'_, K, V> {}
+
 #[cfg(test)]
 mod tests {
-    use super::Treap;
+    use super::{Entry, Treap};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeMap;
 
     /// Returns `true` if all nodes in the tree are valid.
-    fn is_valid<T: Ord>(tree: &Treap<T>) -> bool {
+    fn is_valid<K: Ord, V>(tree: &Treap<K, V>) -> bool {
         tree.node_iter().all(|n| n.is_valid())
     }
 
+    fn squares(keys: impl Iterator<Item = i32>) -> Treap<i32, i32> {
+        keys.map(|x| (x, x * x)).collect()
+    }
+
     #[test]
     fn len() {
-        let tree: Treap<_> = (1..4).collect();
+        let tree = squares(1..4);
         assert_eq!(tree.len(), 3);
     }
 
     #[test]
     fn contains() {
-        let tree: Treap<_> = (1..4).collect();
-        assert!(tree.contains(&1));
-        assert!(!tree.contains(&4));
+        let tree = squares(1..4);
+        assert!(tree.contains_key(&1));
+        assert!(!tree.contains_key(&4));
     }
 
     #[test]
     fn insert() {
         let mut tree = Treap::new();
         // First insert succeeds
-        assert!(tree.insert(1));
-        // Second insert fails
-        assert!(!tree.insert(1));
+        assert_eq!(tree.insert(1, 'a'), None);
+        // Second insert replaces the value
+        assert_eq!(tree.insert(1, 'b'), Some('a'));
+        assert_eq!(tree.get(&1), Some(&'b'));
     }
 
     #[test]
     fn remove() {
-        let mut tree: Treap<_> = (1..8).collect();
+        let mut tree = squares(1..8);
         // First remove succeeds
-        assert!(tree.remove(&4));
+        assert_eq!(tree.remove(&4), Some(16));
         // Second remove fails
-        assert!(!tree.remove(&4));
+        assert_eq!(tree.remove(&4), None);
     }
 
     #[test]
     fn sorted() {
-        let tree: Treap<_> = (1..8).rev().collect();
-        assert!((1..8).eq(tree.iter().copied()));
+        let tree = squares((1..8).rev());
+        assert!((1..8).eq(tree.keys().copied()));
     }
 
     #[test]
     fn valid() {
-        let mut tree: Treap<_> = (1..8).collect();
+        let mut tree = squares(1..8);
         assert!(is_valid(&tree));
         for x in 1..8 {
             tree.remove(&x);
             assert!(is_valid(&tree));
         }
     }
+
+    #[test]
+    fn entry_api() {
+        let mut counts = Treap::new();
+        for c in "mississippi".chars() {
+            counts.entry(c).and_modify(|n| *n += 1).or_insert(1);
+        }
+        assert!(counts.iter().eq([('i', 4), ('m', 1), ('p', 2), ('s', 4)]
+            .iter()
+            .map(|(k, v)| (k, v))));
+        if let Entry::Occupied(mut entry) = counts.entry('p') {
+            assert_eq!(entry.insert(7), 2);
+            assert_eq!(*entry.get(), 7);
+        }
+        assert_eq!(*counts.entry('z').or_default(), 0);
+        *counts.get_mut(&'z').unwrap() += 3;
+        assert_eq!(counts.get(&'z'), Some(&3));
+        assert!(is_valid(&counts));
+    }
+
+    #[test]
+    fn order_statistics_and_neighbours() {
+        let mut tree = squares((0..30).map(|x| x * 3));
+        assert_eq!(tree.rank(&10), 4);
+        assert_eq!(tree.select(4), Some((&12, &144)));
+        assert_eq!(tree.floor(&10), Some((&9, &81)));
+        assert_eq!(tree.ceiling(&10), Some((&12, &144)));
+        assert_eq!(tree.first(), Some((&0, &0)));
+        assert_eq!(tree.last(), Some((&87, &7569)));
+        assert_eq!(tree.pop_first(), Some((0, 0)));
+        assert_eq!(tree.pop_last(), Some((87, 7569)));
+        assert_eq!(
+            tree.range(10..=30).map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![12, 15, 18, 21, 24, 27, 30]
+        );
+        assert!(is_valid(&tree));
+    }
+
+    #[test]
+    fn split_and_merge() {
+        let tree = squares(0..100);
+        let (left, right) = tree.split(&40);
+        assert!((0..40).eq(left.keys().copied()));
+        assert!((40..100).eq(right.keys().copied()));
+        assert!(is_valid(&left) && is_valid(&right));
+        let (middle, right) = right.split(&60);
+        assert_eq!(middle.len(), 20);
+        let merged = left.merge(right);
+        assert!((0..40).chain(60..100).eq(merged.keys().copied()));
+        assert!(is_valid(&merged));
+        let (empty, all) = merged.split(&-1);
+        assert!(empty.is_empty());
+        assert_eq!(all.len(), 80);
+    }
+
+    #[test]
+    #[should_panic(expected = "interleave")]
+    fn merge_interleaving() {
+        squares(0..10).merge(squares(5..15));
+    }
+
+    #[test]
+    fn matches_btree_map() {
+        let mut tree = Treap::new();
+        let mut map = BTreeMap::new();
+        let mut rng = StdRng::seed_from_u64(11);
+        for _ in 0..2000 {
+            let key = rng.gen_range(0..200);
+            if rng.gen_ratio(1, 3) {
+                assert_eq!(tree.remove(&key), map.remove(&key));
+            } else {
+                let value: u32 = rng.gen();
+                assert_eq!(tree.insert(key, value), map.insert(key, value));
+            }
+            assert_eq!(tree.len(), map.len());
+        }
+        assert!(tree.iter().eq(map.iter()));
+        assert!(tree.range(50..150).eq(map.range(50..150)));
+        assert!(is_valid(&tree));
+    }
 }
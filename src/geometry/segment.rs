@@ -1,4 +1,5 @@
 use super::Point;
+use crate::data_structures::Interval;
 
 const TOLERANCE: f64 = 0.0001;
 
@@ -75,6 +76,17 @@ impl Segment {
         self.colinear_point_on_segment(p)
     }
 
+    // The closed interval covered by the segment on the x axis. Segments whose projections do not
+    // overlap on both axes cannot intersect, which an interval tree of projections checks quickly.
+    pub fn x_projection(&self) -> Interval<f64> {
+        Interval::closed(self.a.x.min(self.b.x), self.a.x.max(self.b.x))
+    }
+
+    // The closed interval covered by the segment on the y axis.
+    pub fn y_projection(&self) -> Interval<f64> {
+        Interval::closed(self.a.y.min(self.b.y), self.a.y.max(self.b.y))
+    }
+
     pub fn intersects(&self, other: &Segment) -> bool {
         let direction1 = self.direction(&other.a);
         let direction2 = self.direction(&other.b);
@@ -100,6 +112,7 @@ impl Segment {
 mod tests {
     use super::Point;
     use super::Segment;
+    use crate::data_structures::{CenteredIntervalTree, Interval};
 
     #[test]
     fn colinear() {
@@ -190,4 +203,41 @@ mod tests {
         test_intersect(&s1, &s3, false);
         test_intersect(&s2, &s3, false);
     }
+
+    #[test]
+    fn projections_filter_candidates() {
+        let segments = [
+            Segment::new(0.0, 0.0, 4.0, 4.0),
+            Segment::new(0.0, 4.0, 4.0, 0.0),
+            Segment::new(5.0, 0.0, 6.0, 1.0),
+            Segment::new(3.0, 10.0, 4.0, 11.0),
+            Segment::new(2.0, -1.0, 2.0, 3.0),
+        ];
+        assert_eq!(segments[4].x_projection(), Interval::closed(2.0, 2.0));
+        assert_eq!(segments[1].y_projection(), Interval::closed(0.0, 4.0));
+        // the projections of the first two segments are equal, which a centered tree keeps apart
+        let by_x: CenteredIntervalTree<f64, usize> = segments
+            .iter()
+            .enumerate()
+            .map(|(i, segment)| (segment.x_projection(), i))
+            .collect();
+        // only the segments overlapping on both axes are checked for an intersection
+        let mut candidates: Vec<_> = by_x
+            .overlapping(segments[0].x_projection())
+            .into_iter()
+            .map(|(_, &i)| i)
+            .filter(|&i| {
+                i != 0
+                    && segments[i]
+                        .y_projection()
+                        .overlaps(&segments[0].y_projection())
+            })
+            .collect();
+        candidates.sort_unstable();
+        assert_eq!(candidates, [1, 4]);
+        assert!(candidates
+            .iter()
+            .all(|&i| segments[0].intersects(&segments[i])));
+        assert!(!segments[0].intersects(&segments[3]));
+    }
 }
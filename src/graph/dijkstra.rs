@@ -1,3 +1,4 @@
+use crate::data_structures::{IndexedHeap, PriorityQueue};
 use std::collections::BTreeMap;
 use std::ops::Add;
 
@@ -10,24 +11,40 @@ type Graph<V, E> = BTreeMap<V, BTreeMap<V, E>>;
 // since the start has no predecessor but is reachable, map[start] will be None
 //
 // Time: O(E * logV). For each vertex, we traverse each edge, resulting in O(E). For each edge, we
-// insert a new shortest path for a vertex into the tree, resulting in O(E * logV).
-// Space: O(V). The tree holds up to V vertices.
+// may decrease the distance of a vertex in the indexed binary heap, resulting in O(E * logV).
+// Space: O(V). The heap holds up to V vertices.
 pub fn dijkstra<V: Ord + Copy, E: Ord + Copy + Add<Output = E>>(
     graph: &Graph<V, E>,
     start: V,
+) -> BTreeMap<V, Option<(V, E)>> {
+    dijkstra_with_queue::<IndexedHeap<E, V>, V, E>(graph, start)
+}
+
+// same as `dijkstra`, with any priority queue supporting decrease-key
+// with a Fibonacci heap, decreasing a distance takes O(1) amortized, and the algorithm O(E + V * logV)
+pub fn dijkstra_with_queue<
+    Q: PriorityQueue<E, V>,
+    V: Ord + Copy,
+    E: Ord + Copy + Add<Output = E>,
+>(
+    graph: &Graph<V, E>,
+    start: V,
 ) -> BTreeMap<V, Option<(V, E)>> {
     let mut ans = BTreeMap::new();
-    let mut prio = BTreeMap::new();
+    let mut prio = Q::default();
+    // the handles of the vertices waiting in prio
+    let mut handles = BTreeMap::new();
 
     // start is the special case that doesn't have a predecessor
     ans.insert(start, None);
 
     for (new, weight) in &graph[&start] {
         ans.insert(*new, Some((start, *weight)));
-        prio.insert(*new, *weight);
+        handles.insert(*new, prio.push(*weight, *new));
     }
 
-    while let Some((vertex, path_weight)) = prio.pop_first() {
+    while let Some((path_weight, vertex)) = prio.pop() {
+        handles.remove(&vertex);
         for (next, weight) in &graph[&vertex] {
             let new_weight = path_weight + *weight;
             match ans.get(next) {
@@ -38,7 +55,12 @@ pub fn dijkstra<V: Ord + Copy, E: Ord + Copy + Add<Output = E>>(
                 // the new path is shorter, either new was not in ans or it was farther
                 _ => {
                     ans.insert(*next, Some((vertex, new_weight)));
-                    prio.insert(*next, new_weight);
+                    match handles.get(next) {
+                        Some(&handle) => prio.decrease_key(handle, new_weight),
+                        None => {
+                            handles.insert(*next, prio.push(new_weight, *next));
+                        }
+                    }
                 }
             }
         }
@@ -49,7 +71,9 @@ pub fn dijkstra<V: Ord + Copy, E: Ord + Copy + Add<Output = E>>(
 
 #[cfg(test)]
 mod tests {
-    use super::{dijkstra, Graph};
+    use super::{dijkstra, dijkstra_with_queue, Graph};
+    use crate::data_structures::{FibonacciHeap, PairingHeap};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
     use std::collections::BTreeMap;
 
     fn add_edge<V: Ord + Copy, E: Ord>(graph: &mut Graph<V, E>, v1: V, v2: V, c: E) {
@@ -152,4 +176,28 @@ mod tests {
         dists_e.insert('b', Some(('c', 39)));
         assert_eq!(dijkstra(&graph, 'e'), dists_e);
     }
+
+    #[test]
+    fn same_distances_with_every_queue() {
+        let mut graph = BTreeMap::new();
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..2000 {
+            let (v1, v2): (u64, u64) = (rng.gen_range(0..200), rng.gen_range(0..200));
+            add_edge(&mut graph, v1, v2, rng.gen_range(0..1000));
+        }
+
+        for start in [0, 17, 199] {
+            let dists = dijkstra(&graph, start);
+            let distances = |dists: &BTreeMap<u64, Option<(u64, u64)>>| -> BTreeMap<u64, u64> {
+                dists
+                    .iter()
+                    .map(|(v, d)| (*v, d.map_or(0, |(_, d)| d)))
+                    .collect()
+            };
+            let pairing = dijkstra_with_queue::<PairingHeap<_, _>, _, _>(&graph, start);
+            let fibonacci = dijkstra_with_queue::<FibonacciHeap<_, _>, _, _>(&graph, start);
+            assert_eq!(distances(&pairing), distances(&dists));
+            assert_eq!(distances(&fibonacci), distances(&dists));
+        }
+    }
 }
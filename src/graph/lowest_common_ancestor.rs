@@ -4,9 +4,13 @@ If a tree is not enumerated that way or its vertices are not represented
 using numbers, it can trivially be converted using Depth First Search
 manually or by using `src/graph/graph_enumeration.rs`
 
- Here we implement two different algorithms:
+ Here we implement three different algorithms:
 - The online one is implemented using Sparse Table and has O(n.lg(n))
 time complexity and memory usage. It answers each query in O(lg(n)).
+- The Euler tour one lists the vertices in the order a Depth First Search
+enters and returns to them. The LCA of u and v is the shallowest vertex
+visited between the first visits of u and v, so a range minimum query on
+depths answers it in O(1), after O(n.lg(n)) precomputation.
 - The offline algorithm was discovered by Robert Tarjan. At first each
 query should be determined and saved. Then, vertices are visited in
 Depth First Search order and queries are answered using Disjoint
@@ -16,6 +20,8 @@ because alpha(n) < 5 for n < 10 ^ 600
  */
 
 use super::DisjointSetUnion;
+use crate::data_structures::{MinOp, SparseTable};
+
 pub struct LowestCommonAncestorOnline {
     // Make members public to allow the user to fill them themself.
     pub parents_sparse_table: Vec<Vec<usize>>,
@@ -97,6 +103,57 @@ impl LowestCommonAncestorOnline {
     }
 }
 
+pub struct LowestCommonAncestorEulerTour {
+    // the index of the first visit of each vertex in the tour
+    first_visit: Vec<usize>,
+    // (depth, vertex) for every step of the tour
+    tour: SparseTable<(usize, usize), MinOp>,
+}
+
+impl LowestCommonAncestorEulerTour {
+    pub fn new(root: usize, adj: &[Vec<usize>]) -> Self {
+        let mut first_visit = vec![usize::MAX; adj.len()];
+        let mut tour = Vec::with_capacity(2 * adj.len());
+        // explicit stack of (vertex, parent, depth, next child index), so deep
+        // trees do not overflow the call stack
+        let mut stack = vec![(root, usize::MAX, 0, 0)];
+        while let Some(&mut (vertex, parent, depth, ref mut next)) = stack.last_mut() {
+            if *next == 0 {
+                first_visit[vertex] = tour.len();
+            }
+            tour.push((depth, vertex));
+            // skip the edge back to the parent
+            while *next < adj[vertex].len() && adj[vertex][*next] == parent {
+                *next += 1;
+            }
+            if let Some(&child) = adj[vertex].get(*next) {
+                *next += 1;
+                stack.push((child, vertex, depth + 1, 0));
+            } else {
+                stack.pop();
+                // the parent is recorded again when its loop resumes
+            }
+        }
+        LowestCommonAncestorEulerTour {
+            first_visit,
+            tour: SparseTable::new(&tour),
+        }
+    }
+
+    pub fn get_ancestor(&self, v: usize, u: usize) -> usize {
+        let (a, b) = (self.first_visit[v], self.first_visit[u]);
+        assert!(
+            a != usize::MAX && b != usize::MAX,
+            "both vertices must be reachable from the root"
+        );
+        let range = a.min(b)..a.max(b) + 1;
+        self.tour
+            .query(range)
+            .expect("both vertices are in the tree")
+            .1
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct LCAQuery {
     other: usize,
@@ -176,6 +233,7 @@ impl LowestCommonAncestorOffline {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
     #[test]
     fn small_binary_tree() {
         let num_verts = 127;
@@ -190,6 +248,7 @@ mod tests {
         }
         let mut online_answers: Vec<QueryAnswer> = Vec::new();
         let mut online = LowestCommonAncestorOnline::new(num_verts);
+        let euler_tour = LowestCommonAncestorEulerTour::new(1, &tree);
         let mut offline = LowestCommonAncestorOffline::new(num_verts);
         let mut query_id = 314; // A random number, doesn't matter
         online.fill_sparse_table(1, 0, 0, &tree);
@@ -200,6 +259,7 @@ mod tests {
                     query_id,
                     answer: online.get_ancestor(i, j),
                 });
+                assert_eq!(euler_tour.get_ancestor(i, j), online.get_ancestor(i, j));
                 offline.add_query(i, j, query_id);
                 query_id += 1;
             }
@@ -208,4 +268,54 @@ mod tests {
         offline_answers.sort_unstable_by(|a1, a2| a1.query_id.cmp(&a2.query_id));
         assert_eq!(offline_answers, online_answers);
     }
+
+    #[test]
+    fn euler_tour_on_deep_random_tree() {
+        let num_verts = 20_000;
+        let mut tree: Vec<Vec<usize>> = vec![vec![]; num_verts + 1];
+        let mut rng = StdRng::seed_from_u64(17);
+        for v in 2..=num_verts {
+            // mostly attach to the previous vertex, to build long paths
+            let parent = if rng.gen_ratio(1, 16) {
+                rng.gen_range(1..v)
+            } else {
+                v - 1
+            };
+            tree[parent].push(v);
+            tree[v].push(parent);
+        }
+        let euler_tour = LowestCommonAncestorEulerTour::new(1, &tree);
+        // vertices are numbered so that parents come first
+        let mut parent = vec![0; num_verts + 1];
+        let mut depth = vec![0; num_verts + 1];
+        for v in 2..=num_verts {
+            parent[v] = *tree[v].iter().min().unwrap();
+            depth[v] = depth[parent[v]] + 1;
+        }
+        let naive = |mut u: usize, mut v: usize| {
+            while u != v {
+                if depth[u] < depth[v] {
+                    v = parent[v];
+                } else {
+                    u = parent[u];
+                }
+            }
+            u
+        };
+        for _ in 0..2000 {
+            let u = rng.gen_range(1..=num_verts);
+            let v = rng.gen_range(1..=num_verts);
+            assert_eq!(euler_tour.get_ancestor(u, v), naive(u, v));
+        }
+        assert_eq!(euler_tour.get_ancestor(num_verts, num_verts), num_verts);
+    }
+
+    #[test]
+    #[should_panic(expected = "both vertices must be reachable from the root")]
+    fn euler_tour_rejects_unreachable_vertices() {
+        // vertex 0 is not connected to the tree rooted at 1
+        let tree = vec![vec![], vec![2], vec![1]];
+        let euler_tour = LowestCommonAncestorEulerTour::new(1, &tree);
+        euler_tour.get_ancestor(0, 2);
+    }
 }
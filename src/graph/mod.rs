@@ -35,7 +35,7 @@ pub use self::decremental_connectivity::DecrementalConnectivity;
 pub use self::depth_first_search::depth_first_search;
 pub use self::depth_first_search_tic_tac_toe::minimax;
 pub use self::detect_cycle::DetectCycle;
-pub use self::dijkstra::dijkstra;
+pub use self::dijkstra::{dijkstra, dijkstra_with_queue};
 pub use self::dinic_maxflow::DinicMaxFlow;
 pub use self::disjoint_set_union::DisjointSetUnion;
 pub use self::eulerian_path::EulerianPath;
@@ -45,9 +45,11 @@ pub use self::graph_enumeration::enumerate_graph;
 pub use self::heavy_light_decomposition::HeavyLightDecomposition;
 pub use self::kosaraju::kosaraju;
 pub use self::lee_breadth_first_search::lee;
-pub use self::lowest_common_ancestor::{LowestCommonAncestorOffline, LowestCommonAncestorOnline};
+pub use self::lowest_common_ancestor::{
+    LowestCommonAncestorEulerTour, LowestCommonAncestorOffline, LowestCommonAncestorOnline,
+};
 pub use self::minimum_spanning_tree::kruskal;
-pub use self::prim::{prim, prim_with_start};
+pub use self::prim::{prim, prim_with_queue, prim_with_start};
 pub use self::prufer_code::{prufer_decode, prufer_encode};
 pub use self::strongly_connected_components::StronglyConnectedComponents;
 pub use self::tarjans_ssc::tarjan_scc;
@@ -1,5 +1,5 @@
-use std::cmp::Reverse;
-use std::collections::{BTreeMap, BinaryHeap};
+use crate::data_structures::{IndexedHeap, PriorityQueue};
+use std::collections::BTreeMap;
 use std::ops::Add;
 
 type Graph<V, E> = BTreeMap<V, BTreeMap<V, E>>;
@@ -24,34 +24,51 @@ pub fn prim<V: Ord + Copy + std::fmt::Debug, E: Ord + Add + Copy + std::fmt::Deb
 pub fn prim_with_start<V: Ord + Copy, E: Ord + Add + Copy>(
     graph: &Graph<V, E>,
     start: V,
+) -> Graph<V, E> {
+    prim_with_queue::<IndexedHeap<E, V>, V, E>(graph, start)
+}
+
+// same as `prim_with_start`, with any priority queue supporting decrease-key
+pub fn prim_with_queue<Q: PriorityQueue<E, V>, V: Ord + Copy, E: Ord + Add + Copy>(
+    graph: &Graph<V, E>,
+    start: V,
 ) -> Graph<V, E> {
     // will contain the MST
     let mut mst: Graph<V, E> = Graph::new();
-    // a priority queue based on a binary heap, used to get the cheapest edge
-    // the elements are an edge: the cost, destination and source
-    let mut prio = BinaryHeap::new();
+    // a priority queue of the vertices adjacent to the MST, by the cost of their cheapest edge to it
+    let mut prio = Q::default();
+    // for each vertex in prio: its handle, and the cost and source of its cheapest edge
+    let mut waiting: BTreeMap<V, (Q::Handle, E, V)> = BTreeMap::new();
 
     mst.insert(start, BTreeMap::new());
+    let mut added = Some(start);
 
-    for (v, c) in &graph[&start] {
-        // the heap is a max heap, we have to use Reverse when adding to simulate a min heap
-        prio.push(Reverse((*c, v, start)));
-    }
-
-    while let Some(Reverse((dist, t, prev))) = prio.pop() {
-        // the destination of the edge has already been seen
-        if mst.contains_key(t) {
-            continue;
-        }
-
-        // the destination is a new vertex
-        add_edge(&mut mst, prev, *t, dist);
-
-        for (v, c) in &graph[t] {
-            if !mst.contains_key(v) {
-                prio.push(Reverse((*c, v, *t)));
+    while let Some(t) = added {
+        for (v, c) in &graph[&t] {
+            // the destination of the edge has already been seen
+            if mst.contains_key(v) {
+                continue;
+            }
+            match waiting.get_mut(v) {
+                Some((handle, cost, source)) => {
+                    if c < cost {
+                        prio.decrease_key(*handle, *c);
+                        *cost = *c;
+                        *source = t;
+                    }
+                }
+                None => {
+                    waiting.insert(*v, (prio.push(*c, *v), *c, t));
+                }
             }
         }
+
+        // the cheapest edge leads to a new vertex
+        added = prio.pop().map(|(dist, t)| {
+            let (_, _, prev) = waiting.remove(&t).unwrap();
+            add_edge(&mut mst, prev, t, dist);
+            t
+        });
     }
 
     mst
@@ -59,7 +76,9 @@ pub fn prim_with_start<V: Ord + Copy, E: Ord + Add + Copy>(
 
 #[cfg(test)]
 mod tests {
-    use super::{add_edge, prim, Graph};
+    use super::{add_edge, prim, prim_with_queue, Graph};
+    use crate::data_structures::{FibonacciHeap, PairingHeap};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
     use std::collections::BTreeMap;
 
     #[test]
@@ -196,4 +215,19 @@ mod tests {
 
         assert_eq!(prim(&graph), ans);
     }
+
+    #[test]
+    fn same_tree_with_every_queue() {
+        let mut graph = BTreeMap::new();
+        let mut rng = StdRng::seed_from_u64(9);
+        for i in 0..2000 {
+            let (v1, v2): (u64, u64) = (rng.gen_range(0..200), rng.gen_range(0..200));
+            // distinct costs, so that the MST is unique
+            add_edge(&mut graph, v1, v2, i);
+        }
+
+        let mst = prim(&graph);
+        assert_eq!(prim_with_queue::<PairingHeap<_, _>, _, _>(&graph, 0), mst);
+        assert_eq!(prim_with_queue::<FibonacciHeap<_, _>, _, _>(&graph, 0), mst);
+    }
 }
@@ -8,31 +8,33 @@ pub fn jaro_winkler_distance(str1: &str, str2: &str) -> f64 {
     if str1.is_empty() || str2.is_empty() {
         return 0.0;
     }
-    fn get_matched_characters(s1: &str, s2: &str) -> String {
-        let mut s2 = s2.to_string();
+    // work on characters rather than bytes, so that multi-byte characters count once
+    let chars1: Vec<char> = str1.chars().collect();
+    let chars2: Vec<char> = str2.chars().collect();
+    fn get_matched_characters(s1: &[char], s2: &[char]) -> Vec<char> {
+        let mut s2: Vec<Option<char>> = s2.iter().copied().map(Some).collect();
         let mut matched: Vec<char> = Vec::new();
         let limit = std::cmp::min(s1.len(), s2.len()) / 2;
-        for (i, l) in s1.chars().enumerate() {
-            let left = std::cmp::max(0, i as i32 - limit as i32) as usize;
+        for (i, &l) in s1.iter().enumerate() {
+            let left = i.saturating_sub(limit);
             let right = std::cmp::min(i + limit + 1, s2.len());
-            if s2[left..right].contains(l) {
+            if let Some(j) = (left..right).find(|&j| s2[j] == Some(l)) {
                 matched.push(l);
-                let a = &s2[0..s2.find(l).expect("this exists")];
-                let b = &s2[(s2.find(l).expect("this exists") + 1)..];
-                s2 = format!("{a} {b}");
+                // a character matches at most once
+                s2[j] = None;
             }
         }
-        matched.iter().collect::<String>()
+        matched
     }
 
-    let matching_1 = get_matched_characters(str1, str2);
-    let matching_2 = get_matched_characters(str2, str1);
+    let matching_1 = get_matched_characters(&chars1, &chars2);
+    let matching_2 = get_matched_characters(&chars2, &chars1);
     let match_count = matching_1.len();
 
     // transposition
     let transpositions = {
         let mut count = 0;
-        for (c1, c2) in matching_1.chars().zip(matching_2.chars()) {
+        for (c1, c2) in matching_1.iter().zip(&matching_2) {
             if c1 != c2 {
                 count += 1;
             }
@@ -45,15 +47,14 @@ pub fn jaro_winkler_distance(str1: &str, str2: &str) -> f64 {
             return 0.0;
         } else {
             (1_f64 / 3_f64)
-                * (match_count as f64 / str1.len() as f64
-                    + match_count as f64 / str2.len() as f64
+                * (match_count as f64 / chars1.len() as f64
+                    + match_count as f64 / chars2.len() as f64
                     + (match_count - transpositions) as f64 / match_count as f64)
         }
     };
 
     let mut prefix_len = 0.0;
-    let bound = std::cmp::min(std::cmp::min(str1.len(), str2.len()), 4);
-    for (c1, c2) in str1[..bound].chars().zip(str2[..bound].chars()) {
+    for (c1, c2) in chars1.iter().zip(&chars2).take(4) {
         if c1 == c2 {
             prefix_len += 1.0;
         } else {
@@ -81,5 +82,9 @@ mod tests {
         assert_eq!(a, 0.0);
         let a = jaro_winkler_distance("hello world", "HeLLo W0rlD");
         assert_eq!(a, 0.6363636363636364);
+        let a = jaro_winkler_distance("café au lait", "cafe au lait");
+        assert_eq!(a, jaro_winkler_distance("cafx au lait", "cafe au lait"));
+        let a = jaro_winkler_distance("日本語", "日本");
+        assert!(a > 0.9, "{a}");
     }
 }